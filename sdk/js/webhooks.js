@@ -0,0 +1,46 @@
+// Verifies who-chat webhook deliveries in Node.js.
+//
+// Every delivery carries an X-WhoChat-Timestamp header (unix seconds) and an
+// X-WhoChat-Signature header of the form "sha256=<hex>", the HMAC-SHA256 of
+// "<timestamp>.<raw body>" keyed with the webhook's secret. Verify against the
+// raw request body, before any JSON parsing, e.g. with Express:
+//
+//     const { verifyWebhook } = require("./webhooks");
+//
+//     app.post("/who-chat", express.raw({ type: "application/json" }), (req, res) => {
+//         if (!verifyWebhook(process.env.WHOCHAT_WEBHOOK_SECRET, req.headers, req.body)) {
+//             return res.sendStatus(401);
+//         }
+//         const event = JSON.parse(req.body);
+//         ...
+//     });
+
+const crypto = require("crypto");
+
+// Deliveries older than this are rejected to stop replays
+const DEFAULT_TOLERANCE_SECS = 300;
+
+function signWebhook(secret, timestamp, body) {
+    const mac = crypto.createHmac("sha256", secret);
+    mac.update(`${timestamp}.`);
+    mac.update(body);
+    return "sha256=" + mac.digest("hex");
+}
+
+// `headers` uses Node's lowercased header names; `body` is a string or Buffer
+function verifyWebhook(secret, headers, body, toleranceSecs = DEFAULT_TOLERANCE_SECS) {
+    const timestamp = Number(headers["x-whochat-timestamp"]);
+    const signature = headers["x-whochat-signature"];
+    if (!Number.isInteger(timestamp) || typeof signature !== "string") {
+        return false;
+    }
+    if (Math.abs(Date.now() / 1000 - timestamp) > toleranceSecs) {
+        return false;
+    }
+
+    const expected = Buffer.from(signWebhook(secret, timestamp, body));
+    const actual = Buffer.from(signature);
+    return expected.length === actual.length && crypto.timingSafeEqual(expected, actual);
+}
+
+module.exports = { signWebhook, verifyWebhook };
@@ -0,0 +1,349 @@
+// Registered accounts. Registering claims a nickname with a password; guests
+// can still join with any nickname nobody has registered. Accounts live in
+// memory and are written to the data directory on every change. Deleting an
+// account quarantines its nickname for a while so nobody can pick it up and
+// pass as the previous owner.
+
+use std::collections::{BTreeSet, HashMap};
+
+use argon2::Argon2;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::http::{CookieJar, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Request, Route};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::admin::{ApiResult, api_error};
+use crate::config::CONFIG;
+use crate::sessions::{self, SESSIONS};
+use crate::totp::TotpSettings;
+use crate::auth::{self, Identity};
+use crate::{Role, login_throttle, passkeys, storage, user_data};
+
+const MAX_USERNAME_LEN: usize = 32;
+const MIN_PASSWORD_LEN: usize = 8;
+
+fn local_provider() -> String {
+    auth::LOCAL.to_string()
+}
+
+pub const QUARANTINED: &str = "That nickname belonged to a deleted account and can't be used yet";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    pub username: String,
+    password_hash: String,
+    pub created_at: String,
+    // Account ids of friends and of pending friend requests either way
+    #[serde(default)]
+    pub friends: BTreeSet<String>,
+    #[serde(default)]
+    pub incoming_requests: BTreeSet<String>,
+    #[serde(default)]
+    pub outgoing_requests: BTreeSet<String>,
+    // Nicknames whose messages are hidden from this account
+    #[serde(default)]
+    pub blocked: BTreeSet<String>,
+    // Auth provider that vouches for the account, see src/auth.rs
+    #[serde(default = "local_provider")]
+    pub provider: String,
+    // Server-wide role from the provider (directory groups), or Admin for
+    // the account created by the setup wizard
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory_role: Option<Role>,
+    // Two-factor authentication, once set up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp: Option<TotpSettings>,
+    // Turned off by the identity provider, see src/scim.rs; nothing signs
+    // a deactivated account in
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deactivated: bool,
+    // The identity provider's id for the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+}
+
+impl Account {
+    fn new(username: &str, password_hash: String, provider: &str) -> Self {
+        Account {
+            id: Uuid::new_v4().to_string(),
+            username: username.to_string(),
+            password_hash,
+            created_at: Utc::now().to_rfc3339(),
+            friends: BTreeSet::new(),
+            incoming_requests: BTreeSet::new(),
+            outgoing_requests: BTreeSet::new(),
+            blocked: BTreeSet::new(),
+            provider: provider.to_string(),
+            directory_role: None,
+            totp: None,
+            deactivated: false,
+            external_id: None,
+        }
+    }
+}
+
+pub struct AccountStore {
+    // account id -> account
+    accounts: RwLock<HashMap<String, Account>>,
+    // lowercased nickname of a deleted account -> unix time it's free again
+    quarantine: RwLock<HashMap<String, i64>>,
+}
+
+impl AccountStore {
+    fn load() -> Self {
+        AccountStore {
+            accounts: RwLock::new(storage::load("accounts", "accounts").unwrap_or_default()),
+            quarantine: RwLock::new(storage::load("accounts", "quarantine").unwrap_or_default()),
+        }
+    }
+
+    fn save_quarantine(quarantine: &HashMap<String, i64>) {
+        if let Err(err) = storage::save("accounts", "quarantine", quarantine) {
+            eprintln!("Failed to save nickname quarantine: {}", err);
+        }
+    }
+
+    // Whether the nickname belonged to an account deleted too recently to reuse
+    pub fn is_quarantined(&self, nickname: &str) -> bool {
+        let key = nickname.trim().to_lowercase();
+        let until = self.quarantine.read().get(&key).copied();
+        let now = Utc::now().timestamp();
+        match until {
+            Some(until) if until > now => true,
+            Some(_) => {
+                let mut quarantine = self.quarantine.write();
+                quarantine.retain(|_, until| *until > now);
+                Self::save_quarantine(&quarantine);
+                false
+            },
+            None => false,
+        }
+    }
+
+    fn save(accounts: &HashMap<String, Account>) {
+        if let Err(err) = storage::save("accounts", "accounts", accounts) {
+            eprintln!("Failed to save accounts: {}", err);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Account> {
+        self.accounts.read().get(id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Account> {
+        self.accounts.read().values().cloned().collect()
+    }
+
+    // Usernames are matched case-insensitively
+    pub fn find(&self, username: &str) -> Option<Account> {
+        let accounts = self.accounts.read();
+        accounts
+            .values()
+            .find(|account| account.username.eq_ignore_ascii_case(username))
+            .cloned()
+    }
+
+    fn check_username(&self, username: &str) -> Result<(), String> {
+        if username.is_empty() || username.chars().count() > MAX_USERNAME_LEN {
+            return Err(format!("Usernames must be 1 to {} characters", MAX_USERNAME_LEN));
+        }
+        if username.chars().any(char::is_control) {
+            return Err("Usernames can't contain control characters".to_string());
+        }
+        if self.is_quarantined(username) {
+            return Err(QUARANTINED.to_string());
+        }
+        Ok(())
+    }
+
+    fn hash_password(password: &str) -> Result<String, String> {
+        if password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(format!("Passwords must be at least {} characters", MIN_PASSWORD_LEN));
+        }
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| err.to_string())
+    }
+
+    fn insert(&self, account: Account) -> Result<Account, String> {
+        let mut accounts = self.accounts.write();
+        if accounts.values().any(|other| other.username.eq_ignore_ascii_case(&account.username)) {
+            return Err("That nickname is already registered".to_string());
+        }
+        accounts.insert(account.id.clone(), account.clone());
+        Self::save(&accounts);
+        Ok(account)
+    }
+
+    pub fn register(&self, username: &str, password: &str) -> Result<Account, String> {
+        let username = username.trim();
+        self.check_username(username)?;
+        let password_hash = Self::hash_password(password)?;
+        self.insert(Account::new(username, password_hash, auth::LOCAL))
+    }
+
+    // An account created by the identity provider. Without a password it can
+    // only sign in through `provider`.
+    pub fn provision(&self, username: &str, password: Option<&str>, provider: &str, external_id: Option<String>) -> Result<Account, String> {
+        let username = username.trim();
+        self.check_username(username)?;
+        let password_hash = password.map(Self::hash_password).transpose()?.unwrap_or_default();
+        let mut account = Account::new(username, password_hash, provider);
+        account.external_id = external_id;
+        self.insert(account)
+    }
+
+    // Accounts of other providers have no password hash, so never match here
+    pub fn authenticate(&self, username: &str, password: &str) -> Option<Account> {
+        let account = self.find(username).filter(|account| !account.deactivated)?;
+        let hash = PasswordHash::new(&account.password_hash).ok()?;
+        Argon2::default().verify_password(password.as_bytes(), &hash).ok()?;
+        Some(account)
+    }
+
+    // The account a provider vouched for, created on a directory user's first
+    // login. Fails if the nickname belongs to another provider's account.
+    pub fn sign_in(&self, provider: &str, identity: Identity) -> Option<Account> {
+        let mut accounts = self.accounts.write();
+        let existing = accounts
+            .values_mut()
+            .find(|account| account.username.eq_ignore_ascii_case(&identity.username));
+        if let Some(account) = existing {
+            if account.provider != provider || account.deactivated {
+                return None;
+            }
+            if account.directory_role == identity.role {
+                return Some(account.clone());
+            }
+            account.directory_role = identity.role;
+            let account = account.clone();
+            Self::save(&accounts);
+            return Some(account);
+        }
+        if self.is_quarantined(&identity.username) {
+            return None;
+        }
+
+        let mut account = Account::new(&identity.username, String::new(), provider);
+        account.directory_role = identity.role;
+        accounts.insert(account.id.clone(), account.clone());
+        Self::save(&accounts);
+        Some(account)
+    }
+
+    pub fn directory_role(&self, account_id: &str) -> Option<Role> {
+        self.accounts.read().get(account_id).and_then(|account| account.directory_role)
+    }
+
+    pub fn has_blocked(&self, account_id: &str, nickname: &str) -> bool {
+        self.accounts
+            .read()
+            .get(account_id)
+            .is_some_and(|account| account.blocked.iter().any(|blocked| blocked.eq_ignore_ascii_case(nickname)))
+    }
+
+    // Deletes the account and every friendship, request or block pointing at it
+    pub fn remove(&self, id: &str) -> Option<Account> {
+        let mut accounts = self.accounts.write();
+        let account = accounts.remove(id)?;
+        for other in accounts.values_mut() {
+            other.friends.remove(id);
+            other.incoming_requests.remove(id);
+            other.outgoing_requests.remove(id);
+        }
+        Self::save(&accounts);
+        passkeys::forget(id);
+
+        let until = Utc::now().timestamp() + CONFIG.nickname_quarantine_secs as i64;
+        let mut quarantine = self.quarantine.write();
+        quarantine.insert(account.username.to_lowercase(), until);
+        Self::save_quarantine(&quarantine);
+        Some(account)
+    }
+
+    // Applies a change to the accounts and saves them
+    pub fn update<R>(&self, change: impl FnOnce(&mut HashMap<String, Account>) -> R) -> R {
+        let mut accounts = self.accounts.write();
+        let result = change(&mut accounts);
+        Self::save(&accounts);
+        result
+    }
+}
+
+lazy_static! {
+    pub static ref ACCOUNTS: AccountStore = AccountStore::load();
+}
+
+// Request guard for the signed-in account and its session id, from the
+// private `session_id` cookie
+pub struct AccountSession(pub Account, pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AccountSession {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let session = sessions::current(request.cookies());
+        let account = session
+            .as_ref()
+            .and_then(|session| ACCOUNTS.get(&session.account_id))
+            .filter(|account| !account.deactivated);
+
+        match (account, session) {
+            (Some(account), Some(session)) => Outcome::Success(AccountSession(account, session.id)),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DeleteAccount {
+    password: String,
+    // Replace the nickname on past messages with "deleted-user"
+    #[serde(default)]
+    anonymize: bool,
+}
+
+// Self-service deletion; the password is asked again so a stolen cookie isn't enough
+#[rocket::delete("/", data = "<request>")]
+fn delete_account(session: AccountSession, request: Json<DeleteAccount>, cookies: &CookieJar<'_>) -> ApiResult {
+    let account = session.0;
+    if let Err(wait) = login_throttle::check(&account.username, None) {
+        return Err(api_error(Status::TooManyRequests, login_throttle::refusal(wait)));
+    }
+    let confirmed = auth::authenticate(&account.username, &request.password).is_some_and(|signed_in| signed_in.id == account.id);
+    if !confirmed {
+        login_throttle::failed(&account.username, None);
+        return Err(api_error(Status::Forbidden, "Wrong password"));
+    }
+    ACCOUNTS.remove(&account.id);
+    SESSIONS.revoke_account(&account.id);
+    cookies.remove_private("session_id");
+
+    let (anonymized, retained) = if request.anonymize {
+        user_data::anonymize_messages(&account)
+    } else {
+        (0, Vec::new())
+    };
+    Ok(Json(json!({
+        "deleted": true,
+        "anonymized_messages": anonymized,
+        "retained_in_compliance_rooms": retained,
+        "nickname_available_after_secs": CONFIG.nickname_quarantine_secs,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![delete_account]
+}
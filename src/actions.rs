@@ -0,0 +1,193 @@
+// Client actions the server keeps track of, so every client (the web page,
+// a TUI, a mobile app) behaves the same without copying the web client.
+// Over the WebSocket, clients send
+//
+//   {"type": "action", "action": "<name>", "request_id": <anything, echoed>}
+//
+// and get back
+//
+//   {"type": "action_result", "action": "<name>", "request_id": ..., "ok": true, ...}
+//
+// with these extra fields per action:
+//
+//   mark_read      "read_up_to": id of the newest message, "unread": 0
+//   last_mention   "message_id": newest message mentioning the user, or null
+//   clear_history  "cleared_up_to": id of the newest message; older history
+//                  isn't replayed when reconnecting. /clear does the same.
+//
+// Unknown actions get "ok": false, "code": "UNKNOWN_ACTION" and an "error".
+// Right after the history replay on connect the server also sends
+//
+//   {"type": "read_state", "read_up_to": id|null, "unread": n, "unread_mentions": n,
+//    "last_mention": id|null}
+//
+// where mentions are @nickname or one of the user's highlight keywords.
+//
+// Markers belong to the account, or to the connection for guests; only
+// accounts' markers are saved.
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::protocol::ErrorCode;
+use crate::{ChatMessage, MessageType, RoomState, User, dashboard, keywords, storage};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Markers {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    read_up_to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cleared_up_to: Option<String>,
+}
+
+lazy_static! {
+    // "account:<id>:<room>" or "user:<id>:<room>" -> markers
+    static ref MARKERS: RwLock<HashMap<String, Markers>> =
+        RwLock::new(storage::load("markers", "markers").unwrap_or_default());
+}
+
+fn key(user: &User) -> String {
+    match &user.account_id {
+        Some(account_id) => format!("account:{}:{}", account_id, user.room_id),
+        None => format!("user:{}:{}", user.id, user.room_id),
+    }
+}
+
+fn markers(user: &User) -> Markers {
+    MARKERS.read().get(&key(user)).cloned().unwrap_or_default()
+}
+
+fn save(all: &HashMap<String, Markers>) {
+    let saved: HashMap<&String, &Markers> = all.iter().filter(|(key, _)| key.starts_with("account:")).collect();
+    if let Err(err) = storage::save("markers", "markers", &saved) {
+        eprintln!("Failed to save read markers: {}", err);
+    }
+}
+
+fn update(user: &User, change: impl FnOnce(&mut Markers)) {
+    let mut all = MARKERS.write();
+    change(all.entry(key(user)).or_default());
+    if user.account_id.is_some() {
+        save(&all);
+    }
+}
+
+// Hands a guest's read markers over to the account they registered, see upgrade.rs
+pub fn adopt(user_id: &str, account_id: &str) {
+    let mut all = MARKERS.write();
+    let prefix = format!("user:{}:", user_id);
+    let keys: Vec<String> = all.keys().filter(|key| key.starts_with(&prefix)).cloned().collect();
+    for key in keys {
+        if let Some(markers) = all.remove(&key) {
+            all.insert(format!("account:{}:{}", account_id, &key[prefix.len()..]), markers);
+        }
+    }
+    save(&all);
+}
+
+// @mentions of the user, or one of their highlight keywords
+fn mentions(msg: &ChatMessage, user: &User) -> bool {
+    msg.sender != user.nickname
+        && (msg.content.to_lowercase().contains(&format!("@{}", user.nickname.to_lowercase()))
+            || keywords::matches(&user.room_id, &user.id, user.account_id.as_deref(), &msg.content))
+}
+
+// Index just past the marked message, or 0 if it's gone from the history
+fn position_after(messages: &[ChatMessage], marker: Option<&String>) -> usize {
+    marker
+        .and_then(|id| messages.iter().position(|msg| &msg.id == id))
+        .map_or(0, |index| index + 1)
+}
+
+// Where to start replaying history for the user, skipping what they cleared
+pub fn history_start(user: &User, messages: &[ChatMessage]) -> usize {
+    position_after(messages, markers(user).cleared_up_to.as_ref())
+}
+
+fn last_mention(user: &User, messages: &[ChatMessage]) -> Option<String> {
+    messages
+        .iter()
+        .rev()
+        .find(|msg| mentions(msg, user))
+        .map(|msg| msg.id.clone())
+}
+
+// Other people's messages after the read (or cleared) marker
+fn unread(markers: &Markers, messages: &[ChatMessage], nickname: &str) -> usize {
+    let start = position_after(messages, markers.read_up_to.as_ref())
+        .max(position_after(messages, markers.cleared_up_to.as_ref()));
+    messages[start..]
+        .iter()
+        .filter(|msg| msg.message_type != MessageType::SystemMessage && msg.sender != nickname)
+        .count()
+}
+
+// Unread count for an account in a room it isn't necessarily connected to
+pub fn account_unread(account_id: &str, nickname: &str, room: &RoomState) -> usize {
+    let key = format!("account:{}:{}", account_id, room.id);
+    let markers = MARKERS.read().get(&key).cloned().unwrap_or_default();
+    unread(&markers, &room.messages.read(), nickname)
+}
+
+pub fn read_state(user: &User, room: &RoomState) -> String {
+    let markers = markers(user);
+    let messages = room.messages.read();
+    let unread = unread(&markers, &messages, &user.nickname);
+    let start = position_after(&messages, markers.read_up_to.as_ref());
+    let unread_mentions = messages[start..].iter().filter(|msg| mentions(msg, user)).count();
+    json!({
+        "type": "read_state",
+        "read_up_to": markers.read_up_to,
+        "unread": unread,
+        "unread_mentions": unread_mentions,
+        "last_mention": last_mention(user, &messages),
+    }).to_string()
+}
+
+// Runs an action and returns the result frame
+pub fn perform(user: &User, room: &RoomState, action: &str, request_id: Option<&Value>) -> String {
+    let newest = room.messages.read().last().map(|msg| msg.id.clone());
+    let result = match action {
+        "mark_read" => {
+            update(user, |markers| markers.read_up_to = newest.clone());
+            json!({ "read_up_to": newest, "unread": 0 })
+        },
+        "last_mention" => json!({ "message_id": last_mention(user, &room.messages.read()) }),
+        "clear_history" => {
+            update(user, |markers| {
+                markers.cleared_up_to = newest.clone();
+                markers.read_up_to = newest.clone();
+            });
+            json!({ "cleared_up_to": newest })
+        },
+        _ => {
+            return json!({
+                "type": "action_result",
+                "action": action,
+                "request_id": request_id,
+                "ok": false,
+                "code": ErrorCode::UnknownAction,
+                "error": format!("Unknown action: {}", action),
+            }).to_string();
+        },
+    };
+
+    if let (Some(account_id), "mark_read" | "clear_history") = (&user.account_id, action) {
+        dashboard::room_read(account_id, room);
+    }
+
+    let mut frame = json!({
+        "type": "action_result",
+        "action": action,
+        "request_id": request_id,
+        "ok": true,
+    });
+    if let (Some(frame), Value::Object(fields)) = (frame.as_object_mut(), result) {
+        frame.extend(fields);
+    }
+    frame.to_string()
+}
@@ -0,0 +1,576 @@
+use std::collections::BTreeSet;
+
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use rocket::{Request, Route};
+use serde_json::json;
+
+use crate::accounts::ACCOUNTS;
+use crate::appearance;
+use crate::api_tokens::{API_TOKENS, Scope, authorize, bearer_token, is_admin_token};
+use crate::audit;
+use crate::config::RetentionConfig;
+use crate::incoming_webhooks::{HookFormat, INCOMING_WEBHOOKS};
+use crate::room_templates::TEMPLATES;
+use crate::rooms::{self, INVALID_ROOM_ID, valid_room_id};
+use crate::rules::Rule;
+use crate::scripting::SCRIPTS;
+use crate::translation;
+use crate::webhooks::WEBHOOKS;
+use crate::word_filter::WordFilter;
+use crate::{CHAT_STATE, Role, RoomConfig};
+
+// Request guard for the room admin API: `Authorization: Bearer <token>` with
+// the admin token or an API token scoped admin:rooms
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::AdminRooms).map(|_| Admin)
+    }
+}
+
+// Only the admin token itself, so API tokens can't mint more API tokens
+pub struct ServerAdmin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ServerAdmin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match bearer_token(request) {
+            Some(token) if is_admin_token(token) => Outcome::Success(ServerAdmin),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+pub type ApiResult = Result<Json<Value>, (Status, Json<Value>)>;
+
+pub fn api_error(status: Status, message: impl ToString) -> (Status, Json<Value>) {
+    (status, Json(json!({ "error": message.to_string() })))
+}
+
+// Room settings, rules, filters and roles share the room's config version.
+// Reads send it as an ETag; writes with an `If-Match` header only go through
+// if the room is still at that version, so two admins editing at once get a
+// 412 instead of silently overwriting each other. Writes without the header
+// always apply.
+pub struct IfMatch(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(IfMatch(request.headers().get_one("If-Match").map(str::to_string)))
+    }
+}
+
+fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+impl IfMatch {
+    fn check(&self, config: &RoomConfig) -> Result<(), (Status, Json<Value>)> {
+        let Some(if_match) = &self.0 else {
+            return Ok(());
+        };
+        let current = etag(config.version);
+        let matches = if_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == current);
+        if matches {
+            return Ok(());
+        }
+        Err((Status::PreconditionFailed, Json(json!({
+            "error": "The room's settings changed since you loaded them",
+            "version": config.version,
+        }))))
+    }
+}
+
+#[derive(rocket::Responder)]
+pub struct Versioned {
+    body: Json<Value>,
+    etag: Header<'static>,
+}
+
+impl Versioned {
+    fn new(config: &RoomConfig, mut body: Value) -> Self {
+        body["version"] = json!(config.version);
+        Versioned { body: Json(body), etag: Header::new("ETag", etag(config.version)) }
+    }
+}
+
+type VersionedResult = Result<Versioned, (Status, Json<Value>)>;
+
+#[rocket::get("/rooms/<room_id>/scripts")]
+fn list_scripts(_admin: Admin, room_id: &str) -> Json<Value> {
+    Json(json!({ "scripts": SCRIPTS.list(room_id) }))
+}
+
+#[rocket::put("/rooms/<room_id>/scripts/<name>", data = "<source>")]
+fn put_script(_admin: Admin, room_id: &str, name: &str, source: String) -> ApiResult {
+    SCRIPTS
+        .install(room_id, name, &source)
+        .map_err(|err| api_error(Status::BadRequest, err))?;
+    Ok(Json(json!({ "room_id": room_id, "name": name })))
+}
+
+#[rocket::delete("/rooms/<room_id>/scripts/<name>")]
+fn delete_script(_admin: Admin, room_id: &str, name: &str) -> ApiResult {
+    if SCRIPTS.remove(room_id, name) {
+        Ok(Json(json!({ "room_id": room_id, "name": name })))
+    } else {
+        Err(api_error(Status::NotFound, "No such script"))
+    }
+}
+
+#[rocket::get("/rooms/<room_id>/rules")]
+fn list_rules(_admin: Admin, room_id: &str) -> Versioned {
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let config = room_state.config.read();
+    Versioned::new(&config, json!({ "rules": config.rules }))
+}
+
+// Replaces the room's whole rule list
+#[rocket::put("/rooms/<room_id>/rules", data = "<rules>")]
+fn put_rules(_admin: Admin, if_match: IfMatch, room_id: &str, rules: Json<Vec<Rule>>) -> VersionedResult {
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let mut config = room_state.config.write();
+    if_match.check(&config)?;
+    config.rules = rules.into_inner();
+    config.version += 1;
+    Ok(Versioned::new(&config, json!({ "rules": config.rules })))
+}
+
+#[rocket::get("/rooms/<room_id>/filters")]
+fn list_filters(_admin: Admin, room_id: &str) -> Versioned {
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let config = room_state.config.read();
+    Versioned::new(&config, json!({ "filters": config.word_filters }))
+}
+
+// Replaces the room's whole word filter
+#[rocket::put("/rooms/<room_id>/filters", data = "<filters>")]
+fn put_filters(_admin: Admin, if_match: IfMatch, room_id: &str, filters: Json<Vec<WordFilter>>) -> VersionedResult {
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let mut config = room_state.config.write();
+    if_match.check(&config)?;
+    config.word_filters = filters.into_inner();
+    config.version += 1;
+    Ok(Versioned::new(&config, json!({ "filters": config.word_filters })))
+}
+
+// Room-wide flags; each field of the PATCH body is optional
+#[derive(Deserialize)]
+struct SettingsUpdate {
+    nsfw: Option<bool>,
+    compliance: Option<bool>,
+    public_log: Option<bool>,
+    knock: Option<bool>,
+    anonymous: Option<bool>,
+    // An empty message removes it
+    welcome_message: Option<String>,
+    // Likewise
+    topic: Option<String>,
+    // Replaces the room's tags; an empty list removes them
+    tags: Option<Vec<String>>,
+    // Auto-translation target; empty turns it off
+    language: Option<String>,
+    // Emoji or image URL, and hex color; empty removes them
+    icon: Option<String>,
+    accent_color: Option<String>,
+    // Replaces the room's retention overrides, see retention.rs
+    retention: Option<RetentionConfig>,
+}
+
+const MAX_WELCOME_LEN: usize = 2000;
+const MAX_TOPIC_LEN: usize = 300;
+
+fn settings_json(config: &RoomConfig) -> Versioned {
+    Versioned::new(config, json!({
+        "nsfw": config.nsfw,
+        "welcome_message": config.welcome_message,
+        "topic": config.topic,
+        "tags": config.tags,
+        "compliance": config.compliance,
+        "public_log": config.public_log,
+        "knock": config.knock,
+        "anonymous": config.anonymous,
+        "language": config.language,
+        "icon": config.icon,
+        "accent_color": config.accent_color,
+        "expires_at": config.expires_at.map(|at| at.to_rfc3339()),
+        "retention": config.retention,
+    }))
+}
+
+#[rocket::get("/rooms/<room_id>/settings")]
+fn get_settings(_admin: Admin, room_id: &str) -> Versioned {
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let config = room_state.config.read();
+    settings_json(&config)
+}
+
+#[rocket::patch("/rooms/<room_id>/settings", data = "<update>")]
+fn patch_settings(_admin: Admin, if_match: IfMatch, room_id: &str, update: Json<SettingsUpdate>) -> VersionedResult {
+    if update.welcome_message.as_ref().is_some_and(|message| message.chars().count() > MAX_WELCOME_LEN) {
+        return Err(api_error(Status::BadRequest, format!("Welcome messages are limited to {} characters", MAX_WELCOME_LEN)));
+    }
+    if update.topic.as_ref().is_some_and(|topic| topic.chars().count() > MAX_TOPIC_LEN) {
+        return Err(api_error(Status::BadRequest, format!("Topics are limited to {} characters", MAX_TOPIC_LEN)));
+    }
+
+    let language = update.language.as_deref().map(str::trim);
+    if language.is_some_and(|language| !language.is_empty() && !translation::valid_language(language)) {
+        return Err(api_error(Status::BadRequest, "Languages are codes like \"en\" or \"pt-BR\""));
+    }
+
+    let icon = update.icon.as_deref().map(appearance::parse_icon).transpose().map_err(|err| api_error(Status::BadRequest, err))?;
+    let accent_color = update
+        .accent_color
+        .as_deref()
+        .map(appearance::parse_color)
+        .transpose()
+        .map_err(|err| api_error(Status::BadRequest, err))?;
+    let tags = update.tags.as_deref().map(rooms::parse_tags).transpose().map_err(|err| api_error(Status::BadRequest, err))?;
+
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let mut config = room_state.config.write();
+    if_match.check(&config)?;
+    if let Some(tags) = tags {
+        config.tags = tags;
+    }
+    if let Some(nsfw) = update.nsfw {
+        config.nsfw = nsfw;
+    }
+    if let Some(public_log) = update.public_log {
+        config.public_log = public_log;
+    }
+    if let Some(knock) = update.knock {
+        config.knock = knock;
+    }
+    let anonymous_change = update.anonymous.filter(|&anonymous| anonymous != config.anonymous);
+    if let Some(anonymous) = anonymous_change {
+        config.anonymous = anonymous;
+    }
+    if let Some(message) = &update.welcome_message {
+        config.welcome_message = Some(message.trim().to_string()).filter(|message| !message.is_empty());
+    }
+    if let Some(language) = language {
+        config.language = Some(language.to_string()).filter(|language| !language.is_empty());
+    }
+    if let Some(icon) = icon {
+        config.icon = icon;
+    }
+    if let Some(accent_color) = accent_color {
+        config.accent_color = accent_color;
+    }
+    if let Some(topic) = &update.topic {
+        config.topic = Some(topic.trim().to_string()).filter(|topic| !topic.is_empty());
+    }
+    if let Some(retention) = &update.retention {
+        config.retention = retention.clone();
+    }
+    let compliance_change = update.compliance.filter(|&compliance| compliance != config.compliance);
+    if let Some(compliance) = compliance_change {
+        config.compliance = compliance;
+    }
+    config.version += 1;
+    let body = settings_json(&config);
+    drop(config);
+
+    // Switching compliance off is logged too, so the gap in the log is explained
+    if let Some(compliance) = compliance_change {
+        let event = if compliance { "compliance_enabled" } else { "compliance_disabled" };
+        audit::append(room_id, event, "admin", json!({}));
+    }
+    if let Some(anonymous) = anonymous_change {
+        let event = if anonymous { "anonymous_enabled" } else { "anonymous_disabled" };
+        audit::append(room_id, event, "admin", json!({}));
+    }
+    Ok(body)
+}
+
+#[rocket::get("/rooms/<room_id>/audit")]
+fn get_audit_log(_admin: Admin, room_id: &str) -> ApiResult {
+    let entries = audit::lines(room_id).map_err(|err| api_error(Status::InternalServerError, err))?;
+    Ok(Json(json!({ "entries": entries })))
+}
+
+#[rocket::get("/rooms/<room_id>/audit/verify")]
+fn verify_audit_log(_admin: Admin, room_id: &str) -> ApiResult {
+    let verification = audit::verify(room_id).map_err(|err| api_error(Status::InternalServerError, err))?;
+    Ok(Json(json!({
+        "entries": verification.entries,
+        "valid": verification.first_invalid.is_none(),
+        "first_invalid_seq": verification.first_invalid,
+    })))
+}
+
+#[rocket::get("/templates")]
+fn list_templates(_admin: Admin) -> Json<Value> {
+    Json(json!({ "templates": TEMPLATES.names() }))
+}
+
+#[rocket::get("/templates/<name>")]
+fn get_template(_admin: Admin, name: &str) -> ApiResult {
+    let template = TEMPLATES.get(name).ok_or_else(|| api_error(Status::NotFound, "No such template"))?;
+    Ok(Json(json!(template)))
+}
+
+#[rocket::put("/templates/<name>", data = "<template>")]
+fn put_template(_admin: Admin, name: &str, template: Json<RoomConfig>) -> Json<Value> {
+    let template = template.into_inner();
+    let body = json!(template);
+    TEMPLATES.put(name, template);
+    Json(body)
+}
+
+#[rocket::delete("/templates/<name>")]
+fn delete_template(_admin: Admin, name: &str) -> ApiResult {
+    if TEMPLATES.remove(name) {
+        Ok(Json(json!({ "name": name })))
+    } else {
+        Err(api_error(Status::NotFound, "No such template"))
+    }
+}
+
+// A new room starts from a template or from another room's settings, never its history
+#[derive(Deserialize)]
+struct NewRoom {
+    id: String,
+    template: Option<String>,
+    from_room: Option<String>,
+}
+
+#[rocket::post("/rooms", data = "<new_room>")]
+fn create_room(_admin: Admin, new_room: Json<NewRoom>) -> ApiResult {
+    if !valid_room_id(&new_room.id) {
+        return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+    }
+
+    let settings = match (&new_room.template, &new_room.from_room) {
+        (Some(_), Some(_)) => return Err(api_error(Status::BadRequest, "Give either template or from_room, not both")),
+        (Some(name), None) => TEMPLATES.get(name).ok_or_else(|| api_error(Status::NotFound, "No such template"))?,
+        (None, Some(source)) => {
+            let room = CHAT_STATE.rooms.read().get(source).cloned();
+            let room = room.ok_or_else(|| api_error(Status::NotFound, "No such room"))?;
+            room.config.read().clone()
+        },
+        (None, None) => RoomConfig::default(),
+    };
+
+    if CHAT_STATE.rooms.read().contains_key(&new_room.id) {
+        return Err(api_error(Status::Conflict, "A room with that id already exists"));
+    }
+    let room_state = CHAT_STATE.get_or_create_room(&new_room.id);
+    let mut config = room_state.config.write();
+    config.apply_template(&settings);
+    Ok(Json(json!({ "id": new_room.id, "config": *config })))
+}
+
+const MAX_BULK_ROOMS: usize = 1000;
+
+// One room of a bulk request: settings come from a template or are given inline
+#[derive(Deserialize)]
+struct BulkRoom {
+    id: String,
+    template: Option<String>,
+    config: Option<RoomConfig>,
+}
+
+// Creates or updates every room in the list, or none of them if any entry is invalid
+#[rocket::post("/rooms/bulk", data = "<rooms>")]
+fn bulk_rooms(_admin: Admin, rooms: Json<Vec<BulkRoom>>) -> ApiResult {
+    let rooms = rooms.into_inner();
+    if rooms.len() > MAX_BULK_ROOMS {
+        return Err(api_error(Status::BadRequest, format!("At most {} rooms per request", MAX_BULK_ROOMS)));
+    }
+
+    let mut batch: Vec<(String, RoomConfig)> = Vec::with_capacity(rooms.len());
+    for (index, room) in rooms.into_iter().enumerate() {
+        let invalid = |message: &str| api_error(Status::BadRequest, format!("rooms[{}]: {}", index, message));
+        if !valid_room_id(&room.id) {
+            return Err(invalid(INVALID_ROOM_ID));
+        }
+        if batch.iter().any(|(room_id, _)| *room_id == room.id) {
+            return Err(invalid("duplicate room id"));
+        }
+        let settings = match (room.template, room.config) {
+            (Some(_), Some(_)) => return Err(invalid("give either template or config, not both")),
+            (Some(name), None) => TEMPLATES.get(&name).ok_or_else(|| invalid("no such template"))?,
+            (None, config) => config.unwrap_or_default(),
+        };
+        batch.push((room.id, settings));
+    }
+
+    let results: Vec<Value> = CHAT_STATE
+        .provision_rooms(batch)
+        .into_iter()
+        .map(|(room_id, created)| json!({ "id": room_id, "created": created }))
+        .collect();
+    Ok(Json(json!({ "rooms": results })))
+}
+
+#[rocket::get("/rooms/<room_id>/webhooks")]
+fn list_webhooks(_admin: Admin, room_id: &str) -> Json<Value> {
+    let hooks: Vec<Value> = WEBHOOKS.list(room_id).iter().map(|hook| hook.to_json()).collect();
+    Json(json!({ "webhooks": hooks }))
+}
+
+#[derive(Deserialize)]
+struct NewWebhook {
+    url: String,
+}
+
+// The response is the only time the signing secret is shown
+#[rocket::post("/rooms/<room_id>/webhooks", data = "<new_webhook>")]
+fn add_webhook(_admin: Admin, room_id: &str, new_webhook: Json<NewWebhook>) -> ApiResult {
+    let (webhook, secret) = WEBHOOKS
+        .add(room_id, &new_webhook.url)
+        .map_err(|err| api_error(Status::BadRequest, err))?;
+    let mut body = webhook.to_json();
+    body["secret"] = json!(secret);
+    Ok(Json(body))
+}
+
+#[rocket::delete("/rooms/<room_id>/webhooks/<id>")]
+fn delete_webhook(_admin: Admin, room_id: &str, id: &str) -> ApiResult {
+    if WEBHOOKS.remove(room_id, id) {
+        Ok(Json(json!({ "id": id })))
+    } else {
+        Err(api_error(Status::NotFound, "No such webhook"))
+    }
+}
+
+#[rocket::get("/rooms/<room_id>/incoming-webhooks")]
+fn list_incoming_webhooks(_admin: Admin, room_id: &str) -> Json<Value> {
+    let hooks: Vec<Value> = INCOMING_WEBHOOKS.list(room_id).iter().map(|hook| hook.to_json()).collect();
+    Json(json!({ "webhooks": hooks }))
+}
+
+#[derive(Deserialize)]
+struct NewIncomingWebhook {
+    format: HookFormat,
+    // Name messages are posted under; the format's name when unset
+    sender: Option<String>,
+}
+
+// The response is the only time the webhook's URL is shown
+#[rocket::post("/rooms/<room_id>/incoming-webhooks", data = "<new_webhook>")]
+fn add_incoming_webhook(_admin: Admin, room_id: &str, new_webhook: Json<NewIncomingWebhook>) -> ApiResult {
+    if !valid_room_id(room_id) {
+        return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+    }
+    let new_webhook = new_webhook.into_inner();
+    let webhook = INCOMING_WEBHOOKS.add(room_id, new_webhook.format, new_webhook.sender);
+    let mut body = webhook.to_json();
+    body["url"] = json!(webhook.url());
+    Ok(Json(body))
+}
+
+#[rocket::delete("/rooms/<room_id>/incoming-webhooks/<id>")]
+fn delete_incoming_webhook(_admin: Admin, room_id: &str, id: &str) -> ApiResult {
+    if INCOMING_WEBHOOKS.remove(room_id, id) {
+        Ok(Json(json!({ "id": id })))
+    } else {
+        Err(api_error(Status::NotFound, "No such webhook"))
+    }
+}
+
+#[rocket::get("/tokens")]
+fn list_tokens(_admin: ServerAdmin) -> Json<Value> {
+    let tokens: Vec<Value> = API_TOKENS.list().iter().map(|token| token.to_json()).collect();
+    Json(json!({ "tokens": tokens }))
+}
+
+#[derive(Deserialize)]
+struct NewToken {
+    name: String,
+    scopes: BTreeSet<Scope>,
+}
+
+// The response is the only time the token's secret is shown
+#[rocket::post("/tokens", data = "<new_token>")]
+fn create_token(_admin: ServerAdmin, new_token: Json<NewToken>) -> ApiResult {
+    let name = new_token.name.trim();
+    if name.is_empty() {
+        return Err(api_error(Status::BadRequest, "Tokens need a name"));
+    }
+    if new_token.scopes.is_empty() {
+        return Err(api_error(Status::BadRequest, "Tokens need at least one scope"));
+    }
+
+    let (token, secret) = API_TOKENS.create(name, new_token.scopes.clone());
+    let mut body = token.to_json();
+    body["token"] = json!(secret);
+    Ok(Json(body))
+}
+
+#[rocket::delete("/tokens/<id>")]
+fn revoke_token(_admin: ServerAdmin, id: &str) -> ApiResult {
+    if API_TOKENS.revoke(id) {
+        Ok(Json(json!({ "id": id })))
+    } else {
+        Err(api_error(Status::NotFound, "No such token"))
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleUpdate {
+    role: Role,
+}
+
+// Roles go to accounts, so a guest borrowing a nickname never gets one
+#[rocket::put("/rooms/<room_id>/roles/<username>", data = "<update>")]
+fn put_role(_admin: Admin, if_match: IfMatch, room_id: &str, username: &str, update: Json<RoleUpdate>) -> VersionedResult {
+    let Some(account) = ACCOUNTS.find(username) else {
+        return Err(api_error(Status::NotFound, "No such account"));
+    };
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let mut config = room_state.config.write();
+    if_match.check(&config)?;
+    config.roles.insert(account.id, update.role);
+    config.version += 1;
+    Ok(Versioned::new(&config, json!({ "roles": config.roles })))
+}
+
+#[rocket::delete("/rooms/<room_id>/roles/<username>")]
+fn delete_role(_admin: Admin, if_match: IfMatch, room_id: &str, username: &str) -> VersionedResult {
+    let Some(account) = ACCOUNTS.find(username) else {
+        return Err(api_error(Status::NotFound, "No such account"));
+    };
+    let room_state = CHAT_STATE.get_or_create_room(room_id);
+    let mut config = room_state.config.write();
+    if_match.check(&config)?;
+    if config.roles.remove(&account.id).is_none() {
+        return Err(api_error(Status::NotFound, "User has no role in this room"));
+    }
+    config.version += 1;
+    Ok(Versioned::new(&config, json!({ "roles": config.roles })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![
+        list_scripts, put_script, delete_script,
+        list_rules, put_rules,
+        list_filters, put_filters,
+        get_settings, patch_settings,
+        get_audit_log, verify_audit_log,
+        list_templates, get_template, put_template, delete_template,
+        create_room, bulk_rooms,
+        list_tokens, create_token, revoke_token,
+        list_webhooks, add_webhook, delete_webhook,
+        list_incoming_webhooks, add_incoming_webhook, delete_incoming_webhook,
+        put_role, delete_role,
+    ]
+}
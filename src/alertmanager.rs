@@ -0,0 +1,95 @@
+// Prometheus Alertmanager notifications, received by an incoming webhook
+// registered with the "alertmanager" format (see src/incoming_webhooks.rs)
+// and set as a webhook_config url in alertmanager.yml. Each notification is
+// posted as up to two messages, one for the alerts still firing and one for
+// those resolved, e.g.
+//
+//   [FIRING:2] HighLatency
+//   - api-1: Latency above 1s (critical)
+//   - api-2: Latency above 1s (warning)
+//   http://alertmanager:9093
+//
+// Messages carry "alert": {"status", "severity"} with the most severe
+// label among their alerts, which clients use for coloring.
+
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Known severities, least to most severe; anything else sorts below them
+const SEVERITIES: [&str; 4] = ["info", "warning", "error", "critical"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    // "firing" or "resolved"
+    pub status: String,
+    pub severity: String,
+}
+
+fn rank(severity: &str) -> Option<usize> {
+    SEVERITIES.iter().position(|known| *known == severity)
+}
+
+fn label<'a>(alert: &'a Value, section: &str, name: &str) -> &'a str {
+    alert[section][name].as_str().unwrap_or("").trim()
+}
+
+// One line per alert: what it's about and why
+fn alert_line(alert: &Value) -> String {
+    let subject = match label(alert, "labels", "instance") {
+        "" => label(alert, "labels", "alertname"),
+        instance => instance,
+    };
+    let summary = [
+        label(alert, "annotations", "summary"),
+        label(alert, "annotations", "description"),
+        label(alert, "labels", "alertname"),
+    ]
+    .into_iter()
+    .find(|text| !text.is_empty())
+    .unwrap_or("");
+    match label(alert, "labels", "severity") {
+        "" => format!("- {}: {}", subject, summary),
+        severity => format!("- {}: {} ({})", subject, summary, severity),
+    }
+}
+
+// The messages to post for a notification, with their alert metadata
+pub fn render(payload: &Value) -> Vec<(String, Alert)> {
+    let Some(alerts) = payload["alerts"].as_array() else {
+        return Vec::new();
+    };
+    let title = match payload["groupLabels"]["alertname"].as_str() {
+        Some(name) => name.to_string(),
+        None => payload["groupLabels"]
+            .as_object()
+            .map(|labels| labels.values().filter_map(Value::as_str).collect::<Vec<_>>().join(" "))
+            .unwrap_or_default(),
+    };
+    let link = payload["externalURL"].as_str().unwrap_or("");
+
+    let mut messages = Vec::new();
+    for status in ["firing", "resolved"] {
+        let group: Vec<&Value> = alerts.iter().filter(|alert| alert["status"] == status).collect();
+        if group.is_empty() {
+            continue;
+        }
+        let severity = group
+            .iter()
+            .map(|alert| label(alert, "labels", "severity"))
+            .max_by_key(|severity| rank(severity))
+            .filter(|severity| !severity.is_empty())
+            .unwrap_or("none")
+            .to_string();
+
+        let mut lines = vec![format!("[{}:{}] {}", status.to_uppercase(), group.len(), title).trim_end().to_string()];
+        lines.extend(group.iter().map(|alert| alert_line(alert)));
+        if !link.is_empty() {
+            lines.push(link.to_string());
+        }
+        messages.push((lines.join("\n"), Alert {
+            status: status.to_string(),
+            severity,
+        }));
+    }
+    messages
+}
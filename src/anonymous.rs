@@ -0,0 +1,157 @@
+// Anonymous rooms, for feedback sessions and the like where people speak
+// more freely when their name isn't on it. With the room's `anonymous` flag
+// on, everyone's messages go out under a pseudonym like "Anonymous Otter"
+// instead of their nickname, and without their rank. Each person keeps the
+// same pseudonym for anonymous_rotation_secs, then gets a new one, so long
+// threads can't be pieced together into a profile. The real sender is still
+// written to the room's audit log, compliance mode or not:
+//
+//   {"event": "anonymous_message", "actor": "<nickname>", "data": {"message_id", "pseudonym"}}
+//
+// Room admins turn it on and off with `/anonymous on|off`, server admins
+// through the room settings API.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::Rng;
+use serde_json::json;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::config::CONFIG;
+use crate::protocol::ErrorCode;
+use crate::{CHAT_STATE, ChatMessage, MessageType, RoomState, audit};
+
+const ADJECTIVES: [&str; 16] = [
+    "Amber", "Brave", "Calm", "Clever", "Curious", "Gentle", "Golden", "Happy",
+    "Lucky", "Quiet", "Rapid", "Silver", "Sleepy", "Swift", "Witty", "Zesty",
+];
+const ANIMALS: [&str; 16] = [
+    "Badger", "Beaver", "Falcon", "Ferret", "Fox", "Heron", "Koala", "Lynx",
+    "Marmot", "Otter", "Owl", "Panda", "Puffin", "Raven", "Seal", "Walrus",
+];
+
+struct Pseudonym {
+    name: String,
+    assigned: Instant,
+}
+
+lazy_static! {
+    // (room id, lowercased nickname) -> current pseudonym
+    static ref PSEUDONYMS: Mutex<HashMap<(String, String), Pseudonym>> = Mutex::new(HashMap::new());
+}
+
+fn rotation() -> Duration {
+    Duration::from_secs(CONFIG.anonymous_rotation_secs)
+}
+
+// Two words, with a number added once the room has used up the plain ones
+fn fresh_name(taken: &HashSet<&str>) -> String {
+    let mut rng = rand::rng();
+    for attempt in 0.. {
+        let adjective = ADJECTIVES[rng.random_range(0..ADJECTIVES.len())];
+        let animal = ANIMALS[rng.random_range(0..ANIMALS.len())];
+        let name = match attempt {
+            0..32 => format!("Anonymous {} {}", adjective, animal),
+            _ => format!("Anonymous {} {} {}", adjective, animal, rng.random_range(2..1000)),
+        };
+        if !taken.contains(name.as_str()) {
+            return name;
+        }
+    }
+    unreachable!("the attempts never run out")
+}
+
+// The nickname's pseudonym in the room, picking a new one once it's due
+fn pseudonym(room_id: &str, nickname: &str) -> String {
+    let mut all = PSEUDONYMS.lock();
+    let key = (room_id.to_string(), nickname.to_lowercase());
+    if let Some(pseudonym) = all.get(&key).filter(|pseudonym| pseudonym.assigned.elapsed() < rotation()) {
+        return pseudonym.name.clone();
+    }
+    let taken: HashSet<&str> = all
+        .iter()
+        .filter(|((room, _), _)| room == room_id)
+        .map(|(_, pseudonym)| pseudonym.name.as_str())
+        .collect();
+    let name = fresh_name(&taken);
+    all.insert(key, Pseudonym { name: name.clone(), assigned: Instant::now() });
+    name
+}
+
+// Forgets pseudonyms that are due for rotation, so their names can be reused
+pub fn prune() {
+    PSEUDONYMS.lock().retain(|_, pseudonym| pseudonym.assigned.elapsed() < rotation());
+}
+
+// In anonymous rooms, puts a user's message under their pseudonym before
+// anyone sees it, keeping the real sender in the audit log
+pub fn mask(room: &RoomState, msg: &mut ChatMessage) {
+    let user_message = matches!(msg.message_type, MessageType::UserMessage | MessageType::Location);
+    if !user_message || !room.config.read().anonymous {
+        return;
+    }
+    let pseudonym = pseudonym(&room.id, &msg.sender);
+    audit::append(&room.id, "anonymous_message", &msg.sender, json!({
+        "message_id": msg.id,
+        "pseudonym": pseudonym,
+    }));
+    if let Some(forwarded) = &mut msg.forwarded {
+        forwarded.by = pseudonym.clone();
+    }
+    msg.sender = pseudonym;
+    msg.rank = None;
+}
+
+// Who really sent a message posted under a pseudonym, from the audit log
+pub fn real_sender(room_id: &str, message_id: &str) -> Option<String> {
+    audit::lines(room_id)
+        .ok()?
+        .into_iter()
+        .rev()
+        .find(|entry| entry["event"] == "anonymous_message" && entry["data"]["message_id"] == message_id)
+        .and_then(|entry| entry["actor"].as_str().map(str::to_string))
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        "anonymous",
+        "/anonymous [on|off] - post under rotating pseudonyms in this room (room admins)",
+        anonymous,
+    );
+}
+
+fn anonymous(ctx: &CommandContext) -> CommandOutput {
+    let room_state = CHAT_STATE.get_or_create_room(&ctx.user.room_id);
+    let anonymous = match ctx.args {
+        "" => {
+            let state = if room_state.config.read().anonymous { "on" } else { "off" };
+            return CommandOutput::Reply(format!("Anonymous posting is {} in #{}", state, ctx.user.room_id));
+        },
+        "on" => true,
+        "off" => false,
+        _ => return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /anonymous [on|off]"),
+    };
+    {
+        let mut config = room_state.config.write();
+        if !config.is_admin(ctx.user.account_id.as_deref()) {
+            return CommandOutput::error(ErrorCode::Forbidden, "Only room admins can change anonymous posting");
+        }
+        if config.anonymous == anonymous {
+            return CommandOutput::Reply(format!("Anonymous posting is already {}", ctx.args));
+        }
+        config.anonymous = anonymous;
+        config.version += 1;
+    }
+
+    audit::append(&room_state.id, if anonymous { "anonymous_enabled" } else { "anonymous_disabled" }, &ctx.user.nickname, json!({}));
+    let notice = if anonymous {
+        "Messages in this room are now posted under rotating pseudonyms. Server admins can still find out who sent what."
+    } else {
+        "Anonymous posting is off; messages show their sender again."
+    };
+    room_state.post(ChatMessage::new(&room_state.id, "System", notice, MessageType::SystemMessage));
+    CommandOutput::Reply(format!("Anonymous posting is {}", ctx.args))
+}
@@ -0,0 +1,175 @@
+// API tokens for third-party integrations. Each token carries scopes that
+// the request guards below check; the configured admin token passes all of
+// them. Only a SHA-256 hash of each token is kept.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rand::Rng;
+use rocket::Request;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::config::CONFIG;
+use crate::{secrets, storage};
+
+const TOKEN_PREFIX: &str = "wct_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "read:messages")]
+    ReadMessages,
+    #[serde(rename = "post:messages")]
+    PostMessages,
+    #[serde(rename = "admin:rooms")]
+    AdminRooms,
+    #[serde(rename = "read:metrics")]
+    ReadMetrics,
+    #[serde(rename = "provision:users")]
+    ProvisionUsers,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub scopes: BTreeSet<Scope>,
+    token_hash: String,
+    pub created_at: String,
+}
+
+impl ApiToken {
+    // What the admin API shows; never includes the hash
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "scopes": self.scopes,
+            "created_at": self.created_at,
+        })
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub struct TokenStore {
+    // token id -> token
+    tokens: RwLock<HashMap<String, ApiToken>>,
+}
+
+impl TokenStore {
+    fn load() -> Self {
+        TokenStore {
+            tokens: RwLock::new(storage::load("api_tokens", "tokens").unwrap_or_default()),
+        }
+    }
+
+    fn save(tokens: &HashMap<String, ApiToken>) {
+        if let Err(err) = storage::save("api_tokens", "tokens", tokens) {
+            eprintln!("Failed to save API tokens: {}", err);
+        }
+    }
+
+    pub fn list(&self) -> Vec<ApiToken> {
+        let mut tokens: Vec<ApiToken> = self.tokens.read().values().cloned().collect();
+        tokens.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        tokens
+    }
+
+    // Returns the new token and its secret, which is only ever shown here
+    pub fn create(&self, name: &str, scopes: BTreeSet<Scope>) -> (ApiToken, String) {
+        let mut bytes = [0u8; 32];
+        rand::rng().fill(&mut bytes);
+        let secret = format!("{}{}", TOKEN_PREFIX, hex::encode(bytes));
+
+        let token = ApiToken {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            scopes,
+            token_hash: hash_token(&secret),
+            created_at: Utc::now().to_rfc3339(),
+        };
+        let mut tokens = self.tokens.write();
+        tokens.insert(token.id.clone(), token.clone());
+        Self::save(&tokens);
+        (token, secret)
+    }
+
+    pub fn revoke(&self, id: &str) -> bool {
+        let mut tokens = self.tokens.write();
+        let revoked = tokens.remove(id).is_some();
+        if revoked {
+            Self::save(&tokens);
+        }
+        revoked
+    }
+
+    fn authenticate(&self, secret: &str) -> Option<ApiToken> {
+        let hash = hash_token(secret);
+        self.tokens.read().values().find(|token| token.token_hash == hash).cloned()
+    }
+}
+
+lazy_static! {
+    pub static ref API_TOKENS: TokenStore = TokenStore::load();
+}
+
+pub fn bearer_token<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+// Either value while the admin token is being rotated, see secrets.rs
+pub fn is_admin_token(token: &str) -> bool {
+    CONFIG.admin_token.as_deref().is_some_and(|configured| secrets::accepted(configured).iter().any(|accepted| accepted == token))
+}
+
+// The admin token, or an API token holding `scope`. Succeeds with the API
+// token used, if any.
+pub fn authorize(request: &Request<'_>, scope: Scope) -> Outcome<Option<ApiToken>, ()> {
+    let Some(secret) = bearer_token(request) else {
+        return Outcome::Error((Status::Unauthorized, ()));
+    };
+    if is_admin_token(secret) {
+        return Outcome::Success(None);
+    }
+    match API_TOKENS.authenticate(secret) {
+        Some(token) if token.scopes.contains(&scope) => Outcome::Success(Some(token)),
+        Some(_) => Outcome::Error((Status::Forbidden, ())),
+        None => Outcome::Error((Status::Unauthorized, ())),
+    }
+}
+
+// Request guards for the message API
+pub struct CanReadMessages;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CanReadMessages {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::ReadMessages).map(|_| CanReadMessages)
+    }
+}
+
+// Carries the token so posts can default to its name
+pub struct CanPostMessages(pub Option<ApiToken>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CanPostMessages {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::PostMessages).map(CanPostMessages)
+    }
+}
@@ -0,0 +1,132 @@
+// A room's icon and accent color, so clients showing several rooms at once
+// (the dashboard, the directory, a sidebar) can tell them apart at a glance.
+// The icon is an emoji or a couple of characters, or the URL of an image;
+// the accent color is a hex color that room pages use in place of the
+// theme's primary color. Room admins set them with
+//
+//   /appearance icon <emoji|image URL|none>
+//   /appearance color <#rrggbb|none>
+//
+// and server admins through the room settings API. Wherever rooms are
+// described they carry
+//
+//   "icon": "<text>" | null, "icon_url": "<image URL>" | null, "accent_color": "#rrggbb" | null
+
+use serde_json::{Value, json};
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::ErrorCode;
+use crate::{CHAT_STATE, RoomConfig};
+
+// Emoji with skin tones or joiners take several chars
+const MAX_ICON_CHARS: usize = 8;
+const MAX_ICON_URL_LEN: usize = 500;
+const USAGE: &str = "Usage: /appearance [icon <emoji|image URL|none> | color <#rrggbb|none>]";
+
+fn is_image_url(icon: &str) -> bool {
+    icon.starts_with("https://") || icon.starts_with("http://")
+}
+
+// Checks an icon from a setting; empty clears it
+pub fn parse_icon(icon: &str) -> Result<Option<String>, String> {
+    let icon = icon.trim();
+    if icon.is_empty() {
+        return Ok(None);
+    }
+    if icon.chars().any(char::is_whitespace) {
+        return Err("Icons can't contain spaces".to_string());
+    }
+    if is_image_url(icon) {
+        if icon.len() > MAX_ICON_URL_LEN {
+            return Err(format!("Icon URLs are limited to {} characters", MAX_ICON_URL_LEN));
+        }
+    } else if icon.chars().count() > MAX_ICON_CHARS {
+        return Err("Icons are an emoji, a couple of characters or an http(s) image URL".to_string());
+    }
+    Ok(Some(icon.to_string()))
+}
+
+// Checks a color from a setting, as #rgb or #rrggbb; empty clears it.
+// Colors are kept as lowercase #rrggbb.
+pub fn parse_color(color: &str) -> Result<Option<String>, String> {
+    let color = color.trim();
+    if color.is_empty() {
+        return Ok(None);
+    }
+    let digits = color.strip_prefix('#').unwrap_or(color);
+    if !matches!(digits.len(), 3 | 6) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("Accent colors are hex colors like #3a7bd5".to_string());
+    }
+    let digits = digits.to_lowercase();
+    if digits.len() == 3 {
+        return Ok(Some(digits.chars().fold("#".to_string(), |mut color, c| {
+            color.push(c);
+            color.push(c);
+            color
+        })));
+    }
+    Ok(Some(format!("#{}", digits)))
+}
+
+pub fn json(config: &RoomConfig) -> Value {
+    let icon = config.icon.as_deref();
+    json!({
+        "icon": icon.filter(|icon| !is_image_url(icon)),
+        "icon_url": icon.filter(|icon| is_image_url(icon)),
+        "accent_color": config.accent_color,
+    })
+}
+
+// Adds the room's appearance fields to a JSON object describing it
+pub fn extend(description: &mut Value, config: &RoomConfig) {
+    if let (Some(description), Value::Object(fields)) = (description.as_object_mut(), json(config)) {
+        description.extend(fields);
+    }
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        "appearance",
+        "/appearance [icon <emoji|image URL|none> | color <#rrggbb|none>] - set the room's icon and accent color (room admins)",
+        appearance,
+    );
+}
+
+fn describe(config: &RoomConfig) -> String {
+    format!(
+        "Icon: {}\nAccent color: {}",
+        config.icon.as_deref().unwrap_or("none"),
+        config.accent_color.as_deref().unwrap_or("none")
+    )
+}
+
+fn appearance(ctx: &CommandContext) -> CommandOutput {
+    let room_state = CHAT_STATE.get_or_create_room(&ctx.user.room_id);
+    let mut config = room_state.config.write();
+    let (setting, value) = ctx.args.split_once(' ').unwrap_or((ctx.args, ""));
+    if setting.is_empty() {
+        return CommandOutput::Reply(describe(&config));
+    }
+    if !config.is_admin(ctx.user.account_id.as_deref()) {
+        return CommandOutput::error(ErrorCode::Forbidden, "Only room admins can change the room's appearance");
+    }
+
+    let usage = || CommandOutput::error(ErrorCode::InvalidArguments, USAGE);
+    let value = match value.trim() {
+        "" => return usage(),
+        "none" => "",
+        value => value,
+    };
+    let result = match setting {
+        "icon" => parse_icon(value).map(|icon| config.icon = icon),
+        "color" => parse_color(value).map(|color| config.accent_color = color),
+        _ => return usage(),
+    };
+    match result {
+        Ok(()) => {
+            config.version += 1;
+            CommandOutput::Reply(describe(&config))
+        },
+        Err(err) => CommandOutput::error(ErrorCode::InvalidArguments, err),
+    }
+}
@@ -0,0 +1,138 @@
+// Cold storage for old history. With archive_after_days set, messages older
+// than that leave the room's history in memory and are appended to the
+// room's archive: JSON lines, zstd-compressed one batch per frame, in
+// data/archive/<room_id>.bin. They stay reachable through
+//
+//   GET /api/rooms/<room_id>/messages?before=<message id>&limit=<n>
+//
+// which pages back through history and carries on into the archive once
+// it runs past what's in memory. That's slower, as the whole archive is
+// read each time. Search, public logs and retention only see what's still
+// in memory. Archives are kept through ArchiveStore, so they can live
+// somewhere other than the data directory, e.g. an S3 bucket. With a
+// master_key, message text is encrypted with the room's key first, see
+// encryption.rs.
+
+use std::io;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+
+use crate::config::CONFIG;
+use crate::tasks::Every;
+use crate::{CHAT_STATE, ChatMessage, encryption, storage};
+
+static SWEEPS: Every = Every::new(Duration::from_secs(60));
+const COMPRESSION_LEVEL: i32 = 3;
+
+pub trait ArchiveStore: Send + Sync {
+    // Adds a compressed batch to the end of the room's archive
+    fn append(&self, room_id: &str, batch: &[u8]) -> io::Result<()>;
+    // All of the room's batches, oldest first; empty when it has none
+    fn read(&self, room_id: &str) -> io::Result<Vec<u8>>;
+}
+
+// The data directory, where backups pick archives up too
+struct DiskArchive;
+
+impl ArchiveStore for DiskArchive {
+    fn append(&self, room_id: &str, batch: &[u8]) -> io::Result<()> {
+        storage::append_blob("archive", room_id, batch)
+    }
+
+    fn read(&self, room_id: &str) -> io::Result<Vec<u8>> {
+        match storage::read_blob("archive", room_id) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            result => result,
+        }
+    }
+}
+
+lazy_static! {
+    static ref STORE: Box<dyn ArchiveStore> = Box::new(DiskArchive);
+}
+
+fn sent_before(msg: &ChatMessage, cutoff: DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc3339(&msg.timestamp).is_ok_and(|sent| sent < cutoff)
+}
+
+// Runs the text a message carries through `convert`
+fn convert_text(msg: &mut ChatMessage, convert: impl Fn(&str) -> io::Result<String>) -> io::Result<()> {
+    msg.content = convert(&msg.content)?;
+    if let Some(html) = &mut msg.html {
+        *html = convert(html)?;
+    }
+    if let Some(translation) = &mut msg.translation {
+        translation.content = convert(&translation.content)?;
+    }
+    Ok(())
+}
+
+fn store(room_id: &str, messages: &[ChatMessage]) -> io::Result<()> {
+    let mut lines = String::new();
+    for msg in messages {
+        let mut msg = msg.clone();
+        convert_text(&mut msg, |text| encryption::encrypt(room_id, text))?;
+        lines.push_str(&serde_json::to_string(&msg)?);
+        lines.push('\n');
+    }
+    STORE.append(room_id, &zstd::encode_all(lines.as_bytes(), COMPRESSION_LEVEL)?)
+}
+
+// Everything archived for the room, oldest first
+fn load(room_id: &str) -> io::Result<Vec<ChatMessage>> {
+    let data = STORE.read(room_id)?;
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    let lines = String::from_utf8(zstd::decode_all(data.as_slice())?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut archived: Vec<ChatMessage> = lines.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    for msg in &mut archived {
+        if let Err(err) = convert_text(msg, |text| encryption::decrypt(room_id, text)) {
+            eprintln!("Failed to decrypt archived message {} in {}: {}", msg.id, room_id, err);
+        }
+    }
+    Ok(archived)
+}
+
+// Up to `limit` archived messages before the given one, or the newest ones
+// without one; None when the message isn't in the archive
+pub fn before(room_id: &str, message_id: Option<&str>, limit: usize) -> io::Result<Option<Vec<ChatMessage>>> {
+    let archived = load(room_id)?;
+    let end = match message_id {
+        Some(message_id) => match archived.iter().position(|msg| msg.id == message_id) {
+            Some(at) => at,
+            None => return Ok(None),
+        },
+        None => archived.len(),
+    };
+    Ok(Some(archived[end.saturating_sub(limit)..end].to_vec()))
+}
+
+// Runs from the task loop, sweeping once a minute
+pub fn sweep() {
+    let Some(days) = CONFIG.archive_after_days else {
+        return;
+    };
+    if !SWEEPS.due() {
+        return;
+    }
+    let cutoff = Utc::now() - chrono::Duration::days(days as i64);
+    let rooms: Vec<_> = CHAT_STATE.rooms.read().values().cloned().collect();
+    for room in rooms {
+        // History is oldest first, so the old messages are all at the front
+        let old: Vec<ChatMessage> = {
+            let mut messages = room.messages.write();
+            let count = messages.iter().take_while(|msg| sent_before(msg, cutoff)).count();
+            messages.drain(..count).collect()
+        };
+        if old.is_empty() {
+            continue;
+        }
+        if let Err(err) = store(&room.id, &old) {
+            eprintln!("Failed to archive history of {}: {}", room.id, err);
+            room.messages.write().splice(0..0, old);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// Static CSS and JS, compiled into the binary from static/. Pages link to
+// them by content hash (chat.3f9a1c0e.css) through the `asset` template
+// helper, so they can be cached forever and a new build changes the URL.
+// theme.css is rendered from the theme template and config instead.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use rocket::Route;
+use rocket::http::{ContentType, Header};
+use rocket_dyn_templates::Template;
+use rust_embed::RustEmbed;
+
+use crate::config::CONFIG;
+use crate::proxy;
+
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct StaticFiles;
+
+const HASH_LEN: usize = 8;
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+// For unhashed names, which can change under the same URL
+const REVALIDATE: &str = "no-cache";
+
+lazy_static! {
+    // hashed name -> file name
+    static ref HASHED: HashMap<String, String> = StaticFiles::iter()
+        .map(|name| (hashed_name(&name), name.to_string()))
+        .collect();
+}
+
+fn hashed_name(name: &str) -> String {
+    let hash = StaticFiles::get(name)
+        .map(|file| hex::encode(file.metadata.sha256_hash()))
+        .unwrap_or_default();
+    let hash = &hash[..HASH_LEN.min(hash.len())];
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => format!("{}.{}.{}", stem, hash, ext),
+        None => format!("{}.{}", name, hash),
+    }
+}
+
+// URL of a static file, for templates
+pub fn url(name: &str) -> String {
+    proxy::url(format!("/static/{}", hashed_name(name)))
+}
+
+#[derive(rocket::Responder)]
+pub struct Asset {
+    body: Cow<'static, [u8]>,
+    content_type: ContentType,
+    cache_control: Header<'static>,
+}
+
+#[rocket::get("/theme.css")]
+fn theme() -> (ContentType, Template) {
+    (ContentType::CSS, Template::render("theme", rocket_dyn_templates::context! { theme: &CONFIG.theme }))
+}
+
+#[rocket::get("/<path..>", rank = 2)]
+fn asset(path: PathBuf) -> Option<Asset> {
+    let requested = path.to_str()?;
+    let (name, cache_control) = match HASHED.get(requested) {
+        Some(name) => (name.as_str(), IMMUTABLE),
+        None => (requested, REVALIDATE),
+    };
+    let file = StaticFiles::get(name)?;
+    let content_type = name
+        .rsplit_once('.')
+        .and_then(|(_, ext)| ContentType::from_extension(ext))
+        .unwrap_or(ContentType::Binary);
+
+    Some(Asset {
+        body: file.data,
+        content_type,
+        cache_control: Header::new("Cache-Control", cache_control),
+    })
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![theme, asset]
+}
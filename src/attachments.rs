@@ -0,0 +1,205 @@
+// Files attached to messages. Each is kept under a random id, in the data
+// directory next to a small JSON record of its name and type, or with an
+// [s3] table in an S3 bucket (see s3.rs), so containers need no volume for
+// them. Messages link to
+//
+//   GET /attachments/<id>/<name>
+//
+// which serves the file from the data directory, or redirects to a fresh
+// presigned URL in the bucket; frames sent to clients carry presigned URLs
+// straight away, or links signed as media.rs describes. Images are shown
+// inline; everything else is sent as a download so a crafted HTML or SVG
+// file can't run in the chat's origin.
+
+use std::io;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use rocket::Either::{self, Left, Right};
+use rocket::Route;
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::Redirect;
+use rocket::serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::CONFIG;
+use crate::s3::Bucket;
+use crate::{media, proxy, storage};
+
+// Largest single file kept
+pub const MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+const MAX_NAME_LEN: usize = 100;
+// Shown inline rather than downloaded
+const INLINE_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    pub content_type: String,
+    pub size: usize,
+    pub url: String,
+}
+
+impl Attachment {
+    fn inline(&self) -> bool {
+        INLINE_TYPES.contains(&self.content_type.as_str())
+    }
+
+    // The type the file is served as
+    fn served_type(&self) -> &str {
+        if self.inline() { &self.content_type } else { "application/octet-stream" }
+    }
+
+    fn disposition(&self) -> String {
+        let ascii_name: String = self.name.chars().map(|c| if c.is_ascii() { c } else { '_' }).collect();
+        format!("{}; filename=\"{}\"", if self.inline() { "inline" } else { "attachment" }, ascii_name)
+    }
+}
+
+// Where attached files are kept
+pub trait AttachmentStore: Send + Sync {
+    fn put(&self, attachment: &Attachment, data: &[u8]) -> io::Result<()>;
+    fn delete(&self, id: &str) -> io::Result<()>;
+    // Where clients fetch the file from
+    fn link(&self, attachment: &Attachment) -> String;
+    fn serve(&self, id: &str) -> Option<Either<AttachmentFile, Redirect>>;
+}
+
+struct DiskStore;
+
+impl AttachmentStore for DiskStore {
+    fn put(&self, attachment: &Attachment, data: &[u8]) -> io::Result<()> {
+        storage::write_blob("attachments", &attachment.id, data)?;
+        storage::save("attachments", &attachment.id, attachment)
+    }
+
+    fn delete(&self, id: &str) -> io::Result<()> {
+        storage::remove_blob("attachments", id)?;
+        storage::remove("attachments", id)
+    }
+
+    fn link(&self, attachment: &Attachment) -> String {
+        media::link(&attachment.url, &format!("attachments/{}", attachment.id))
+    }
+
+    fn serve(&self, id: &str) -> Option<Either<AttachmentFile, Redirect>> {
+        let attachment: Attachment = storage::load("attachments", id)?;
+        let data = storage::read_blob("attachments", id).ok()?;
+        Some(Left(AttachmentFile {
+            content_type: ContentType::parse_flexible(attachment.served_type()).unwrap_or(ContentType::Binary),
+            disposition: Header::new("Content-Disposition", attachment.disposition()),
+            max_age: None,
+            data,
+        }))
+    }
+}
+
+// The bucket serves the files, with the type and disposition they were put with
+impl AttachmentStore for Bucket {
+    fn put(&self, attachment: &Attachment, data: &[u8]) -> io::Result<()> {
+        Bucket::put(self, &attachment.id, data, attachment.served_type(), &attachment.disposition())
+    }
+
+    fn delete(&self, id: &str) -> io::Result<()> {
+        Bucket::delete(self, id)
+    }
+
+    fn link(&self, attachment: &Attachment) -> String {
+        self.presign(&attachment.id).unwrap_or_else(|_| attachment.url.clone())
+    }
+
+    fn serve(&self, id: &str) -> Option<Either<AttachmentFile, Redirect>> {
+        self.presign(id).ok().map(|url| Right(Redirect::to(url)))
+    }
+}
+
+lazy_static! {
+    static ref STORE: Box<dyn AttachmentStore> = match &CONFIG.s3 {
+        Some(s3) => Box::new(Bucket::new(s3.clone())),
+        None => Box::new(DiskStore),
+    };
+}
+
+// A file name with any path and odd characters stripped
+fn clean_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '"' | ';'))
+        .take(MAX_NAME_LEN)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// Keeps the file and returns what to attach to the message
+pub fn store(name: &str, content_type: &str, data: &[u8]) -> io::Result<Attachment> {
+    if data.len() > MAX_ATTACHMENT_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "file too large"));
+    }
+    let id = Uuid::new_v4().simple().to_string();
+    let name = clean_name(name);
+    let attachment = Attachment {
+        url: proxy::url(format!("/attachments/{}/{}", id, url::form_urlencoded::byte_serialize(name.as_bytes()).collect::<String>())),
+        id,
+        name,
+        content_type: content_type.to_string(),
+        size: data.len(),
+    };
+    STORE.put(&attachment, data)?;
+    Ok(attachment)
+}
+
+// Deletes the file, once no message has it any more
+pub fn remove(id: &str) -> io::Result<()> {
+    STORE.delete(id)
+}
+
+// The attachment as sent to clients, linking wherever the file is
+pub fn linked(attachment: &Attachment) -> Attachment {
+    Attachment { url: STORE.link(attachment), ..attachment.clone() }
+}
+
+pub fn links(attachments: &[Attachment]) -> Vec<Attachment> {
+    attachments.iter().map(linked).collect()
+}
+
+pub struct AttachmentFile {
+    content_type: ContentType,
+    disposition: Header<'static>,
+    // For signed links, how long caches may keep the file
+    max_age: Option<i64>,
+    data: Vec<u8>,
+}
+
+impl<'r> rocket::response::Responder<'r, 'static> for AttachmentFile {
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let mut response = (self.content_type, self.data).respond_to(request)?;
+        response.set_header(self.disposition);
+        if let Some(max_age) = self.max_age {
+            response.set_header(Header::new("Cache-Control", format!("public, max-age={}", max_age)));
+        }
+        Ok(response)
+    }
+}
+
+// The name in the path is only there for the browser; the id finds the
+// file. With media keys set, only signed links are served.
+#[rocket::get("/attachments/<id>/<_name>?<expires>&<sig>")]
+fn download(id: &str, _name: &str, expires: Option<i64>, sig: Option<&str>) -> Result<Either<AttachmentFile, Redirect>, Status> {
+    if !media::verify(&format!("attachments/{}", id), expires, sig) {
+        return Err(Status::Forbidden);
+    }
+    let mut served = STORE.serve(id).ok_or(Status::NotFound)?;
+    if let (Left(file), Some(expires)) = (&mut served, expires.filter(|_| !CONFIG.media.keys.is_empty())) {
+        file.max_age = Some((expires - Utc::now().timestamp()).max(0));
+    }
+    Ok(served)
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![download]
+}
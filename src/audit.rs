@@ -0,0 +1,167 @@
+// Append-only audit log for rooms in compliance mode. Every event (joins,
+// messages, commands, whiteboard changes, setting changes) becomes one JSON
+// line in data/audit/<room>.jsonl. Each entry is HMAC-signed and carries the
+// previous entry's signature, so removing, reordering or editing a line
+// breaks verification from that point on.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::Rng;
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::config::CONFIG;
+use crate::{CHAT_STATE, secrets, storage};
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    timestamp: String,
+    event: String,
+    actor: String,
+    data: Value,
+    // Signature of the previous entry, empty for the first
+    prev: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    signature: String,
+}
+
+impl Entry {
+    // Signs everything but the signature itself
+    fn sign(&self, key: &[u8]) -> String {
+        let unsigned = Entry {
+            signature: String::new(),
+            seq: self.seq,
+            timestamp: self.timestamp.clone(),
+            event: self.event.clone(),
+            actor: self.actor.clone(),
+            data: self.data.clone(),
+            prev: self.prev.clone(),
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+        mac.update(&serde_json::to_vec(&unsigned).unwrap_or_default());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+// The configured key, or one generated on first use and kept in the data directory
+fn load_key() -> Vec<u8> {
+    if let Some(key) = &CONFIG.audit_key {
+        return secrets::resolve(key).into_bytes();
+    }
+    if let Some(key) = storage::load::<String>("audit", "signing-key") {
+        return key.into_bytes();
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::rng().fill(&mut bytes);
+    let key = hex::encode(bytes);
+    if let Err(err) = storage::save("audit", "signing-key", &key) {
+        eprintln!("Failed to save the audit signing key: {}", err);
+    }
+    key.into_bytes()
+}
+
+lazy_static! {
+    static ref KEY: Vec<u8> = load_key();
+    // room id -> (last seq, last signature), picked up from the log on first use
+    static ref HEADS: Mutex<HashMap<String, (u64, String)>> = Mutex::new(HashMap::new());
+}
+
+fn is_enabled(room_id: &str) -> bool {
+    CHAT_STATE
+        .rooms
+        .read()
+        .get(room_id)
+        .is_some_and(|room| room.config.read().compliance)
+}
+
+fn read_head(room_id: &str) -> (u64, String) {
+    storage::read_lines("audit", room_id)
+        .ok()
+        .and_then(|lines| lines.last().cloned())
+        .and_then(|line| serde_json::from_str::<Entry>(&line).ok())
+        .map(|entry| (entry.seq, entry.signature))
+        .unwrap_or_default()
+}
+
+// Records an event if the room is in compliance mode
+pub fn record(room_id: &str, event: &str, actor: &str, data: Value) {
+    if !is_enabled(room_id) {
+        return;
+    }
+    append(room_id, event, actor, data);
+}
+
+// Records an event whether or not compliance mode is on, e.g. turning it off
+pub fn append(room_id: &str, event: &str, actor: &str, data: Value) {
+    let mut heads = HEADS.lock();
+    let head = heads.entry(room_id.to_string()).or_insert_with(|| read_head(room_id));
+
+    let mut entry = Entry {
+        seq: head.0 + 1,
+        timestamp: Utc::now().to_rfc3339(),
+        event: event.to_string(),
+        actor: actor.to_string(),
+        data,
+        prev: head.1.clone(),
+        signature: String::new(),
+    };
+    entry.signature = entry.sign(&KEY);
+
+    let line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(err) => {
+            eprintln!("Failed to serialize audit entry for {}: {}", room_id, err);
+            return;
+        }
+    };
+    match storage::append_line("audit", room_id, &line) {
+        Ok(()) => *head = (entry.seq, entry.signature),
+        Err(err) => eprintln!("Failed to write audit log for {}: {}", room_id, err),
+    }
+}
+
+pub struct Verification {
+    pub entries: u64,
+    // Sequence number of the first entry that doesn't check out
+    pub first_invalid: Option<u64>,
+}
+
+pub fn verify(room_id: &str) -> std::io::Result<Verification> {
+    let lines = storage::read_lines("audit", room_id)?;
+    let mut prev = String::new();
+
+    for (expected_seq, line) in (1..).zip(lines.iter()) {
+        let entry = serde_json::from_str::<Entry>(line).ok();
+        let valid = entry.as_ref().is_some_and(|entry| {
+            entry.seq == expected_seq && entry.prev == prev && entry.signature == entry.sign(&KEY)
+        });
+        if !valid {
+            return Ok(Verification {
+                entries: lines.len() as u64,
+                first_invalid: Some(expected_seq),
+            });
+        }
+        if let Some(entry) = entry {
+            prev = entry.signature;
+        }
+    }
+
+    Ok(Verification {
+        entries: lines.len() as u64,
+        first_invalid: None,
+    })
+}
+
+pub fn lines(room_id: &str) -> std::io::Result<Vec<Value>> {
+    Ok(storage::read_lines("audit", room_id)?
+        .iter()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
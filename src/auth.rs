@@ -0,0 +1,83 @@
+// Pluggable password checks. Locally registered accounts are checked against
+// their own hash; directory providers (LDAP with the `ldap` feature) vouch for
+// users the directory knows, who get an account here on first login and
+// server-wide roles from their directory groups. A nickname stays tied to the
+// provider that first claimed it.
+
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+
+use crate::Role;
+use crate::accounts::{ACCOUNTS, Account};
+
+#[cfg(feature = "ldap")]
+mod ldap;
+
+// Provider name of accounts registered on this server
+pub const LOCAL: &str = "local";
+
+// Who a provider says the user is
+pub struct Identity {
+    pub username: String,
+    // Role granted in every room, from directory groups
+    pub role: Option<Role>,
+}
+
+pub trait AuthProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Ok(None) if the user is unknown or the password is wrong; errors are
+    // for the provider itself failing
+    fn authenticate(&self, username: &str, password: &str) -> Result<Option<Identity>, String>;
+}
+
+struct LocalProvider;
+
+impl AuthProvider for LocalProvider {
+    fn name(&self) -> &str {
+        LOCAL
+    }
+
+    fn authenticate(&self, username: &str, password: &str) -> Result<Option<Identity>, String> {
+        Ok(ACCOUNTS.authenticate(username, password).map(|account| Identity {
+            username: account.username,
+            role: None,
+        }))
+    }
+}
+
+fn load_providers() -> Vec<Arc<dyn AuthProvider>> {
+    let providers: Vec<Arc<dyn AuthProvider>> = vec![Arc::new(LocalProvider)];
+
+    #[cfg(feature = "ldap")]
+    let providers = [providers, ldap::from_config()].concat();
+
+    providers
+}
+
+lazy_static! {
+    static ref PROVIDERS: Vec<Arc<dyn AuthProvider>> = load_providers();
+}
+
+// Whether a password may sign in a nickname that has no account here yet
+pub fn directory_enabled() -> bool {
+    directory_provider().is_some()
+}
+
+// The first directory provider configured, if any
+pub fn directory_provider() -> Option<&'static str> {
+    PROVIDERS.iter().map(|provider| provider.name()).find(|name| *name != LOCAL)
+}
+
+// Tries each provider in turn, returning the signed-in account
+pub fn authenticate(username: &str, password: &str) -> Option<Account> {
+    for provider in PROVIDERS.iter() {
+        match provider.authenticate(username, password) {
+            Ok(Some(identity)) => return ACCOUNTS.sign_in(provider.name(), identity),
+            Ok(None) => {},
+            Err(err) => eprintln!("{} authentication failed: {}", provider.name(), err),
+        }
+    }
+    None
+}
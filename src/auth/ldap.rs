@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use ldap3::{LdapConn, LdapConnSettings, Scope, SearchEntry, dn_escape, ldap_escape};
+
+use crate::Role;
+use crate::auth::{AuthProvider, Identity};
+use crate::config::{CONFIG, LdapConfig};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Checks passwords by binding to the directory as the user, then maps the
+// groups listed in the user's `memberOf` to roles
+pub struct LdapProvider {
+    config: LdapConfig,
+}
+
+pub fn from_config() -> Vec<Arc<dyn AuthProvider>> {
+    match &CONFIG.ldap {
+        Some(config) => {
+            println!("LDAP authentication enabled against {}", config.url);
+            vec![Arc::new(LdapProvider { config: config.clone() })]
+        },
+        None => Vec::new(),
+    }
+}
+
+// First RDN value of a DN, e.g. "admins" for "cn=admins,ou=groups,dc=example,dc=com"
+fn common_name(dn: &str) -> &str {
+    let rdn = dn.split(',').next().unwrap_or(dn);
+    rdn.split_once('=').map_or(rdn, |(_, value)| value)
+}
+
+impl LdapProvider {
+    fn role_for(&self, groups: &[String]) -> Option<Role> {
+        let roles: Vec<Role> = groups
+            .iter()
+            .filter_map(|group| {
+                self.config.group_roles.iter().find_map(|(name, role)| {
+                    let matches = name.eq_ignore_ascii_case(group) || name.eq_ignore_ascii_case(common_name(group));
+                    matches.then_some(*role)
+                })
+            })
+            .collect();
+        if roles.contains(&Role::Admin) {
+            Some(Role::Admin)
+        } else {
+            roles.first().copied()
+        }
+    }
+
+    fn groups(&self, conn: &mut LdapConn, username: &str) -> Result<Vec<String>, String> {
+        let Some(base) = &self.config.user_base else {
+            return Ok(Vec::new());
+        };
+        let filter = self.config.user_filter.replace("{username}", &ldap_escape(username));
+        let (entries, _) = conn
+            .search(base, Scope::Subtree, &filter, vec!["memberOf"])
+            .and_then(|result| result.success())
+            .map_err(|err| err.to_string())?;
+
+        Ok(entries
+            .into_iter()
+            .map(SearchEntry::construct)
+            .flat_map(|entry| entry.attrs.get("memberOf").cloned().unwrap_or_default())
+            .collect())
+    }
+}
+
+impl AuthProvider for LdapProvider {
+    fn name(&self) -> &str {
+        "ldap"
+    }
+
+    fn authenticate(&self, username: &str, password: &str) -> Result<Option<Identity>, String> {
+        // An empty password would be an anonymous bind, which always succeeds
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let settings = LdapConnSettings::new().set_conn_timeout(TIMEOUT);
+        let mut conn = LdapConn::with_settings(settings, &self.config.url).map_err(|err| err.to_string())?;
+        let dn = self.config.bind_dn.replace("{username}", &dn_escape(username));
+        let bound = conn.simple_bind(&dn, password).map_err(|err| err.to_string())?;
+        if bound.success().is_err() {
+            let _ = conn.unbind();
+            return Ok(None);
+        }
+
+        let groups = self.groups(&mut conn, username);
+        let _ = conn.unbind();
+        Ok(Some(Identity {
+            username: username.to_string(),
+            role: self.role_for(&groups?),
+        }))
+    }
+}
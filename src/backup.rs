@@ -0,0 +1,244 @@
+// Backups of a deployment: the whole data directory (accounts, rooms and
+// their settings, history, attachments, logs) plus the config file, as one
+// zstd-compressed tarball laid out as
+//
+//   data/...           the data directory
+//   WhoChat.toml       the config file, when there is one
+//
+// From the command line, for a stopped server:
+//
+//   who-chat backup [--out FILE.tar.zst]
+//   who-chat restore FILE.tar.zst [--force]
+//
+// On a running server the admin API takes consistent backups, holding off
+// writes to the data directory while it reads it:
+//
+//   POST /api/admin/backups            -> {"name", "size", "created_at"}
+//   GET  /api/admin/backups            -> the backups in backup_dir
+//   GET  /api/admin/backups/<name>     -> the file
+//
+// Restoring moves any existing data directory aside (with --force) rather
+// than merging into it, and only puts the config file back where there is
+// none, so a restore never quietly swaps the server's secrets.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rocket::Route;
+use rocket::fs::NamedFile;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::admin::{ApiResult, ServerAdmin, api_error};
+use crate::config::{self, CONFIG};
+use crate::storage;
+
+const EXTENSION: &str = ".tar.zst";
+const COMPRESSION_LEVEL: i32 = 3;
+const CONFIG_ENTRY: &str = "WhoChat.toml";
+
+pub fn default_name() -> String {
+    format!("who-chat-{}{}", Utc::now().format("%Y%m%d-%H%M%S"), EXTENSION)
+}
+
+// Adds the files under `dir` as `name/...`, leaving out the temporary files
+// storage writes before renaming them into place
+fn append_dir<W: io::Write>(tar: &mut tar::Builder<W>, dir: &Path, name: &Path) -> io::Result<()> {
+    tar.append_dir(name, dir)?;
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let entry_name = name.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            append_dir(tar, &path, &entry_name)?;
+        } else if path.extension().is_none_or(|extension| extension != "tmp") {
+            tar.append_path_with_name(&path, &entry_name)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes a backup to `out` and returns its size
+pub fn write(out: &Path) -> io::Result<u64> {
+    let file = File::create(out)?;
+    let encoder = zstd::Encoder::new(file, COMPRESSION_LEVEL)?;
+    let mut tar = tar::Builder::new(encoder);
+    tar.follow_symlinks(false);
+    storage::paused(|| {
+        if CONFIG.data_dir.is_dir() {
+            append_dir(&mut tar, &CONFIG.data_dir, Path::new("data"))?;
+        }
+        let config = config::path();
+        if config.is_file() {
+            tar.append_path_with_name(config, CONFIG_ENTRY)?;
+        }
+        Ok::<_, io::Error>(())
+    })?;
+    tar.into_inner()?.finish()?.sync_all()?;
+    Ok(fs::metadata(out)?.len())
+}
+
+fn is_empty_dir(dir: &Path) -> io::Result<bool> {
+    match fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_none()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+// Unpacks next to the data directory first, so a broken archive leaves the
+// current data alone. Returns where the old data directory went, if anywhere.
+pub fn restore(archive: &Path, force: bool) -> Result<Option<PathBuf>, String> {
+    let data_dir = &CONFIG.data_dir;
+    let occupied = !is_empty_dir(data_dir).map_err(|err| err.to_string())?;
+    if occupied && !force {
+        return Err(format!("{} isn't empty; pass --force to move it aside and restore anyway", data_dir.display()));
+    }
+
+    let staging = data_dir.with_extension("restoring");
+    let _ = fs::remove_dir_all(&staging);
+    let unpacked = File::open(archive)
+        .and_then(zstd::Decoder::new)
+        .and_then(|decoder| tar::Archive::new(decoder).unpack(&staging));
+    if let Err(err) = unpacked {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(format!("Couldn't unpack {}: {}", archive.display(), err));
+    }
+    if !staging.join("data").is_dir() {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(format!("{} isn't a who-chat backup", archive.display()));
+    }
+
+    let mut moved = None;
+    if data_dir.exists() {
+        let aside = data_dir.with_extension(format!("old-{}", Utc::now().format("%Y%m%d-%H%M%S")));
+        fs::rename(data_dir, &aside).map_err(|err| format!("Couldn't move {} aside: {}", data_dir.display(), err))?;
+        moved = Some(aside);
+    }
+    fs::rename(staging.join("data"), data_dir).map_err(|err| err.to_string())?;
+
+    let config = config::path();
+    let backed_up_config = staging.join(CONFIG_ENTRY);
+    if backed_up_config.is_file() && !config.exists() {
+        fs::rename(backed_up_config, &config).map_err(|err| err.to_string())?;
+    }
+    let _ = fs::remove_dir_all(&staging);
+    Ok(moved)
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("Usage: who-chat backup [--out FILE.tar.zst]");
+    eprintln!("       who-chat restore FILE.tar.zst [--force]");
+    std::process::exit(2);
+}
+
+// `who-chat backup ...` and `who-chat restore ...`
+pub fn run(command: &str, args: impl Iterator<Item = String>) {
+    let mut out = None;
+    let mut archive = None;
+    let mut force = false;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" if command == "backup" => out = Some(args.next().unwrap_or_else(|| usage("--out needs a value"))),
+            "--force" if command == "restore" => force = true,
+            // Handled by the config loader
+            "--data-dir" => {
+                args.next();
+            },
+            _ if arg.starts_with("--data-dir=") => {},
+            _ if command == "restore" && archive.is_none() && !arg.starts_with("--") => archive = Some(arg),
+            _ => usage(&format!("Unknown option {}", arg)),
+        }
+    }
+
+    if command == "backup" {
+        let out = PathBuf::from(out.unwrap_or_else(default_name));
+        match write(&out) {
+            Ok(size) => println!("Backed up {} to {} ({} bytes)", CONFIG.data_dir.display(), out.display(), size),
+            Err(err) => {
+                eprintln!("Backup failed: {}", err);
+                let _ = fs::remove_file(&out);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
+    let archive = PathBuf::from(archive.unwrap_or_else(|| usage("Which backup should be restored?")));
+    match restore(&archive, force) {
+        Ok(moved) => {
+            println!("Restored {} from {}", CONFIG.data_dir.display(), archive.display());
+            if let Some(moved) = moved {
+                println!("The previous data is in {}", moved.display());
+            }
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    }
+}
+
+// Backup files in backup_dir, newest first
+fn list() -> io::Result<Vec<Value>> {
+    let entries = match fs::read_dir(&CONFIG.backup_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.ends_with(EXTENSION) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        let created_at = metadata.modified().map(|at| DateTime::<Utc>::from(at).to_rfc3339()).ok();
+        backups.push(json!({ "name": name, "size": metadata.len(), "created_at": created_at }));
+    }
+    backups.sort_by(|a, b| b["name"].as_str().cmp(&a["name"].as_str()));
+    Ok(backups)
+}
+
+#[rocket::post("/backups")]
+async fn create(_admin: ServerAdmin) -> ApiResult {
+    let name = default_name();
+    let out = CONFIG.backup_dir.join(&name);
+    let written = rocket::tokio::task::spawn_blocking(move || {
+        fs::create_dir_all(&CONFIG.backup_dir)?;
+        write(&out).inspect_err(|_| {
+            let _ = fs::remove_file(&out);
+        })
+    })
+    .await
+    .map_err(|err| api_error(Status::InternalServerError, err))?;
+    let size = written.map_err(|err| api_error(Status::InternalServerError, format!("Backup failed: {}", err)))?;
+    Ok(Json(json!({ "name": name, "size": size, "created_at": Utc::now().to_rfc3339() })))
+}
+
+#[rocket::get("/backups")]
+fn index(_admin: ServerAdmin) -> ApiResult {
+    let backups = list().map_err(|err| api_error(Status::InternalServerError, err))?;
+    Ok(Json(json!({ "backups": backups })))
+}
+
+#[rocket::get("/backups/<name>")]
+async fn download(_admin: ServerAdmin, name: &str) -> Option<NamedFile> {
+    // Names as handed out above, nothing that could leave backup_dir
+    let valid = name.ends_with(EXTENSION) && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'));
+    if !valid || name.starts_with('.') {
+        return None;
+    }
+    NamedFile::open(CONFIG.backup_dir.join(name)).await.ok()
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![create, index, download]
+}
@@ -0,0 +1,145 @@
+// A room banner for maintenance windows and event notices, shown above the
+// message list until it's taken down or expires. Room admins set it with
+//
+//   PUT    /api/admin/rooms/<room_id>/banner
+//          {"text": "...", "severity": "info"|"warning"|"critical", "expires_at": RFC 3339}
+//   DELETE /api/admin/rooms/<room_id>/banner
+//   GET    /api/admin/rooms/<room_id>/banner
+//
+// Severity defaults to info and expires_at is optional. Every connection in
+// the room gets {"type": "banner", "banner": {...}|null} when it changes,
+// and new connections get the current one on connect. While the server is
+// in maintenance mode its notice is shown instead, see maintenance.rs.
+
+use chrono::{DateTime, Utc};
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::admin::{Admin, ApiResult, api_error};
+use crate::{CHAT_STATE, RoomConfig, RoomState, audit, maintenance};
+
+const MAX_BANNER_LEN: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Banner {
+    pub text: String,
+    #[serde(default)]
+    pub severity: Severity,
+    // RFC 3339
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+impl Banner {
+    fn expiry(&self) -> Option<DateTime<Utc>> {
+        let at = self.expires_at.as_deref()?;
+        DateTime::parse_from_rfc3339(at).ok().map(|at| at.with_timezone(&Utc))
+    }
+}
+
+// The maintenance notice, or else the room's banner unless it has expired
+pub fn current(config: &RoomConfig) -> Option<Banner> {
+    maintenance::banner().or_else(|| {
+        config
+            .banner
+            .clone()
+            .filter(|banner| banner.expiry().is_none_or(|at| at > Utc::now()))
+    })
+}
+
+fn frame(banner: Option<&Banner>) -> String {
+    json!({ "type": "banner", "banner": banner }).to_string()
+}
+
+// Sent to a new connection, if the room has a banner up
+pub fn connect_frame(room: &RoomState) -> Option<String> {
+    current(&room.config.read()).map(|banner| frame(Some(&banner)))
+}
+
+// Sends the room whatever banner should show now
+pub fn refresh(room: &RoomState) {
+    let banner = current(&room.config.read());
+    room.broadcast(&frame(banner.as_ref()));
+}
+
+// Takes down expired banners; run from the task loop
+pub fn expire() {
+    let now = Utc::now();
+    let rooms: Vec<RoomState> = CHAT_STATE.rooms.read().values().cloned().collect();
+    for room in rooms {
+        let expired = {
+            let mut config = room.config.write();
+            let expired = config
+                .banner
+                .as_ref()
+                .and_then(Banner::expiry)
+                .is_some_and(|at| at <= now);
+            if expired {
+                config.banner = None;
+            }
+            expired
+        };
+        if expired {
+            refresh(&room);
+        }
+    }
+}
+
+#[rocket::get("/rooms/<room_id>/banner")]
+fn get_banner(_admin: Admin, room_id: &str) -> Json<Value> {
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    let config = room.config.read();
+    Json(json!({ "banner": current(&config) }))
+}
+
+#[rocket::put("/rooms/<room_id>/banner", data = "<banner>")]
+fn put_banner(_admin: Admin, room_id: &str, banner: Json<Banner>) -> ApiResult {
+    let mut banner = banner.into_inner();
+    banner.text = banner.text.trim().to_string();
+    if banner.text.is_empty() {
+        return Err(api_error(Status::BadRequest, "Banners need text"));
+    }
+    if banner.text.chars().count() > MAX_BANNER_LEN {
+        return Err(api_error(Status::BadRequest, format!("Banners are limited to {} characters", MAX_BANNER_LEN)));
+    }
+    if banner.expires_at.is_some() {
+        match banner.expiry() {
+            None => return Err(api_error(Status::BadRequest, "expires_at must be an RFC 3339 time")),
+            Some(at) if at <= Utc::now() => return Err(api_error(Status::BadRequest, "expires_at is in the past")),
+            Some(at) => banner.expires_at = Some(at.to_rfc3339()),
+        }
+    }
+
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    room.config.write().banner = Some(banner.clone());
+    audit::record(room_id, "banner", "admin", json!(banner));
+    refresh(&room);
+    Ok(Json(json!({ "banner": banner })))
+}
+
+#[rocket::delete("/rooms/<room_id>/banner")]
+fn delete_banner(_admin: Admin, room_id: &str) -> ApiResult {
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    if room.config.write().banner.take().is_none() {
+        return Err(api_error(Status::NotFound, "This room has no banner"));
+    }
+    audit::record(room_id, "banner", "admin", json!(null));
+    refresh(&room);
+    Ok(Json(json!({ "banner": null })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![get_banner, put_banner, delete_banner]
+}
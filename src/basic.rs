@@ -0,0 +1,164 @@
+// Server-rendered room view for browsers without JavaScript, such as text
+// browsers and screen readers that struggle with live-updating pages. The
+// page shows recent history when loaded and posts with a plain form that
+// redirects back; it never updates on its own, so it links to itself for
+// refreshing. Joining goes through the usual login form with `basic` set.
+
+use chrono::DateTime;
+use rocket::Route;
+use rocket::Either::{self, Left, Right};
+use rocket::form::{Form, FromForm};
+use rocket::http::ContentType;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket_dyn_templates::{Template, context};
+use serde_json::{Value, json};
+
+use crate::accounts::{ACCOUNTS, AccountSession};
+use crate::commands::CommandOutput;
+use crate::appearance;
+use crate::config::CONFIG;
+use crate::{CHAT_STATE, attachments, banner, ChatMessage, knock, MessageType, User, UserSession, proxy, publish, run_command};
+
+const HISTORY: usize = 100;
+
+fn page(room_id: &str) -> String {
+    proxy::url(format!("/rooms/{}/basic", room_id))
+}
+
+pub fn entry(msg: &ChatMessage) -> Value {
+    let time = DateTime::parse_from_rfc3339(&msg.timestamp)
+        .map(|at| at.format("%H:%M").to_string())
+        .unwrap_or_default();
+    json!({
+        "id": msg.id,
+        "time": time,
+        "sender": msg.sender,
+        "rank": msg.rank,
+        "content": msg.content,
+        // Highlighted code, rendered and escaped server-side
+        "html": msg.html,
+        "system": msg.message_type == MessageType::SystemMessage,
+        "spoiler": msg.spoiler,
+        "content_warning": msg.content_warning,
+        "preview": msg.preview,
+        "forwarded": msg.forwarded,
+        "attachments": attachments::links(&msg.attachments),
+    })
+}
+
+#[rocket::get("/<room_id>/basic")]
+fn view(
+    room_id: &str,
+    user_session: Option<UserSession>,
+    account: Option<AccountSession>,
+    flash: Option<FlashMessage<'_>>,
+) -> (ContentType, Template) {
+    let notice = flash.map(|flash| json!({ "error": flash.kind() == "error", "message": flash.message() }));
+    let session = user_session.filter(|session| session.room_id == room_id);
+    let Some(session) = session else {
+        return (ContentType::HTML, Template::render("basic", context! {
+            room_id,
+            title: format!("Join Room: {}", room_id),
+            nickname: account.map(|account| account.0.username),
+            notice,
+            base: proxy::prefix(),
+            theme: &CONFIG.theme,
+        }));
+    };
+
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    if let Some(waiting) = knock::waiting(&room, &session.user_id, session.account_id.as_deref()) {
+        return knock::waiting_page(room_id, &session.nickname, waiting);
+    }
+    let blocked = |sender: &str| session.account_id.as_deref().is_some_and(|id| ACCOUNTS.has_blocked(id, sender));
+    let messages: Vec<Value> = {
+        let messages = room.messages.read();
+        let visible: Vec<&ChatMessage> = messages.iter().filter(|msg| !blocked(&msg.sender)).collect();
+        visible[visible.len().saturating_sub(HISTORY)..].iter().map(|msg| entry(msg)).collect()
+    };
+    let mut users: Vec<String> = room.users.read().values().map(|user| user.nickname.clone()).collect();
+    users.sort();
+    users.dedup();
+    let config = room.config.read();
+
+    (ContentType::HTML, Template::render("basic", context! {
+        room_id,
+        title: format!("Chat Room: {}", room_id),
+        joined: true,
+        nickname: session.nickname,
+        messages,
+        users,
+        welcome_message: config.welcome_message.clone(),
+        banner: banner::current(&config),
+        locked: config.locked,
+        room: appearance::json(&config),
+        notice,
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    }))
+}
+
+// Nothing to report on success, the message shows up in the history
+fn published(msg: ChatMessage) -> Result<String, String> {
+    publish(msg).map(|_| String::new()).map_err(|rejection| rejection.detail)
+}
+
+#[derive(FromForm)]
+struct BasicPost {
+    content: String,
+}
+
+// Posts a message or runs a command, then goes back to the page so
+// reloading doesn't post again
+#[rocket::post("/<room_id>/basic", data = "<form>")]
+fn post(room_id: &str, user_session: Option<UserSession>, form: Form<BasicPost>) -> Either<Flash<Redirect>, Redirect> {
+    let Some(session) = user_session.filter(|session| session.room_id == room_id) else {
+        return Right(Redirect::to(page(room_id)));
+    };
+    // Still knocking; the page says so
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    if knock::waiting(&room, &session.user_id, session.account_id.as_deref()).is_some() {
+        return Right(Redirect::to(page(room_id)));
+    }
+    let back = |result: Result<String, String>| match result {
+        Ok(message) => Flash::success(Redirect::to(page(room_id)), message),
+        Err(message) => Flash::error(Redirect::to(page(room_id)), message),
+    };
+    let content = form.content.trim();
+    if content.is_empty() {
+        return Left(back(Err("Messages need content".to_string())));
+    }
+
+    let user = User {
+        id: session.user_id,
+        nickname: session.nickname,
+        room_id: room_id.to_string(),
+        account_id: session.account_id,
+        session_id: session.session_id,
+    };
+    if !content.starts_with('/') {
+        let mut msg = ChatMessage::new(room_id, &user.nickname, content, MessageType::UserMessage);
+        msg.account_id = user.account_id.clone();
+        return Left(back(published(msg)));
+    }
+
+    let result = match run_command(&user, content) {
+        CommandOutput::Reply(reply) => Ok(reply),
+        CommandOutput::Message(msg) => published(*msg),
+        CommandOutput::Bot(reply) => {
+            let room = CHAT_STATE.get_or_create_room(room_id);
+            room.post(ChatMessage::new(room_id, &reply.sender, &reply.content, MessageType::Bot));
+            Ok(String::new())
+        },
+        CommandOutput::Hint(reply) => Ok(format!("{}: {}", reply.sender, reply.content)),
+        CommandOutput::Client("logout") => return Right(Redirect::to(proxy::url("/logout"))),
+        CommandOutput::Client(command) => Err(format!("/{} only works in the full client", command)),
+        CommandOutput::Error(error) => Err(error.detail),
+    };
+    Left(back(result))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![view, post]
+}
@@ -0,0 +1,78 @@
+// Per-account block lists. Messages from a blocked nickname are dropped
+// from the blocker's connections on the server, in rooms and DMs alike.
+
+use rocket::Route;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::accounts::{ACCOUNTS, AccountSession};
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::ErrorCode;
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("block", "/block <nickname> - hide messages and DMs from a user", block);
+    registry.register("unblock", "/unblock <nickname> - stop hiding a user's messages", unblock);
+}
+
+fn block(ctx: &CommandContext) -> CommandOutput {
+    let Some(account_id) = &ctx.user.account_id else {
+        return CommandOutput::error(ErrorCode::AccountRequired, "Register your nickname to block users");
+    };
+    if ctx.args.is_empty() {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /block <nickname>");
+    }
+    if ctx.args.eq_ignore_ascii_case(&ctx.user.nickname) {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "You can't block yourself");
+    }
+
+    // Registered users are stored under their canonical username
+    let nickname = ACCOUNTS
+        .find(ctx.args)
+        .map(|account| account.username)
+        .unwrap_or_else(|| ctx.args.to_string());
+    let added = ACCOUNTS.update(|accounts| {
+        let Some(account) = accounts.get_mut(account_id) else {
+            return false;
+        };
+        if account.blocked.iter().any(|blocked| blocked.eq_ignore_ascii_case(&nickname)) {
+            return false;
+        }
+        account.blocked.insert(nickname.clone())
+    });
+
+    if added {
+        CommandOutput::Reply(format!("Blocked {}", nickname))
+    } else {
+        CommandOutput::Reply(format!("{} is already blocked", nickname))
+    }
+}
+
+fn unblock(ctx: &CommandContext) -> CommandOutput {
+    let Some(account_id) = &ctx.user.account_id else {
+        return CommandOutput::error(ErrorCode::AccountRequired, "Register your nickname to block users");
+    };
+    if ctx.args.is_empty() {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /unblock <nickname>");
+    }
+
+    let removed = ACCOUNTS.update(|accounts| {
+        let account = accounts.get_mut(account_id)?;
+        let blocked = account.blocked.iter().find(|blocked| blocked.eq_ignore_ascii_case(ctx.args))?.clone();
+        account.blocked.remove(&blocked);
+        Some(blocked)
+    });
+
+    match removed {
+        Some(nickname) => CommandOutput::Reply(format!("Unblocked {}", nickname)),
+        None => CommandOutput::Reply(format!("{} isn't blocked", ctx.args)),
+    }
+}
+
+#[rocket::get("/")]
+fn list(session: AccountSession) -> Json<Value> {
+    Json(json!({ "blocked": session.0.blocked }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list]
+}
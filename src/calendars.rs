@@ -0,0 +1,391 @@
+// External calendars a room is subscribed to, e.g. a team's Google Calendar
+// through its secret iCal address. Subscriptions are managed with
+//
+//   GET    /api/admin/rooms/<room_id>/calendars
+//   POST   /api/admin/rooms/<room_id>/calendars         {"url", "lead_minutes"}
+//   PATCH  /api/admin/rooms/<room_id>/calendars/<id>    {"lead_minutes"}
+//   DELETE /api/admin/rooms/<room_id>/calendars/<id>
+//
+// Each feed is fetched soon after it's added and then every POLL_INTERVAL,
+// and the room is reminded lead_minutes before each upcoming event. Times
+// with a TZID are read in the server's time zone, and recurring events are
+// only reminded of at the occurrence their DTSTART names.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::thread;
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::admin::{Admin, ApiResult, api_error};
+use crate::reminders::{self, LATE_REMINDER_SECS};
+use crate::rooms::{INVALID_ROOM_ID, valid_room_id};
+use crate::storage;
+
+const MAX_CALENDARS: usize = 10;
+const DEFAULT_LEAD_MINUTES: i64 = 15;
+const MAX_LEAD_MINUTES: i64 = 7 * 24 * 60;
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(10 * 60);
+// How often to look for feeds due a fetch
+const CHECK_INTERVAL: StdDuration = StdDuration::from_secs(60);
+const FETCH_TIMEOUT: StdDuration = StdDuration::from_secs(15);
+// Largest feed read
+const MAX_FEED_BYTES: u64 = 2 * 1024 * 1024;
+// Events further out than this aren't kept until a later poll
+const LOOKAHEAD_DAYS: i64 = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CalendarEvent {
+    uid: String,
+    summary: String,
+    // RFC 3339
+    starts_at: String,
+    #[serde(default)]
+    reminded: bool,
+}
+
+impl CalendarEvent {
+    fn start(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.starts_at).ok().map(|at| at.with_timezone(&Utc))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Subscription {
+    id: String,
+    url: String,
+    lead_minutes: i64,
+    created_at: String,
+    // X-WR-CALNAME of the feed, once fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fetched_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    #[serde(default)]
+    events: Vec<CalendarEvent>,
+}
+
+impl Subscription {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "url": self.url,
+            "lead_minutes": self.lead_minutes,
+            "created_at": self.created_at,
+            "name": self.name,
+            "fetched_at": self.fetched_at,
+            "last_error": self.last_error,
+            "upcoming": self.events.len(),
+        })
+    }
+}
+
+lazy_static! {
+    // room id -> subscriptions
+    static ref CALENDARS: RwLock<HashMap<String, Vec<Subscription>>> =
+        RwLock::new(storage::load("calendars", "calendars").unwrap_or_default());
+}
+
+fn save(calendars: &HashMap<String, Vec<Subscription>>) {
+    if let Err(err) = storage::save("calendars", "calendars", calendars) {
+        eprintln!("Failed to save calendars: {}", err);
+    }
+}
+
+// Joins folded content lines back together
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {},
+        }
+    }
+    out
+}
+
+// "20261020T180000Z" in UTC, "20261020T180000" in local time, or an all-day
+// "20261020" from local midnight
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok().map(|at| at.and_utc());
+    }
+    let local = match NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        Ok(at) => at,
+        Err(_) => NaiveDate::parse_from_str(value, "%Y%m%d").ok()?.and_hms_opt(0, 0, 0)?,
+    };
+    Local.from_local_datetime(&local).earliest().map(|at| at.with_timezone(&Utc))
+}
+
+// The feed's name and its events starting between `from` and `until`
+fn parse_feed(text: &str, from: DateTime<Utc>, until: DateTime<Utc>) -> (Option<String>, Vec<CalendarEvent>) {
+    let mut name = None;
+    let mut events = Vec::new();
+    let mut current: Option<HashMap<String, String>> = None;
+    for line in unfold(text) {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        // Parameters such as TZID are dropped
+        let property = key.split(';').next().unwrap_or(key).to_ascii_uppercase();
+        match (property.as_str(), value, &mut current) {
+            ("BEGIN", "VEVENT", _) => current = Some(HashMap::new()),
+            ("END", "VEVENT", Some(_)) => {
+                let event = current.take().unwrap_or_default();
+                if event.get("STATUS").is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED")) {
+                    continue;
+                }
+                let Some(start) = event.get("DTSTART").and_then(|value| parse_time(value)) else { continue };
+                if start < from || start > until {
+                    continue;
+                }
+                events.push(CalendarEvent {
+                    uid: event.get("UID").cloned().unwrap_or_default(),
+                    summary: event.get("SUMMARY").map(|summary| unescape(summary)).unwrap_or_else(|| "(untitled)".to_string()),
+                    starts_at: start.to_rfc3339(),
+                    reminded: false,
+                });
+            },
+            (_, _, Some(event)) => {
+                event.entry(property).or_insert_with(|| value.to_string());
+            },
+            ("X-WR-CALNAME", _, None) => name = Some(unescape(value)),
+            _ => {},
+        }
+    }
+    events.sort_by_key(CalendarEvent::start);
+    (name, events)
+}
+
+fn fetch(agent: &ureq::Agent, url: &str) -> Result<String, String> {
+    let response = agent.get(url).call().map_err(|err| err.to_string())?;
+    let mut body = String::new();
+    response
+        .into_reader()
+        .take(MAX_FEED_BYTES)
+        .read_to_string(&mut body)
+        .map_err(|err| err.to_string())?;
+    Ok(body)
+}
+
+// Refetches feeds not tried within POLL_INTERVAL, keeping what was already
+// reminded of. `tried` is when each subscription was last fetched.
+fn poll(agent: &ureq::Agent, tried: &mut HashMap<String, Instant>) {
+    let subscriptions: Vec<(String, String, String)> = CALENDARS
+        .read()
+        .iter()
+        .flat_map(|(room_id, subscriptions)| subscriptions.iter().map(|sub| (room_id.clone(), sub.id.clone(), sub.url.clone())))
+        .filter(|(_, id, _)| tried.get(id).is_none_or(|at| at.elapsed() >= POLL_INTERVAL))
+        .collect();
+
+    for (room_id, id, url) in subscriptions {
+        tried.insert(id.clone(), Instant::now());
+        let now = Utc::now();
+        let fetched = fetch(agent, &url).map(|text| parse_feed(&text, now - Duration::hours(1), now + Duration::days(LOOKAHEAD_DAYS)));
+        let mut all = CALENDARS.write();
+        let Some(sub) = all.get_mut(&room_id).and_then(|subs| subs.iter_mut().find(|sub| sub.id == id)) else {
+            continue;
+        };
+        match fetched {
+            Ok((name, mut events)) => {
+                for event in &mut events {
+                    event.reminded = sub
+                        .events
+                        .iter()
+                        .any(|old| old.reminded && old.uid == event.uid && old.starts_at == event.starts_at);
+                }
+                sub.name = name;
+                sub.events = events;
+                sub.fetched_at = Some(now.to_rfc3339());
+                sub.last_error = None;
+            },
+            Err(err) => {
+                eprintln!("Failed to fetch calendar {} for {}: {}", url, room_id, err);
+                sub.last_error = Some(err);
+            },
+        }
+        save(&all);
+    }
+}
+
+// Polls the feeds on a background thread, so slow calendar servers never
+// hold up the task loop
+pub fn start() {
+    thread::spawn(|| {
+        let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+        let mut tried = HashMap::new();
+        loop {
+            poll(&agent, &mut tried);
+            thread::sleep(CHECK_INTERVAL);
+        }
+    });
+}
+
+fn describe_lead(minutes: i64) -> String {
+    match minutes {
+        0 => "Starting now".to_string(),
+        1 => "Starting in 1 minute".to_string(),
+        m if m < 120 => format!("Starting in {} minutes", m),
+        m if m < 48 * 60 => format!("Starting in {} hours", m / 60),
+        m => format!("Starting in {} days", m / (24 * 60)),
+    }
+}
+
+// Sends due reminders; run from the task loop
+pub fn remind() {
+    let now = Utc::now();
+    let mut announcements = Vec::new();
+    {
+        let mut all = CALENDARS.write();
+        let mut changed = false;
+        for (room_id, subscriptions) in all.iter_mut() {
+            for sub in subscriptions.iter_mut() {
+                let lead = Duration::minutes(sub.lead_minutes);
+                for event in sub.events.iter_mut().filter(|event| !event.reminded) {
+                    let Some(start) = event.start() else { continue };
+                    if start - lead > now {
+                        continue;
+                    }
+                    event.reminded = true;
+                    changed = true;
+                    if now - (start - lead) > Duration::seconds(LATE_REMINDER_SECS) {
+                        continue;
+                    }
+                    let minutes = ((start - now).num_seconds().max(0) + 59) / 60;
+                    let source = sub.name.as_ref().map(|name| format!(" ({})", name)).unwrap_or_default();
+                    announcements.push((room_id.clone(), format!("{}: {}{}", describe_lead(minutes), event.summary, source)));
+                }
+            }
+        }
+        if changed {
+            save(&all);
+        }
+    }
+    for (room_id, content) in announcements {
+        reminders::announce(&room_id, &content);
+    }
+}
+
+fn check_lead(lead_minutes: i64) -> Result<i64, (Status, Json<Value>)> {
+    if !(0..=MAX_LEAD_MINUTES).contains(&lead_minutes) {
+        return Err(api_error(Status::BadRequest, format!("lead_minutes must be between 0 and {}", MAX_LEAD_MINUTES)));
+    }
+    Ok(lead_minutes)
+}
+
+#[rocket::get("/api/admin/rooms/<room_id>/calendars")]
+fn list(_admin: Admin, room_id: &str) -> Json<Value> {
+    let calendars: Vec<Value> = CALENDARS
+        .read()
+        .get(room_id)
+        .map(|subs| subs.iter().map(Subscription::to_json).collect())
+        .unwrap_or_default();
+    Json(json!({ "calendars": calendars }))
+}
+
+#[derive(Deserialize)]
+struct NewSubscription {
+    url: String,
+    lead_minutes: Option<i64>,
+}
+
+// The feed is fetched within CHECK_INTERVAL
+#[rocket::post("/api/admin/rooms/<room_id>/calendars", data = "<new_sub>")]
+fn subscribe(_admin: Admin, room_id: &str, new_sub: Json<NewSubscription>) -> ApiResult {
+    if !valid_room_id(room_id) {
+        return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+    }
+    // Calendar apps hand out webcal:// links for the same feed
+    let url = match new_sub.url.trim().strip_prefix("webcal://") {
+        Some(rest) => format!("https://{}", rest),
+        None => new_sub.url.trim().to_string(),
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(api_error(Status::BadRequest, "Calendar URLs must be http://, https:// or webcal://"));
+    }
+    let lead_minutes = check_lead(new_sub.lead_minutes.unwrap_or(DEFAULT_LEAD_MINUTES))?;
+
+    let mut all = CALENDARS.write();
+    let subs = all.entry(room_id.to_string()).or_default();
+    if subs.len() >= MAX_CALENDARS {
+        return Err(api_error(Status::Conflict, format!("Rooms can subscribe to at most {} calendars", MAX_CALENDARS)));
+    }
+    let sub = Subscription {
+        id: Uuid::new_v4().simple().to_string()[..8].to_string(),
+        url,
+        lead_minutes,
+        created_at: Utc::now().to_rfc3339(),
+        name: None,
+        fetched_at: None,
+        last_error: None,
+        events: Vec::new(),
+    };
+    subs.push(sub.clone());
+    save(&all);
+    Ok(Json(sub.to_json()))
+}
+
+#[derive(Deserialize)]
+struct SubscriptionUpdate {
+    lead_minutes: i64,
+}
+
+#[rocket::patch("/api/admin/rooms/<room_id>/calendars/<id>", data = "<update>")]
+fn update(_admin: Admin, room_id: &str, id: &str, update: Json<SubscriptionUpdate>) -> ApiResult {
+    let lead_minutes = check_lead(update.lead_minutes)?;
+    let mut all = CALENDARS.write();
+    let sub = all
+        .get_mut(room_id)
+        .and_then(|subs| subs.iter_mut().find(|sub| sub.id == id))
+        .ok_or_else(|| api_error(Status::NotFound, "No such calendar"))?;
+    sub.lead_minutes = lead_minutes;
+    let body = sub.to_json();
+    save(&all);
+    Ok(Json(body))
+}
+
+#[rocket::delete("/api/admin/rooms/<room_id>/calendars/<id>")]
+fn unsubscribe(_admin: Admin, room_id: &str, id: &str) -> ApiResult {
+    let mut all = CALENDARS.write();
+    let subs = all.get_mut(room_id).ok_or_else(|| api_error(Status::NotFound, "No such calendar"))?;
+    let position = subs
+        .iter()
+        .position(|sub| sub.id == id)
+        .ok_or_else(|| api_error(Status::NotFound, "No such calendar"))?;
+    let sub = subs.remove(position);
+    if subs.is_empty() {
+        all.remove(room_id);
+    }
+    save(&all);
+    Ok(Json(sub.to_json()))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, subscribe, update, unsubscribe]
+}
@@ -0,0 +1,82 @@
+// What this deployment can do, for clients and SDKs to adapt to instead of
+// finding out from errors:
+//
+//   GET /api/server
+//     {"name", "version",
+//      "features": {"uploads", "e2e", "federation", "push", ...},
+//      "limits": {"max_message_len", "max_upload_bytes", "upload_chunk_size", ...},
+//      "auth": {"modes": ["guest", "password", ...], "directory", "two_factor",
+//               "guest_pow_difficulty"}}
+//
+// Features are false when they aren't built in or aren't configured; e2e,
+// federation and push aren't available in this version at all, and are
+// listed so clients can check for them the same way once they are.
+
+use rocket::Route;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::attachments::MAX_ATTACHMENT_BYTES;
+use crate::config::CONFIG;
+use crate::flags::{self, Flag};
+use crate::{auth, pow, uploads};
+
+fn auth_modes() -> Vec<&'static str> {
+    let mut modes = vec!["guest", "password"];
+    if let Some(directory) = auth::directory_provider() {
+        modes.push(directory);
+    }
+    // Bearer tokens for the REST API: the admin token and scoped API tokens
+    modes.push("api_token");
+    modes
+}
+
+#[cfg(feature = "mqtt")]
+fn mqtt_bridge() -> bool {
+    CONFIG.mqtt.is_some()
+}
+
+#[cfg(not(feature = "mqtt"))]
+fn mqtt_bridge() -> bool {
+    false
+}
+
+#[rocket::get("/")]
+fn server() -> Json<Value> {
+    Json(json!({
+        "name": CONFIG.theme.name,
+        "version": env!("CARGO_PKG_VERSION"),
+        "features": {
+            "uploads": flags::enabled(Flag::Uploads, None),
+            "link_previews": flags::enabled(Flag::LinkPreviews, None),
+            "bots": flags::enabled(Flag::Bots, None),
+            "e2e": false,
+            "federation": false,
+            "push": false,
+            "pwa": CONFIG.pwa.enabled,
+            "translation": CONFIG.translation.is_some(),
+            "email": CONFIG.email.is_some(),
+            "mqtt": mqtt_bridge(),
+            "ranks": !CONFIG.ranks.is_empty(),
+            "scim": true,
+        },
+        "limits": {
+            "max_message_len": CONFIG.max_message_len,
+            "max_upload_bytes": MAX_ATTACHMENT_BYTES,
+            "upload_chunk_size": uploads::CHUNK_SIZE,
+            "rate_limit_messages": CONFIG.rate_limit_messages,
+            "rate_limit_secs": CONFIG.rate_limit_secs,
+            "daily_message_quota": CONFIG.daily_message_quota,
+        },
+        "auth": {
+            "modes": auth_modes(),
+            "directory": auth::directory_provider(),
+            "two_factor": true,
+            "guest_pow_difficulty": pow::difficulty(),
+        },
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![server]
+}
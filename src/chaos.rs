@@ -0,0 +1,93 @@
+// Fault injection for soak tests, compiled in only with the `chaos` feature.
+// Everything starts switched off; the server admin turns faults on with
+//
+//   PUT /api/admin/chaos {"broadcast_delay_ms": [min, max],
+//                         "disconnect_probability": 0.01,
+//                         "storage_error_probability": 0.05}
+//
+// and GET shows the current settings. Fields left out of a PUT are reset.
+//   broadcast_delay_ms         each broadcast sleeps a random time in the range
+//                              before sending, like a congested server
+//   disconnect_probability     chance that a connection is dropped instead of
+//                              getting a frame, to exercise client reconnects
+//   storage_error_probability  chance that a write to the data directory fails
+//                              with an I/O error
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rand::Rng;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::admin::{ApiResult, ServerAdmin, api_error};
+
+const MAX_DELAY_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Settings {
+    broadcast_delay_ms: Option<(u64, u64)>,
+    disconnect_probability: f64,
+    storage_error_probability: f64,
+}
+
+lazy_static! {
+    static ref SETTINGS: RwLock<Settings> = RwLock::new(Settings::default());
+}
+
+fn roll(probability: f64) -> bool {
+    probability > 0.0 && rand::rng().random_bool(probability.min(1.0))
+}
+
+pub fn delay_broadcast() {
+    let Some((min, max)) = SETTINGS.read().broadcast_delay_ms else {
+        return;
+    };
+    let delay = rand::rng().random_range(min..=max);
+    thread::sleep(Duration::from_millis(delay));
+}
+
+pub fn drop_connection() -> bool {
+    roll(SETTINGS.read().disconnect_probability)
+}
+
+pub fn storage_fault() -> io::Result<()> {
+    if roll(SETTINGS.read().storage_error_probability) {
+        return Err(io::Error::other("chaos: injected storage error"));
+    }
+    Ok(())
+}
+
+#[rocket::get("/chaos")]
+fn get_chaos(_admin: ServerAdmin) -> Json<Value> {
+    Json(json!(*SETTINGS.read()))
+}
+
+#[rocket::put("/chaos", data = "<settings>")]
+fn put_chaos(_admin: ServerAdmin, settings: Json<Settings>) -> ApiResult {
+    let settings = settings.into_inner();
+    if settings.broadcast_delay_ms.is_some_and(|(min, max)| min > max || max > MAX_DELAY_MS) {
+        return Err(api_error(Status::BadRequest, format!("broadcast_delay_ms must be [min, max] with max at most {}", MAX_DELAY_MS)));
+    }
+    for probability in [settings.disconnect_probability, settings.storage_error_probability] {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(api_error(Status::BadRequest, "Probabilities must be between 0 and 1"));
+        }
+    }
+
+    eprintln!("Chaos settings changed: {}", json!(settings));
+    let body = json!(settings);
+    *SETTINGS.write() = settings;
+    Ok(Json(body))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![get_chaos, put_chaos]
+}
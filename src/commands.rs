@@ -0,0 +1,128 @@
+// Slash command framework. Each command is a plain function registered by
+// name; modules add their commands through a `register` function.
+
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+
+use crate::{ChatMessage, MessageType, User};
+use crate::plugins::BotReply;
+use crate::protocol::{self, ErrorCode};
+
+mod fun;
+mod moderation;
+
+pub struct CommandContext<'a> {
+    // The caller; `user.room_id` is the room the command was sent in
+    pub user: &'a User,
+    pub args: &'a str,
+}
+
+pub enum CommandOutput {
+    // System message shown only to the caller
+    Reply(String),
+    // Message posted to the room under a bot's name
+    Bot(BotReply),
+    // A bot's reply shown only to the caller
+    Hint(BotReply),
+    // Message from the caller, published like anything they type
+    Message(Box<ChatMessage>),
+    // Action for the caller's client to perform, e.g. "clear"
+    Client(&'static str),
+    // The command was turned down; sent to the caller as an error frame
+    Error(protocol::Error),
+}
+
+impl CommandOutput {
+    pub fn error(code: ErrorCode, detail: impl Into<String>) -> Self {
+        CommandOutput::Error(protocol::Error::new(code, detail))
+    }
+
+    pub fn bot(sender: &str, content: impl Into<String>) -> Self {
+        CommandOutput::Bot(BotReply {
+            sender: sender.to_string(),
+            content: content.into(),
+        })
+    }
+
+    pub fn hint(sender: &str, content: impl Into<String>) -> Self {
+        CommandOutput::Hint(BotReply {
+            sender: sender.to_string(),
+            content: content.into(),
+        })
+    }
+}
+
+pub type CommandHandler = fn(&CommandContext) -> CommandOutput;
+
+struct Command {
+    usage: &'static str,
+    handler: CommandHandler,
+}
+
+pub struct CommandRegistry {
+    commands: BTreeMap<&'static str, Command>,
+}
+
+impl CommandRegistry {
+    fn new() -> Self {
+        let mut registry = CommandRegistry {
+            commands: BTreeMap::new(),
+        };
+        registry.register("help", "/help - list available commands", help);
+        registry.register("clear", "/clear - clear your message view", |_| CommandOutput::Client("clear"));
+        registry.register("logout", "/logout - leave the room", |_| CommandOutput::Client("logout"));
+        registry.register("spoiler", "/spoiler [warning |] <text> - send a message hidden until clicked", spoiler);
+        fun::register(&mut registry);
+        moderation::register(&mut registry);
+        crate::trivia::register(&mut registry);
+        crate::blocking::register(&mut registry);
+        crate::word_filter::register(&mut registry);
+        crate::keywords::register(&mut registry);
+        crate::forwarding::register(&mut registry);
+        crate::events::register(&mut registry);
+        crate::reminders::register(&mut registry);
+        crate::meet::register(&mut registry);
+        crate::quiet::register(&mut registry);
+        crate::knock::register(&mut registry);
+        crate::appearance::register(&mut registry);
+        crate::ranks::register(&mut registry);
+        crate::karma::register(&mut registry);
+        crate::anonymous::register(&mut registry);
+        registry
+    }
+
+    pub fn register(&mut self, name: &'static str, usage: &'static str, handler: CommandHandler) {
+        self.commands.insert(name, Command { usage, handler });
+    }
+
+    // None if no command with this name is registered
+    pub fn run(&self, name: &str, ctx: &CommandContext) -> Option<CommandOutput> {
+        self.commands.get(name).map(|command| (command.handler)(ctx))
+    }
+}
+
+fn help(_: &CommandContext) -> CommandOutput {
+    let usage: Vec<&str> = COMMANDS.commands.values().map(|command| command.usage).collect();
+    CommandOutput::Reply(usage.join("\n"))
+}
+
+fn spoiler(ctx: &CommandContext) -> CommandOutput {
+    let (warning, text) = match ctx.args.split_once('|') {
+        Some((warning, text)) => (Some(warning.trim()).filter(|w| !w.is_empty()), text.trim()),
+        None => (None, ctx.args),
+    };
+    if text.is_empty() {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /spoiler [warning |] <text>");
+    }
+
+    let mut msg = ChatMessage::new(&ctx.user.room_id, &ctx.user.nickname, text, MessageType::UserMessage);
+    msg.account_id = ctx.user.account_id.clone();
+    msg.spoiler = true;
+    msg.content_warning = warning.map(str::to_string);
+    CommandOutput::Message(Box::new(msg))
+}
+
+lazy_static! {
+    pub static ref COMMANDS: CommandRegistry = CommandRegistry::new();
+}
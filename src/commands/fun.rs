@@ -0,0 +1,135 @@
+// Dice and other small game commands for tabletop rooms
+
+use rand::Rng;
+use rand::seq::IndexedRandom;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::ErrorCode;
+
+const BOT_NAME: &str = "Dice";
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+// No point adding more than the dice could ever roll
+const MAX_MODIFIER: i64 = MAX_DICE as i64 * MAX_SIDES as i64;
+
+const EIGHT_BALL_ANSWERS: &[&str] = &[
+    "It is certain.",
+    "Without a doubt.",
+    "You may rely on it.",
+    "Most likely.",
+    "Signs point to yes.",
+    "Reply hazy, try again.",
+    "Ask again later.",
+    "Cannot predict now.",
+    "Don't count on it.",
+    "My sources say no.",
+    "Very doubtful.",
+];
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("roll", "/roll [NdM[+K]] - roll dice, e.g. /roll 2d6+1", roll);
+    registry.register("flip", "/flip - flip a coin", flip);
+    registry.register("8ball", "/8ball <question> - ask the magic 8-ball", eight_ball);
+}
+
+struct DiceSpec {
+    count: u32,
+    sides: u32,
+    modifier: i64,
+}
+
+// Parses "2d6", "d20", "3d8+2" or "1d10-1"
+fn parse_dice(spec: &str) -> Option<DiceSpec> {
+    let spec = spec.to_ascii_lowercase();
+    let (count, rest) = spec.split_once('d')?;
+    let count = if count.is_empty() { 1 } else { count.parse().ok()? };
+
+    let (sides, modifier) = match rest.find(['+', '-']) {
+        Some(pos) => (&rest[..pos], rest[pos..].parse().ok()?),
+        None => (rest, 0),
+    };
+    let sides = sides.parse().ok()?;
+
+    if !(1..=MAX_DICE).contains(&count)
+        || !(2..=MAX_SIDES).contains(&sides)
+        || !(-MAX_MODIFIER..=MAX_MODIFIER).contains(&modifier)
+    {
+        return None;
+    }
+    Some(DiceSpec { count, sides, modifier })
+}
+
+fn roll(ctx: &CommandContext) -> CommandOutput {
+    let spec = if ctx.args.is_empty() { "1d6" } else { ctx.args };
+    let usage = || {
+        CommandOutput::error(ErrorCode::InvalidArguments, format!(
+            "Usage: /roll NdM[+K], with up to {} dice of {} sides and K up to {}",
+            MAX_DICE, MAX_SIDES, MAX_MODIFIER
+        ))
+    };
+    let Some(dice) = parse_dice(spec) else {
+        return usage();
+    };
+
+    let mut rng = rand::rng();
+    let rolls: Vec<u32> = (0..dice.count).map(|_| rng.random_range(1..=dice.sides)).collect();
+    let Some(total) = rolls.iter().map(|&r| r as i64).sum::<i64>().checked_add(dice.modifier) else {
+        return usage();
+    };
+
+    let rolls = rolls.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", ");
+    let content = match dice.modifier {
+        0 => format!("{} rolled {}: [{}] = {}", ctx.user.nickname, spec, rolls, total),
+        m => format!("{} rolled {}: [{}] {:+} = {}", ctx.user.nickname, spec, rolls, m, total),
+    };
+    CommandOutput::bot(BOT_NAME, content)
+}
+
+fn flip(ctx: &CommandContext) -> CommandOutput {
+    let side = if rand::rng().random_bool(0.5) { "heads" } else { "tails" };
+    CommandOutput::bot(BOT_NAME, format!("{} flipped a coin: {}", ctx.user.nickname, side))
+}
+
+fn eight_ball(ctx: &CommandContext) -> CommandOutput {
+    if ctx.args.is_empty() {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /8ball <question>");
+    }
+
+    let answer = EIGHT_BALL_ANSWERS.choose(&mut rand::rng()).copied().unwrap_or("Ask again later.");
+    CommandOutput::bot(BOT_NAME, format!("{} asked \"{}\" - {}", ctx.user.nickname, ctx.args, answer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dice() {
+        let dice = parse_dice("3D8+2").unwrap();
+        assert_eq!((dice.count, dice.sides, dice.modifier), (3, 8, 2));
+        let dice = parse_dice("d20").unwrap();
+        assert_eq!((dice.count, dice.sides, dice.modifier), (1, 20, 0));
+        let dice = parse_dice("1d10-1").unwrap();
+        assert_eq!(dice.modifier, -1);
+    }
+
+    #[test]
+    fn rejects_out_of_range_dice() {
+        assert!(parse_dice("0d6").is_none());
+        assert!(parse_dice("101d6").is_none());
+        assert!(parse_dice("1d1").is_none());
+        assert!(parse_dice("1d1001").is_none());
+        assert!(parse_dice("2x6").is_none());
+        assert!(parse_dice("2d6+").is_none());
+    }
+
+    #[test]
+    fn bounds_the_modifier() {
+        assert_eq!(parse_dice("1d6+100000").unwrap().modifier, MAX_MODIFIER);
+        assert_eq!(parse_dice("1d6-100000").unwrap().modifier, -MAX_MODIFIER);
+        assert!(parse_dice("1d6+100001").is_none());
+        assert!(parse_dice("1d6+9223372036854775807").is_none());
+        assert!(parse_dice("1d6-9223372036854775808").is_none());
+        assert!(parse_dice("1d6+99999999999999999999").is_none());
+    }
+}
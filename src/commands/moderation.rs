@@ -0,0 +1,58 @@
+// Room moderation commands for moderators and admins
+
+use chrono::{Duration, Utc};
+
+use crate::CHAT_STATE;
+use crate::accounts::ACCOUNTS;
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::ErrorCode;
+
+const DEFAULT_MUTE_MINS: i64 = 10;
+const MAX_MUTE_MINS: i64 = 7 * 24 * 60;
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("mute", "/mute <nickname> [minutes] - stop a user posting for a while (moderators)", mute);
+    registry.register("unmute", "/unmute <nickname> - let a muted user post again (moderators)", unmute);
+}
+
+fn mute(ctx: &CommandContext) -> CommandOutput {
+    let mut args = ctx.args.split_whitespace();
+    let Some(nickname) = args.next() else {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /mute <nickname> [minutes]");
+    };
+    let minutes = match args.next().map(str::parse::<i64>) {
+        None => DEFAULT_MUTE_MINS,
+        Some(Ok(minutes)) if (1..=MAX_MUTE_MINS).contains(&minutes) => minutes,
+        Some(_) => {
+            return CommandOutput::error(ErrorCode::InvalidArguments, format!("Minutes must be between 1 and {}", MAX_MUTE_MINS));
+        },
+    };
+
+    let room_state = CHAT_STATE.get_or_create_room(&ctx.user.room_id);
+    let mut config = room_state.config.write();
+    if !config.is_moderator(ctx.user.account_id.as_deref()) {
+        return CommandOutput::error(ErrorCode::Forbidden, "Only moderators can mute users");
+    }
+    // Registered nicknames can only be used by their account
+    let target = ACCOUNTS.find(nickname).map(|account| account.id);
+    if config.is_moderator(target.as_deref()) {
+        return CommandOutput::error(ErrorCode::Forbidden, "Moderators can't be muted");
+    }
+    config.muted.insert(nickname.to_lowercase(), Utc::now() + Duration::minutes(minutes));
+    CommandOutput::Reply(format!("Muted {} for {} minutes", nickname, minutes))
+}
+
+fn unmute(ctx: &CommandContext) -> CommandOutput {
+    if ctx.args.is_empty() {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /unmute <nickname>");
+    }
+    let room_state = CHAT_STATE.get_or_create_room(&ctx.user.room_id);
+    let mut config = room_state.config.write();
+    if !config.is_moderator(ctx.user.account_id.as_deref()) {
+        return CommandOutput::error(ErrorCode::Forbidden, "Only moderators can unmute users");
+    }
+    match config.muted.remove(&ctx.args.to_lowercase()) {
+        Some(_) => CommandOutput::Reply(format!("Unmuted {}", ctx.args)),
+        None => CommandOutput::Reply(format!("{} isn't muted", ctx.args)),
+    }
+}
@@ -0,0 +1,675 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use rocket::data::{Limits, ToByteUnit};
+use rocket::figment::Figment;
+use rocket::figment::providers::{Env, Format, Serialized, Toml};
+use rocket::serde::{Deserialize, Serialize};
+
+// Server settings, read from WhoChat.toml (or the file named by
+// WHOCHAT_CONFIG) and overridden by WHOCHAT_* environment variables
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Bearer token required by the /api/admin routes; admin API is disabled when unset
+    pub admin_token: Option<String>,
+    // JSON file with trivia questions; a small built-in bank is used when unset
+    pub trivia_questions: Option<PathBuf>,
+    // How long players get to answer each trivia question
+    pub trivia_answer_secs: u64,
+    // Where persisted state (accounts, snapshots, logs, template overrides)
+    // is written; also settable with --data-dir
+    pub data_dir: PathBuf,
+    // Where backups taken through the admin API are written
+    pub backup_dir: PathBuf,
+    // Save whiteboards to the data directory so they survive restarts
+    pub whiteboard_snapshots: bool,
+    // Longest time-to-live accepted for burner rooms
+    pub max_room_ttl_secs: u64,
+    // Messages each user may send per UTC day; unlimited when unset
+    pub daily_message_quota: Option<u32>,
+    // Longest message accepted, in characters
+    pub max_message_len: usize,
+    // Messages a user may send to a room within rate_limit_secs
+    pub rate_limit_messages: usize,
+    pub rate_limit_secs: u64,
+    // Messages visitors see when previewing a public room before joining;
+    // 0 turns previews off
+    pub preview_messages: usize,
+    // Room previews each address may load per minute
+    pub preview_rate_limit: usize,
+    // Key for signing compliance audit logs; generated and kept in the data
+    // directory when unset
+    pub audit_key: Option<String>,
+    // How long the nickname of a deleted account stays unusable
+    pub nickname_quarantine_secs: u64,
+    // How long someone keeps a pseudonym in anonymous rooms
+    pub anonymous_rotation_secs: u64,
+    // Leading zero bits of proof-of-work asked of guests before they join,
+    // see src/pow.rs; 0 turns it off
+    pub guest_pow_difficulty: u32,
+    // Room nobody is told about; whoever joins it is banned as a bot, see
+    // src/honeypot.rs
+    pub honeypot_room: Option<String>,
+    // How long addresses caught in the honeypot stay banned
+    pub honeypot_ban_secs: u64,
+    // Reverse proxies whose X-Forwarded-For header is believed
+    pub trusted_proxies: Vec<IpAddr>,
+    // Serve everything under this path, e.g. "/chat", when the proxy forwards
+    // a sub-path to us
+    pub path_prefix: String,
+    // Publish sitemap.xml and robots.txt so search engines find listed rooms
+    pub sitemap: bool,
+    // JSON fields blanked out of recorded WebSocket sessions
+    pub recording_redact: Vec<String>,
+    // Port the WebSocket server listens on
+    pub ws_port: u16,
+    // Open WebSocket connections beyond this are turned away
+    pub ws_max_connections: usize,
+    // Outgoing frames the WebSocket server can have pending, per connection
+    // on average; a burst of broadcasts beyond ws_max_connections times this
+    // stalls the server until they drain
+    pub ws_queue_size: usize,
+    // WebSocket URL handed to clients, e.g. "wss://chat.example.com/ws", when
+    // it can't be worked out from the request
+    pub ws_public_url: Option<String>,
+    // Address people reach the chat at, e.g. "https://chat.example.com", for
+    // links that leave the browser such as permalinks in webhooks
+    pub public_url: Option<String>,
+    // Conference link /meet invites the room to; {room} is replaced with the
+    // room id and {slug} with a random id per call
+    pub meet_url: String,
+    // HTTP server settings, as an [http] table
+    pub http: HttpConfig,
+    // Directory with templates replacing the built-in ones; defaults to
+    // templates/ under the data directory
+    pub template_dir: Option<PathBuf>,
+    // Branding for the built-in pages, as a [theme] table
+    pub theme: ThemeConfig,
+    // Response security headers, as a [security] table
+    pub security: SecurityConfig,
+    // Installing the chat as a web app, as a [pwa] table
+    pub pwa: PwaConfig,
+    // Directory to authenticate accounts against, as an [ldap] table
+    #[cfg(feature = "ldap")]
+    pub ldap: Option<LdapConfig>,
+    // Broker to bridge rooms to, as an [mqtt] table
+    #[cfg(feature = "mqtt")]
+    pub mqtt: Option<MqttConfig>,
+    // Mail posted into rooms by an email provider, as an [email] table
+    pub email: Option<EmailConfig>,
+    // Bucket attachments are kept in instead of the data directory, as an
+    // [s3] table
+    pub s3: Option<S3Config>,
+    // Signed links to attachments, as a [media] table
+    pub media: MediaConfig,
+    // Provider for rooms with a language set, as a [translation] table
+    pub translation: Option<TranslationConfig>,
+    // Activity ranks earned in each room, lowest first, as [[ranks]] tables;
+    // empty turns ranks off
+    pub ranks: Vec<RankConfig>,
+    // Feature flags turned on or off by name, see src/flags.rs
+    pub flags: HashMap<String, bool>,
+    // Telling moderators about abuse, as an [escalation] table
+    pub escalation: EscalationConfig,
+    // How long history is kept, as a [retention] table
+    pub retention: RetentionConfig,
+    // Moving old history out of memory, see src/archive.rs; unset keeps it all in
+    pub archive_after_days: Option<u64>,
+    // Encrypts the keys stored history is encrypted with, see
+    // src/encryption.rs; unset stores it in the clear
+    pub master_key: Option<String>,
+    // Where "secret:<name>" values are looked up, as a [secrets] table; see
+    // src/secrets.rs
+    pub secrets: SecretsConfig,
+    // Backoff and lockout after failed password sign-ins, as a
+    // [login_throttle] table
+    pub login_throttle: LoginThrottleConfig,
+}
+
+// Transport settings passed on to Rocket, so Rocket.toml isn't needed. Set
+// from WhoChat.toml or e.g. WHOCHAT_HTTP__PORT.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub address: IpAddr,
+    pub port: u16,
+    // Request-handling threads; Rocket uses one per CPU core when unset
+    pub workers: Option<usize>,
+    // How long idle connections stay open, in seconds; 0 turns keep-alive off.
+    // Longer than Rocket's default since chat pages keep calling the API.
+    pub keep_alive_secs: u32,
+    // Largest accepted bodies, in KiB
+    pub form_limit_kib: u64,
+    pub json_limit_kib: u64,
+    // Plain-text bodies, such as scripts uploaded through the admin API
+    pub text_limit_kib: u64,
+    // Multipart bodies with files, such as inbound email
+    pub upload_limit_kib: u64,
+    // PEM certificate chain and private key; serving TLS also enables HTTP/2
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    // Key private cookies are encrypted with, usually "secret:<name>";
+    // Rocket's own secret_key setting is used when unset
+    pub secret_key: Option<String>,
+    // The key before the last rotation; cookies made with it are still
+    // accepted, see src/cookie_keys.rs
+    pub previous_secret_key: Option<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8000,
+            workers: None,
+            keep_alive_secs: 30,
+            form_limit_kib: 64,
+            json_limit_kib: 1024,
+            text_limit_kib: 256,
+            upload_limit_kib: 10 * 1024,
+            tls_cert: None,
+            tls_key: None,
+            secret_key: None,
+            previous_secret_key: None,
+        }
+    }
+}
+
+impl HttpConfig {
+    // Rocket's own configuration with these settings on top
+    pub fn figment(&self) -> Figment {
+        let limits = Limits::default()
+            .limit("form", self.form_limit_kib.kibibytes())
+            .limit("json", self.json_limit_kib.kibibytes())
+            .limit("string", self.text_limit_kib.kibibytes())
+            .limit("data-form", self.upload_limit_kib.kibibytes())
+            .limit("file", self.upload_limit_kib.kibibytes());
+
+        let mut figment = rocket::Config::figment()
+            .merge(("address", self.address))
+            .merge(("port", self.port))
+            .merge(("keep_alive", self.keep_alive_secs))
+            .merge(("limits", limits));
+        if let Some(workers) = self.workers {
+            figment = figment.merge(("workers", workers));
+        }
+        if let Some(secret_key) = &self.secret_key {
+            figment = figment.merge(("secret_key", crate::secrets::resolve(secret_key)));
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => figment.merge(("tls.certs", cert)).merge(("tls.key", key)),
+            (None, None) => figment,
+            _ => {
+                eprintln!("TLS needs both http.tls_cert and http.tls_key, serving plain HTTP");
+                figment
+            },
+        }
+    }
+}
+
+// Passed to every page as `theme`; colors are any CSS color
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    // Site name, shown in page titles
+    pub name: String,
+    pub logo_url: Option<String>,
+    pub primary_color: String,
+    pub primary_hover_color: String,
+    pub background_color: String,
+    pub text_color: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            name: "WhoChat".to_string(),
+            logo_url: None,
+            primary_color: "#4CAF50".to_string(),
+            primary_hover_color: "#45a049".to_string(),
+            background_color: "#f5f5f5".to_string(),
+            text_color: "#333".to_string(),
+        }
+    }
+}
+
+// Web app manifest and service worker, see src/pwa.rs. The app's name is
+// the theme name; the icon and color default to the theme's.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PwaConfig {
+    pub enabled: bool,
+    // Shown under the home screen icon
+    pub short_name: Option<String>,
+    pub description: String,
+    // Square image, ideally 512x512 or SVG
+    pub icon_url: Option<String>,
+    pub theme_color: Option<String>,
+}
+
+impl Default for PwaConfig {
+    fn default() -> Self {
+        PwaConfig {
+            enabled: true,
+            short_name: None,
+            description: "Chat rooms".to_string(),
+            icon_url: None,
+            theme_color: None,
+        }
+    }
+}
+
+// When src/escalation.rs alerts moderators; 0 turns a trigger off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EscalationConfig {
+    // Open reports against someone in a room
+    pub report_threshold: usize,
+    // Posts turned away as spam within spam_window_secs
+    pub spam_strikes: usize,
+    pub spam_window_secs: u64,
+    // Least time between alerts about the same person for the same reason
+    pub cooldown_secs: u64,
+    // Also POST alerts here, signed with webhook_secret
+    pub webhook_url: Option<String>,
+    pub webhook_secret: String,
+}
+
+impl Default for EscalationConfig {
+    fn default() -> Self {
+        EscalationConfig {
+            report_threshold: 3,
+            spam_strikes: 5,
+            spam_window_secs: 60,
+            cooldown_secs: 300,
+            webhook_url: None,
+            webhook_secret: String::new(),
+        }
+    }
+}
+
+// How src/login_throttle.rs slows down password guessing; 0 for a lockout
+// threshold turns that lockout off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginThrottleConfig {
+    // Failures allowed before tries have to wait
+    pub free_attempts: u32,
+    pub backoff_base_secs: u64,
+    pub backoff_max_secs: u64,
+    pub account_lockout_after: u32,
+    // Higher than for accounts, as many people can share an address
+    pub ip_lockout_after: u32,
+    pub lockout_secs: u64,
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        LoginThrottleConfig {
+            free_attempts: 3,
+            backoff_base_secs: 1,
+            backoff_max_secs: 300,
+            account_lockout_after: 10,
+            ip_lockout_after: 50,
+            lockout_secs: 15 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretsProviderKind {
+    Env,
+    File,
+    Vault,
+}
+
+// Where src/secrets.rs looks secrets up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    pub provider: SecretsProviderKind,
+    // For the file provider, e.g. "/run/secrets"
+    pub dir: PathBuf,
+    // For the vault provider; the token falls back to VAULT_TOKEN
+    pub vault_url: String,
+    pub vault_token: Option<String>,
+    pub vault_path: String,
+    // How often secrets in use are read again, to pick up rotations
+    pub refresh_secs: u64,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        SecretsConfig {
+            provider: SecretsProviderKind::Env,
+            dir: PathBuf::from("/run/secrets"),
+            vault_url: "http://127.0.0.1:8200".to_string(),
+            vault_token: None,
+            vault_path: "secret/data/who-chat".to_string(),
+            refresh_secs: 300,
+        }
+    }
+}
+
+// Hours stored history is kept, by kind of message; unset keeps it. Rooms
+// can set their own, where 0 keeps it; see src/retention.rs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    // What people send, including locations and calls
+    pub messages_hours: Option<u64>,
+    // Join and leave notices and other system messages
+    pub system_hours: Option<u64>,
+    pub bot_hours: Option<u64>,
+    // Files attached to messages; the message itself follows messages_hours
+    pub attachments_hours: Option<u64>,
+}
+
+// Headers set by src/security.rs; unset or empty values leave a header out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityConfig {
+    pub content_security_policy: Option<String>,
+    // Send X-Frame-Options: DENY, except under frame_exempt_paths
+    pub deny_framing: bool,
+    pub frame_exempt_paths: Vec<String>,
+    pub referrer_policy: Option<String>,
+    // Only sent on HTTPS requests
+    pub hsts_max_age_secs: Option<u64>,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig {
+            // Inline styles are still needed for highlighted code blocks;
+            // link previews may show images from anywhere
+            content_security_policy: Some(
+                "default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline'; \
+                 img-src 'self' data: https:; connect-src 'self' ws: wss:; object-src 'none'; \
+                 base-uri 'self'; form-action 'self'"
+                    .to_string(),
+            ),
+            deny_framing: true,
+            frame_exempt_paths: vec!["/embed".to_string()],
+            referrer_policy: Some("strict-origin-when-cross-origin".to_string()),
+            hsts_max_age_secs: Some(180 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[cfg(feature = "ldap")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LdapConfig {
+    // e.g. ldaps://ldap.example.com
+    pub url: String,
+    // DN to bind as, with {username} filled in, e.g.
+    // "uid={username},ou=people,dc=example,dc=com", or "{username}@example.com"
+    // for Active Directory
+    pub bind_dn: String,
+    // Where to find the user's entry for its memberOf groups; roles aren't
+    // looked up when unset
+    #[serde(default)]
+    pub user_base: Option<String>,
+    #[serde(default = "default_user_filter")]
+    pub user_filter: String,
+    // Group DN or cn -> role granted in every room
+    #[serde(default)]
+    pub group_roles: HashMap<String, crate::Role>,
+}
+
+#[cfg(feature = "ldap")]
+fn default_user_filter() -> String {
+    "(|(uid={username})(sAMAccountName={username}))".to_string()
+}
+
+#[cfg(feature = "mqtt")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    // Name incoming payloads are posted under
+    #[serde(default = "default_mqtt_sender")]
+    pub sender: String,
+    // Topic filter (with + and # wildcards) -> room its payloads go to
+    #[serde(default)]
+    pub subscribe: HashMap<String, String>,
+    // Room -> topic its messages are published to
+    #[serde(default)]
+    pub publish: HashMap<String, String>,
+}
+
+#[cfg(feature = "mqtt")]
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+#[cfg(feature = "mqtt")]
+fn default_mqtt_client_id() -> String {
+    "who-chat".to_string()
+}
+
+#[cfg(feature = "mqtt")]
+fn default_mqtt_sender() -> String {
+    "MQTT".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    // Mail to <room>@<domain> is posted in the room
+    pub domain: String,
+    // Mailgun's HTTP webhook signing key; unsigned or stale posts are refused
+    pub signing_key: String,
+    // Rooms that take mail; others bounce
+    #[serde(default)]
+    pub rooms: Vec<String>,
+    // Name emails are posted under
+    #[serde(default = "default_email_sender")]
+    pub sender: String,
+}
+
+fn default_email_sender() -> String {
+    "Email".to_string()
+}
+
+// Links to files in the data directory, see src/media.rs. Files in an S3
+// bucket get the bucket's own presigned links instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MediaConfig {
+    // Signing keys, newest first; empty leaves links unsigned and permanent
+    pub keys: Vec<String>,
+    pub url_ttl_secs: u64,
+    // CDN in front of the chat, e.g. "https://cdn.example.com"
+    pub base_url: Option<String>,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig {
+            keys: Vec::new(),
+            url_ttl_secs: 3600,
+            base_url: None,
+        }
+    }
+}
+
+// An S3 bucket or an S3-compatible store like MinIO, see src/s3.rs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Config {
+    // e.g. "https://s3.eu-west-1.amazonaws.com" or "http://minio:9000"
+    pub endpoint: String,
+    // Endpoint put in the URLs handed to browsers, when they can't reach
+    // `endpoint`
+    #[serde(default)]
+    pub public_endpoint: Option<String>,
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    // Put in front of every object key
+    #[serde(default = "default_s3_prefix")]
+    pub prefix: String,
+    // https://endpoint/bucket/key rather than https://bucket.endpoint/key,
+    // which MinIO and most other S3-compatible stores need
+    #[serde(default = "default_true")]
+    pub path_style: bool,
+    // How long presigned URLs work
+    #[serde(default = "default_s3_url_ttl")]
+    pub url_ttl_secs: u64,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_s3_prefix() -> String {
+    "attachments/".to_string()
+}
+
+fn default_s3_url_ttl() -> u64 {
+    3600
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranslationConfig {
+    // Base URL of a LibreTranslate-compatible API
+    pub url: String,
+    pub api_key: Option<String>,
+}
+
+// A rank reached with at least min_messages messages in a room over at
+// least min_days since the first, see src/ranks.rs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankConfig {
+    pub name: String,
+    #[serde(default)]
+    pub min_messages: u64,
+    #[serde(default)]
+    pub min_days: u64,
+}
+
+impl RankConfig {
+    fn new(name: &str, min_messages: u64, min_days: u64) -> Self {
+        RankConfig { name: name.to_string(), min_messages, min_days }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            admin_token: None,
+            trivia_questions: None,
+            trivia_answer_secs: 30,
+            data_dir: PathBuf::from("data"),
+            backup_dir: PathBuf::from("backups"),
+            whiteboard_snapshots: false,
+            max_room_ttl_secs: 7 * 24 * 60 * 60,
+            daily_message_quota: None,
+            max_message_len: 4000,
+            rate_limit_messages: 10,
+            rate_limit_secs: 10,
+            preview_messages: 20,
+            preview_rate_limit: 20,
+            audit_key: None,
+            nickname_quarantine_secs: 30 * 24 * 60 * 60,
+            anonymous_rotation_secs: 60 * 60,
+            guest_pow_difficulty: 0,
+            honeypot_room: None,
+            honeypot_ban_secs: 7 * 24 * 60 * 60,
+            trusted_proxies: Vec::new(),
+            path_prefix: String::new(),
+            sitemap: false,
+            recording_redact: ["ticket", "token", "password", "secret"].map(String::from).to_vec(),
+            ws_port: 8082,
+            ws_max_connections: 100,
+            ws_queue_size: 5,
+            ws_public_url: None,
+            public_url: None,
+            meet_url: "https://meet.jit.si/{room}-{slug}".to_string(),
+            http: HttpConfig::default(),
+            template_dir: None,
+            theme: ThemeConfig::default(),
+            security: SecurityConfig::default(),
+            pwa: PwaConfig::default(),
+            #[cfg(feature = "ldap")]
+            ldap: None,
+            #[cfg(feature = "mqtt")]
+            mqtt: None,
+            email: None,
+            s3: None,
+            media: MediaConfig::default(),
+            translation: None,
+            ranks: vec![
+                RankConfig::new("newcomer", 0, 0),
+                RankConfig::new("regular", 50, 7),
+                RankConfig::new("veteran", 500, 90),
+            ],
+            flags: HashMap::new(),
+            escalation: EscalationConfig::default(),
+            retention: RetentionConfig::default(),
+            archive_after_days: None,
+            master_key: None,
+            secrets: SecretsConfig::default(),
+            login_throttle: LoginThrottleConfig::default(),
+        }
+    }
+}
+
+// `--data-dir <path>` (or `--data-dir=<path>`) on the command line
+fn data_dir_arg() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--data-dir" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--data-dir=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+// WHOCHAT_CONFIG, or WhoChat.toml in the working directory
+pub fn path() -> PathBuf {
+    PathBuf::from(std::env::var("WHOCHAT_CONFIG").unwrap_or_else(|_| "WhoChat.toml".to_string()))
+}
+
+impl Config {
+    fn load() -> Self {
+        let path = path();
+
+        let mut config: Config = Figment::from(Serialized::defaults(Config::default()))
+            .merge(Toml::file(path))
+            .merge(Env::prefixed("WHOCHAT_").ignore(&["config"]).split("__"))
+            .extract()
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid configuration, using defaults: {}", err);
+                Config::default()
+            });
+        // The command line wins over the config file and environment
+        if let Some(data_dir) = data_dir_arg() {
+            config.data_dir = data_dir;
+        }
+        config
+    }
+}
+
+lazy_static! {
+    pub static ref CONFIG: Config = Config::load();
+}
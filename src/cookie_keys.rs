@@ -0,0 +1,57 @@
+// Private cookies across a change of http.secret_key. Rocket only decrypts
+// cookies with the current key, so a new key on a deploy would sign
+// everyone out at once. With http.previous_secret_key set, or a
+// "secret:<name>" key whose <name>_previous is set (see secrets.rs), cookies
+// made with the old key are still read, and sent back under the new one as
+// they are; once sessions have had time to come back the old key can go.
+
+use lazy_static::lazy_static;
+use rocket::http::private::cookie::Key;
+use rocket::http::{Cookie, CookieJar};
+
+use crate::config::CONFIG;
+use crate::secrets;
+
+// As Rocket reads secret_key: 256 bits of material or a 512-bit master key,
+// in base64 or hex
+fn parse_key(value: &str) -> Option<Key> {
+    let bytes = match value.len() {
+        44 | 88 => data_encoding::BASE64.decode(value.as_bytes()).ok()?,
+        64 => data_encoding::HEXLOWER_PERMISSIVE.decode(value.as_bytes()).ok()?,
+        _ => return None,
+    };
+    match bytes.len() {
+        64 => Some(Key::from(&bytes)),
+        32 => Some(Key::derive_from(&bytes)),
+        _ => None,
+    }
+}
+
+fn previous_key() -> Option<Key> {
+    let previous = match &CONFIG.http.previous_secret_key {
+        Some(previous) => secrets::resolve(previous),
+        None => secrets::accepted(CONFIG.http.secret_key.as_deref()?).into_iter().nth(1)?,
+    };
+    let key = parse_key(&previous);
+    if key.is_none() {
+        eprintln!("http.previous_secret_key isn't a 256-bit base64 or hex key, ignoring it");
+    }
+    key
+}
+
+lazy_static! {
+    // Read once, like the current key
+    static ref PREVIOUS_KEY: Option<Key> = previous_key();
+}
+
+// Use instead of `cookies.get_private(name)`
+pub fn get_private(cookies: &CookieJar<'_>, name: &str) -> Option<Cookie<'static>> {
+    if let Some(cookie) = cookies.get_private(name) {
+        return Some(cookie);
+    }
+    let key = PREVIOUS_KEY.as_ref()?;
+    let sealed = cookies.get(name)?.clone();
+    let cookie = rocket::http::private::cookie::CookieJar::new().private(key).decrypt(sealed)?;
+    cookies.add_private(Cookie::new(name.to_string(), cookie.value().to_string()));
+    Some(cookie)
+}
@@ -0,0 +1,221 @@
+// Dashboard of every room a signed-in account is in, for a sidebar of rooms
+// with unread badges instead of one tab per room. Rooms are added when the
+// account joins one over the WebSocket and stay until taken off:
+//
+//   GET    /dashboard                        the page
+//   GET    /api/dashboard                    {"rooms": [{"room_id", "unread", "users",
+//                                              "last_message": {...} | null, "url",
+//                                              "icon", "icon_url", "accent_color"}, ...],
+//                                             "unread": <total>}
+//   DELETE /api/dashboard/rooms/<room_id>    takes a room off
+//
+// The page keeps its badges live through one WebSocket for all the rooms,
+// opened at `<ws url>/.dashboard?ticket=<ticket>` with the ticket the page
+// hands out. It gets the list as {"type": "rooms", "rooms": [...]} when it
+// connects, then {"type": "room", "room": {...one entry...}} whenever a
+// message comes in or the account reads a room elsewhere. Nothing is sent
+// back on it.
+
+use std::collections::{BTreeSet, HashMap};
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Either::{self, Left, Right};
+use rocket::Route;
+use rocket::http::{ContentType, Status};
+use rocket::response::Redirect;
+use rocket::serde::json::{Json, Value};
+use rocket_dyn_templates::{Template, context};
+use serde_json::json;
+use uuid::Uuid;
+use ws::{CloseCode, Sender};
+
+use crate::accounts::{ACCOUNTS, Account, AccountSession};
+use crate::admin::{ApiResult, api_error};
+use crate::appearance;
+use crate::config::CONFIG;
+use crate::sessions::SESSIONS;
+use crate::{CHAT_STATE, RoomState, actions, inbox, proxy, storage};
+
+// WebSocket path of the subscription; not a valid room id, so no room can
+// take it
+pub const WS_PATH: &str = "/.dashboard";
+
+struct Subscriber {
+    account_id: String,
+    sender: Sender,
+}
+
+lazy_static! {
+    // account id -> room ids
+    static ref ROOMS: RwLock<HashMap<String, BTreeSet<String>>> =
+        RwLock::new(storage::load("dashboard", "rooms").unwrap_or_default());
+    // ticket -> (account id, session id)
+    static ref TICKETS: RwLock<HashMap<String, (String, String)>> = RwLock::new(HashMap::new());
+    static ref SUBSCRIBERS: RwLock<Vec<Subscriber>> = RwLock::new(Vec::new());
+}
+
+fn save(all: &HashMap<String, BTreeSet<String>>) {
+    if let Err(err) = storage::save("dashboard", "rooms", all) {
+        eprintln!("Failed to save dashboard rooms: {}", err);
+    }
+}
+
+// Adds the room to the account's dashboard, when it isn't there yet
+pub fn remember(account_id: &str, room_id: &str) {
+    let mut all = ROOMS.write();
+    if all.entry(account_id.to_string()).or_default().insert(room_id.to_string()) {
+        save(&all);
+    }
+}
+
+fn room_ids(account_id: &str) -> BTreeSet<String> {
+    ROOMS.read().get(account_id).cloned().unwrap_or_default()
+}
+
+// Rooms that aren't loaded show as quiet rather than being opened
+fn entry(me: &Account, room_id: &str) -> Value {
+    let room = CHAT_STATE.rooms.read().get(room_id).cloned();
+    let mut entry = json!({
+        "room_id": room_id,
+        "unread": room.as_ref().map_or(0, |room| actions::account_unread(&me.id, &me.username, room)),
+        "users": room.as_ref().map_or(0, |room| room.users.read().len()),
+        "last_message": room.as_ref().and_then(|room| inbox::last_message(me, room)).as_ref().map(inbox::summary),
+        "url": proxy::url(format!("/?rid={}", room_id)),
+    });
+    if let Some(room) = &room {
+        appearance::extend(&mut entry, &room.config.read());
+    }
+    entry
+}
+
+// The account's rooms, leaving out private ones it's no longer a member of
+fn rooms(me: &Account) -> Vec<Value> {
+    room_ids(&me.id)
+        .iter()
+        .filter(|room_id| {
+            let room = CHAT_STATE.rooms.read().get(room_id.as_str()).cloned();
+            room.is_none_or(|room| room.config.read().admits(Some(&me.id)))
+        })
+        .map(|room_id| entry(me, room_id))
+        .collect()
+}
+
+fn total_unread(rooms: &[Value]) -> u64 {
+    rooms.iter().filter_map(|room| room["unread"].as_u64()).sum()
+}
+
+// Reuses the session's ticket so reloading the page doesn't pile them up
+fn issue_ticket(session: &AccountSession) -> String {
+    let mut tickets = TICKETS.write();
+    let owner = (session.0.id.clone(), session.1.clone());
+    if let Some((ticket, _)) = tickets.iter().find(|(_, held)| **held == owner) {
+        return ticket.clone();
+    }
+    let ticket = Uuid::new_v4().to_string();
+    tickets.insert(ticket.clone(), owner);
+    ticket
+}
+
+// Opens a subscription for the connection at WS_PATH; tickets of signed-out
+// sessions are turned away
+pub fn subscribe(sender: &Sender, query: &str) {
+    let owner = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("ticket="))
+        .and_then(|ticket| TICKETS.read().get(ticket).cloned());
+    let account = owner
+        .filter(|(account_id, session_id)| SESSIONS.for_account(account_id).iter().any(|session| session.id == *session_id))
+        .and_then(|(account_id, _)| ACCOUNTS.get(&account_id));
+    let Some(account) = account else {
+        let _ = sender.close(CloseCode::Policy);
+        return;
+    };
+
+    SUBSCRIBERS.write().push(Subscriber {
+        account_id: account.id.clone(),
+        sender: sender.clone(),
+    });
+    let _ = sender.send(json!({ "type": "rooms", "rooms": rooms(&account) }).to_string());
+}
+
+pub fn unsubscribe(sender: &Sender) {
+    SUBSCRIBERS
+        .write()
+        .retain(|subscriber| subscriber.sender.connection_id() != sender.connection_id());
+}
+
+// Sends the room's entry to the subscribers it's on the dashboard of, or
+// only to `account_id`'s
+fn update(room: &RoomState, account_id: Option<&str>) {
+    let subscribers = SUBSCRIBERS.read();
+    if subscribers.is_empty() {
+        return;
+    }
+    let all = ROOMS.read();
+    let mut frames: HashMap<&str, String> = HashMap::new();
+    for subscriber in subscribers.iter() {
+        let watching = account_id.is_none_or(|id| id == subscriber.account_id)
+            && all.get(&subscriber.account_id).is_some_and(|rooms| rooms.contains(room.id.as_ref()));
+        if !watching {
+            continue;
+        }
+        let frame = frames.entry(&subscriber.account_id).or_insert_with(|| {
+            let entry = ACCOUNTS.get(&subscriber.account_id).map(|me| entry(&me, &room.id));
+            json!({ "type": "room", "room": entry }).to_string()
+        });
+        let _ = subscriber.sender.send(frame.as_str());
+    }
+}
+
+// A message came in
+pub fn room_changed(room: &RoomState) {
+    update(room, None);
+}
+
+// The account read or cleared the room somewhere
+pub fn room_read(account_id: &str, room: &RoomState) {
+    update(room, Some(account_id));
+}
+
+#[rocket::get("/api/dashboard")]
+fn list(session: AccountSession) -> Json<Value> {
+    let rooms = rooms(&session.0);
+    Json(json!({
+        "unread": total_unread(&rooms),
+        "rooms": rooms,
+    }))
+}
+
+#[rocket::delete("/api/dashboard/rooms/<room_id>")]
+fn forget(session: AccountSession, room_id: &str) -> ApiResult {
+    let mut all = ROOMS.write();
+    let removed = all.get_mut(&session.0.id).is_some_and(|rooms| rooms.remove(room_id));
+    if !removed {
+        return Err(api_error(Status::NotFound, "That room isn't on your dashboard"));
+    }
+    save(&all);
+    Ok(Json(json!({ "removed": room_id })))
+}
+
+#[rocket::get("/dashboard")]
+fn page(session: Option<AccountSession>) -> Either<(ContentType, Template), Redirect> {
+    // Only accounts are remembered across rooms
+    let Some(session) = session else {
+        return Right(Redirect::to(proxy::url("/")));
+    };
+    let rooms = rooms(&session.0);
+    Left((ContentType::HTML, Template::render("dashboard", context! {
+        title: "Your rooms",
+        nickname: &session.0.username,
+        unread: total_unread(&rooms),
+        rooms,
+        ws_ticket: issue_ticket(&session),
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, forget, page]
+}
@@ -0,0 +1,41 @@
+// Room directory page at /rooms: the listed rooms, busiest first, with
+// their topics and tags. Clicking a tag narrows the list to rooms with it
+// (`/rooms?tag=games`, or several with `tag=games,eu`), the same filter the
+// discovery API takes.
+
+use rocket::Route;
+use rocket::http::ContentType;
+use rocket_dyn_templates::{Template, context};
+use serde_json::{Value, json};
+
+use crate::config::CONFIG;
+use crate::proxy;
+use crate::rooms::{discoverable, tag_counts, tag_filter};
+
+#[rocket::get("/?<tag>")]
+fn page(tag: Option<&str>) -> (ContentType, Template) {
+    let selected = tag_filter(tag);
+    let rooms: Vec<Value> = discoverable(false, &selected)
+        .into_iter()
+        .map(|room| room.to_json())
+        .collect();
+    let tags: Vec<Value> = tag_counts(false)
+        .into_iter()
+        .map(|(tag, rooms)| json!({ "tag": tag, "rooms": rooms, "selected": selected.contains(&tag) }))
+        .collect();
+    let filtered = !selected.is_empty();
+
+    (ContentType::HTML, Template::render("rooms", context! {
+        title: "Rooms",
+        rooms,
+        tags,
+        filtered,
+        selected: selected.into_iter().collect::<Vec<_>>().join(", "),
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![page]
+}
@@ -0,0 +1,257 @@
+// Email-to-room gateway. Point a Mailgun route for the [email] domain at
+//
+//   POST /email/inbound
+//
+// and mail sent to <room>@<domain> is posted in the room under the
+// configured sender name, as "<from>: <subject>" followed by the text, with
+// the email's files attached. Only rooms listed in the [email] table take
+// mail. Posts carry Mailgun's signature (HMAC-SHA256 of timestamp and token,
+// keyed with the webhook signing key), and unsigned or stale ones are refused.
+// Mail goes through each room's filters as chat messages do.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rocket::Route;
+use rocket::data::ToByteUnit;
+use rocket::form::{DataField, Form, FromForm, Options, ValueField};
+use rocket::http::Status;
+use sha2::Sha256;
+
+use crate::attachments::{self, Attachment, MAX_ATTACHMENT_BYTES};
+use crate::config::{CONFIG, EmailConfig};
+use crate::{CHAT_STATE, ChatMessage, MessageType, maintenance, publish_bot, secrets};
+
+// How old a signed post may be
+const MAX_AGE_SECS: i64 = 5 * 60;
+const MAX_ATTACHMENTS: usize = 10;
+
+struct EmailFile {
+    name: String,
+    content_type: String,
+    data: Vec<u8>,
+}
+
+// The provider's form: plain fields by name, plus any files. Mailgun names
+// its files attachment-1, attachment-2, ...
+struct InboundEmail {
+    fields: HashMap<String, String>,
+    files: Vec<EmailFile>,
+}
+
+impl InboundEmail {
+    fn field(&self, name: &str) -> &str {
+        self.fields.get(name).map(String::as_str).unwrap_or("")
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromForm<'r> for InboundEmail {
+    type Context = InboundEmail;
+
+    fn init(_opts: Options) -> Self::Context {
+        InboundEmail {
+            fields: HashMap::new(),
+            files: Vec::new(),
+        }
+    }
+
+    fn push_value(ctxt: &mut Self::Context, field: ValueField<'r>) {
+        ctxt.fields.insert(field.name.source().to_string(), field.value.to_string());
+    }
+
+    async fn push_data(ctxt: &mut Self::Context, field: DataField<'r, '_>) {
+        let name = field.name.source().to_string();
+        let file_name = field.file_name.map(|file_name| file_name.dangerous_unsafe_unsanitized_raw().as_str());
+        let is_file = file_name.is_some() || name.starts_with("attachment");
+        if is_file && ctxt.files.len() >= MAX_ATTACHMENTS {
+            eprintln!("Inbound email has more than {} files; dropping {}", MAX_ATTACHMENTS, name);
+            return;
+        }
+        let limit = field.request.limits().get("file").unwrap_or(MAX_ATTACHMENT_BYTES.bytes());
+        let data = match field.data.open(limit).into_bytes().await {
+            Ok(data) if data.is_complete() => data.into_inner(),
+            Ok(_) => {
+                eprintln!("Inbound email field {} is too large; dropping it", name);
+                return;
+            },
+            Err(err) => {
+                eprintln!("Failed to read inbound email field {}: {}", name, err);
+                return;
+            },
+        };
+
+        if is_file {
+            ctxt.files.push(EmailFile {
+                name: file_name.unwrap_or(&name).to_string(),
+                content_type: field.content_type.to_string(),
+                data,
+            });
+        } else {
+            // Text sent with a content type, e.g. body-html
+            ctxt.fields.insert(name, String::from_utf8_lossy(&data).into_owned());
+        }
+    }
+
+    fn finalize(ctxt: Self::Context) -> rocket::form::Result<'r, Self> {
+        Ok(ctxt)
+    }
+}
+
+fn verify(config: &EmailConfig, email: &InboundEmail) -> bool {
+    let timestamp = email.field("timestamp");
+    let fresh = timestamp
+        .parse::<i64>()
+        .is_ok_and(|timestamp| (Utc::now().timestamp() - timestamp).abs() <= MAX_AGE_SECS);
+    let Ok(signature) = hex::decode(email.field("signature")) else {
+        return false;
+    };
+    let signed = secrets::accepted(&config.signing_key).iter().any(|key| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any length");
+        mac.update(timestamp.as_bytes());
+        mac.update(email.field("token").as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    });
+    fresh && signed
+}
+
+// The rooms among the recipients, e.g. "ops" for ops@<domain>
+fn rooms_for(config: &EmailConfig, recipients: &str) -> Vec<String> {
+    let mut rooms = Vec::new();
+    for address in recipients.split(',') {
+        let address = address.trim().trim_start_matches('<').trim_end_matches('>');
+        let Some((local, domain)) = address.rsplit_once('@') else {
+            continue;
+        };
+        if !domain.eq_ignore_ascii_case(&config.domain) {
+            continue;
+        }
+        if let Some(room_id) = config.rooms.iter().find(|room_id| room_id.eq_ignore_ascii_case(local))
+            && !rooms.contains(room_id)
+        {
+            rooms.push(room_id.clone());
+        }
+    }
+    rooms
+}
+
+fn content(email: &InboundEmail) -> String {
+    let from = match email.field("from") {
+        "" => email.field("sender"),
+        from => from,
+    };
+    // Mailgun strips quoted replies and signatures into stripped-text
+    let body = match email.field("stripped-text").trim() {
+        "" => email.field("body-plain").trim(),
+        body => body,
+    };
+    let subject = match email.field("subject").trim() {
+        "" => "(no subject)",
+        subject => subject,
+    };
+    format!("{}: {}\n\n{}", from, subject, body).trim_end().chars().take(CONFIG.max_message_len).collect()
+}
+
+// 406 tells Mailgun not to retry mail that will never be taken
+#[rocket::post("/inbound", data = "<email>")]
+fn inbound(email: Form<InboundEmail>) -> Status {
+    let Some(config) = &CONFIG.email else {
+        return Status::NotFound;
+    };
+    if !verify(config, &email) {
+        return Status::Unauthorized;
+    }
+    let rooms = rooms_for(config, email.field("recipient"));
+    if rooms.is_empty() {
+        return Status::NotAcceptable;
+    }
+    // Asks the provider to try again later
+    if maintenance::read_only() {
+        return Status::ServiceUnavailable;
+    }
+
+    let mut files: Vec<Attachment> = Vec::new();
+    for file in &email.files {
+        match attachments::store(&file.name, &file.content_type, &file.data) {
+            Ok(attachment) => files.push(attachment),
+            Err(err) => eprintln!("Failed to keep email attachment {}: {}", file.name, err),
+        }
+    }
+    let content = content(&email);
+    for room_id in rooms {
+        let room = CHAT_STATE.get_or_create_room(&room_id);
+        if room.config.read().locked {
+            continue;
+        }
+        let mut message = ChatMessage::new(&room_id, &config.sender, &content, MessageType::Bot);
+        message.attachments = files.clone();
+        // A room's filters turning it down doesn't bounce it from the others
+        if let Err(err) = publish_bot(&room, message) {
+            eprintln!("Email to {} not posted: {}", room_id, err);
+        }
+    }
+    Status::Ok
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![inbound]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> EmailConfig {
+        EmailConfig {
+            domain: "chat.example.com".to_string(),
+            signing_key: "mailgun-key".to_string(),
+            rooms: Vec::new(),
+            sender: "Email".to_string(),
+        }
+    }
+
+    // Signed the way Mailgun does: HMAC-SHA256 of timestamp then token
+    fn email(key: &str, timestamp: i64, token: &str) -> InboundEmail {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).unwrap();
+        mac.update(format!("{}{}", timestamp, token).as_bytes());
+        let fields = [
+            ("timestamp", timestamp.to_string()),
+            ("token", token.to_string()),
+            ("signature", hex::encode(mac.finalize().into_bytes())),
+        ];
+        InboundEmail {
+            fields: fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn accepts_signed_mail() {
+        assert!(verify(&config(), &email("mailgun-key", Utc::now().timestamp(), "token")));
+    }
+
+    #[test]
+    fn rejects_mail_signed_with_another_key() {
+        assert!(!verify(&config(), &email("other-key", Utc::now().timestamp(), "token")));
+    }
+
+    #[test]
+    fn rejects_tampered_mail() {
+        let mut email = email("mailgun-key", Utc::now().timestamp(), "token");
+        email.fields.insert("token".to_string(), "another token".to_string());
+        assert!(!verify(&config(), &email));
+        email.fields.insert("signature".to_string(), "not hex".to_string());
+        assert!(!verify(&config(), &email));
+        email.fields.remove("signature");
+        assert!(!verify(&config(), &email));
+    }
+
+    #[test]
+    fn rejects_stale_mail() {
+        let stale = Utc::now().timestamp() - MAX_AGE_SECS - 60;
+        assert!(!verify(&config(), &email("mailgun-key", stale, "token")));
+        let future = Utc::now().timestamp() + MAX_AGE_SECS + 60;
+        assert!(!verify(&config(), &email("mailgun-key", future, "token")));
+    }
+}
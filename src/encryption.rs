@@ -0,0 +1,207 @@
+// Encryption at rest for stored history. Each room gets its own random
+// AES-256-GCM key, kept in data/room_keys/<room_id>.json wrapped by a
+// KeyProvider: the config's master_key, or a KMS plugged in behind the
+// trait. History written to disk (see archive.rs) has its content stored as
+//
+//   "enc:<base64 of nonce and ciphertext>"
+//
+// and decrypted as it's read back, so a copy of the data directory without
+// the master key doesn't give the history away. Without master_key nothing
+// is encrypted, and what was stored in the clear stays readable after it's
+// set.
+
+use std::collections::HashMap;
+use std::io;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use data_encoding::BASE64;
+use hkdf::Hkdf;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::Rng;
+use rocket::serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::config::CONFIG;
+use crate::{secrets, storage};
+
+const PREFIX: &str = "enc:";
+const NONCE_LEN: usize = 12;
+
+// Keeps room keys encrypted under a key of its own
+pub trait KeyProvider: Send + Sync {
+    fn wrap(&self, key: &[u8]) -> io::Result<Vec<u8>>;
+    fn unwrap(&self, wrapped: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+fn invalid(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+// Nonce followed by the ciphertext
+fn seal(key: &Key<Aes256Gcm>, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rng().fill(&mut nonce);
+    let ciphertext = Aes256Gcm::new(key).encrypt(Nonce::from_slice(&nonce), plaintext).map_err(invalid)?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn open(key: &Key<Aes256Gcm>, sealed: &[u8]) -> io::Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(invalid("ciphertext too short"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    Aes256Gcm::new(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid("can't decrypt, wrong key?"))
+}
+
+// The config's master_key, stretched to an AES key
+struct MasterKey(Key<Aes256Gcm>);
+
+impl MasterKey {
+    fn new(secret: &str) -> Self {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, secret.as_bytes())
+            .expand(b"who-chat master key", &mut key)
+            .expect("32 bytes is a valid HKDF length");
+        MasterKey(key.into())
+    }
+}
+
+impl KeyProvider for MasterKey {
+    fn wrap(&self, key: &[u8]) -> io::Result<Vec<u8>> {
+        seal(&self.0, key)
+    }
+
+    fn unwrap(&self, wrapped: &[u8]) -> io::Result<Vec<u8>> {
+        open(&self.0, wrapped)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredKey {
+    // Base64
+    wrapped: String,
+}
+
+lazy_static! {
+    static ref PROVIDER: Option<Box<dyn KeyProvider>> = CONFIG
+        .master_key
+        .as_deref()
+        .map(|secret| Box::new(MasterKey::new(&secrets::resolve(secret))) as Box<dyn KeyProvider>);
+    // room id -> its key, unwrapped
+    static ref ROOM_KEYS: Mutex<HashMap<String, Key<Aes256Gcm>>> = Mutex::new(HashMap::new());
+}
+
+// The room's key, made on first use; None when encryption is off
+fn room_key(provider: Option<&dyn KeyProvider>, room_id: &str) -> io::Result<Option<Key<Aes256Gcm>>> {
+    let Some(provider) = provider else {
+        return Ok(None);
+    };
+    let mut keys = ROOM_KEYS.lock();
+    if let Some(key) = keys.get(room_id) {
+        return Ok(Some(*key));
+    }
+    let key = match storage::load::<StoredKey>("room_keys", room_id) {
+        Some(stored) => {
+            let key = provider.unwrap(&BASE64.decode(stored.wrapped.as_bytes()).map_err(invalid)?)?;
+            *Key::<Aes256Gcm>::from_slice(&key)
+        },
+        None => {
+            let mut key = [0u8; 32];
+            rand::rng().fill(&mut key);
+            storage::save("room_keys", room_id, &StoredKey { wrapped: BASE64.encode(&provider.wrap(&key)?) })?;
+            key.into()
+        },
+    };
+    keys.insert(room_id.to_string(), key);
+    Ok(Some(key))
+}
+
+// "enc:" and the sealed text
+fn encode(key: &Key<Aes256Gcm>, text: &str) -> io::Result<String> {
+    Ok(format!("{}{}", PREFIX, BASE64.encode(&seal(key, text.as_bytes())?)))
+}
+
+// The text from what follows "enc:"
+fn decode(key: &Key<Aes256Gcm>, sealed: &str) -> io::Result<String> {
+    let sealed = BASE64.decode(sealed.as_bytes()).map_err(invalid)?;
+    String::from_utf8(open(key, &sealed)?).map_err(invalid)
+}
+
+// Text as it should be stored for the room
+pub fn encrypt(room_id: &str, text: &str) -> io::Result<String> {
+    encrypt_for(PROVIDER.as_deref(), room_id, text)
+}
+
+fn encrypt_for(provider: Option<&dyn KeyProvider>, room_id: &str, text: &str) -> io::Result<String> {
+    match room_key(provider, room_id)? {
+        Some(key) => encode(&key, text),
+        None => Ok(text.to_string()),
+    }
+}
+
+// Stored text as it was written, whether or not it was encrypted
+pub fn decrypt(room_id: &str, stored: &str) -> io::Result<String> {
+    decrypt_for(PROVIDER.as_deref(), room_id, stored)
+}
+
+fn decrypt_for(provider: Option<&dyn KeyProvider>, room_id: &str, stored: &str) -> io::Result<String> {
+    let Some(sealed) = stored.strip_prefix(PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let key = room_key(provider, room_id)?.ok_or_else(|| invalid("history is encrypted and there's no master_key"))?;
+    decode(&key, sealed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> Key<Aes256Gcm> {
+        [byte; 32].into()
+    }
+
+    #[test]
+    fn seals_and_opens() {
+        let sealed = seal(&key(1), b"hello").unwrap();
+        assert_eq!(open(&key(1), &sealed).unwrap(), b"hello");
+        // A fresh nonce each time
+        assert_ne!(seal(&key(1), b"hello").unwrap(), sealed);
+    }
+
+    #[test]
+    fn refuses_the_wrong_key_or_tampering() {
+        let mut sealed = seal(&key(1), b"hello").unwrap();
+        assert!(open(&key(2), &sealed).is_err());
+        assert!(open(&key(1), &sealed[..NONCE_LEN - 1]).is_err());
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(open(&key(1), &sealed).is_err());
+    }
+
+    #[test]
+    fn stored_text_round_trips() {
+        let stored = encode(&key(1), "héllo, world").unwrap();
+        let sealed = stored.strip_prefix(PREFIX).unwrap();
+        assert_eq!(decode(&key(1), sealed).unwrap(), "héllo, world");
+        assert!(decode(&key(2), sealed).is_err());
+        assert!(decode(&key(1), "not base64!").is_err());
+    }
+
+    #[test]
+    fn master_key_wraps_room_keys() {
+        let master = MasterKey::new("master secret");
+        let wrapped = master.wrap(&[7; 32]).unwrap();
+        assert_eq!(MasterKey::new("master secret").unwrap(&wrapped).unwrap(), [7; 32]);
+        assert!(MasterKey::new("another secret").unwrap(&wrapped).is_err());
+    }
+
+    #[test]
+    fn stores_text_as_is_without_a_master_key() {
+        assert_eq!(encrypt_for(None, "lobby", "hello").unwrap(), "hello");
+        assert_eq!(decrypt_for(None, "lobby", "hello").unwrap(), "hello");
+        assert!(decrypt_for(None, "lobby", &encode(&key(1), "hello").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+// Getting abuse in front of moderators quickly. Someone who keeps tripping
+// the spam checks, being rate limited or having messages rejected by a
+// filter `spam_strikes` times within `spam_window_secs`, or whose messages
+// in a room pile up `report_threshold` open reports, is escalated: the
+// moderators online in the room are sent
+//
+//   {"type": "escalation", "reason": "spam" | "reports", "nickname", "detail", "ephemeral": true}
+//
+// and, with webhook_url set in the [escalation] table, the URL is POSTed
+//
+//   {"event": "moderation.escalation", "room_id", "reason", "nickname", "detail", "at"}
+//
+// signed with webhook_secret like room webhooks (see webhooks.rs), which is
+// also the way to get emails or pages out of it. Honeypot catches go to the
+// webhook with reason "honeypot". The same person is escalated for the same
+// reason at most once per `cooldown_secs`.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde_json::json;
+
+use crate::config::CONFIG;
+use crate::protocol::{self, ErrorCode};
+use crate::{CHAT_STATE, audit, secrets, webhooks};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Spam,
+    Reports,
+    Honeypot,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Reason::Spam => "spam",
+            Reason::Reports => "reports",
+            Reason::Honeypot => "honeypot",
+        }
+    }
+}
+
+lazy_static! {
+    // "<room>\n<lowercased nickname>" -> strike times within the window
+    static ref STRIKES: Mutex<HashMap<String, VecDeque<Instant>>> = Mutex::new(HashMap::new());
+    // "<room>\n<lowercased nickname>\n<reason>" -> when it was last escalated
+    static ref ESCALATED: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+}
+
+fn key(room_id: &str, nickname: &str) -> String {
+    format!("{}\n{}", room_id, nickname.to_lowercase())
+}
+
+fn window() -> Duration {
+    Duration::from_secs(CONFIG.escalation.spam_window_secs)
+}
+
+fn cooldown() -> Duration {
+    Duration::from_secs(CONFIG.escalation.cooldown_secs)
+}
+
+// Counts a post turned away by the spam checks, escalating once the sender
+// has too many; hands the error back for the caller to return
+pub fn strike(room_id: &str, nickname: &str, error: protocol::Error) -> protocol::Error {
+    if !matches!(error.code, ErrorCode::RateLimited | ErrorCode::Rejected) || CONFIG.escalation.spam_strikes == 0 {
+        return error;
+    }
+    let now = Instant::now();
+    let strikes = {
+        let mut all = STRIKES.lock();
+        let strikes = all.entry(key(room_id, nickname)).or_default();
+        while strikes.front().is_some_and(|at| now.duration_since(*at) >= window()) {
+            strikes.pop_front();
+        }
+        strikes.push_back(now);
+        strikes.len()
+    };
+    if strikes >= CONFIG.escalation.spam_strikes {
+        let detail = format!("{} posts turned away in {} seconds, the last: {}", strikes, window().as_secs(), error.detail);
+        escalate(room_id, Reason::Spam, nickname, &detail);
+    }
+    error
+}
+
+// Called with how many open reports there are against the sender in the room
+pub fn reported(room_id: &str, nickname: &str, open: usize) {
+    let threshold = CONFIG.escalation.report_threshold;
+    if threshold > 0 && open >= threshold {
+        escalate(room_id, Reason::Reports, nickname, &format!("{} open reports", open));
+    }
+}
+
+// Lets the room's moderators and the webhook know, unless they were told
+// about the same thing recently
+pub fn escalate(room_id: &str, reason: Reason, nickname: &str, detail: &str) {
+    {
+        let now = Instant::now();
+        let mut escalated = ESCALATED.lock();
+        let key = format!("{}\n{}", key(room_id, nickname), reason.as_str());
+        if escalated.get(&key).is_some_and(|at| now.duration_since(*at) < cooldown()) {
+            return;
+        }
+        escalated.insert(key, now);
+    }
+    audit::record(room_id, "escalation", nickname, json!({ "reason": reason.as_str(), "detail": detail }));
+
+    // Only rooms that are loaded have anyone to tell
+    if let Some(room) = CHAT_STATE.rooms.read().get(room_id).cloned() {
+        let frame = json!({
+            "type": "escalation",
+            "reason": reason.as_str(),
+            "nickname": nickname,
+            "detail": detail,
+            "ephemeral": true,
+        }).to_string();
+        let config = room.config.read();
+        room.send_where(&frame, |conn| config.is_moderator(conn.account_id.as_deref()));
+    }
+    if let Some(url) = &CONFIG.escalation.webhook_url {
+        let body = json!({
+            "event": "moderation.escalation",
+            "room_id": room_id,
+            "reason": reason.as_str(),
+            "nickname": nickname,
+            "detail": detail,
+            "at": Utc::now().to_rfc3339(),
+        }).to_string();
+        webhooks::post(url, &secrets::resolve(&CONFIG.escalation.webhook_secret), body);
+    }
+}
+
+// Forgets strikes and escalations that have run their course
+pub fn prune() {
+    let now = Instant::now();
+    STRIKES
+        .lock()
+        .retain(|_, strikes| strikes.back().is_some_and(|at| now.duration_since(*at) < window()));
+    ESCALATED.lock().retain(|_, at| now.duration_since(*at) < cooldown());
+}
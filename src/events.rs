@@ -0,0 +1,351 @@
+// Scheduled events in a room, like a game night or a release call. Anyone in
+// the room can add one with
+//
+//   /event add <YYYY-MM-DD HH:MM> <title> [| description]      (UTC)
+//   /event list
+//   /event remove <id>                  (whoever added it, or a moderator)
+//
+// or through the API:
+//
+//   GET    /api/rooms/<room_id>/events                 upcoming events
+//   POST   /api/admin/rooms/<room_id>/events           {"title", "starts_at", "description"}
+//   DELETE /api/admin/rooms/<room_id>/events/<id>
+//   GET    /rooms/<room_id>/events.ics                 iCalendar feed to subscribe to
+//
+// The room is reminded REMINDERS seconds before each event starts. Events are
+// kept for a while after they start so calendars still show them.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::admin::{Admin, ApiResult, api_error};
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::config::CONFIG;
+use crate::protocol::ErrorCode;
+use crate::reminders::{self, LATE_REMINDER_SECS};
+use crate::rooms::public_room;
+use crate::{CHAT_STATE, storage};
+
+const MAX_EVENTS: usize = 50;
+const MAX_TITLE_LEN: usize = 200;
+const MAX_DESCRIPTION_LEN: usize = 2000;
+// Seconds before the start to remind the room, the last one when it starts
+const REMINDERS: [i64; 2] = [15 * 60, 0];
+// How long past events stay in the list and the feed
+const KEEP_PAST_DAYS: i64 = 30;
+// Calendar entries are given this length
+const EVENT_MINUTES: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ScheduledEvent {
+    id: String,
+    title: String,
+    // RFC 3339
+    starts_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    created_by: String,
+    // Smallest reminder threshold already announced, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reminded: Option<i64>,
+}
+
+impl ScheduledEvent {
+    fn start(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.starts_at).ok().map(|at| at.with_timezone(&Utc))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "title": self.title,
+            "starts_at": self.starts_at,
+            "description": self.description,
+            "created_by": self.created_by,
+        })
+    }
+}
+
+lazy_static! {
+    // room id -> events, soonest first
+    static ref EVENTS: RwLock<HashMap<String, Vec<ScheduledEvent>>> =
+        RwLock::new(storage::load("events", "events").unwrap_or_default());
+}
+
+fn save(events: &HashMap<String, Vec<ScheduledEvent>>) {
+    if let Err(err) = storage::save("events", "events", events) {
+        eprintln!("Failed to save events: {}", err);
+    }
+}
+
+// "2026-10-20 18:00" in UTC, or an RFC 3339 time
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .map(|at| at.with_timezone(&Utc))
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").ok().map(|at| at.and_utc()))
+}
+
+fn add(room_id: &str, title: &str, starts_at: DateTime<Utc>, description: Option<&str>, created_by: &str) -> Result<ScheduledEvent, String> {
+    let title = title.trim();
+    let description = description.map(str::trim).filter(|description| !description.is_empty());
+    if title.is_empty() {
+        return Err("Events need a title".to_string());
+    }
+    if title.chars().count() > MAX_TITLE_LEN {
+        return Err(format!("Titles are limited to {} characters", MAX_TITLE_LEN));
+    }
+    if description.is_some_and(|description| description.chars().count() > MAX_DESCRIPTION_LEN) {
+        return Err(format!("Descriptions are limited to {} characters", MAX_DESCRIPTION_LEN));
+    }
+    if starts_at <= Utc::now() {
+        return Err("That time has already passed".to_string());
+    }
+
+    let mut all = EVENTS.write();
+    let events = all.entry(room_id.to_string()).or_default();
+    if events.len() >= MAX_EVENTS {
+        return Err(format!("Rooms can have at most {} events", MAX_EVENTS));
+    }
+    let event = ScheduledEvent {
+        id: Uuid::new_v4().simple().to_string()[..8].to_string(),
+        title: title.to_string(),
+        starts_at: starts_at.to_rfc3339(),
+        description: description.map(str::to_string),
+        created_by: created_by.to_string(),
+        reminded: None,
+    };
+    events.push(event.clone());
+    events.sort_by_key(ScheduledEvent::start);
+    save(&all);
+    Ok(event)
+}
+
+// Removes the event if `allowed` says the caller may; None if there's no such event
+fn remove(room_id: &str, id: &str, allowed: impl Fn(&ScheduledEvent) -> bool) -> Option<Result<ScheduledEvent, ()>> {
+    let mut all = EVENTS.write();
+    let events = all.get_mut(room_id)?;
+    let position = events.iter().position(|event| event.id == id)?;
+    if !allowed(&events[position]) {
+        return Some(Err(()));
+    }
+    let event = events.remove(position);
+    if events.is_empty() {
+        all.remove(room_id);
+    }
+    save(&all);
+    Some(Ok(event))
+}
+
+fn list(room_id: &str) -> Vec<ScheduledEvent> {
+    EVENTS.read().get(room_id).cloned().unwrap_or_default()
+}
+
+fn upcoming(room_id: &str) -> Vec<ScheduledEvent> {
+    let now = Utc::now();
+    list(room_id)
+        .into_iter()
+        .filter(|event| event.start().is_some_and(|start| start + Duration::minutes(EVENT_MINUTES) > now))
+        .collect()
+}
+
+// Sends due reminders and drops old events; run from the task loop
+pub fn remind() {
+    let now = Utc::now();
+    let mut announcements = Vec::new();
+    {
+        let mut all = EVENTS.write();
+        let mut changed = false;
+        for (room_id, events) in all.iter_mut() {
+            let before = events.len();
+            events.retain(|event| event.start().is_some_and(|start| now - start < Duration::days(KEEP_PAST_DAYS)));
+            changed |= events.len() != before;
+
+            for event in events.iter_mut() {
+                let Some(start) = event.start() else { continue };
+                let remaining = (start - now).num_seconds();
+                let Some(threshold) = REMINDERS.iter().copied().filter(|&t| remaining <= t).min() else {
+                    continue;
+                };
+                if event.reminded.is_some_and(|reminded| reminded <= threshold) {
+                    continue;
+                }
+                event.reminded = Some(threshold);
+                changed = true;
+                if remaining < threshold - LATE_REMINDER_SECS {
+                    continue;
+                }
+                let content = match threshold {
+                    0 => format!("Starting now: {}", event.title),
+                    _ => match (remaining + 59) / 60 {
+                        1 => format!("Starting in 1 minute: {}", event.title),
+                        minutes => format!("Starting in {} minutes: {}", minutes, event.title),
+                    },
+                };
+                announcements.push((room_id.clone(), content));
+            }
+        }
+        all.retain(|_, events| !events.is_empty());
+        if changed {
+            save(&all);
+        }
+    }
+    for (room_id, content) in announcements {
+        reminders::announce(&room_id, &content);
+    }
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("event", "/event add <YYYY-MM-DD HH:MM> <title> [| description], /event list or /event remove <id>", event);
+}
+
+fn describe(event: &ScheduledEvent) -> String {
+    let when = event.start().map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string()).unwrap_or_default();
+    format!("[{}] {} - {}", event.id, when, event.title)
+}
+
+fn event(ctx: &CommandContext) -> CommandOutput {
+    let room_id = &ctx.user.room_id;
+    let (action, rest) = ctx.args.split_once(' ').unwrap_or((ctx.args, ""));
+    match action {
+        "" | "list" => {
+            let events = upcoming(room_id);
+            if events.is_empty() {
+                return CommandOutput::Reply("No upcoming events".to_string());
+            }
+            let lines: Vec<String> = events.iter().map(describe).collect();
+            CommandOutput::Reply(format!("Upcoming events:\n{}", lines.join("\n")))
+        },
+        "add" => {
+            let mut parts = rest.trim().splitn(3, ' ');
+            let (Some(date), Some(time), Some(details)) = (parts.next(), parts.next(), parts.next()) else {
+                return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /event add <YYYY-MM-DD HH:MM> <title> [| description]");
+            };
+            let Some(starts_at) = parse_time(&format!("{} {}", date, time)) else {
+                return CommandOutput::error(ErrorCode::InvalidArguments, "Times look like 2026-10-20 18:00 (UTC)");
+            };
+            let (title, description) = match details.split_once('|') {
+                Some((title, description)) => (title, Some(description)),
+                None => (details, None),
+            };
+            match add(room_id, title, starts_at, description, &ctx.user.nickname) {
+                Ok(event) => {
+                    reminders::announce(room_id, &format!("{} scheduled an event: {}", ctx.user.nickname, describe(&event)));
+                    CommandOutput::Reply(format!("Added event {}", event.id))
+                },
+                Err(err) => CommandOutput::error(ErrorCode::InvalidArguments, err),
+            }
+        },
+        "remove" => {
+            let id = rest.trim();
+            let moderator = CHAT_STATE
+                .rooms
+                .read()
+                .get(room_id)
+                .is_some_and(|room| room.config.read().is_moderator(ctx.user.account_id.as_deref()));
+            match remove(room_id, id, |event| moderator || event.created_by == ctx.user.nickname) {
+                Some(Ok(event)) => CommandOutput::Reply(format!("Removed {}", event.title)),
+                Some(Err(())) => CommandOutput::error(ErrorCode::Forbidden, "Only whoever added it or a moderator can remove an event"),
+                None => CommandOutput::error(ErrorCode::InvalidArguments, format!("No event {}", id)),
+            }
+        },
+        _ => CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /event add|list|remove"),
+    }
+}
+
+#[rocket::get("/api/rooms/<room_id>/events")]
+fn get_events(room_id: &str) -> ApiResult {
+    public_room(room_id)?;
+    let events: Vec<Value> = upcoming(room_id).iter().map(ScheduledEvent::to_json).collect();
+    Ok(Json(json!({ "events": events })))
+}
+
+#[derive(Deserialize)]
+struct NewEvent {
+    title: String,
+    starts_at: String,
+    description: Option<String>,
+}
+
+#[rocket::post("/api/admin/rooms/<room_id>/events", data = "<new_event>")]
+fn post_event(_admin: Admin, room_id: &str, new_event: Json<NewEvent>) -> ApiResult {
+    let starts_at = parse_time(&new_event.starts_at)
+        .ok_or_else(|| api_error(Status::BadRequest, "starts_at must be an RFC 3339 time"))?;
+    let event = add(room_id, &new_event.title, starts_at, new_event.description.as_deref(), "admin")
+        .map_err(|err| api_error(Status::BadRequest, err))?;
+    Ok(Json(event.to_json()))
+}
+
+#[rocket::delete("/api/admin/rooms/<room_id>/events/<id>")]
+fn delete_event(_admin: Admin, room_id: &str, id: &str) -> ApiResult {
+    match remove(room_id, id, |_| true) {
+        Some(Ok(event)) => Ok(Json(event.to_json())),
+        _ => Err(api_error(Status::NotFound, "No such event")),
+    }
+}
+
+// Escapes text for an iCalendar property value
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+// Folds a content line to 75 octets, continuing with a leading space
+fn ics_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+fn calendar(room_id: &str, events: &[ScheduledEvent]) -> String {
+    let stamp = |at: DateTime<Utc>| at.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut ics = String::new();
+    ics_line(&mut ics, "BEGIN:VCALENDAR");
+    ics_line(&mut ics, "VERSION:2.0");
+    ics_line(&mut ics, &format!("PRODID:-//{}//Room events//EN", ics_text(&CONFIG.theme.name)));
+    ics_line(&mut ics, &format!("X-WR-CALNAME:{}", ics_text(&format!("#{} - {}", room_id, CONFIG.theme.name))));
+    for event in events {
+        let Some(start) = event.start() else { continue };
+        ics_line(&mut ics, "BEGIN:VEVENT");
+        ics_line(&mut ics, &format!("UID:{}-{}@who-chat", event.id, ics_text(room_id)));
+        ics_line(&mut ics, &format!("DTSTAMP:{}", stamp(Utc::now())));
+        ics_line(&mut ics, &format!("DTSTART:{}", stamp(start)));
+        ics_line(&mut ics, &format!("DTEND:{}", stamp(start + Duration::minutes(EVENT_MINUTES))));
+        ics_line(&mut ics, &format!("SUMMARY:{}", ics_text(&event.title)));
+        if let Some(description) = &event.description {
+            ics_line(&mut ics, &format!("DESCRIPTION:{}", ics_text(description)));
+        }
+        ics_line(&mut ics, "END:VEVENT");
+    }
+    ics_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+#[rocket::get("/rooms/<room_id>/events.ics")]
+fn feed(room_id: &str) -> Result<(ContentType, String), (Status, Json<Value>)> {
+    public_room(room_id)?;
+    Ok((ContentType::Calendar, calendar(room_id, &list(room_id))))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![get_events, post_event, delete_event, feed]
+}
@@ -0,0 +1,226 @@
+// Feature flags, so operators can switch off a feature that's misbehaving
+// or unwanted without a new build. Every flag is on unless turned off, most
+// specific first:
+//
+//   1. for one room, through the admin API
+//   2. server-wide, through the admin API
+//   3. in the config, e.g.
+//
+//        [flags]
+//        uploads = false
+//
+// The flags:
+//
+//   uploads         chunked uploads and attaching them to messages
+//   link_previews   preview cards added to messages by the server
+//   bots            bot messages: bot commands, plugin and script replies,
+//                   and posting through the message API
+//
+// Admin API; the server-wide routes take the admin token only, the room
+// ones also an admin:rooms API token:
+//
+//   GET /api/admin/flags                           every flag, how it's set and its room overrides
+//   PUT /api/admin/flags/<name>                    {"enabled": true | false | null}
+//   GET /api/admin/rooms/<room_id>/flags           the flags as they apply to the room
+//   PUT /api/admin/rooms/<room_id>/flags/<name>    {"enabled": true | false | null}
+//
+// null takes the override away again. Overrides are kept in the data
+// directory; room ones are also recorded in the room's audit log.
+
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::admin::{Admin, ApiResult, ServerAdmin, api_error};
+use crate::config::CONFIG;
+use crate::protocol::{self, ErrorCode};
+use crate::rooms::{INVALID_ROOM_ID, valid_room_id};
+use crate::{audit, storage};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flag {
+    Uploads,
+    LinkPreviews,
+    Bots,
+}
+
+impl Flag {
+    const ALL: [Flag; 3] = [Flag::Uploads, Flag::LinkPreviews, Flag::Bots];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::Uploads => "uploads",
+            Flag::LinkPreviews => "link_previews",
+            Flag::Bots => "bots",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Flag::Uploads => "Chunked uploads and attaching them to messages",
+            Flag::LinkPreviews => "Preview cards added to messages by the server",
+            Flag::Bots => "Bot commands, plugin and script replies, and bot posts through the message API",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Flag> {
+        Flag::ALL.into_iter().find(|flag| flag.name() == name)
+    }
+
+    // What a subsystem turns away when the flag is off
+    pub fn error(self) -> protocol::Error {
+        protocol::Error::new(ErrorCode::FeatureDisabled, format!("{} are turned off here", self.label()))
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Flag::Uploads => "Uploads",
+            Flag::LinkPreviews => "Link previews",
+            Flag::Bots => "Bots",
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Overrides {
+    // flag name -> on or off
+    server: BTreeMap<String, bool>,
+    // room id -> flag name -> on or off
+    rooms: BTreeMap<String, BTreeMap<String, bool>>,
+}
+
+lazy_static! {
+    static ref OVERRIDES: RwLock<Overrides> = RwLock::new(storage::load("flags", "overrides").unwrap_or_default());
+}
+
+fn save(overrides: &Overrides) {
+    if let Err(err) = storage::save("flags", "overrides", overrides) {
+        eprintln!("Failed to save feature flags: {}", err);
+    }
+}
+
+// The server-wide setting, leaving room overrides aside
+fn server_enabled(overrides: &Overrides, flag: Flag) -> bool {
+    overrides
+        .server
+        .get(flag.name())
+        .or_else(|| CONFIG.flags.get(flag.name()))
+        .copied()
+        .unwrap_or(true)
+}
+
+// Whether the flag is on, in the room if there is one
+pub fn enabled(flag: Flag, room_id: Option<&str>) -> bool {
+    let overrides = OVERRIDES.read();
+    room_id
+        .and_then(|room_id| overrides.rooms.get(room_id))
+        .and_then(|room| room.get(flag.name()).copied())
+        .unwrap_or_else(|| server_enabled(&overrides, flag))
+}
+
+// Ok if the flag is on, otherwise the error to send back
+pub fn check(flag: Flag, room_id: Option<&str>) -> Result<(), protocol::Error> {
+    if enabled(flag, room_id) { Ok(()) } else { Err(flag.error()) }
+}
+
+fn find(name: &str) -> Result<Flag, (Status, Json<Value>)> {
+    Flag::parse(name).ok_or_else(|| api_error(Status::NotFound, "No such feature flag"))
+}
+
+#[derive(Deserialize)]
+struct FlagUpdate {
+    // None takes the override away
+    enabled: Option<bool>,
+}
+
+fn flag_json(overrides: &Overrides, flag: Flag) -> Value {
+    let rooms: BTreeMap<&str, bool> = overrides
+        .rooms
+        .iter()
+        .filter_map(|(room_id, room)| room.get(flag.name()).map(|&on| (room_id.as_str(), on)))
+        .collect();
+    json!({
+        "name": flag.name(),
+        "description": flag.description(),
+        "enabled": server_enabled(overrides, flag),
+        "configured": CONFIG.flags.get(flag.name()),
+        "override": overrides.server.get(flag.name()),
+        "rooms": rooms,
+    })
+}
+
+#[rocket::get("/flags")]
+fn list(_admin: ServerAdmin) -> Json<Value> {
+    let overrides = OVERRIDES.read();
+    let flags: Vec<Value> = Flag::ALL.into_iter().map(|flag| flag_json(&overrides, flag)).collect();
+    Json(json!({ "flags": flags }))
+}
+
+#[rocket::put("/flags/<name>", data = "<update>")]
+fn put(_admin: ServerAdmin, name: &str, update: Json<FlagUpdate>) -> ApiResult {
+    let flag = find(name)?;
+    let mut overrides = OVERRIDES.write();
+    match update.enabled {
+        Some(on) => overrides.server.insert(flag.name().to_string(), on),
+        None => overrides.server.remove(flag.name()),
+    };
+    save(&overrides);
+    Ok(Json(flag_json(&overrides, flag)))
+}
+
+fn room_json(overrides: &Overrides, room_id: &str) -> Value {
+    let room = overrides.rooms.get(room_id);
+    let flags: BTreeMap<&str, Value> = Flag::ALL
+        .into_iter()
+        .map(|flag| {
+            let room_override = room.and_then(|room| room.get(flag.name()));
+            let enabled = room_override.copied().unwrap_or_else(|| server_enabled(overrides, flag));
+            (flag.name(), json!({ "enabled": enabled, "override": room_override }))
+        })
+        .collect();
+    json!({ "room_id": room_id, "flags": flags })
+}
+
+#[rocket::get("/rooms/<room_id>/flags")]
+fn room_flags(_admin: Admin, room_id: &str) -> Json<Value> {
+    Json(room_json(&OVERRIDES.read(), room_id))
+}
+
+#[rocket::put("/rooms/<room_id>/flags/<name>", data = "<update>")]
+fn put_room_flag(_admin: Admin, room_id: &str, name: &str, update: Json<FlagUpdate>) -> ApiResult {
+    if !valid_room_id(room_id) {
+        return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+    }
+    let flag = find(name)?;
+    let body = {
+        let mut overrides = OVERRIDES.write();
+        match update.enabled {
+            Some(on) => {
+                overrides.rooms.entry(room_id.to_string()).or_default().insert(flag.name().to_string(), on);
+            },
+            None => {
+                if let Some(room) = overrides.rooms.get_mut(room_id) {
+                    room.remove(flag.name());
+                    if room.is_empty() {
+                        overrides.rooms.remove(room_id);
+                    }
+                }
+            },
+        }
+        save(&overrides);
+        room_json(&overrides, room_id)
+    };
+    audit::record(room_id, "feature_flag", "admin", json!({ "flag": flag.name(), "enabled": update.enabled }));
+    Ok(Json(body))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, put, room_flags, put_room_flag]
+}
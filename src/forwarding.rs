@@ -0,0 +1,99 @@
+// Forwarding a message from the current room into another room the user is
+// in, either with
+//
+//   /forward <message_id> <room_id>
+//
+// or over the WebSocket with
+//
+//   {"type": "forward", "message_id": "<id>", "room_id": "<room>", "client_id": ...}
+//
+// which is acked with the new message's id like an ordinary message. The
+// copy is posted as the user's own message in the other room, so it goes
+// through that room's mutes, locks, rate limits and filters, and carries
+// "forwarded": {"room_id", "sender", "message_id", "by"} saying where it
+// came from.
+
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::flags::{self, Flag};
+use crate::protocol::{self, ErrorCode};
+use crate::trace::TraceContext;
+use crate::{CHAT_STATE, ChatMessage, MessageType, User, publish};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Forwarded {
+    // Where the original was posted, and by whom
+    pub room_id: String,
+    pub sender: String,
+    pub message_id: String,
+    // Who forwarded it
+    pub by: String,
+}
+
+// Copies the message into the room and returns the copy's id
+pub fn forward(user: &User, message_id: &str, room_id: &str) -> Result<String, protocol::Error> {
+    if room_id == user.room_id {
+        return Err(protocol::Error::new(ErrorCode::InvalidArguments, "The message is already in this room"));
+    }
+    let original = CHAT_STATE
+        .rooms
+        .read()
+        .get(&user.room_id)
+        .and_then(|room| room.messages.read().iter().find(|msg| msg.id == message_id).cloned())
+        .filter(|msg| msg.message_type != MessageType::SystemMessage);
+    let Some(original) = original else {
+        return Err(protocol::Error::new(ErrorCode::InvalidArguments, "No such message in this room"));
+    };
+
+    // The user's presence in the other room, by account or for guests by id
+    let target = CHAT_STATE.rooms.read().get(room_id).cloned();
+    let member = target.and_then(|room| {
+        room.users
+            .read()
+            .values()
+            .find(|other| match &user.account_id {
+                Some(account_id) => other.account_id.as_ref() == Some(account_id),
+                None => other.id == user.id,
+            })
+            .cloned()
+    });
+    let Some(member) = member else {
+        return Err(protocol::Error::new(ErrorCode::Forbidden, format!("You aren't in #{}", room_id)));
+    };
+
+    let message_type = match original.message_type {
+        MessageType::Location => MessageType::Location,
+        _ => MessageType::UserMessage,
+    };
+    let mut msg = ChatMessage::new(room_id, &member.nickname, &original.content, message_type);
+    msg.account_id = member.account_id.clone();
+    msg.location = original.location;
+    msg.preview = original.preview.clone().filter(|_| flags::enabled(Flag::LinkPreviews, Some(room_id)));
+    msg.spoiler = original.spoiler;
+    msg.content_warning = original.content_warning.clone();
+    msg.trace = original.trace.as_ref().map(TraceContext::child);
+    msg.forwarded = Some(Forwarded {
+        room_id: user.room_id.clone(),
+        sender: original.sender.clone(),
+        message_id: original.id.clone(),
+        by: user.nickname.clone(),
+    });
+    publish(msg)
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("forward", "/forward <message_id> <room> - copy a message into another room you're in", forward_command);
+}
+
+fn forward_command(ctx: &CommandContext) -> CommandOutput {
+    let mut args = ctx.args.split_whitespace();
+    let (Some(message_id), Some(room_id), None) = (args.next(), args.next(), args.next()) else {
+        return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /forward <message_id> <room>");
+    };
+    let room_id = room_id.trim_start_matches('#');
+    match forward(ctx.user, message_id, room_id) {
+        Ok(_) => CommandOutput::Reply(format!("Forwarded to #{}", room_id)),
+        Err(error) => CommandOutput::Error(error),
+    }
+}
@@ -0,0 +1,259 @@
+// Friend requests and contact lists for registered accounts. Online friends
+// get a `presence` frame whenever an account connects or disconnects, and
+// friends can open a private DM room with each other.
+
+use std::collections::{BTreeSet, HashMap};
+
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::{CHAT_STATE, proxy};
+use crate::accounts::{ACCOUNTS, Account, AccountSession};
+use crate::admin::{ApiResult, api_error};
+
+// DM rooms are named after both account ids, sorted so either side gets the
+// same room. Account ids are UUIDs, which never contain '_'.
+pub fn dm_room_id(a: &str, b: &str) -> String {
+    let (a, b) = if a < b { (a, b) } else { (b, a) };
+    format!("dm_{}_{}", a, b)
+}
+
+// Accounts allowed into a DM room, or None for ordinary rooms
+pub fn dm_members(room_id: &str) -> Option<BTreeSet<String>> {
+    let (a, b) = room_id.strip_prefix("dm_")?.split_once('_')?;
+    Some(BTreeSet::from([a.to_string(), b.to_string()]))
+}
+
+// Public rooms the account currently has a connection in
+fn public_rooms(account_id: &str) -> Vec<String> {
+    let rooms = CHAT_STATE.rooms.read();
+    let mut public: Vec<String> = rooms
+        .iter()
+        .filter(|(_, room)| room.config.read().members.is_none())
+        .filter(|(_, room)| {
+            room.connections
+                .read()
+                .iter()
+                .any(|conn| conn.account_id.as_deref() == Some(account_id))
+        })
+        .map(|(room_id, _)| room_id.clone())
+        .collect();
+    public.sort();
+    public
+}
+
+fn is_online(account_id: &str) -> bool {
+    let rooms = CHAT_STATE.rooms.read();
+    rooms.values().any(|room| {
+        room.connections
+            .read()
+            .iter()
+            .any(|conn| conn.account_id.as_deref() == Some(account_id))
+    })
+}
+
+fn presence(account: &Account) -> Value {
+    json!({
+        "username": account.username,
+        "online": is_online(&account.id),
+        "rooms": public_rooms(&account.id),
+    })
+}
+
+// Sends a frame to every connection signed in as one of the accounts
+fn send_to_accounts(account_ids: &BTreeSet<String>, frame: &str) {
+    let rooms: Vec<_> = CHAT_STATE.rooms.read().values().cloned().collect();
+    for room in rooms {
+        room.send_where(frame, |conn| {
+            conn.account_id.as_ref().is_some_and(|id| account_ids.contains(id))
+        });
+    }
+}
+
+// Tells the account's friends where it is now
+pub fn announce_presence(account_id: &str) {
+    let Some(account) = ACCOUNTS.get(account_id) else {
+        return;
+    };
+    if account.friends.is_empty() {
+        return;
+    }
+    let frame = json!({
+        "type": "presence",
+        "friend": presence(&account),
+    }).to_string();
+    send_to_accounts(&account.friends, &frame);
+}
+
+fn usernames(account_ids: &BTreeSet<String>) -> Vec<String> {
+    account_ids
+        .iter()
+        .filter_map(|id| ACCOUNTS.get(id))
+        .map(|account| account.username)
+        .collect()
+}
+
+fn find_other(me: &Account, username: &str) -> Result<Account, (Status, Json<Value>)> {
+    let other = ACCOUNTS
+        .find(username)
+        .ok_or_else(|| api_error(Status::NotFound, "No such account"))?;
+    if other.id == me.id {
+        return Err(api_error(Status::BadRequest, "That's your own account"));
+    }
+    Ok(other)
+}
+
+fn befriend(accounts: &mut HashMap<String, Account>, a: &str, b: &str) {
+    for (id, other) in [(a, b), (b, a)] {
+        if let Some(account) = accounts.get_mut(id) {
+            account.incoming_requests.remove(other);
+            account.outgoing_requests.remove(other);
+            account.friends.insert(other.to_string());
+        }
+    }
+}
+
+// Drops any friendship or pending request between the two accounts
+fn unlink(accounts: &mut HashMap<String, Account>, a: &str, b: &str) -> bool {
+    let mut changed = false;
+    for (id, other) in [(a, b), (b, a)] {
+        if let Some(account) = accounts.get_mut(id) {
+            changed |= account.incoming_requests.remove(other);
+            changed |= account.outgoing_requests.remove(other);
+            changed |= account.friends.remove(other);
+        }
+    }
+    changed
+}
+
+#[rocket::get("/")]
+fn list(session: AccountSession) -> Json<Value> {
+    let me = session.0;
+    let friends: Vec<Value> = me
+        .friends
+        .iter()
+        .filter_map(|id| ACCOUNTS.get(id))
+        .map(|friend| presence(&friend))
+        .collect();
+
+    Json(json!({
+        "friends": friends,
+        "incoming": usernames(&me.incoming_requests),
+        "outgoing": usernames(&me.outgoing_requests),
+    }))
+}
+
+#[derive(Deserialize)]
+struct FriendRequest {
+    username: String,
+}
+
+// Sends a friend request, or accepts theirs if they already asked
+#[rocket::post("/requests", data = "<request>")]
+fn send_request(session: AccountSession, request: Json<FriendRequest>) -> ApiResult {
+    let me = session.0;
+    let other = find_other(&me, &request.username)?;
+    if ACCOUNTS.has_blocked(&other.id, &me.username) {
+        return Err(api_error(Status::Forbidden, "That account isn't accepting requests from you"));
+    }
+
+    let accepted = ACCOUNTS.update(|accounts| {
+        let Some(account) = accounts.get_mut(&me.id) else {
+            return Err(api_error(Status::NotFound, "No such account"));
+        };
+        if account.friends.contains(&other.id) {
+            return Err(api_error(Status::Conflict, "Already friends"));
+        }
+        if account.incoming_requests.contains(&other.id) {
+            befriend(accounts, &me.id, &other.id);
+            return Ok(true);
+        }
+        account.outgoing_requests.insert(other.id.clone());
+        if let Some(other_account) = accounts.get_mut(&other.id) {
+            other_account.incoming_requests.insert(me.id.clone());
+        }
+        Ok(false)
+    })?;
+
+    if accepted {
+        announce_presence(&me.id);
+        announce_presence(&other.id);
+    } else {
+        let frame = json!({ "type": "friend_request", "from": me.username }).to_string();
+        send_to_accounts(&BTreeSet::from([other.id.clone()]), &frame);
+    }
+    Ok(Json(json!({ "username": other.username, "status": if accepted { "friends" } else { "pending" } })))
+}
+
+#[rocket::post("/requests/<username>/accept")]
+fn accept_request(session: AccountSession, username: &str) -> ApiResult {
+    let me = session.0;
+    let other = find_other(&me, username)?;
+
+    ACCOUNTS.update(|accounts| {
+        let pending = accounts
+            .get(&me.id)
+            .is_some_and(|account| account.incoming_requests.contains(&other.id));
+        if !pending {
+            return Err(api_error(Status::NotFound, "No friend request from that account"));
+        }
+        befriend(accounts, &me.id, &other.id);
+        Ok(())
+    })?;
+
+    announce_presence(&me.id);
+    announce_presence(&other.id);
+    Ok(Json(json!({ "username": other.username, "status": "friends" })))
+}
+
+// Declines an incoming request or cancels an outgoing one
+#[rocket::delete("/requests/<username>")]
+fn delete_request(session: AccountSession, username: &str) -> ApiResult {
+    let me = session.0;
+    let other = find_other(&me, username)?;
+    if !me.incoming_requests.contains(&other.id) && !me.outgoing_requests.contains(&other.id) {
+        return Err(api_error(Status::NotFound, "No pending request with that account"));
+    }
+
+    ACCOUNTS.update(|accounts| unlink(accounts, &me.id, &other.id));
+    Ok(Json(json!({ "username": other.username, "status": "none" })))
+}
+
+#[rocket::delete("/<username>")]
+fn remove_friend(session: AccountSession, username: &str) -> ApiResult {
+    let me = session.0;
+    let other = find_other(&me, username)?;
+    if !me.friends.contains(&other.id) {
+        return Err(api_error(Status::NotFound, "Not friends with that account"));
+    }
+
+    ACCOUNTS.update(|accounts| unlink(accounts, &me.id, &other.id));
+    Ok(Json(json!({ "username": other.username, "status": "none" })))
+}
+
+// Opens (or reopens) the private room shared with a friend
+#[rocket::post("/<username>/dm")]
+fn start_dm(session: AccountSession, username: &str) -> ApiResult {
+    let me = session.0;
+    let other = find_other(&me, username)?;
+    if !me.friends.contains(&other.id) {
+        return Err(api_error(Status::Forbidden, "You can only message friends"));
+    }
+    if ACCOUNTS.has_blocked(&other.id, &me.username) {
+        return Err(api_error(Status::Forbidden, "That account has blocked you"));
+    }
+
+    let room_id = dm_room_id(&me.id, &other.id);
+    CHAT_STATE.get_or_create_room(&room_id);
+    Ok(Json(json!({
+        "room_id": room_id,
+        "url": proxy::url(format!("/?rid={}", room_id)),
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, send_request, accept_request, delete_request, remove_friend, start_dm]
+}
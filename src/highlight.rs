@@ -0,0 +1,140 @@
+// Fenced code blocks (```lang ... ```) in messages, rendered to
+// syntax-highlighted HTML on the server so every client shows the same thing.
+
+use lazy_static::lazy_static;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::highlighted_html_for_string;
+use syntect::parsing::SyntaxSet;
+
+use crate::ChatMessage;
+
+const THEME: &str = "InspiredGitHub";
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+}
+
+pub struct CodeBlock {
+    pub language: Option<String>,
+    pub code: String,
+}
+
+enum Segment {
+    Text(String),
+    Code(CodeBlock),
+}
+
+// Splits content into text and fenced code blocks. An unterminated fence is
+// left as plain text.
+fn parse(content: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut lines = content.lines();
+
+    while let Some(line) = lines.next() {
+        let Some(info) = line.trim_start().strip_prefix("```") else {
+            text.push_str(line);
+            text.push('\n');
+            continue;
+        };
+
+        let mut closed = false;
+        let mut body = String::new();
+        for code_line in lines.by_ref() {
+            if code_line.trim_start().starts_with("```") {
+                closed = true;
+                break;
+            }
+            body.push_str(code_line);
+            body.push('\n');
+        }
+
+        if !closed {
+            text.push_str(line);
+            text.push('\n');
+            text.push_str(&body);
+            continue;
+        }
+
+        if !text.trim().is_empty() {
+            segments.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        text.clear();
+
+        let language = info.trim();
+        segments.push(Segment::Code(CodeBlock {
+            language: (!language.is_empty()).then(|| language.to_string()),
+            code: body,
+        }));
+    }
+
+    if !text.trim().is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+pub fn code_blocks(content: &str) -> Vec<CodeBlock> {
+    parse(content)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Code(block) => Some(block),
+            Segment::Text(_) => None,
+        })
+        .collect()
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn highlight(block: &CodeBlock, theme: &Theme) -> String {
+    let syntax = block
+        .language
+        .as_deref()
+        .and_then(|language| SYNTAX_SET.find_syntax_by_token(language))
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+
+    highlighted_html_for_string(&block.code, &SYNTAX_SET, syntax, theme)
+        .unwrap_or_else(|_| format!("<pre>{}</pre>", escape_html(&block.code)))
+}
+
+// Fills in the language tag and rendered HTML for messages containing code
+pub fn annotate(message: &mut ChatMessage) {
+    let segments = parse(&message.content);
+    if !segments.iter().any(|segment| matches!(segment, Segment::Code(_))) {
+        return;
+    }
+
+    let Some(theme) = THEME_SET.themes.get(THEME) else {
+        return;
+    };
+
+    let mut html = String::new();
+    for segment in &segments {
+        match segment {
+            Segment::Text(text) => {
+                html.push_str(&format!("<div class=\"text\">{}</div>", escape_html(text.trim_end())));
+            },
+            Segment::Code(block) => {
+                if message.code_language.is_none() {
+                    message.code_language = block.language.clone();
+                }
+                html.push_str(&highlight(block, theme));
+            }
+        }
+    }
+    message.html = Some(html);
+}
@@ -0,0 +1,142 @@
+// A honeypot room for catching spam bots that scan for room ids. Nobody is
+// told about the room set as honeypot_room, so whoever joins it or opens a
+// WebSocket to it is taken to be a bot: their address is banned for
+// honeypot_ban_secs, and the catch is written to the honeypot room's audit
+// log as
+//
+//   {"event": "honeypot_ban", "actor": "<nickname>", "data": {"ip", "account_id"}}
+//
+// Banned addresses can't join any room or open WebSockets. The room itself
+// is never created, so it doesn't turn up in listings. Server admins see
+// and lift bans with
+//
+//   GET    /api/admin/bans         {"bans": [{"ip", "room_id", "nickname", "account_id", "banned_at", "expires_at"}]}
+//   DELETE /api/admin/bans/<ip>
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::admin::{ApiResult, ServerAdmin, api_error};
+use crate::config::CONFIG;
+use crate::escalation::{self, Reason};
+use crate::{audit, storage};
+
+pub const BANNED: &str = "You can't join from this address";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Ban {
+    room_id: String,
+    nickname: String,
+    account_id: Option<String>,
+    // RFC 3339
+    banned_at: String,
+    expires_at: String,
+}
+
+impl Ban {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        DateTime::parse_from_rfc3339(&self.expires_at).map_or(true, |expires_at| expires_at <= now)
+    }
+}
+
+lazy_static! {
+    // address -> its ban
+    static ref BANS: RwLock<HashMap<String, Ban>> = RwLock::new(storage::load("honeypot", "bans").unwrap_or_default());
+}
+
+fn save(bans: &HashMap<String, Ban>) {
+    if let Err(err) = storage::save("honeypot", "bans", bans) {
+        eprintln!("Failed to save honeypot bans: {}", err);
+    }
+}
+
+fn is_honeypot(room_id: &str) -> bool {
+    CONFIG.honeypot_room.as_deref() == Some(room_id)
+}
+
+pub fn is_banned(ip: Option<&str>) -> bool {
+    ip.is_some_and(|ip| BANS.read().get(ip).is_some_and(|ban| !ban.expired(Utc::now())))
+}
+
+// Bans the client if the room is the honeypot, returning whether it was
+pub fn trap(room_id: &str, ip: Option<&str>, nickname: &str, account_id: Option<&str>) -> bool {
+    if !is_honeypot(room_id) {
+        return false;
+    }
+    eprintln!("Honeypot room joined by {} from {}", nickname, ip.unwrap_or("an unknown address"));
+    audit::append(room_id, "honeypot_ban", nickname, json!({ "ip": ip, "account_id": account_id }));
+    escalation::escalate(room_id, Reason::Honeypot, nickname, &format!("Caught from {}", ip.unwrap_or("an unknown address")));
+    if let Some(ip) = ip {
+        let now = Utc::now();
+        let expires_at = now + Duration::seconds(CONFIG.honeypot_ban_secs as i64);
+        let mut bans = BANS.write();
+        bans.insert(ip.to_string(), Ban {
+            room_id: room_id.to_string(),
+            nickname: nickname.to_string(),
+            account_id: account_id.map(str::to_string),
+            banned_at: now.to_rfc3339(),
+            expires_at: expires_at.to_rfc3339(),
+        });
+        save(&bans);
+    }
+    true
+}
+
+// Lifts bans that have run out
+pub fn expire() {
+    let now = Utc::now();
+    if !BANS.read().values().any(|ban| ban.expired(now)) {
+        return;
+    }
+    let mut bans = BANS.write();
+    bans.retain(|_, ban| !ban.expired(now));
+    save(&bans);
+}
+
+#[rocket::get("/bans")]
+fn list(_admin: ServerAdmin) -> Json<Value> {
+    let now = Utc::now();
+    let mut bans: Vec<(String, Ban)> = BANS
+        .read()
+        .iter()
+        .filter(|(_, ban)| !ban.expired(now))
+        .map(|(ip, ban)| (ip.clone(), ban.clone()))
+        .collect();
+    bans.sort_by(|a, b| b.1.banned_at.cmp(&a.1.banned_at));
+    let bans: Vec<Value> = bans
+        .into_iter()
+        .map(|(ip, ban)| {
+            json!({
+                "ip": ip,
+                "room_id": ban.room_id,
+                "nickname": ban.nickname,
+                "account_id": ban.account_id,
+                "banned_at": ban.banned_at,
+                "expires_at": ban.expires_at,
+            })
+        })
+        .collect();
+    Json(json!({ "bans": bans }))
+}
+
+#[rocket::delete("/bans/<ip>")]
+fn lift(_admin: ServerAdmin, ip: &str) -> ApiResult {
+    let mut bans = BANS.write();
+    if bans.remove(ip).is_none() {
+        return Err(api_error(Status::NotFound, "That address isn't banned"));
+    }
+    save(&bans);
+    Ok(Json(json!({ "ip": ip, "banned": false })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, lift]
+}
@@ -0,0 +1,116 @@
+// Direct message inbox: every DM room the signed-in account has with a
+// friend, newest conversation first, with the unread count and a preview of
+// the last message, so DMs can be found without the room open in a tab.
+//
+//   GET /inbox       the page
+//   GET /api/inbox   {"conversations": [{"room_id", "username", "unread",
+//                     "last_message": {"id", "sender", "preview", "timestamp"} | null,
+//                     "url"}, ...], "unread": <total>}
+//
+// Unread counts use the same read markers as the `mark_read` action.
+
+use rocket::Either::{self, Left, Right};
+use rocket::Route;
+use rocket::http::ContentType;
+use rocket::response::Redirect;
+use rocket::serde::json::{Json, Value};
+use rocket_dyn_templates::{Template, context};
+use serde_json::json;
+
+use crate::accounts::{ACCOUNTS, Account, AccountSession};
+use crate::config::CONFIG;
+use crate::{CHAT_STATE, ChatMessage, MessageType, RoomState, actions, friends, proxy};
+
+const PREVIEW_LEN: usize = 80;
+
+fn preview(content: &str) -> String {
+    let mut chars = content.chars();
+    let mut preview: String = chars.by_ref().take(PREVIEW_LEN).collect();
+    if chars.next().is_some() {
+        preview.push('…');
+    }
+    preview
+}
+
+// The newest message the account would see, leaving out system notices
+pub fn last_message(me: &Account, room: &RoomState) -> Option<ChatMessage> {
+    room.messages
+        .read()
+        .iter()
+        .rev()
+        .find(|msg| msg.message_type != MessageType::SystemMessage && !ACCOUNTS.has_blocked(&me.id, &msg.sender))
+        .cloned()
+}
+
+pub fn summary(msg: &ChatMessage) -> Value {
+    json!({
+        "id": msg.id,
+        "sender": msg.sender,
+        "preview": preview(&msg.content),
+        "timestamp": msg.timestamp,
+    })
+}
+
+// The account's DM conversations, most recent activity first
+fn conversations(me: &Account) -> Vec<Value> {
+    let rooms: Vec<_> = CHAT_STATE
+        .rooms
+        .read()
+        .iter()
+        .filter(|(room_id, _)| friends::dm_members(room_id).is_some_and(|members| members.contains(&me.id)))
+        .map(|(_, room)| room.clone())
+        .collect();
+
+    let mut conversations: Vec<(String, Value)> = rooms
+        .iter()
+        .filter_map(|room| {
+            let members = friends::dm_members(&room.id)?;
+            let other = members.iter().find(|id| **id != me.id).and_then(|id| ACCOUNTS.get(id))?;
+            let last = last_message(me, room);
+            let sort_key = last.as_ref().map(|msg| msg.timestamp.clone()).unwrap_or_default();
+            Some((sort_key, json!({
+                "room_id": room.id.as_ref(),
+                "username": other.username,
+                "unread": actions::account_unread(&me.id, &me.username, room),
+                "last_message": last.as_ref().map(summary),
+                "url": proxy::url(format!("/?rid={}", room.id)),
+            })))
+        })
+        .collect();
+    conversations.sort_by(|a, b| b.0.cmp(&a.0));
+    conversations.into_iter().map(|(_, conversation)| conversation).collect()
+}
+
+fn total_unread(conversations: &[Value]) -> u64 {
+    conversations.iter().filter_map(|conversation| conversation["unread"].as_u64()).sum()
+}
+
+#[rocket::get("/api/inbox")]
+fn list(session: AccountSession) -> Json<Value> {
+    let conversations = conversations(&session.0);
+    Json(json!({
+        "unread": total_unread(&conversations),
+        "conversations": conversations,
+    }))
+}
+
+#[rocket::get("/inbox")]
+fn page(session: Option<AccountSession>) -> Either<(ContentType, Template), Redirect> {
+    // DMs are between accounts, so guests have no inbox
+    let Some(session) = session else {
+        return Right(Redirect::to(proxy::url("/")));
+    };
+    let conversations = conversations(&session.0);
+    Left((ContentType::HTML, Template::render("inbox", context! {
+        title: "Inbox",
+        nickname: session.0.username,
+        unread: total_unread(&conversations),
+        conversations,
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, page]
+}
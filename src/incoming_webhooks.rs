@@ -0,0 +1,351 @@
+// Incoming webhooks: each one is a secret URL that posts into its room,
+//
+//   POST /hooks/<token>
+//
+// registered through the admin API with a format saying how to read the
+// JSON body:
+//   plain   {"text": "...", "sender": "..."}; sender is optional
+//   github  GitHub deliveries, by their X-GitHub-Event header
+//   gitlab  GitLab deliveries, by the payload's object_kind
+//   alertmanager  Prometheus Alertmanager notifications, see src/alertmanager.rs
+// GitHub and GitLab pushes, pull/merge requests, issues and comments are
+// written out as readable messages with links; other events are ignored.
+// Messages go through the room's filters as chat messages do; any they turn
+// down make the delivery fail with 422.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rand::Rng;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Request, Route};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::admin::api_error;
+use crate::alertmanager::{self, Alert};
+use crate::config::CONFIG;
+use crate::{CHAT_STATE, ChatMessage, MessageType, maintenance, publish_bot, storage};
+
+// Commits listed for a push; the rest are counted
+const MAX_PUSH_COMMITS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFormat {
+    Plain,
+    Github,
+    Gitlab,
+    Alertmanager,
+}
+
+impl HookFormat {
+    fn default_sender(self) -> &'static str {
+        match self {
+            HookFormat::Plain => "Webhook",
+            HookFormat::Github => "GitHub",
+            HookFormat::Gitlab => "GitLab",
+            HookFormat::Alertmanager => "Alertmanager",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomingWebhook {
+    pub id: String,
+    pub room_id: String,
+    pub format: HookFormat,
+    // Name messages are posted under; the format's name when unset
+    pub sender: Option<String>,
+    token: String,
+    pub created_at: String,
+}
+
+impl IncomingWebhook {
+    // What the admin API lists; the URL is only shown when created
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "format": self.format,
+            "sender": self.sender,
+            "created_at": self.created_at,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        crate::proxy::url(format!("/hooks/{}", self.token))
+    }
+}
+
+pub struct IncomingWebhookStore {
+    // token -> webhook
+    hooks: RwLock<HashMap<String, IncomingWebhook>>,
+}
+
+impl IncomingWebhookStore {
+    fn load() -> Self {
+        let hooks: Vec<IncomingWebhook> = storage::load("webhooks", "incoming").unwrap_or_default();
+        IncomingWebhookStore {
+            hooks: RwLock::new(hooks.into_iter().map(|hook| (hook.token.clone(), hook)).collect()),
+        }
+    }
+
+    fn save(hooks: &HashMap<String, IncomingWebhook>) {
+        let hooks: Vec<&IncomingWebhook> = hooks.values().collect();
+        if let Err(err) = storage::save("webhooks", "incoming", &hooks) {
+            eprintln!("Failed to save incoming webhooks: {}", err);
+        }
+    }
+
+    pub fn list(&self, room_id: &str) -> Vec<IncomingWebhook> {
+        let mut hooks: Vec<IncomingWebhook> = self.hooks.read().values().filter(|hook| hook.room_id == room_id).cloned().collect();
+        hooks.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        hooks
+    }
+
+    pub fn add(&self, room_id: &str, format: HookFormat, sender: Option<String>) -> IncomingWebhook {
+        let mut bytes = [0u8; 24];
+        rand::rng().fill(&mut bytes);
+        let webhook = IncomingWebhook {
+            id: Uuid::new_v4().to_string(),
+            room_id: room_id.to_string(),
+            format,
+            sender,
+            token: hex::encode(bytes),
+            created_at: Utc::now().to_rfc3339(),
+        };
+
+        let mut hooks = self.hooks.write();
+        hooks.insert(webhook.token.clone(), webhook.clone());
+        Self::save(&hooks);
+        webhook
+    }
+
+    pub fn remove(&self, room_id: &str, id: &str) -> bool {
+        let mut hooks = self.hooks.write();
+        let before = hooks.len();
+        hooks.retain(|_, hook| !(hook.room_id == room_id && hook.id == id));
+        let removed = hooks.len() != before;
+        if removed {
+            Self::save(&hooks);
+        }
+        removed
+    }
+
+    fn find(&self, token: &str) -> Option<IncomingWebhook> {
+        self.hooks.read().get(token).cloned()
+    }
+}
+
+lazy_static! {
+    pub static ref INCOMING_WEBHOOKS: IncomingWebhookStore = IncomingWebhookStore::load();
+}
+
+fn str_at<'a>(payload: &'a Value, pointer: &str) -> &'a str {
+    payload.pointer(pointer).and_then(Value::as_str).unwrap_or("")
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or("").trim()
+}
+
+fn branch(git_ref: &str) -> &str {
+    git_ref.strip_prefix("refs/heads/").or_else(|| git_ref.strip_prefix("refs/tags/")).unwrap_or(git_ref)
+}
+
+// "<who> pushed 3 commits to <repo>:<branch>" and a line per commit
+fn push_message(who: &str, repo: &str, git_ref: &str, commits: &[Value], total: usize, link: &str) -> String {
+    let mut lines = vec![format!(
+        "{} pushed {} commit{} to {}:{}",
+        who,
+        total,
+        if total == 1 { "" } else { "s" },
+        repo,
+        branch(git_ref)
+    )];
+    for commit in commits.iter().take(MAX_PUSH_COMMITS) {
+        let sha: String = str_at(commit, "/id").chars().take(7).collect();
+        lines.push(format!("- {} {}", sha, first_line(str_at(commit, "/message"))));
+    }
+    if total > MAX_PUSH_COMMITS {
+        lines.push(format!("- and {} more", total - MAX_PUSH_COMMITS));
+    }
+    if !link.is_empty() {
+        lines.push(link.to_string());
+    }
+    lines.join("\n")
+}
+
+fn github(event: &str, payload: &Value) -> Option<String> {
+    let who = str_at(payload, "/sender/login");
+    let repo = str_at(payload, "/repository/full_name");
+    match event {
+        "push" => {
+            let commits = payload["commits"].as_array()?;
+            if commits.is_empty() {
+                return None;
+            }
+            Some(push_message(who, repo, str_at(payload, "/ref"), commits, commits.len(), str_at(payload, "/compare")))
+        },
+        "pull_request" => {
+            let pr = &payload["pull_request"];
+            let action = match str_at(payload, "/action") {
+                "closed" if pr["merged"].as_bool() == Some(true) => "merged",
+                action @ ("opened" | "closed" | "reopened") => action,
+                _ => return None,
+            };
+            Some(format!(
+                "{} {} pull request #{} in {}: {}\n{}",
+                who,
+                action,
+                pr["number"],
+                repo,
+                str_at(pr, "/title"),
+                str_at(pr, "/html_url")
+            ))
+        },
+        "issues" => {
+            let action = str_at(payload, "/action");
+            if !matches!(action, "opened" | "closed" | "reopened") {
+                return None;
+            }
+            let issue = &payload["issue"];
+            Some(format!(
+                "{} {} issue #{} in {}: {}\n{}",
+                who,
+                action,
+                issue["number"],
+                repo,
+                str_at(issue, "/title"),
+                str_at(issue, "/html_url")
+            ))
+        },
+        "issue_comment" if str_at(payload, "/action") == "created" => {
+            let issue = &payload["issue"];
+            Some(format!(
+                "{} commented on #{} in {}: {}\n{}\n{}",
+                who,
+                issue["number"],
+                repo,
+                str_at(issue, "/title"),
+                first_line(str_at(payload, "/comment/body")),
+                str_at(payload, "/comment/html_url")
+            ))
+        },
+        _ => None,
+    }
+}
+
+fn gitlab(payload: &Value) -> Option<String> {
+    let repo = str_at(payload, "/project/path_with_namespace");
+    let attributes = &payload["object_attributes"];
+    match str_at(payload, "/object_kind") {
+        "push" | "tag_push" => {
+            let commits = payload["commits"].as_array()?;
+            let total = payload["total_commits_count"].as_u64().map_or(commits.len(), |total| total as usize);
+            if total == 0 {
+                return None;
+            }
+            Some(push_message(str_at(payload, "/user_name"), repo, str_at(payload, "/ref"), commits, total, str_at(payload, "/project/web_url")))
+        },
+        kind @ ("merge_request" | "issue") => {
+            let action = match str_at(attributes, "/action") {
+                "open" => "opened",
+                "close" => "closed",
+                "reopen" => "reopened",
+                "merge" => "merged",
+                _ => return None,
+            };
+            let (what, sigil) = if kind == "merge_request" { ("merge request", '!') } else { ("issue", '#') };
+            Some(format!(
+                "{} {} {} {}{} in {}: {}\n{}",
+                str_at(payload, "/user/name"),
+                action,
+                what,
+                sigil,
+                attributes["iid"],
+                repo,
+                str_at(attributes, "/title"),
+                str_at(attributes, "/url")
+            ))
+        },
+        "note" => Some(format!(
+            "{} commented in {}: {}\n{}",
+            str_at(payload, "/user/name"),
+            repo,
+            first_line(str_at(attributes, "/note")),
+            str_at(attributes, "/url")
+        )),
+        _ => None,
+    }
+}
+
+// The X-GitHub-Event header, if any
+struct GithubEvent(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for GithubEvent {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(GithubEvent(request.headers().get_one("X-GitHub-Event").map(str::to_string)))
+    }
+}
+
+#[rocket::post("/<token>", data = "<payload>")]
+fn receive(token: &str, event: GithubEvent, payload: Json<Value>) -> Result<Status, (Status, Json<Value>)> {
+    let hook = INCOMING_WEBHOOKS.find(token).ok_or_else(|| api_error(Status::NotFound, "No such webhook"))?;
+    maintenance::check_api()?;
+    let plain = |content: Option<String>| -> Vec<(String, Option<Alert>)> { content.into_iter().map(|content| (content, None)).collect() };
+    let (sender, posts) = match hook.format {
+        HookFormat::Plain => {
+            let text = str_at(&payload, "/text").trim();
+            if text.is_empty() {
+                return Err(api_error(Status::BadRequest, "Messages need text"));
+            }
+            (payload["sender"].as_str().map(str::to_string), plain(Some(text.to_string())))
+        },
+        HookFormat::Github => (None, plain(github(event.0.as_deref().unwrap_or(""), &payload))),
+        HookFormat::Gitlab => (None, plain(gitlab(&payload))),
+        HookFormat::Alertmanager => (
+            None,
+            alertmanager::render(&payload).into_iter().map(|(content, alert)| (content, Some(alert))).collect(),
+        ),
+    };
+    // Events nobody needs to read, such as GitHub's ping
+    if posts.is_empty() {
+        return Ok(Status::NoContent);
+    }
+
+    let room = CHAT_STATE.get_or_create_room(&hook.room_id);
+    if room.config.read().locked {
+        return Err(api_error(Status::Conflict, "This room has expired and is locked"));
+    }
+    let sender = sender
+        .or(hook.sender)
+        .unwrap_or_else(|| hook.format.default_sender().to_string());
+    // Alerts the filters turn down don't keep the rest of the batch out
+    let mut rejected = None;
+    for (content, alert) in posts {
+        let content: String = content.chars().take(CONFIG.max_message_len).collect();
+        let mut message = ChatMessage::new(&hook.room_id, &sender, &content, MessageType::Bot);
+        message.alert = alert;
+        if let Err(err) = publish_bot(&room, message) {
+            rejected = Some(err);
+        }
+    }
+    match rejected {
+        Some(err) => Err(api_error(Status::UnprocessableEntity, err)),
+        None => Ok(Status::NoContent),
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![receive]
+}
+
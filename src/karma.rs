@@ -0,0 +1,165 @@
+// Karma: +1 and -1 votes on messages, apart from anything else clients hang
+// off a message. Signed-in accounts send
+//
+//   {"type": "vote", "message_id": "<id>", "vote": 1 | -1 | 0}
+//
+// to vote a message in the current room up or down, or with 0 take their
+// vote back; voting again replaces the earlier vote. Everyone's own messages
+// are off limits. The votes are kept on the message as
+//
+//   "votes": {"up": ["alice", ...], "down": ["bob", ...]}
+//
+// and the room gets {"type": "votes", "message_id": ..., "votes": {...},
+// "score": <up - down>} after each change. Every vote counts towards the
+// sender's karma in the room, which stays after the message is gone:
+//
+//   /karma [nickname]                    someone's karma here and overall
+//   GET /api/rooms/<room_id>/karma       {"room_id", "leaderboard": [{"nickname", "karma"}, ...]}
+//
+// The leaderboard is highest first, `?limit=` entries long (10 by default).
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::serde::json::Json;
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::admin::ApiResult;
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::{self, ErrorCode};
+use crate::rooms::public_room;
+use crate::{MessageType, RoomState, User, storage};
+
+const DEFAULT_LEADERBOARD: usize = 10;
+const MAX_LEADERBOARD: usize = 100;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Votes {
+    // Nicknames, first vote first
+    #[serde(default)]
+    pub up: Vec<String>,
+    #[serde(default)]
+    pub down: Vec<String>,
+}
+
+impl Votes {
+    pub fn score(&self) -> i64 {
+        self.up.len() as i64 - self.down.len() as i64
+    }
+
+    // Puts the nickname's vote at `vote`, returning how much the score moved
+    fn set(&mut self, nickname: &str, vote: i64) -> i64 {
+        let before = self.score();
+        self.up.retain(|voter| voter != nickname);
+        self.down.retain(|voter| voter != nickname);
+        match vote {
+            1 => self.up.push(nickname.to_string()),
+            -1 => self.down.push(nickname.to_string()),
+            _ => {},
+        }
+        self.score() - before
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Total {
+    // As last seen on a message
+    nickname: String,
+    karma: i64,
+}
+
+lazy_static! {
+    // room id -> lowercased nickname -> karma
+    static ref TOTALS: RwLock<HashMap<String, HashMap<String, Total>>> =
+        RwLock::new(storage::load("karma", "totals").unwrap_or_default());
+}
+
+fn save(totals: &HashMap<String, HashMap<String, Total>>) {
+    if let Err(err) = storage::save("karma", "totals", totals) {
+        eprintln!("Failed to save karma: {}", err);
+    }
+}
+
+// Handles a `vote` frame, telling the room about the new tally
+pub fn vote(user: &User, room: &RoomState, message_id: Option<&str>, vote: Option<i64>) -> Result<(), protocol::Error> {
+    if user.account_id.is_none() {
+        return Err(protocol::Error::new(ErrorCode::AccountRequired, "Register your nickname to vote on messages"));
+    }
+    let (Some(message_id), Some(vote @ -1..=1)) = (message_id, vote) else {
+        return Err(protocol::Error::new(ErrorCode::InvalidFrame, "Votes need a \"message_id\" string and a \"vote\" of 1, -1 or 0"));
+    };
+
+    let (sender, votes, change) = {
+        let mut messages = room.messages.write();
+        let msg = messages
+            .iter_mut()
+            .find(|msg| msg.id == message_id && msg.message_type == MessageType::UserMessage)
+            .ok_or(protocol::Error::new(ErrorCode::InvalidArguments, "No such message in this room"))?;
+        if msg.sender.eq_ignore_ascii_case(&user.nickname) {
+            return Err(protocol::Error::new(ErrorCode::InvalidArguments, "You can't vote on your own messages"));
+        }
+        let votes = msg.votes.get_or_insert_default();
+        let change = votes.set(&user.nickname, vote);
+        let votes = votes.clone();
+        if votes.up.is_empty() && votes.down.is_empty() {
+            msg.votes = None;
+        }
+        (msg.sender.clone(), votes, change)
+    };
+
+    if change != 0 {
+        let mut totals = TOTALS.write();
+        let total = totals
+            .entry(room.id.to_string())
+            .or_default()
+            .entry(sender.to_lowercase())
+            .or_insert_with(|| Total { nickname: sender.clone(), karma: 0 });
+        total.nickname = sender;
+        total.karma += change;
+        save(&totals);
+    }
+
+    room.broadcast(&json!({
+        "type": "votes",
+        "message_id": message_id,
+        "score": votes.score(),
+        "votes": votes,
+    }).to_string());
+    Ok(())
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("karma", "/karma [nickname] - show someone's karma from votes on their messages", karma);
+}
+
+fn karma(ctx: &CommandContext) -> CommandOutput {
+    let nickname = if ctx.args.is_empty() { ctx.user.nickname.as_str() } else { ctx.args };
+    let key = nickname.to_lowercase();
+    let totals = TOTALS.read();
+    let here = totals
+        .get(ctx.user.room_id.as_str())
+        .and_then(|room| room.get(&key))
+        .map_or(0, |total| total.karma);
+    let overall: i64 = totals.values().filter_map(|room| room.get(&key)).map(|total| total.karma).sum();
+    CommandOutput::Reply(format!(
+        "{} has {} karma in #{} ({} across all rooms)",
+        nickname, here, ctx.user.room_id, overall
+    ))
+}
+
+#[rocket::get("/api/rooms/<room_id>/karma?<limit>")]
+fn leaderboard(room_id: &str, limit: Option<usize>) -> ApiResult {
+    public_room(room_id)?;
+    let limit = limit.unwrap_or(DEFAULT_LEADERBOARD).min(MAX_LEADERBOARD);
+    let mut totals: Vec<Total> = TOTALS.read().get(room_id).map(|room| room.values().cloned().collect()).unwrap_or_default();
+    totals.sort_by(|a, b| b.karma.cmp(&a.karma).then_with(|| a.nickname.cmp(&b.nickname)));
+    totals.truncate(limit);
+    Ok(Json(json!({ "room_id": room_id, "leaderboard": totals })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![leaderboard]
+}
@@ -0,0 +1,133 @@
+// Per-room highlight keywords. Besides @mentions, users can pick words they
+// want to be alerted to in a room:
+//
+//   /keyword add <word>      /keyword remove <word>      /keyword list
+//
+// Messages containing one of the user's keywords as a whole word (ignoring
+// case) are sent to that user's connections with "highlight": true, and
+// count as mentions in their read state. Like read markers, keywords belong
+// to the account, or to the connection for guests; only accounts' are saved.
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::ErrorCode;
+use crate::storage;
+
+const MAX_KEYWORDS: usize = 20;
+const MAX_KEYWORD_LEN: usize = 32;
+
+lazy_static! {
+    // "account:<id>:<room>" or "user:<id>:<room>" -> lowercased keywords
+    static ref KEYWORDS: RwLock<HashMap<String, Vec<String>>> =
+        RwLock::new(storage::load("keywords", "keywords").unwrap_or_default());
+}
+
+fn key(room_id: &str, user_id: &str, account_id: Option<&str>) -> String {
+    match account_id {
+        Some(account_id) => format!("account:{}:{}", account_id, room_id),
+        None => format!("user:{}:{}", user_id, room_id),
+    }
+}
+
+fn save(all: &HashMap<String, Vec<String>>) {
+    let saved: HashMap<&String, &Vec<String>> = all.iter().filter(|(key, _)| key.starts_with("account:")).collect();
+    if let Err(err) = storage::save("keywords", "keywords", &saved) {
+        eprintln!("Failed to save keywords: {}", err);
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+// Whether the content has one of the user's keywords for the room
+pub fn matches(room_id: &str, user_id: &str, account_id: Option<&str>, content: &str) -> bool {
+    let all = KEYWORDS.read();
+    let Some(keywords) = all.get(&key(room_id, user_id, account_id)) else {
+        return false;
+    };
+    content
+        .split(|c: char| !is_word_char(c))
+        .any(|word| keywords.contains(&word.to_lowercase()))
+}
+
+// Hands a guest's keywords over to the account they registered, see upgrade.rs
+pub fn adopt(user_id: &str, account_id: &str) {
+    let mut all = KEYWORDS.write();
+    let prefix = format!("user:{}:", user_id);
+    let keys: Vec<String> = all.keys().filter(|key| key.starts_with(&prefix)).cloned().collect();
+    for key in keys {
+        if let Some(keywords) = all.remove(&key) {
+            all.insert(format!("account:{}:{}", account_id, &key[prefix.len()..]), keywords);
+        }
+    }
+    save(&all);
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("keyword", "/keyword add|remove <word> or /keyword list - highlight messages with a word", keyword);
+}
+
+fn keyword(ctx: &CommandContext) -> CommandOutput {
+    let user = ctx.user;
+    let key = key(&user.room_id, &user.id, user.account_id.as_deref());
+    let (action, word) = ctx.args.split_once(' ').unwrap_or((ctx.args, ""));
+    let word = word.trim().to_lowercase();
+
+    let mut all = KEYWORDS.write();
+    let reply = match (action, word.as_str()) {
+        ("list", _) | ("", _) => {
+            return match all.get(&key) {
+                Some(keywords) => CommandOutput::Reply(format!("Your keywords here: {}", keywords.join(", "))),
+                None => CommandOutput::Reply("You have no keywords in this room".to_string()),
+            };
+        },
+        ("add" | "remove", "") => {
+            return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /keyword add|remove <word>");
+        },
+        ("add", word) => {
+            let valid = word.chars().all(is_word_char) && word.chars().count() <= MAX_KEYWORD_LEN;
+            if !valid {
+                return CommandOutput::error(
+                    ErrorCode::InvalidArguments,
+                    format!("Keywords are single words of up to {} letters, digits, '-' or '_'", MAX_KEYWORD_LEN),
+                );
+            }
+            let keywords = all.entry(key.clone()).or_default();
+            if keywords.iter().any(|keyword| keyword == word) {
+                return CommandOutput::Reply(format!("\"{}\" is already a keyword", word));
+            }
+            if keywords.len() >= MAX_KEYWORDS {
+                return CommandOutput::error(
+                    ErrorCode::InvalidArguments,
+                    format!("You can have at most {} keywords per room", MAX_KEYWORDS),
+                );
+            }
+            keywords.push(word.to_string());
+            format!("Messages with \"{}\" will be highlighted", word)
+        },
+        ("remove", word) => {
+            let Some(keywords) = all.get_mut(&key) else {
+                return CommandOutput::Reply(format!("\"{}\" isn't a keyword", word));
+            };
+            let before = keywords.len();
+            keywords.retain(|keyword| keyword != word);
+            if keywords.len() == before {
+                return CommandOutput::Reply(format!("\"{}\" isn't a keyword", word));
+            }
+            if keywords.is_empty() {
+                all.remove(&key);
+            }
+            format!("Messages with \"{}\" won't be highlighted any more", word)
+        },
+        _ => return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /keyword add|remove <word> or /keyword list"),
+    };
+    if user.account_id.is_some() {
+        save(&all);
+    }
+    CommandOutput::Reply(reply)
+}
@@ -0,0 +1,153 @@
+// "Knock to join" rooms, for rooms an admin has set knock on. Logging in
+// to one doesn't join it: the request is queued and the person gets a
+// waiting page that reloads itself until they're let in. Moderators in the
+// room are sent
+//
+//   {"type": "knock", "nickname": "<who>", "pending": ["<who>", ...]}
+//
+// and answer with
+//
+//   /approve <nickname>      /deny <nickname>      /knocks
+//
+// Approval lasts as long as the room is loaded, and belongs to the account
+// when there is one, otherwise to that login. Moderators never knock.
+
+use rocket::http::ContentType;
+use rocket_dyn_templates::{Template, context};
+use serde_json::json;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::config::CONFIG;
+use crate::protocol::ErrorCode;
+use crate::{CHAT_STATE, RoomConfig, RoomState, User, audit, proxy};
+
+// Seconds between reloads of the waiting page
+const WAITING_REFRESH_SECS: u32 = 5;
+
+// Who is knocking or was approved: the account, or for guests the login
+pub fn key(user_id: &str, account_id: Option<&str>) -> String {
+    account_id.unwrap_or(user_id).to_string()
+}
+
+pub fn approved(config: &RoomConfig, user: &User) -> bool {
+    config.approved.contains(&key(&user.id, user.account_id.as_deref()))
+}
+
+fn pending(config: &RoomConfig) -> Vec<&String> {
+    config.knocks.values().collect()
+}
+
+// Queues the user and lets the moderators know
+pub fn request(room: &RoomState, user: &User) {
+    let key = key(&user.id, user.account_id.as_deref());
+    let frame = {
+        let mut config = room.config.write();
+        config.denied.remove(&key);
+        config.knocks.insert(key, user.nickname.clone());
+        json!({
+            "type": "knock",
+            "nickname": user.nickname,
+            "pending": pending(&config),
+        }).to_string()
+    };
+    audit::record(&room.id, "knock", &user.nickname, json!({ "user_id": user.id }));
+    let config = room.config.read();
+    room.send_where(&frame, |conn| config.is_moderator(conn.account_id.as_deref()));
+}
+
+// Drops a request when the person gives up and logs out
+pub fn withdraw(room: &RoomState, user_id: &str, account_id: Option<&str>) {
+    room.config.write().knocks.remove(&key(user_id, account_id));
+}
+
+pub enum Waiting {
+    Pending,
+    Denied,
+}
+
+pub fn waiting(room: &RoomState, user_id: &str, account_id: Option<&str>) -> Option<Waiting> {
+    let config = room.config.read();
+    let key = key(user_id, account_id);
+    if config.knocks.contains_key(&key) {
+        Some(Waiting::Pending)
+    } else if config.denied.contains(&key) {
+        Some(Waiting::Denied)
+    } else {
+        None
+    }
+}
+
+pub fn waiting_page(room_id: &str, nickname: &str, waiting: Waiting) -> (ContentType, Template) {
+    let denied = matches!(waiting, Waiting::Denied);
+    (ContentType::HTML, Template::render("knock", context! {
+        room_id,
+        title: format!("Waiting to join: {}", room_id),
+        nickname,
+        denied,
+        refresh: (!denied).then_some(WAITING_REFRESH_SECS),
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    }))
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("approve", "/approve <nickname> - let someone knocking into the room (moderators)", approve);
+    registry.register("deny", "/deny <nickname> - turn down someone knocking (moderators)", deny);
+    registry.register("knocks", "/knocks - list who is waiting to join (moderators)", knocks);
+}
+
+// Takes the request of `nickname` off the queue, for /approve and /deny
+fn answer(ctx: &CommandContext, command: &str, let_in: bool) -> CommandOutput {
+    let nickname = ctx.args.trim();
+    if nickname.is_empty() {
+        return CommandOutput::error(ErrorCode::InvalidArguments, format!("Usage: /{} <nickname>", command));
+    }
+    let room = CHAT_STATE.get_or_create_room(&ctx.user.room_id);
+    let mut config = room.config.write();
+    if !config.is_moderator(ctx.user.account_id.as_deref()) {
+        return CommandOutput::error(ErrorCode::Forbidden, "Only moderators can answer knocks");
+    }
+    let key = config
+        .knocks
+        .iter()
+        .find(|(_, knocking)| knocking.eq_ignore_ascii_case(nickname))
+        .map(|(key, _)| key.clone());
+    let Some(key) = key else {
+        return CommandOutput::Reply(format!("{} isn't waiting to join", nickname));
+    };
+    let nickname = config.knocks.remove(&key).unwrap_or_default();
+    if let_in {
+        config.approved.insert(key);
+    } else {
+        config.denied.insert(key);
+    }
+    drop(config);
+
+    let event = if let_in { "knock_approved" } else { "knock_denied" };
+    audit::record(&room.id, event, &ctx.user.nickname, json!({ "nickname": nickname }));
+    CommandOutput::Reply(match let_in {
+        true => format!("Let {} in", nickname),
+        false => format!("Turned {} away", nickname),
+    })
+}
+
+fn approve(ctx: &CommandContext) -> CommandOutput {
+    answer(ctx, "approve", true)
+}
+
+fn deny(ctx: &CommandContext) -> CommandOutput {
+    answer(ctx, "deny", false)
+}
+
+fn knocks(ctx: &CommandContext) -> CommandOutput {
+    let room = CHAT_STATE.get_or_create_room(&ctx.user.room_id);
+    let config = room.config.read();
+    if !config.is_moderator(ctx.user.account_id.as_deref()) {
+        return CommandOutput::error(ErrorCode::Forbidden, "Only moderators can see who is knocking");
+    }
+    let pending: Vec<&str> = pending(&config).into_iter().map(String::as_str).collect();
+    match pending.is_empty() {
+        true => CommandOutput::Reply("Nobody is waiting to join".to_string()),
+        false => CommandOutput::Reply(format!("Waiting to join: {}", pending.join(", "))),
+    }
+}
@@ -0,0 +1,33 @@
+// Link previews attached to messages so clients can show a card without
+// fetching anything themselves.
+
+use rocket::serde::{Deserialize, Serialize};
+
+const OSM_ZOOM: u8 = 15;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preview {
+    pub url: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+}
+
+// OpenStreetMap link plus a static map image centered on the location
+pub fn location_preview(lat: f64, lon: f64) -> Preview {
+    Preview {
+        url: format!(
+            "https://www.openstreetmap.org/?mlat={lat:.6}&mlon={lon:.6}#map={zoom}/{lat:.6}/{lon:.6}",
+            lat = lat,
+            lon = lon,
+            zoom = OSM_ZOOM
+        ),
+        title: format!("Location {:.5}, {:.5}", lat, lon),
+        image_url: Some(format!(
+            "https://staticmap.openstreetmap.de/staticmap.php?center={lat:.6},{lon:.6}&zoom={zoom}&size=400x200&markers={lat:.6},{lon:.6},red-pushpin",
+            lat = lat,
+            lon = lon,
+            zoom = OSM_ZOOM
+        )),
+    }
+}
@@ -0,0 +1,187 @@
+// Slows down password guessing. Failed sign-ins are counted per account and
+// per address; after free_attempts failures each further try has to wait
+// twice as long as the one before (backoff_base_secs, doubling up to
+// backoff_max_secs), and at account_lockout_after or ip_lockout_after
+// failures the account or address is locked out for lockout_secs. Signing
+// in clears the account's count but not the address's, so one good password
+// doesn't buy more guesses at others. Counts are kept in memory only and
+// forgotten after lockout_secs without a failure. Server admins see recent
+// failures and current lockouts, and can clear an account's, with
+//
+//   GET    /api/admin/logins      {"failures": [{"username", "ip", "at"}], "locked": [{"account"|"ip", "failures", "until"}]}
+//   DELETE /api/admin/logins/<username>
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::admin::{ApiResult, ServerAdmin, api_error};
+use crate::config::CONFIG;
+
+// Failures kept for the admin API
+const MAX_RECENT: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Account,
+    Ip,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Account => "account",
+            Kind::Ip => "ip",
+        }
+    }
+
+    fn lockout_after(self) -> u32 {
+        match self {
+            Kind::Account => CONFIG.login_throttle.account_lockout_after,
+            Kind::Ip => CONFIG.login_throttle.ip_lockout_after,
+        }
+    }
+}
+
+struct Counter {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl Counter {
+    // How long until the next try is allowed, if it isn't yet
+    fn wait(&self, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.locked_until {
+            return (until > now).then(|| until - now);
+        }
+        let config = &CONFIG.login_throttle;
+        let over = self.failures.checked_sub(config.free_attempts).filter(|over| *over > 0)?;
+        let delay = config.backoff_base_secs.saturating_mul(1u64 << (over - 1).min(32)).min(config.backoff_max_secs);
+        (self.last_failure + Duration::from_secs(delay)).checked_duration_since(now).filter(|wait| !wait.is_zero())
+    }
+
+    fn stale(&self, now: Instant) -> bool {
+        let lockout = Duration::from_secs(CONFIG.login_throttle.lockout_secs);
+        match self.locked_until {
+            Some(until) => until <= now,
+            None => now.duration_since(self.last_failure) >= lockout,
+        }
+    }
+}
+
+struct Failure {
+    username: String,
+    ip: Option<String>,
+    // RFC 3339
+    at: String,
+}
+
+lazy_static! {
+    static ref COUNTERS: Mutex<HashMap<(Kind, String), Counter>> = Mutex::new(HashMap::new());
+    // Newest last
+    static ref RECENT: Mutex<VecDeque<Failure>> = Mutex::new(VecDeque::new());
+}
+
+fn keys(username: &str, ip: Option<&str>) -> Vec<(Kind, String)> {
+    let mut keys = vec![(Kind::Account, username.to_lowercase())];
+    if let Some(ip) = ip {
+        keys.push((Kind::Ip, ip.to_string()));
+    }
+    keys
+}
+
+// Whether a sign-in may be tried now, or how long until it may
+pub fn check(username: &str, ip: Option<&str>) -> Result<(), Duration> {
+    let now = Instant::now();
+    let counters = COUNTERS.lock();
+    let wait = keys(username, ip)
+        .iter()
+        .filter_map(|key| counters.get(key).filter(|counter| !counter.stale(now))?.wait(now))
+        .max();
+    wait.map_or(Ok(()), Err)
+}
+
+pub fn failed(username: &str, ip: Option<&str>) {
+    let now = Instant::now();
+    let mut counters = COUNTERS.lock();
+    for key in keys(username, ip) {
+        let kind = key.0;
+        let counter = counters.entry(key).or_insert(Counter { failures: 0, last_failure: now, locked_until: None });
+        if counter.stale(now) {
+            *counter = Counter { failures: 0, last_failure: now, locked_until: None };
+        }
+        counter.failures += 1;
+        counter.last_failure = now;
+        if kind.lockout_after() > 0 && counter.failures >= kind.lockout_after() {
+            counter.locked_until = Some(now + Duration::from_secs(CONFIG.login_throttle.lockout_secs));
+        }
+    }
+    drop(counters);
+
+    let mut recent = RECENT.lock();
+    recent.push_back(Failure {
+        username: username.to_string(),
+        ip: ip.map(str::to_string),
+        at: Utc::now().to_rfc3339(),
+    });
+    while recent.len() > MAX_RECENT {
+        recent.pop_front();
+    }
+}
+
+pub fn succeeded(username: &str) {
+    COUNTERS.lock().remove(&(Kind::Account, username.to_lowercase()));
+}
+
+// The message shown for a refused try
+pub fn refusal(wait: Duration) -> String {
+    format!("Too many failed sign-ins, try again in {} seconds", wait.as_secs().max(1))
+}
+
+// Forgets counts that have run out
+pub fn prune() {
+    let now = Instant::now();
+    COUNTERS.lock().retain(|_, counter| !counter.stale(now));
+}
+
+#[rocket::get("/logins")]
+fn list(_admin: ServerAdmin) -> Json<Value> {
+    let now = Instant::now();
+    let failures: Vec<Value> = RECENT
+        .lock()
+        .iter()
+        .rev()
+        .map(|failure| json!({ "username": failure.username, "ip": failure.ip, "at": failure.at }))
+        .collect();
+    let locked: Vec<Value> = COUNTERS
+        .lock()
+        .iter()
+        .filter(|(_, counter)| !counter.stale(now))
+        .filter_map(|((kind, value), counter)| {
+            let wait = counter.wait(now)?;
+            let until = Utc::now() + chrono::Duration::from_std(wait).ok()?;
+            Some(json!({ kind.name(): value, "failures": counter.failures, "until": until.to_rfc3339() }))
+        })
+        .collect();
+    Json(json!({ "failures": failures, "locked": locked }))
+}
+
+#[rocket::delete("/logins/<username>")]
+fn clear(_admin: ServerAdmin, username: &str) -> ApiResult {
+    if COUNTERS.lock().remove(&(Kind::Account, username.to_lowercase())).is_none() {
+        return Err(api_error(Status::NotFound, "No failed sign-ins for that account"));
+    }
+    Ok(Json(json!({ "username": username, "locked": false })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, clear]
+}
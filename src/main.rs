@@ -1,24 +1,146 @@
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 use std::thread;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
 use chrono::{DateTime, Utc};
 use lazy_static::lazy_static;
-use parking_lot::RwLock;
-use rocket::fs::{FileServer, relative};
+use parking_lot::{Mutex, RwLock};
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
-use rocket::{Request};
+use rocket::{Build, Request, Rocket};
 use rocket::serde::{Deserialize, Serialize};
 use rocket::form::{Form, FromForm};
-use rocket::response::Redirect;
-use rocket::http::CookieJar;
+use rocket::Either;
+use rocket::response::{Flash, Redirect};
+use rocket::request::FlashMessage;
+use rocket::http::{ContentType, CookieJar};
 use rocket_dyn_templates::{Template, context};
 use rocket::uri;
+use rand::Rng;
 use serde_json::json;
 use uuid::Uuid;
-use ws::{listen, Handler, Sender, Message, Handshake, CloseCode};
+use ws::{Handler, Sender, Message, Handshake, CloseCode, Frame, OpCode};
+
+use accounts::{ACCOUNTS, AccountSession};
+use alertmanager::Alert;
+use attachments::Attachment;
+use banner::Banner;
+use commands::{COMMANDS, CommandContext, CommandOutput};
+use config::{CONFIG, RetentionConfig};
+use flags::Flag;
+use forwarding::Forwarded;
+use link_preview::Preview;
+use meet::Call;
+use plugins::{MessageVerdict, PLUGINS};
+use protocol::ErrorCode;
+use room_core::{Effect, Event};
+use sessions::{ClientInfo, SESSIONS};
+use trace::TraceContext;
+use translation::Translation;
+use rules::Rule;
+use whiteboard::Whiteboard;
+use word_filter::WordFilter;
+
+mod accounts;
+mod actions;
+mod admin;
+mod alertmanager;
+mod anonymous;
+mod api_tokens;
+mod appearance;
+mod archive;
+mod assets;
+mod attachments;
+mod auth;
+mod audit;
+mod backup;
+mod banner;
+mod basic;
+mod blocking;
+mod calendars;
+mod capabilities;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod commands;
+mod config;
+mod cookie_keys;
+mod dashboard;
+mod directory;
+mod email;
+mod encryption;
+mod escalation;
+mod events;
+mod flags;
+mod forwarding;
+mod friends;
+mod highlight;
+mod honeypot;
+mod inbox;
+mod incoming_webhooks;
+mod karma;
+mod keywords;
+mod knock;
+mod link_preview;
+mod login_throttle;
+mod maintenance;
+mod media;
+mod meet;
+mod membership;
+mod metrics;
+mod migrations;
+mod passkeys;
+mod permalinks;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod plugins;
+mod pow;
+mod presence;
+mod preview;
+mod protocol;
+mod proxy;
+mod ranks;
+mod rate_limit;
+mod recording;
+mod reminders;
+mod reports;
+mod retention;
+mod pwa;
+mod quiet;
+mod quota;
+mod room_templates;
+mod room_core;
+mod room_log;
+mod room_merge;
+mod rooms;
+mod rules;
+mod s3;
+mod scripting;
+mod scim;
+mod search;
+mod secrets;
+mod security;
+mod seo;
+mod setup;
+mod sessions;
+mod simulate;
+mod starred;
+mod stats;
+mod storage;
+mod tasks;
+mod templates;
+mod totp;
+mod trace;
+mod translation;
+mod trivia;
+mod upgrade;
+mod uploads;
+mod user_data;
+mod webhooks;
+mod whiteboard;
+mod ws_config;
+mod word_filter;
 
 // Data structures
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,9 +148,54 @@ struct ChatMessage {
     id: String,
     room_id: String,
     sender: String,
+    // The sender's account, for data requests; guests have none and it's
+    // never sent to clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    account_id: Option<String>,
     content: String,
     timestamp: String,
     message_type: MessageType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    // Language of the first fenced code block, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    code_language: Option<String>,
+    // Server-rendered HTML for messages with code blocks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preview: Option<Preview>,
+    // Sent with /spoiler; clients hide the content until clicked
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    spoiler: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_warning: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trace: Option<TraceContext>,
+    // Where a forwarded message came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forwarded: Option<Forwarded>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
+    // Status and severity of monitoring alerts, for coloring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alert: Option<Alert>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    call: Option<Call>,
+    // Into the room's language, added once the provider answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    translation: Option<Translation>,
+    // Join or leave notice, which users can turn off per room
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    presence: bool,
+    // The sender's activity rank in the room when it was sent, see ranks.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rank: Option<String>,
+    // +1/-1 votes, see karma.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    votes: Option<karma::Votes>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -36,6 +203,78 @@ enum MessageType {
     UserMessage,
     SystemMessage,
     Command,
+    Bot,
+    Location,
+    // Invitation to a call started with /meet
+    Call,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Location {
+    lat: f64,
+    lon: f64,
+}
+
+impl ChatMessage {
+    fn new(room_id: &str, sender: &str, content: &str, message_type: MessageType) -> Self {
+        ChatMessage {
+            id: Uuid::new_v4().to_string(),
+            room_id: room_id.to_string(),
+            sender: sender.to_string(),
+            account_id: None,
+            content: content.to_string(),
+            timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
+            message_type,
+            tags: Vec::new(),
+            code_language: None,
+            html: None,
+            location: None,
+            preview: None,
+            spoiler: false,
+            content_warning: None,
+            trace: Some(TraceContext::start()),
+            forwarded: None,
+            attachments: Vec::new(),
+            alert: None,
+            call: None,
+            translation: None,
+            presence: false,
+            rank: None,
+            votes: None,
+        }
+    }
+
+    // JSON frame sent to clients
+    fn to_frame(&self) -> serde_json::Value {
+        json!({
+            "type": match self.message_type {
+                MessageType::UserMessage => "message",
+                MessageType::SystemMessage => "system",
+                MessageType::Command => "command",
+                MessageType::Bot => "bot",
+                MessageType::Location => "location",
+                MessageType::Call => "call",
+            },
+            "id": self.id,
+            "sender": self.sender,
+            "content": self.content,
+            "timestamp": self.timestamp,
+            "tags": self.tags,
+            "code_language": self.code_language,
+            "html": self.html,
+            "location": self.location,
+            "preview": self.preview,
+            "spoiler": self.spoiler,
+            "content_warning": self.content_warning,
+            "forwarded": self.forwarded,
+            "attachments": attachments::links(&self.attachments),
+            "alert": self.alert,
+            "call": self.call,
+            "translation": self.translation,
+            "rank": self.rank,
+            "votes": self.votes,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,34 +282,296 @@ struct User {
     id: String,
     nickname: String,
     room_id: String,
+    // Set for users signed in to a registered account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    account_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Role {
+    Admin,
+    Moderator,
+}
+
+// Per-room settings managed through the admin API. The serialized fields
+// are what room templates and clones copy; runtime state is skipped.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct RoomConfig {
+    // account id -> role; guests never have one
+    roles: HashMap<String, Role>,
+    rules: Vec<Rule>,
+    word_filters: Vec<WordFilter>,
+    // Hidden from room discovery unless asked for, and flagged in the UI
+    nsfw: bool,
+    // Shown privately to everyone who joins
+    welcome_message: Option<String>,
+    // What the room is about, shown in link previews
+    topic: Option<String>,
+    // Lowercased categories for browsing the room directory
+    tags: BTreeSet<String>,
+    // Compliance mode: every event goes to the signed audit log and nothing
+    // in the room's history can be changed or deleted
+    compliance: bool,
+    // History readable by anyone, a day at a time, at /rooms/<id>/log
+    public_log: bool,
+    // Joining takes a moderator's approval, see knock.rs
+    knock: bool,
+    // Language code messages are auto-translated into, e.g. "en"; see
+    // translation.rs
+    language: Option<String>,
+    // Emoji or image URL and hex color telling the room apart, see appearance.rs
+    icon: Option<String>,
+    accent_color: Option<String>,
+    // Messages go out under rotating pseudonyms, see anonymous.rs
+    anonymous: bool,
+    // Overrides the server's [retention] table, see retention.rs
+    retention: RetentionConfig,
+    // Burner rooms are locked at this time and deleted shortly after
+    #[serde(skip)]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    locked: bool,
+    // Smallest countdown warning already announced, in seconds
+    #[serde(skip)]
+    expiry_warning: Option<i64>,
+    // Private rooms (DMs) only admit these account ids
+    #[serde(skip)]
+    members: Option<BTreeSet<String>>,
+    // Lowercased nickname -> when they may post again
+    #[serde(skip)]
+    muted: HashMap<String, DateTime<Utc>>,
+    // Lowercased nicknames and account ids an admin banned, see reports.rs
+    #[serde(skip)]
+    banned: HashSet<String>,
+    // Bumped on every settings change; the admin API hands it out as an ETag
+    #[serde(skip)]
+    version: u64,
+    // Notice shown above the messages, see banner.rs
+    #[serde(skip)]
+    banner: Option<Banner>,
+    // Knock rooms: who is waiting (knock::key -> nickname), who was let in
+    // and who was turned away
+    #[serde(skip)]
+    knocks: BTreeMap<String, String>,
+    #[serde(skip)]
+    approved: HashSet<String>,
+    #[serde(skip)]
+    denied: HashSet<String>,
+}
+
+impl RoomConfig {
+    // Room roles, or a server-wide role from the account's directory groups
+    fn role(&self, account_id: Option<&str>) -> Option<Role> {
+        let account_id = account_id?;
+        self.roles.get(account_id).copied().or_else(|| ACCOUNTS.directory_role(account_id))
+    }
+
+    fn is_moderator(&self, account_id: Option<&str>) -> bool {
+        self.role(account_id).is_some()
+    }
+
+    fn is_admin(&self, account_id: Option<&str>) -> bool {
+        self.role(account_id) == Some(Role::Admin)
+    }
+
+    // Takes over another config's settings, keeping this room's runtime state
+    fn apply_template(&mut self, template: &RoomConfig) {
+        self.roles = template.roles.clone();
+        self.rules = template.rules.clone();
+        self.word_filters = template.word_filters.clone();
+        self.nsfw = template.nsfw;
+        self.welcome_message = template.welcome_message.clone();
+        self.topic = template.topic.clone();
+        self.tags = template.tags.clone();
+        self.compliance = template.compliance;
+        self.public_log = template.public_log;
+        self.knock = template.knock;
+        self.language = template.language.clone();
+        self.icon = template.icon.clone();
+        self.accent_color = template.accent_color.clone();
+        self.anonymous = template.anonymous;
+        self.retention = template.retention.clone();
+        self.version += 1;
+    }
+
+    fn bans(&self, nickname: &str, account_id: Option<&str>) -> bool {
+        self.banned.contains(&nickname.to_lowercase()) || account_id.is_some_and(|id| self.banned.contains(id))
+    }
+
+    fn admits(&self, account_id: Option<&str>) -> bool {
+        match &self.members {
+            Some(members) => account_id.is_some_and(|id| members.contains(id)),
+            None => true,
+        }
+    }
 }
 
 // Global state
 struct ChatState {
     rooms: RwLock<HashMap<String, RoomState>>,
+    // WebSocket ticket -> user; the WS server can't read Rocket's private
+    // cookies, so the client asks for a ticket before each connection
+    ws_tickets: RwLock<HashMap<String, WsTicket>>,
+}
+
+// How long a WebSocket ticket can wait to be used. Tickets travel in the
+// URL, so each is good for a single connection and only briefly
+const WS_TICKET_TTL: Duration = Duration::from_secs(60);
+
+struct WsTicket {
+    user: User,
+    expires_at: Instant,
+}
+
+// A live WebSocket connection and the user it belongs to
+#[derive(Clone)]
+struct Connection {
+    sender: Sender,
+    user_id: String,
+    nickname: String,
+    account_id: Option<String>,
+    session_id: Option<String>,
+}
+
+// Marks a message frame as matching the recipient's keywords
+fn highlight_frame(mut frame: serde_json::Value) -> serde_json::Value {
+    frame["highlight"] = json!(true);
+    frame
+}
+
+impl Connection {
+    // Whether the content has one of this connection's highlight keywords
+    fn highlights(&self, room_id: &str, content: &str) -> bool {
+        keywords::matches(room_id, &self.user_id, self.account_id.as_deref(), content)
+    }
+
+    // Whether join and leave notices are turned off for this connection
+    fn hides_presence(&self, room_id: &str) -> bool {
+        quiet::hides_presence(room_id, &self.user_id, self.account_id.as_deref())
+    }
+
+    // Whether this connection's account has blocked the sender
+    fn blocks(&self, sender: &str) -> bool {
+        self.account_id
+            .as_deref()
+            .is_some_and(|account_id| ACCOUNTS.has_blocked(account_id, sender))
+    }
 }
 
 #[derive(Clone)]
 struct RoomState {
+    id: Arc<str>,
     users: Arc<RwLock<HashMap<String, User>>>,
     messages: Arc<RwLock<Vec<ChatMessage>>>,
-    connections: Arc<RwLock<Vec<Sender>>>,
+    connections: Arc<RwLock<Vec<Connection>>>,
+    config: Arc<RwLock<RoomConfig>>,
+    whiteboard: Arc<Mutex<Whiteboard>>,
 }
 
 impl RoomState {
-    fn new() -> Self {
+    fn new(room_id: &str) -> Self {
         RoomState {
+            id: Arc::from(room_id),
             users: Arc::new(RwLock::new(HashMap::new())),
             messages: Arc::new(RwLock::new(Vec::new())),
             connections: Arc::new(RwLock::new(Vec::new())),
+            config: Arc::new(RwLock::new(RoomConfig::default())),
+            whiteboard: Arc::new(Mutex::new(Whiteboard::default())),
         }
     }
 
     fn broadcast(&self, msg: &str) {
+        self.send_where(msg, |_| true);
+    }
+
+    // Send to the connections matching `filter`, timing how long each one
+    // waits from the call until its frame is queued
+    fn send_where(&self, msg: &str, filter: impl Fn(&Connection) -> bool) {
+        let started = Instant::now();
+        #[cfg(feature = "chaos")]
+        chaos::delay_broadcast();
         let connections = self.connections.read();
-        for connection in connections.iter() {
-            let _ = connection.send(msg);
+        let mut latencies = Vec::with_capacity(connections.len());
+        let mut failures = 0;
+        for connection in connections.iter().filter(|conn| filter(conn)) {
+            #[cfg(feature = "chaos")]
+            if chaos::drop_connection() {
+                let _ = connection.sender.close(CloseCode::Away);
+                continue;
+            }
+            if connection.sender.send(msg).is_err() {
+                failures += 1;
+            }
+            latencies.push(started.elapsed());
+        }
+        drop(connections);
+        metrics::record_broadcast(&self.id, &latencies, failures);
+    }
+
+    // Whether the user could join right now, see room_core::check_join
+    fn check_join(&self, user: &User) -> Result<(), protocol::Error> {
+        let config = self.config.read();
+        room_core::check_join(&config, &self.users.read(), user)
+    }
+
+    // Runs an event through the room's rules and carries out the effects
+    // that stay within the room. What's left (publishing, commands and
+    // rejections) is returned for the caller to deal with.
+    fn apply(&self, event: Event) -> Vec<Effect> {
+        let effects = {
+            let config = self.config.read();
+            let mut users = self.users.write();
+            let mut room = room_core::Room {
+                users: &mut users,
+                config: &config,
+                now: Utc::now(),
+                max_message_len: CONFIG.max_message_len,
+            };
+            room_core::apply(&mut room, event)
+        };
+
+        let mut rest = Vec::new();
+        for effect in effects {
+            match effect {
+                Effect::Notice(content) => {
+                    let mut notice = ChatMessage::new(&self.id, "System", &content, MessageType::SystemMessage);
+                    notice.presence = true;
+                    self.messages.write().push(notice);
+                    self.send_where(&json!({
+                        "type": "system",
+                        "content": content
+                    }).to_string(), |conn| !conn.hides_presence(&self.id));
+                },
+                Effect::Audit { event, actor, data } => audit::record(&self.id, event, &actor, data),
+                Effect::Joined(user) => PLUGINS.user_joined(&self.id, &user),
+                effect => rest.push(effect),
+            }
+        }
+        rest
+    }
+
+    // Store a message in the history and send it to everyone in the room
+    // who hasn't blocked the sender
+    fn post(&self, msg: ChatMessage) {
+        let frame = msg.to_frame();
+        let mut audited = frame.clone();
+        if let Some(trace) = &msg.trace {
+            audited["trace_id"] = json!(trace.trace_id);
+            audited["span_id"] = json!(trace.span_id);
         }
+        audit::record(&msg.room_id, "message", &msg.sender, audited);
+        let highlighted = highlight_frame(frame.clone()).to_string();
+        let frame = frame.to_string();
+        let (sender, content) = (msg.sender.clone(), msg.content.clone());
+        self.messages.write().push(msg);
+        let highlights = |conn: &Connection| conn.nickname != sender && conn.highlights(&self.id, &content);
+        self.send_where(&frame, |conn| !conn.blocks(&sender) && !highlights(conn));
+        self.send_where(&highlighted, |conn| !conn.blocks(&sender) && highlights(conn));
+        dashboard::room_changed(self);
     }
 }
 
@@ -78,15 +579,86 @@ impl ChatState {
     fn new() -> Self {
         ChatState {
             rooms: RwLock::new(HashMap::new()),
+            ws_tickets: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn issue_ws_ticket(&self, user: User) -> String {
+        let now = Instant::now();
+        let mut tickets = self.ws_tickets.write();
+        tickets.retain(|_, ticket| ticket.expires_at > now);
+        let ticket = Uuid::new_v4().to_string();
+        tickets.insert(ticket.clone(), WsTicket { user, expires_at: now + WS_TICKET_TTL });
+        ticket
+    }
+
+    // Uses the ticket up; None if it's unknown or has expired
+    fn redeem_ws_ticket(&self, ticket: &str) -> Option<User> {
+        let ticket = self.ws_tickets.write().remove(ticket)?;
+        (ticket.expires_at > Instant::now()).then_some(ticket.user)
+    }
+
+    fn revoke_ws_tickets(&self, user_id: &str) {
+        self.ws_tickets.write().retain(|_, ticket| ticket.user.id != user_id);
+    }
+
+    // Tears a room down: disconnects everyone and forgets its state
+    fn remove_room(&self, room_id: &str) {
+        let Some(room) = self.rooms.write().remove(room_id) else {
+            return;
+        };
+        self.ws_tickets.write().retain(|_, ticket| ticket.user.room_id != room_id);
+        for connection in room.connections.read().iter() {
+            let _ = connection.sender.close(CloseCode::Away);
         }
+        whiteboard::discard(room_id);
+        metrics::discard(room_id);
+        PLUGINS.room_destroyed(room_id);
+    }
+
+    fn new_room(room_id: &str) -> RoomState {
+        let room = RoomState::new(room_id);
+        *room.whiteboard.lock() = Whiteboard::restore(room_id);
+        {
+            let mut config = room.config.write();
+            config.members = friends::dm_members(room_id);
+            membership::restore(room_id, &mut config);
+        }
+        room
     }
 
     fn get_or_create_room(&self, room_id: &str) -> RoomState {
         let mut rooms = self.rooms.write();
-        if !rooms.contains_key(room_id) {
-            rooms.insert(room_id.to_string(), RoomState::new());
+        if let Some(room) = rooms.get(room_id) {
+            return room.clone();
+        }
+        let room = Self::new_room(room_id);
+        rooms.insert(room_id.to_string(), room.clone());
+        drop(rooms); // Plugins may look rooms up again, so don't hold the lock
+
+        PLUGINS.room_created(room_id);
+        room
+    }
+
+    // Creates or updates a batch of rooms under one lock, so nobody sees the
+    // batch half applied. Returns each room id and whether it was created.
+    fn provision_rooms(&self, batch: Vec<(String, RoomConfig)>) -> Vec<(String, bool)> {
+        let mut rooms = self.rooms.write();
+        let mut results = Vec::with_capacity(batch.len());
+        for (room_id, settings) in batch {
+            let created = !rooms.contains_key(&room_id);
+            let room = rooms
+                .entry(room_id.clone())
+                .or_insert_with(|| Self::new_room(&room_id));
+            room.config.write().apply_template(&settings);
+            results.push((room_id, created));
         }
-        rooms.get(room_id).unwrap().clone()
+        drop(rooms);
+
+        for (room_id, _) in results.iter().filter(|(_, created)| *created) {
+            PLUGINS.room_created(room_id);
+        }
+        results
     }
 }
 
@@ -98,6 +670,19 @@ lazy_static! {
 #[derive(FromForm)]
 struct NicknameForm {
     nickname: String,
+    // Required for registered nicknames, or to register one
+    password: Option<String>,
+    register: bool,
+    // Authenticator or recovery code, for accounts with two-factor authentication
+    otp: Option<String>,
+    // Joining from the no-JavaScript view, which is where to go next
+    basic: bool,
+    // One of the suggestions offered when the nickname was taken, which
+    // takes the place of `nickname`
+    suggestion: Option<String>,
+    // Proof-of-work for guests and new nicknames, see pow.rs
+    pow_challenge: Option<String>,
+    pow_nonce: Option<String>,
 }
 
 // Request guards
@@ -105,6 +690,8 @@ struct UserSession {
     user_id: String,
     nickname: String,
     room_id: String,
+    account_id: Option<String>,
+    session_id: Option<String>,
 }
 
 #[rocket::async_trait]
@@ -115,14 +702,17 @@ impl<'r> FromRequest<'r> for UserSession {
         let cookies = request.cookies();
 
         if let (Some(user_id), Some(nickname), Some(room_id)) = (
-            cookies.get_private("user_id").map(|c| c.value().to_string()),
-            cookies.get_private("nickname").map(|c| c.value().to_string()),
-            cookies.get_private("room_id").map(|c| c.value().to_string()),
+            cookie_keys::get_private(cookies, "user_id").map(|c| c.value().to_string()),
+            cookie_keys::get_private(cookies, "nickname").map(|c| c.value().to_string()),
+            cookie_keys::get_private(cookies, "room_id").map(|c| c.value().to_string()),
         ) {
+            let session = sessions::current(cookies);
             Outcome::Success(UserSession {
                 user_id,
                 nickname,
                 room_id,
+                account_id: session.as_ref().map(|session| session.account_id.clone()),
+                session_id: session.map(|session| session.id),
             })
         } else {
             Outcome::Forward(Status::SeeOther)
@@ -131,75 +721,237 @@ impl<'r> FromRequest<'r> for UserSession {
 }
 
 // Routes
-#[rocket::get("/?<rid>")]
-fn index(rid: Option<&str>, user_session: Option<UserSession>) -> Template {
+// `focus` is a message to bring into view, from a permalink
+#[rocket::get("/?<rid>&<focus>")]
+fn index(
+    rid: Option<&str>,
+    focus: Option<&str>,
+    user_session: Option<UserSession>,
+    account: Option<AccountSession>,
+    flash: Option<FlashMessage<'_>>,
+    origin: proxy::Origin,
+) -> (ContentType, Template) {
     let room_id = rid.unwrap_or("lobby").to_string();
 
     match user_session {
         Some(session) if session.room_id == room_id => {
-            Template::render("chat", context! {
+            let room = CHAT_STATE.get_or_create_room(&room_id);
+            if room.config.read().bans(&session.nickname, session.account_id.as_deref()) {
+                return login_page(&room_id, None, Some(room_core::BANNED.to_string()), Vec::new(), &origin);
+            }
+            if let Some(waiting) = knock::waiting(&room, &session.user_id, session.account_id.as_deref()) {
+                return knock::waiting_page(&room_id, &session.nickname, waiting);
+            }
+            let registered = session.account_id.is_some();
+            let (nsfw, shareable, appearance) = {
+                let config = room.config.read();
+                (config.nsfw, config.members.is_none(), appearance::json(&config))
+            };
+            (ContentType::HTML, Template::render("chat", context! {
                 room_id: room_id.clone(),
                 nickname: session.nickname,
                 title: format!("Chat Room: {}", room_id),
-                ws_path: format!("/{}", room_id),
-            })
+                base: proxy::prefix(),
+                focus,
+                max_message_len: CONFIG.max_message_len,
+                registered,
+                nsfw,
+                shareable,
+                room: appearance,
+                theme: &CONFIG.theme,
+                pwa: CONFIG.pwa.enabled,
+            }))
         },
         _ => {
-            Template::render("login", context! {
-                room_id: room_id.clone(),
-                title: format!("Join Room: {}", room_id),
-            })
+            // Signed-in accounts can rejoin without their password
+            let nickname = account.map(|account| account.0.username);
+            let error = flash.map(|flash| flash.message().to_string());
+            login_page(&room_id, nickname, error, Vec::new(), &origin)
         }
     }
 }
 
-#[rocket::post("/?<rid>", data = "<form>")]
-fn login(rid: Option<&str>, form: Form<NicknameForm>, cookies: &CookieJar<'_>) -> Redirect {
-    let room_id = rid.unwrap_or("lobby").to_string();
-    let nickname = form.nickname.clone();
+fn login_page(room_id: &str, nickname: Option<String>, error: Option<String>, suggestions: Vec<String>, origin: &proxy::Origin) -> (ContentType, Template) {
+    (ContentType::HTML, Template::render("login", context! {
+        room_id,
+        title: format!("Join Room: {}", room_id),
+        nickname,
+        error,
+        suggestions,
+        trending: stats::trending(stats::LANDING_TRENDING),
+        online_users: stats::online_users(),
+        meta: seo::room_meta(room_id, &origin.0),
+        preview: preview::available(room_id),
+        pow: pow::challenge(),
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+        pwa: CONFIG.pwa.enabled,
+    }))
+}
 
-    // Check if the nickname is already taken in this room
-    let room_state = CHAT_STATE.get_or_create_room(&room_id);
-    let users = room_state.users.read();
+const MAX_SUGGESTIONS: usize = 3;
 
-    if users.values().any(|user| user.nickname == nickname && user.room_id == room_id) {
-        // Nickname is taken, redirect back to log in
-        return Redirect::to(uri!(index(Some(&room_id))));
+// Free variations on a nickname someone in the room already has, like
+// "name2" and "name_83"
+fn nickname_suggestions(room_state: &RoomState, nickname: &str) -> Vec<String> {
+    let users = room_state.users.read();
+    let free = |candidate: &str| {
+        !users.values().any(|user| user.nickname == candidate)
+            && ACCOUNTS.find(candidate).is_none()
+            && !ACCOUNTS.is_quarantined(candidate)
+    };
+    // The first free of name2..name9, then a few with random suffixes
+    let mut suggestions: Vec<String> = (2..10)
+        .map(|n| format!("{}{}", nickname, n))
+        .find(|candidate| free(candidate))
+        .into_iter()
+        .collect();
+    let mut rng = rand::rng();
+    for _ in 0..20 {
+        if suggestions.len() == MAX_SUGGESTIONS {
+            break;
+        }
+        let candidate = format!("{}_{}", nickname, rng.random_range(10..100));
+        if !suggestions.contains(&candidate) && free(&candidate) {
+            suggestions.push(candidate);
+        }
     }
+    suggestions
+}
 
-    // Set cookies
-    let user_id = Uuid::new_v4().to_string();
-    cookies.add_private(rocket::http::Cookie::new("user_id", user_id.clone()));
-    cookies.add_private(rocket::http::Cookie::new("nickname", nickname.clone()));
-    cookies.add_private(rocket::http::Cookie::new("room_id", room_id.clone()));
+// Back to the form with an error, or for a taken nickname the login page
+// again with suggestions
+type LoginRefusal = Either<Flash<Redirect>, (Status, (ContentType, Template))>;
+
+#[rocket::post("/?<rid>&<focus>", data = "<form>")]
+fn login(
+    rid: Option<&str>,
+    focus: Option<&str>,
+    form: Form<NicknameForm>,
+    account: Option<AccountSession>,
+    client: ClientInfo,
+    origin: proxy::Origin,
+    cookies: &CookieJar<'_>,
+) -> Result<Redirect, Box<LoginRefusal>> {
+    let room_id = rid.unwrap_or("lobby").to_string();
+    let mut nickname = match form.suggestion.as_deref().map(str::trim) {
+        Some(suggestion) if !suggestion.is_empty() => suggestion.to_string(),
+        _ => form.nickname.clone(),
+    };
+    let page = if form.basic {
+        proxy::url(format!("/rooms/{}/basic", room_id))
+    } else {
+        proxy::url(uri!(index(Some(&room_id), focus)))
+    };
+    let back = |message: &str| Box::new(Either::Left(Flash::error(Redirect::to(page.clone()), message)));
+    let signed_in_id = account.as_ref().map(|account| account.0.id.as_str());
+    if honeypot::is_banned(client.ip()) || honeypot::trap(&room_id, client.ip(), &nickname, signed_in_id) {
+        return Err(back(honeypot::BANNED));
+    }
 
-    // Add user to room
-    drop(users); // Release the read lock before acquiring write lock
-    let mut users = room_state.users.write();
-    users.insert(user_id.clone(), User {
-        id: user_id,
-        nickname: nickname.clone(),
-        room_id: room_id.clone(),
-    });
+    // Registered nicknames need the password, unless already signed in as that account
+    let password = form.password.as_deref().filter(|password| !password.is_empty());
+    let signed_in = account.as_ref().map(|account| (account.0.id.clone(), account.1.clone()));
+    let prove = || pow::verify(form.pow_challenge.as_deref(), form.pow_nonce.as_deref()).map_err(&back);
+    let sign_in = |password: &str| {
+        login_throttle::check(&nickname, client.ip()).map_err(|wait| back(&login_throttle::refusal(wait)))?;
+        match auth::authenticate(&nickname, password) {
+            Some(account) if totp::check_login(&account, form.otp.as_deref()) => {
+                login_throttle::succeeded(&nickname);
+                Ok(Some(account))
+            },
+            Some(_) => {
+                login_throttle::failed(&nickname, client.ip());
+                Err(back("Enter a valid authentication code for that nickname"))
+            },
+            None => {
+                login_throttle::failed(&nickname, client.ip());
+                Err(back("Wrong password for that nickname"))
+            },
+        }
+    };
+    let account = match (ACCOUNTS.find(&nickname), password) {
+        (Some(registered), _) if account.as_ref().is_some_and(|a| a.0.id == registered.id) => Some(registered),
+        (Some(_), Some(password)) => sign_in(password)?,
+        (Some(_), None) => return Err(back("That nickname is registered, enter its password")),
+        (None, Some(password)) if form.register => {
+            prove()?;
+            match ACCOUNTS.register(&nickname, password) {
+                Ok(account) => Some(account),
+                Err(err) => return Err(back(&err)),
+            }
+        },
+        // Directory users sign in with their directory password on first login
+        (None, Some(password)) if auth::directory_enabled() => sign_in(password)?,
+        (None, _) if ACCOUNTS.is_quarantined(&nickname) => return Err(back(accounts::QUARANTINED)),
+        (None, _) => {
+            prove()?;
+            None
+        },
+    };
+    if let Some(account) = &account {
+        nickname = account.username.clone();
+    }
+    let account_id = account.as_ref().map(|account| account.id.clone());
 
-    // Add a system message
-    let mut messages = room_state.messages.write();
-    messages.push(ChatMessage {
+    // Check the room would take the user before setting anything up
+    let room_state = CHAT_STATE.get_or_create_room(&room_id);
+    let mut user = User {
         id: Uuid::new_v4().to_string(),
-        room_id: room_id.clone(),
-        sender: "System".to_string(),
-        content: format!("{} has joined the room", nickname),
-        timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
-        message_type: MessageType::SystemMessage,
+        nickname,
+        room_id,
+        account_id,
+        session_id: None,
+    };
+    let refuse = |error: protocol::Error, user: &User| {
+        if error.code != ErrorCode::NicknameTaken || user.account_id.is_some() {
+            return back(&error.detail);
+        }
+        let suggestions = nickname_suggestions(&room_state, &user.nickname);
+        if suggestions.is_empty() {
+            return back(&error.detail);
+        }
+        if form.basic {
+            return back(&format!("{}. Try {}", error.detail, suggestions.join(", ")));
+        }
+        let page = login_page(&user.room_id, Some(user.nickname.clone()), Some(error.detail), suggestions, &origin);
+        Box::new(Either::Right((Status::Conflict, page)))
+    };
+    let knocking = match room_state.check_join(&user) {
+        Err(error) if error.code == ErrorCode::KnockRequired => true,
+        Err(error) => return Err(refuse(error, &user)),
+        Ok(()) => false,
+    };
+
+    // Set cookies
+    cookies.add_private(rocket::http::Cookie::new("user_id", user.id.clone()));
+    cookies.add_private(rocket::http::Cookie::new("nickname", user.nickname.clone()));
+    cookies.add_private(rocket::http::Cookie::new("room_id", user.room_id.clone()));
+    user.session_id = account.as_ref().map(|account| match &signed_in {
+        // Staying signed in to the same account keeps its session
+        Some((id, session_id)) if *id == account.id => session_id.clone(),
+        _ => SESSIONS.create(&account.id, &client).id,
     });
+    match &user.session_id {
+        Some(session_id) => cookies.add_private(rocket::http::Cookie::new("session_id", session_id.clone())),
+        None => cookies.remove_private("session_id"),
+    }
 
-    // Broadcast the join message
-    room_state.broadcast(&json!({
-        "type": "system",
-        "content": format!("{} has joined the room", nickname)
-    }).to_string());
+    // Knock rooms only take the user once a moderator says so; until then
+    // the room's pages show them the waiting page
+    if knocking {
+        knock::request(&room_state, &user);
+        return Ok(Redirect::to(page));
+    }
 
-    Redirect::to(uri!(index(Some(&room_id))))
+    // Add user to room
+    for effect in room_state.apply(Event::Join(user.clone())) {
+        if let Effect::Reject(error) = effect {
+            return Err(refuse(error, &user));
+        }
+    }
+
+    Ok(Redirect::to(page))
 }
 
 #[rocket::get("/logout")]
@@ -207,33 +959,36 @@ fn logout(user_session: Option<UserSession>, cookies: &CookieJar<'_>) -> Redirec
     if let Some(session) = user_session {
         // Remove user from room
         let room_state = CHAT_STATE.get_or_create_room(&session.room_id);
-        let mut users = room_state.users.write();
-        users.remove(&session.user_id);
-
-        // Add a system message
-        let mut messages = room_state.messages.write();
-        messages.push(ChatMessage {
-            id: Uuid::new_v4().to_string(),
-            room_id: session.room_id.clone(),
-            sender: "System".to_string(),
-            content: format!("{} has left the room", session.nickname),
-            timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
-            message_type: MessageType::SystemMessage,
-        });
-
-        // Broadcast the leave message
-        room_state.broadcast(&json!({
-            "type": "system",
-            "content": format!("{} has left the room", session.nickname)
-        }).to_string());
+        room_state.apply(Event::Leave { user_id: session.user_id.clone() });
+        knock::withdraw(&room_state, &session.user_id, session.account_id.as_deref());
+        CHAT_STATE.revoke_ws_tickets(&session.user_id);
 
         // Clear cookies
         cookies.remove_private("user_id");
         cookies.remove_private("nickname");
         cookies.remove_private("room_id");
+        if let Some(session_id) = &session.session_id {
+            SESSIONS.revoke(session_id);
+        }
+        cookies.remove_private("session_id");
     }
 
-    Redirect::to(uri!(index(None::<&str>)))
+    Redirect::to(proxy::url(uri!(index(None::<&str>, None::<&str>))))
+}
+
+// Raw text of the code blocks in a message, for sharing snippets; only
+// found in rooms the caller could search
+#[rocket::get("/paste/<message_id>")]
+fn paste(message_id: &str, account: Option<AccountSession>, session: Option<UserSession>) -> Option<String> {
+    let account_id = account.map(|account| account.0.id);
+    let message = search::searchable_rooms(account_id.as_deref(), session.as_ref())
+        .iter()
+        .find_map(|room| room.messages.read().iter().find(|msg| msg.id == message_id).cloned())?;
+    let blocks = highlight::code_blocks(&message.content);
+    if blocks.is_empty() {
+        return None;
+    }
+    Some(blocks.into_iter().map(|block| block.code).collect::<Vec<_>>().join("\n"))
 }
 
 // WebSocket handler
@@ -242,559 +997,661 @@ struct ChatSocketHandler {
     room_id: String,
     user_id: String,
     nickname: String,
+    account_id: Option<String>,
+    session_id: Option<String>,
+    // Messages acknowledged on this connection so far
+    acked: Cell<u64>,
+    recorder: Option<recording::Recorder>,
+    // Message the client asked to be shown, see permalinks.rs
+    focus: Option<String>,
+    // A dashboard subscription rather than a room connection, see dashboard.rs
+    dashboard: bool,
+    // Turned away on open; frames the client sent before hearing so are dropped
+    refused: bool,
 }
 
 impl ChatSocketHandler {
     fn new(sender: Sender, handshake: &Handshake) -> Self {
-        // Extract room_id from URL path
-        let path = handshake.request.resource();
+        // Extract room_id and ticket from URL path, e.g. /lobby?ticket=...
+        let resource = handshake.request.resource();
+        let (path, query) = resource.split_once('?').unwrap_or((resource, ""));
+        let path = proxy::strip_prefix(path);
         let room_id = if path.starts_with('/') && path.len() > 1 {
             path[1..].to_string() // Remove leading '/'
         } else {
             "lobby".to_string()
         };
 
-        // Parse cookies to get user info
-        let mut user_id = Uuid::new_v4().to_string();
-        let mut nickname = format!("User-{}", sender.connection_id());
-
-        // Try to extract user info from cookies
-        if let Some(cookie_header) = handshake.request.header("Cookie") {
-            if let Ok(cookie_str) = std::str::from_utf8(cookie_header) {
-                for cookie in cookie_str.split(';') {
-                    let parts: Vec<&str> = cookie.trim().split('=').collect();
-                    if parts.len() == 2 {
-                        match parts[0] {
-                            "user_id" => user_id = parts[1].to_string(),
-                            "nickname" => nickname = parts[1].to_string(),
-                            _ => {}
-                        }
-                    }
-                }
-            }
-        }
+        let focus = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("focus="))
+            .map(|id| id.to_string());
+        let ticket_user = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("ticket="))
+            .and_then(|ticket| CHAT_STATE.redeem_ws_ticket(ticket))
+            .filter(|user| user.room_id == room_id);
+
+        // Connections without a valid ticket join as anonymous guests
+        let (user_id, nickname, account_id, session_id) = match ticket_user {
+            Some(user) => (user.id, user.nickname, user.account_id, user.session_id),
+            None => (Uuid::new_v4().to_string(), format!("User-{}", sender.connection_id()), None, None),
+        };
 
         ChatSocketHandler {
             sender,
             room_id,
             user_id,
             nickname,
+            account_id,
+            session_id,
+            acked: Cell::new(0),
+            recorder: None,
+            focus,
+            dashboard: false,
+            refused: false,
         }
     }
 }
 
 impl Handler for ChatSocketHandler {
     fn on_open(&mut self, handshake: Handshake) -> ws::Result<()> {
+        let ip = proxy::ws_client_ip(&handshake).map(|ip| ip.to_string());
+        if honeypot::is_banned(ip.as_deref()) {
+            self.refused = true;
+            return self.sender.close(CloseCode::Policy);
+        }
+        let resource = handshake.request.resource();
+        let (path, query) = resource.split_once('?').unwrap_or((resource, ""));
+        if proxy::strip_prefix(path) == dashboard::WS_PATH {
+            self.dashboard = true;
+            dashboard::subscribe(&self.sender, query);
+            return Ok(());
+        }
+
         // Update handler with handshake info if needed
         *self = ChatSocketHandler::new(self.sender.clone(), &handshake);
+        if honeypot::trap(&self.room_id, ip.as_deref(), &self.nickname, self.account_id.as_deref()) {
+            self.refused = true;
+            return self.sender.close(CloseCode::Policy);
+        }
+        self.recorder = recording::Recorder::start(&self.user());
         let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+        let admitted = {
+            let config = room_state.config.read();
+            room_core::admit(&config, self.account_id.as_deref())
+                .and_then(|()| room_core::check_ban(&config, &self.user()))
+                .and_then(|()| room_core::check_knock(&config, &self.user()))
+        };
+        if admitted.is_err() {
+            self.refused = true;
+            return self.sender.close(CloseCode::Policy);
+        }
 
         // Add connection to the room
         {
             let mut connections = room_state.connections.write();
-            connections.push(self.sender.clone());
+            connections.push(Connection {
+                sender: self.sender.clone(),
+                user_id: self.user_id.clone(),
+                nickname: self.nickname.clone(),
+                account_id: self.account_id.clone(),
+                session_id: self.session_id.clone(),
+            });
         }
 
-        // Send message history to a new user
+        // Send message history to a new user, minus what they cleared
         {
             let messages = room_state.messages.read();
-            for msg in messages.iter() {
-                let _ = self.sender.send(json!({
-                    "type": match msg.message_type {
-                        MessageType::UserMessage => "message",
-                        MessageType::SystemMessage => "system",
-                        MessageType::Command => "command",
-                    },
-                    "id": msg.id,
-                    "sender": msg.sender,
-                    "content": msg.content,
-                    "timestamp": msg.timestamp,
-                }).to_string());
+            let blocked = |sender: &str| self.account_id.as_deref().is_some_and(|id| ACCOUNTS.has_blocked(id, sender));
+            let quiet = quiet::hides_presence(&self.room_id, &self.user_id, self.account_id.as_deref());
+            let hidden = |msg: &ChatMessage| blocked(&msg.sender) || (quiet && msg.presence);
+            let start = actions::history_start(&self.user(), &messages);
+            for msg in messages[start..].iter().filter(|msg| !hidden(msg)) {
+                let frame = msg.to_frame();
+                let highlighted = msg.sender != self.nickname
+                    && keywords::matches(&self.room_id, &self.user_id, self.account_id.as_deref(), &msg.content);
+                let frame = if highlighted { highlight_frame(frame) } else { frame };
+                let _ = self.sender.send(frame.to_string());
+            }
+            if let Some(focus) = &self.focus {
+                let _ = self.sender.send(permalinks::focus_frame(&messages[start..], focus));
             }
         }
+        let _ = self.sender.send(actions::read_state(&self.user(), &room_state));
+
+        let welcome_message = room_state.config.read().welcome_message.clone();
+        if let Some(welcome_message) = welcome_message {
+            let _ = self.sender.send(protocol::ephemeral(&welcome_message).to_string());
+        }
+
+        if let Some(frame) = banner::connect_frame(&room_state) {
+            let _ = self.sender.send(frame);
+        }
+
+        // Bring the whiteboard up to date
+        if let Some(frame) = room_state.whiteboard.lock().snapshot_frame() {
+            let _ = self.sender.send(frame);
+        }
 
         // Add user to room if not already there
-        {
-            let mut users = room_state.users.write();
-            if !users.contains_key(&self.user_id) {
-                users.insert(self.user_id.clone(), User {
-                    id: self.user_id.clone(),
-                    nickname: self.nickname.clone(),
-                    room_id: self.room_id.clone(),
-                });
-
-                // Add a system message
-                let mut messages = room_state.messages.write();
-                messages.push(ChatMessage {
-                    id: Uuid::new_v4().to_string(),
-                    room_id: self.room_id.clone(),
-                    sender: "System".to_string(),
-                    content: format!("{} has joined the room", self.nickname),
-                    timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
-                    message_type: MessageType::SystemMessage,
-                });
-
-                // Broadcast the join message
-                room_state.broadcast(&json!({
-                    "type": "system",
-                    "content": format!("{} has joined the room", self.nickname)
-                }).to_string());
+        for effect in room_state.apply(Event::Join(self.user())) {
+            if let Effect::Reject(error) = effect {
+                let _ = self.sender.send(error.frame());
+                self.refused = true;
+                return self.sender.close(CloseCode::Policy);
             }
         }
 
+        if let Some(account_id) = &self.account_id {
+            dashboard::remember(account_id, &self.room_id);
+            friends::announce_presence(account_id);
+        }
+
         Ok(())
     }
 
     fn on_message(&mut self, msg: Message) -> ws::Result<()> {
-        if let Ok(text) = msg.into_text() {
-            // Parse the message
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                if let Some(content) = json.get("content").and_then(|v| v.as_str()) {
-                    let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+        if self.dashboard || self.refused {
+            return Ok(());
+        }
+        self.adopt_account();
+        let text = msg.into_text().ok();
+        if let (Some(recorder), Some(text)) = (&self.recorder, &text) {
+            recorder.record("in", text);
+        }
+
+        // Parse the message
+        let json = text.and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok());
+        let Some(json) = json else {
+            let error = protocol::Error::new(ErrorCode::InvalidFrame, "Frames must be JSON text");
+            return self.sender.send(error.frame());
+        };
 
-                    // Check if it's a command
-                    if content.starts_with('/') {
-                        self.handle_command(content);
-                    } else {
-                        // Regular message
-                        let msg = ChatMessage {
-                            id: Uuid::new_v4().to_string(),
-                            room_id: self.room_id.clone(),
-                            sender: self.nickname.clone(),
-                            content: content.to_string(),
-                            timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
-                            message_type: MessageType::UserMessage,
-                        };
-
-                        // Add to history
-                        {
-                            let mut messages = room_state.messages.write();
-                            messages.push(msg.clone());
-                        }
-
-                        // Broadcast to all users in the room
-                        room_state.broadcast(&json!({
-                            "type": "message",
-                            "id": msg.id,
-                            "sender": msg.sender,
-                            "content": msg.content,
-                            "timestamp": msg.timestamp,
-                        }).to_string());
-                    }
+        // Echoed back in the ack or nack for this message
+        let client_id = json.get("client_id");
+        // Continues the client's trace, if it sent one
+        let traceparent = json.get("traceparent").and_then(|v| v.as_str());
+        match json.get("type").and_then(|v| v.as_str()).unwrap_or("message") {
+            "whiteboard" => self.handle_whiteboard(json.get("event")),
+            "location" => self.handle_location(&json, client_id, traceparent),
+            "forward" => {
+                let message_id = json.get("message_id").and_then(|v| v.as_str());
+                let room_id = json.get("room_id").and_then(|v| v.as_str());
+                let result = match (message_id, room_id) {
+                    (Some(message_id), Some(room_id)) => forwarding::forward(&self.user(), message_id, room_id),
+                    _ => Err(protocol::Error::new(ErrorCode::InvalidFrame, "Forwarding needs \"message_id\" and \"room_id\" strings")),
+                };
+                match result {
+                    Ok(id) => self.ack(client_id, Some(&id)),
+                    Err(error) => self.reject(client_id, &error),
                 }
-            }
+            },
+            "star" => {
+                let message_id = json.get("message_id").and_then(|v| v.as_str());
+                let starred = json.get("starred").and_then(|v| v.as_bool()).unwrap_or(true);
+                let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+                let reply = starred::handle(&self.user(), &room_state, message_id, starred);
+                let _ = self.sender.send(reply.unwrap_or_else(|error| error.frame()));
+            },
+            "vote" => {
+                let message_id = json.get("message_id").and_then(|v| v.as_str());
+                let vote = json.get("vote").and_then(|v| v.as_i64());
+                let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+                if let Err(error) = karma::vote(&self.user(), &room_state, message_id, vote) {
+                    let _ = self.sender.send(error.frame());
+                }
+            },
+            "join_call" => {
+                let message_id = json.get("message_id").and_then(|v| v.as_str());
+                let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+                if let Err(error) = meet::join(&self.user(), &room_state, message_id) {
+                    let _ = self.sender.send(error.frame());
+                }
+            },
+            "report" => {
+                let message_id = json.get("message_id").and_then(|v| v.as_str());
+                let reason = json.get("reason").and_then(|v| v.as_str());
+                let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+                let reply = reports::submit(&self.user(), &room_state, message_id, reason);
+                let _ = self.sender.send(reply.unwrap_or_else(|error| error.frame()));
+            },
+            "action" => {
+                let action = json.get("action").and_then(|v| v.as_str()).unwrap_or_default();
+                let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+                let _ = self.sender.send(actions::perform(&self.user(), &room_state, action, json.get("request_id")));
+            },
+            _ => match json.get("content").and_then(|v| v.as_str()) {
+                Some(content) => {
+                    let upload = json.get("upload").and_then(|v| v.as_str());
+                    self.handle_chat_message(content, upload, client_id, traceparent)
+                },
+                None => {
+                    let error = protocol::Error::new(ErrorCode::InvalidFrame, "Messages need a \"content\" string");
+                    self.reject(client_id, &error);
+                },
+            },
         }
 
         Ok(())
     }
 
+    fn on_send_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        if let Some(recorder) = &self.recorder
+            && frame.opcode() == OpCode::Text
+        {
+            recorder.record("out", &String::from_utf8_lossy(frame.payload()));
+        }
+        Ok(Some(frame))
+    }
+
     fn on_close(&mut self, _: CloseCode, _: &str) {
-        let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+        if self.dashboard {
+            return dashboard::unsubscribe(&self.sender);
+        }
+        self.adopt_account();
+        // The room may already be gone, e.g. an expired burner room
+        let room_state = CHAT_STATE.rooms.read().get(&self.room_id).cloned();
+        if let Some(room_state) = room_state {
+            self.leave(&room_state);
+        }
+
+        if let Some(account_id) = &self.account_id {
+            friends::announce_presence(account_id);
+        }
+    }
+}
 
+impl ChatSocketHandler {
+    // Picks up the account a guest registered while connected, see upgrade.rs
+    fn adopt_account(&mut self) {
+        if self.account_id.is_some() {
+            return;
+        }
+        let Some(room_state) = CHAT_STATE.rooms.read().get(&self.room_id).cloned() else {
+            return;
+        };
+        let connections = room_state.connections.read();
+        if let Some(conn) = connections.iter().find(|conn| conn.sender.connection_id() == self.sender.connection_id()) {
+            self.account_id = conn.account_id.clone();
+            self.session_id = conn.session_id.clone();
+        }
+    }
+
+    fn leave(&self, room_state: &RoomState) {
         // Remove connection from the room
         {
             let mut connections = room_state.connections.write();
-            connections.retain(|conn| conn.connection_id() != self.sender.connection_id());
+            connections.retain(|conn| conn.sender.connection_id() != self.sender.connection_id());
         }
 
         // Check if this was the last connection for this user
         let is_last_connection = {
             let connections = room_state.connections.read();
-            connections.iter().filter(|conn| {
-                // This is a simplification - in a real app, you'd need to track which connection belongs to which user
-                conn.connection_id() == self.sender.connection_id()
-            }).count() == 0
+            !connections.iter().any(|conn| conn.user_id == self.user_id)
         };
 
         if is_last_connection {
-            // Remove user from room
-            {
-                let mut users = room_state.users.write();
-                users.remove(&self.user_id);
-            }
+            room_state.apply(Event::Leave { user_id: self.user_id.clone() });
+        }
+    }
 
-            // Add a system message
-            {
-                let mut messages = room_state.messages.write();
-                messages.push(ChatMessage {
-                    id: Uuid::new_v4().to_string(),
-                    room_id: self.room_id.clone(),
-                    sender: "System".to_string(),
-                    content: format!("{} has left the room", self.nickname),
-                    timestamp: DateTime::<Utc>::from(SystemTime::now()).to_rfc3339(),
-                    message_type: MessageType::SystemMessage,
-                });
-            }
+    // `upload` is a finished chunked upload to attach, see uploads.rs
+    fn handle_chat_message(&self, content: &str, upload: Option<&str>, client_id: Option<&serde_json::Value>, traceparent: Option<&str>) {
+        // Check if it's a command
+        if content.starts_with('/') && upload.is_none() {
+            self.handle_command(content, client_id);
+            return;
+        }
 
-            // Broadcast the leave message
-            room_state.broadcast(&json!({
-                "type": "system",
-                "content": format!("{} has left the room", self.nickname)
-            }).to_string());
+        // Regular message
+        let mut msg = ChatMessage::new(&self.room_id, &self.nickname, content, MessageType::UserMessage);
+        msg.account_id = self.account_id.clone();
+        msg.trace = Some(TraceContext::continue_from(traceparent));
+        if let Some(upload) = upload {
+            match uploads::take(&uploads::Uploader::user(&self.user_id), upload, &self.room_id) {
+                Ok(attachment) => msg.attachments.push(attachment),
+                Err(error) => return self.reject(client_id, &error),
+            }
         }
+        self.publish(msg, client_id);
     }
-}
 
-impl ChatSocketHandler {
-    fn handle_command(&self, command: &str) {
-        match command {
-            "/clear" => {
-                // Clear messages for this user only
-                let _ = self.sender.send(json!({
-                    "type": "command",
-                    "command": "clear"
-                }).to_string());
-            },
-            "/logout" => {
-                // Tell the client to redirect to log out
-                let _ = self.sender.send(json!({
-                    "type": "command",
-                    "command": "logout"
-                }).to_string());
+    fn handle_location(&self, json: &serde_json::Value, client_id: Option<&serde_json::Value>, traceparent: Option<&str>) {
+        let coordinate = |key: &str| json.get(key).and_then(|v| v.as_f64()).filter(|v| v.is_finite());
+        let location = match (coordinate("lat"), coordinate("lon")) {
+            (Some(lat), Some(lon)) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) => {
+                Location { lat, lon }
             },
             _ => {
-                // Unknown command
-                let _ = self.sender.send(json!({
-                    "type": "system",
-                    "content": format!("Unknown command: {}", command)
-                }).to_string());
+                let error = protocol::Error::new(ErrorCode::InvalidLocation, "Invalid location: lat must be within ±90 and lon within ±180");
+                self.reject(client_id, &error);
+                return;
             }
+        };
+
+        let content = format!("{:.5}, {:.5}", location.lat, location.lon);
+        let mut msg = ChatMessage::new(&self.room_id, &self.nickname, &content, MessageType::Location);
+        msg.account_id = self.account_id.clone();
+        if flags::enabled(Flag::LinkPreviews, Some(&self.room_id)) {
+            msg.preview = Some(link_preview::location_preview(location.lat, location.lon));
         }
+        msg.location = Some(location);
+        msg.trace = Some(TraceContext::continue_from(traceparent));
+        self.publish(msg, client_id);
     }
-}
-
-// Start a WebSocket server in a separate thread
-fn start_websocket_server() {
-    thread::spawn(|| {
-        listen("0.0.0.0:8082", |out| {
-            ChatSocketHandler {
-                sender: out,
-                room_id: String::new(), // Will be set in on_open
-                user_id: String::new(), // Will be set in on_open
-                nickname: String::new(), // Will be set in on_open
-            }
-        }).unwrap();
-    });
-}
 
-#[rocket::launch]
-fn rocket() -> _ {
-    // Start WebSocket server
-    start_websocket_server();
-
-    // Create a templates directory if it doesn't exist
-    std::fs::create_dir_all("templates").ok();
-
-    // Create a static directory if it doesn't exist
-    std::fs::create_dir_all("static").ok();
-
-    // Create login template
-    let login_template = r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{{ title }}</title>
-    <style>
-        body {
-            font-family: Arial, sans-serif;
-            margin: 0;
-            padding: 0;
-            display: flex;
-            justify-content: center;
-            align-items: center;
-            height: 100vh;
-            background-color: #f5f5f5;
-        }
-        .login-container {
-            background-color: white;
-            padding: 2rem;
-            border-radius: 8px;
-            box-shadow: 0 2px 10px rgba(0, 0, 0, 0.1);
-            width: 100%;
-            max-width: 400px;
-        }
-        h1 {
-            margin-top: 0;
-            color: #333;
-        }
-        form {
-            display: flex;
-            flex-direction: column;
-        }
-        input {
-            padding: 0.8rem;
-            margin-bottom: 1rem;
-            border: 1px solid #ddd;
-            border-radius: 4px;
-            font-size: 1rem;
-        }
-        button {
-            padding: 0.8rem;
-            background-color: #4CAF50;
-            color: white;
-            border: none;
-            border-radius: 4px;
-            font-size: 1rem;
-            cursor: pointer;
-        }
-        button:hover {
-            background-color: #45a049;
-        }
-        @media (max-width: 480px) {
-            .login-container {
-                width: 90%;
-                padding: 1.5rem;
-            }
+    // Publishes a message from this user and tells them whether it went through
+    fn publish(&self, msg: ChatMessage, client_id: Option<&serde_json::Value>) {
+        match publish(msg) {
+            Ok(id) => self.ack(client_id, Some(&id)),
+            Err(error) => self.reject(client_id, &error),
         }
-    </style>
-</head>
-<body>
-    <div class="login-container">
-        <h1>{{ title }}</h1>
-        <form method="post">
-            <input type="text" name="nickname" placeholder="Enter your nickname" required autofocus>
-            <button type="submit">Join Chat</button>
-        </form>
-    </div>
-</body>
-</html>"#;
-
-    // Create a chat template
-    let chat_template = r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{{ title }}</title>
-    <style>
-        body {
-            font-family: Arial, sans-serif;
-            margin: 0;
-            padding: 0;
-            display: flex;
-            flex-direction: column;
-            height: 100vh;
-            background-color: #f5f5f5;
-        }
-        .chat-container {
-            display: flex;
-            flex-direction: column;
-            height: 100%;
-            max-width: 1200px;
-            margin: 0 auto;
-            width: 100%;
-            background-color: white;
-            box-shadow: 0 2px 10px rgba(0, 0, 0, 0.1);
-        }
-        .chat-header {
-            padding: 1rem;
-            background-color: #4CAF50;
-            color: white;
-            display: flex;
-            justify-content: space-between;
-            align-items: center;
-        }
-        .chat-header h1 {
-            margin: 0;
-            font-size: 1.5rem;
-        }
-        .chat-header a {
-            color: white;
-            text-decoration: none;
-        }
-        .chat-messages {
-            flex: 1;
-            overflow-y: auto;
-            padding: 1rem;
-        }
-        .message {
-            margin-bottom: 1rem;
-            padding: 0.8rem;
-            border-radius: 4px;
-            max-width: 80%;
-        }
-        .message.user {
-            background-color: #e6f7ff;
-            align-self: flex-end;
-            margin-left: auto;
-        }
-        .message.system {
-            background-color: #f0f0f0;
-            color: #666;
-            font-style: italic;
-            text-align: center;
-            max-width: 100%;
-        }
-        .message .sender {
-            font-weight: bold;
-            margin-bottom: 0.3rem;
-        }
-        .message .time {
-            font-size: 0.8rem;
-            color: #999;
-            margin-top: 0.3rem;
-        }
-        .chat-input {
-            display: flex;
-            padding: 1rem;
-            border-top: 1px solid #eee;
-        }
-        .chat-input input {
-            flex: 1;
-            padding: 0.8rem;
-            border: 1px solid #ddd;
-            border-radius: 4px;
-            font-size: 1rem;
-            margin-right: 0.5rem;
-        }
-        .chat-input button {
-            padding: 0.8rem 1.5rem;
-            background-color: #4CAF50;
-            color: white;
-            border: none;
-            border-radius: 4px;
-            font-size: 1rem;
-            cursor: pointer;
-        }
-        .chat-input button:hover {
-            background-color: #45a049;
-        }
-        @media (max-width: 768px) {
-            .chat-header h1 {
-                font-size: 1.2rem;
-            }
-            .message {
-                max-width: 90%;
-            }
-        }
-    </style>
-</head>
-<body>
-    <div class="chat-container">
-        <div class="chat-header">
-            <h1>{{ title }}</h1>
-            <a href="/logout">Logout</a>
-        </div>
-        <div class="chat-messages" id="messages"></div>
-        <div class="chat-input">
-            <input type="text" id="message-input" placeholder="Type a message..." autocomplete="off">
-            <button id="send-button">Send</button>
-        </div>
-    </div>
-
-    <script>
-        const nickname = "{{ nickname }}";
-        const roomId = "{{ room_id }}";
-        const wsPath = "{{ ws_path }}";
-        // Use port 8082 for WebSocket connections
-        const wsUrl = "ws://" + window.location.hostname + ":8082" + wsPath;
-
-        let ws;
-
-        function connect() {
-            ws = new WebSocket(wsUrl);
-
-            ws.onopen = function() {
-                console.log("Connected to WebSocket");
-            };
-
-            ws.onmessage = function(event) {
-                const data = JSON.parse(event.data);
+    }
 
-                if (data.type === "command") {
-                    handleCommand(data);
-                } else {
-                    addMessage(data);
-                }
-            };
+    // Confirms a message or command was accepted. `client_id` is whatever
+    // the client sent along to match the two up; `id` is the stored
+    // message's id, if one was posted.
+    fn ack(&self, client_id: Option<&serde_json::Value>, id: Option<&str>) {
+        let seq = self.acked.get() + 1;
+        self.acked.set(seq);
+        let _ = self.sender.send(json!({
+            "type": "ack",
+            "client_id": client_id,
+            "id": id,
+            "seq": seq,
+        }).to_string());
+    }
 
-            ws.onclose = function() {
-                console.log("Disconnected from WebSocket");
-                // Try to reconnect after a delay
-                setTimeout(connect, 3000);
-            };
+    // A nack for frames the client can match up by `client_id`, otherwise a
+    // plain error frame
+    fn reject(&self, client_id: Option<&serde_json::Value>, error: &protocol::Error) {
+        let frame = match client_id {
+            Some(client_id) => json!({
+                "type": "nack",
+                "client_id": client_id,
+                "code": error.code,
+                "detail": error.detail,
+            }).to_string(),
+            None => error.frame(),
+        };
+        let _ = self.sender.send(frame);
+    }
 
-            ws.onerror = function(error) {
-                console.error("WebSocket error:", error);
-            };
+    // Whiteboard events are relayed to the rest of the room, never stored as chat
+    fn handle_whiteboard(&self, event: Option<&serde_json::Value>) {
+        let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+        let locked = room_state.config.read().locked;
+        let result = event
+            .ok_or(protocol::Error::new(ErrorCode::InvalidFrame, "Missing whiteboard event"))
+            .and_then(|event| match locked {
+                true => Err(protocol::Error::new(ErrorCode::RoomLocked, "This room is locked")),
+                false => Ok(event),
+            })
+            .and_then(|event| {
+                room_state.whiteboard.lock().apply(event).map_err(|reason| {
+                    protocol::Error::new(ErrorCode::InvalidWhiteboard, format!("Whiteboard event rejected: {}", reason))
+                })
+            });
+
+        match result {
+            Ok(()) => {
+                audit::record(&self.room_id, "whiteboard", &self.nickname, json!(event));
+                let frame = json!({
+                    "type": "whiteboard",
+                    "sender": self.nickname,
+                    "event": event,
+                }).to_string();
+                let connection_id = self.sender.connection_id();
+                room_state.send_where(&frame, |conn| conn.sender.connection_id() != connection_id);
+            },
+            Err(error) => {
+                let _ = self.sender.send(error.frame());
+            }
         }
+    }
 
-        function handleCommand(data) {
-            switch (data.command) {
-                case "clear":
-                    document.getElementById("messages").innerHTML = "";
-                    break;
-                case "logout":
-                    window.location.href = "/logout";
-                    break;
+    fn handle_command(&self, command: &str, client_id: Option<&serde_json::Value>) {
+        match run_command(&self.user(), command) {
+            CommandOutput::Reply(content) => {
+                self.ack(client_id, None);
+                let _ = self.sender.send(protocol::ephemeral(&content).to_string());
+            },
+            CommandOutput::Hint(reply) => {
+                self.ack(client_id, None);
+                let _ = self.sender.send(protocol::hint(&reply.sender, &reply.content).to_string());
+            },
+            CommandOutput::Message(msg) => self.publish(*msg, client_id),
+            CommandOutput::Error(error) => self.reject(client_id, &error),
+            CommandOutput::Bot(reply) => {
+                self.ack(client_id, None);
+                let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+                room_state.post(ChatMessage::new(&self.room_id, &reply.sender, &reply.content, MessageType::Bot));
+            },
+            CommandOutput::Client(command) => {
+                // /clear is remembered like the clear_history action
+                if command == "clear" {
+                    let room_state = CHAT_STATE.get_or_create_room(&self.room_id);
+                    actions::perform(&self.user(), &room_state, "clear_history", None);
+                }
+                // Tell the client to act, e.g. clear its view or log out
+                self.ack(client_id, None);
+                let _ = self.sender.send(json!({
+                    "type": "command",
+                    "command": command
+                }).to_string());
             }
         }
+    }
 
-        function addMessage(data) {
-            const messagesDiv = document.getElementById("messages");
-            const messageDiv = document.createElement("div");
+    fn user(&self) -> User {
+        User {
+            id: self.user_id.clone(),
+            nickname: self.nickname.clone(),
+            room_id: self.room_id.clone(),
+            account_id: self.account_id.clone(),
+            session_id: self.session_id.clone(),
+        }
+    }
+}
 
-            messageDiv.className = `message ${data.type}`;
+// Runs a new message from a user through the room's limits and plugins,
+// stores and broadcasts it. Returns the id it was stored under.
+fn publish(msg: ChatMessage) -> Result<String, protocol::Error> {
+    maintenance::check()?;
+    let room_state = CHAT_STATE.get_or_create_room(&msg.room_id);
+    let mut msg = match room_state.apply(Event::Post(Box::new(msg))).pop() {
+        Some(Effect::Publish(msg)) => *msg,
+        Some(Effect::Reject(error)) => return Err(error),
+        effect => unreachable!("posting gave {:?}", effect),
+    };
+    if let Err(wait) = rate_limit::check(&msg.room_id, &msg.sender) {
+        return Err(escalation::strike(&msg.room_id, &msg.sender, protocol::Error::new(
+            ErrorCode::RateLimited,
+            format!("You're sending messages too fast, try again in {} seconds", wait.as_secs().max(1)),
+        )));
+    }
 
-            if (data.type === "message") {
-                const senderDiv = document.createElement("div");
-                senderDiv.className = "sender";
-                senderDiv.textContent = data.sender;
-                messageDiv.appendChild(senderDiv);
+    // Let plugins rewrite or drop the message before it is stored
+    if let MessageVerdict::Reject(reason) = PLUGINS.filter_message(&mut msg) {
+        let error = protocol::Error::new(ErrorCode::Rejected, format!("Message rejected: {}", reason));
+        return Err(escalation::strike(&msg.room_id, &msg.sender, error));
+    }
 
-                const contentDiv = document.createElement("div");
-                contentDiv.className = "content";
-                contentDiv.textContent = data.content;
-                messageDiv.appendChild(contentDiv);
+    highlight::annotate(&mut msg);
+    anonymous::mask(&room_state, &mut msg);
 
-                const timeDiv = document.createElement("div");
-                timeDiv.className = "time";
-                timeDiv.textContent = new Date(data.timestamp).toLocaleTimeString();
-                messageDiv.appendChild(timeDiv);
-            } else if (data.type === "system") {
-                messageDiv.textContent = data.content;
-            }
+    // Add to history and broadcast to all users in the room
+    room_state.post(msg.clone());
 
-            messagesDiv.appendChild(messageDiv);
-            messagesDiv.scrollTop = messagesDiv.scrollHeight;
-        }
+    // Replies are part of the message's trace
+    if !flags::enabled(Flag::Bots, Some(&msg.room_id)) {
+        return Ok(msg.id);
+    }
+    for reply in PLUGINS.message_posted(&msg) {
+        let mut reply = ChatMessage::new(&msg.room_id, &reply.sender, &reply.content, MessageType::Bot);
+        reply.trace = msg.trace.as_ref().map(TraceContext::child);
+        room_state.post(reply);
+    }
+    Ok(msg.id)
+}
 
-        document.getElementById("send-button").addEventListener("click", sendMessage);
-        document.getElementById("message-input").addEventListener("keypress", function(e) {
-            if (e.key === "Enter") {
-                sendMessage();
-            }
-        });
+// Runs a message from outside the chat (the message API, inbound email,
+// incoming webhooks, MQTT) through the plugins' filters, then stores and
+// broadcasts it. Mutes and rate limits are for people and don't apply.
+// Returns the message as stored, or why a plugin turned it down.
+fn publish_bot(room_state: &RoomState, mut msg: ChatMessage) -> Result<ChatMessage, String> {
+    if let MessageVerdict::Reject(reason) = PLUGINS.filter_message(&mut msg) {
+        return Err(format!("Message rejected: {}", reason));
+    }
+    highlight::annotate(&mut msg);
+    room_state.post(msg.clone());
+    Ok(msg)
+}
 
-        function sendMessage() {
-            const input = document.getElementById("message-input");
-            const message = input.value.trim();
+// Runs a slash command through the room's rules, then executes it
+fn run_command(user: &User, command: &str) -> CommandOutput {
+    let room_state = CHAT_STATE.get_or_create_room(&user.room_id);
+    let event = Event::Command { user: user.clone(), command: command.to_string() };
+    match room_state.apply(event).pop() {
+        Some(Effect::RunCommand { user, command }) => execute_command(&user, &command),
+        Some(Effect::Reject(error)) => CommandOutput::Error(error),
+        effect => unreachable!("a command gave {:?}", effect),
+    }
+}
 
-            if (message && ws && ws.readyState === WebSocket.OPEN) {
-                ws.send(JSON.stringify({
-                    content: message
-                }));
+// Built-in commands first, then plugins, before reporting an unknown command
+fn execute_command(user: &User, command: &str) -> CommandOutput {
+    let (name, args) = command[1..].split_once(' ').unwrap_or((&command[1..], ""));
+    let ctx = CommandContext {
+        user,
+        args: args.trim(),
+    };
+
+    let output = COMMANDS
+        .run(name, &ctx)
+        .or_else(|| PLUGINS.dispatch_command(&user.room_id, user, name, ctx.args).map(CommandOutput::Bot))
+        .unwrap_or_else(|| CommandOutput::error(ErrorCode::UnknownCommand, format!("Unknown command: {}", command)));
+    match output {
+        CommandOutput::Bot(_) if !flags::enabled(Flag::Bots, Some(&user.room_id)) => CommandOutput::Error(Flag::Bots.error()),
+        CommandOutput::Bot(reply) => match maintenance::check() {
+            Ok(()) => CommandOutput::Bot(reply),
+            Err(err) => CommandOutput::Error(err),
+        },
+        output => output,
+    }
+}
 
-                input.value = "";
-            } else if (message && (!ws || ws.readyState !== WebSocket.OPEN)) {
-                console.log("WebSocket not connected, attempting to reconnect...");
-                connect();
+// Start a WebSocket server in a separate thread
+fn start_websocket_server() {
+    thread::spawn(|| {
+        let settings = ws::Settings {
+            max_connections: CONFIG.ws_max_connections,
+            queue_size: CONFIG.ws_queue_size,
+            ..ws::Settings::default()
+        };
+        let server = ws::Builder::new().with_settings(settings).build(|out| {
+            ChatSocketHandler {
+                sender: out,
+                room_id: String::new(), // Will be set in on_open
+                user_id: String::new(), // Will be set in on_open
+                nickname: String::new(), // Will be set in on_open
+                account_id: None, // Will be set in on_open
+                session_id: None, // Will be set in on_open
+                acked: Cell::new(0),
+                recorder: None,
+                focus: None,
+                dashboard: false,
+                refused: false,
             }
-        }
+        }).unwrap();
+        server.listen(("0.0.0.0", CONFIG.ws_port)).unwrap();
+    });
+}
 
-        // Connect to WebSocket when page loads
-        connect();
-    </script>
-</body>
-</html>"#;
+fn main() {
+    // `who-chat simulate ...` generates traffic against a running server instead,
+    // `who-chat backup|restore ...` snapshot or restore the data directory and
+    // `who-chat migrate ...` brings it up to date
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("simulate") => return simulate::run(args),
+        Some(command @ ("backup" | "restore")) => return backup::run(command, args),
+        Some("migrate") => return migrations::run_command(args),
+        _ => {},
+    }
+    let _ = rocket::async_main(rocket().launch());
+}
 
-    // Write templates to files
-    std::fs::write("templates/login.html.hbs", login_template).ok();
-    std::fs::write("templates/chat.html.hbs", chat_template).ok();
+fn rocket() -> Rocket<Build> {
+    migrations::run();
+    // Load plugins before any room or connection can trigger a hook
+    lazy_static::initialize(&PLUGINS);
 
-    rocket::build()
-        .mount("/", rocket::routes![index, login, logout])
-        .mount("/static", FileServer::from(relative!("static")))
-        .attach(Template::fairing())
+    // Start WebSocket server
+    start_websocket_server();
+    tasks::start();
+    calendars::start();
+    setup::announce();
+    #[cfg(feature = "mqtt")]
+    mqtt::start();
+
+    let rocket = rocket::custom(CONFIG.http.figment().merge(("template_dir", templates::override_dir())))
+        .mount(proxy::url("/"), rocket::routes![index, login, logout, paste])
+        .mount(proxy::url("/"), pwa::routes())
+        .mount(proxy::url("/"), seo::routes())
+        .mount(proxy::url("/"), metrics::routes())
+        .mount(proxy::url("/"), inbox::routes())
+        .mount(proxy::url("/"), dashboard::routes())
+        .mount(proxy::url("/"), starred::routes())
+        .mount(proxy::url("/"), events::routes())
+        .mount(proxy::url("/"), karma::routes())
+        .mount(proxy::url("/"), calendars::routes())
+        .mount(proxy::url("/"), attachments::routes())
+        .mount(proxy::url("/"), setup::routes())
+        .mount(proxy::url("/rooms"), basic::routes())
+        .mount(proxy::url("/rooms"), directory::routes())
+        .mount(proxy::url("/rooms"), room_log::routes())
+        .mount(proxy::url("/rooms"), preview::routes())
+        .mount(proxy::url("/rooms"), permalinks::routes())
+        .mount(proxy::url("/api/admin"), admin::routes())
+        .mount(proxy::url("/api/admin"), recording::routes())
+        .mount(proxy::url("/api/admin"), banner::routes())
+        .mount(proxy::url("/api/admin"), backup::routes())
+        .mount(proxy::url("/api/admin"), flags::routes())
+        .mount(proxy::url("/api/admin"), maintenance::routes())
+        .mount(proxy::url("/api/admin"), honeypot::routes())
+        .mount(proxy::url("/api/admin"), login_throttle::routes())
+        .mount(proxy::url("/api/admin"), room_merge::routes())
+        .mount(proxy::url("/api/admin"), reports::routes())
+        .mount(proxy::url("/admin"), reports::page_routes())
+        .mount(proxy::url("/api/account"), accounts::routes())
+        .mount(proxy::url("/api/account/totp"), totp::routes())
+        .mount(proxy::url("/api/account"), upgrade::routes())
+        .mount(proxy::url("/api/account/passkeys"), passkeys::routes())
+        .mount(proxy::url("/api/passkeys"), passkeys::sign_in_routes())
+        .mount(proxy::url("/api/sessions"), sessions::routes())
+        .mount(proxy::url("/api/friends"), friends::routes())
+        .mount(proxy::url("/api/blocks"), blocking::routes())
+        .mount(proxy::url("/api/rooms"), rooms::routes())
+        .mount(proxy::url("/api/rooms"), membership::routes())
+        .mount(proxy::url("/api/presence"), presence::routes())
+        .mount(proxy::url("/api/quota"), quota::routes())
+        .mount(proxy::url("/api/search"), search::routes())
+        .mount(proxy::url("/api/uploads"), uploads::routes())
+        .mount(proxy::url("/api/users"), user_data::routes())
+        .mount(proxy::url("/api/ws-config"), ws_config::routes())
+        .mount(proxy::url("/api/ws-ticket"), ws_config::ticket_routes())
+        .mount(proxy::url("/api/server"), capabilities::routes())
+        .mount(proxy::url("/api/pow"), pow::routes())
+        .mount(proxy::url("/scim/v2"), scim::routes())
+        .mount(proxy::url("/email"), email::routes())
+        .mount(proxy::url("/hooks"), incoming_webhooks::routes())
+        .mount(proxy::url("/static"), assets::routes())
+        .attach(templates::fairing())
+        .attach(security::shield())
+        .attach(security::SecurityHeaders);
+    #[cfg(feature = "chaos")]
+    let rocket = rocket.mount(proxy::url("/api/admin"), chaos::routes());
+    rocket
 }
@@ -0,0 +1,137 @@
+// Read-only mode for the whole server, e.g. during a migration or while an
+// incident is looked into. While it's on, nothing new is posted: chat
+// messages are answered with
+//
+//   {"type": "error", "code": "READ_ONLY", "detail": "<the notice>", "ephemeral": true}
+//
+// (or a nack), the message API, incoming webhooks and inbound email get 503,
+// and MQTT payloads are dropped. Every room shows the notice as a critical
+// banner in place of its own. Server admins turn it on and off with
+//
+//   GET    /api/admin/maintenance    {"read_only": bool, "message", "since"}
+//   PUT    /api/admin/maintenance    {"message": "..."}   message optional
+//   DELETE /api/admin/maintenance
+//
+// The mode is kept in the data directory, so a restart doesn't end it.
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::admin::{ApiResult, ServerAdmin, api_error};
+use crate::banner::{self, Banner, Severity};
+use crate::protocol::{self, ErrorCode};
+use crate::{CHAT_STATE, RoomState, storage};
+
+const DEFAULT_MESSAGE: &str = "The server is in read-only maintenance mode; you can read but not send for now.";
+const MAX_MESSAGE_LEN: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Maintenance {
+    message: String,
+    // RFC 3339
+    since: String,
+}
+
+lazy_static! {
+    static ref MODE: RwLock<Option<Maintenance>> = RwLock::new(storage::load("maintenance", "mode"));
+}
+
+pub fn read_only() -> bool {
+    MODE.read().is_some()
+}
+
+// The notice to show in every room while the server is read-only
+pub fn banner() -> Option<Banner> {
+    MODE.read().as_ref().map(|mode| Banner {
+        text: mode.message.clone(),
+        severity: Severity::Critical,
+        expires_at: None,
+    })
+}
+
+// Ok unless the server is read-only
+pub fn check() -> Result<(), protocol::Error> {
+    match &*MODE.read() {
+        Some(mode) => Err(protocol::Error::new(ErrorCode::ReadOnly, mode.message.clone())),
+        None => Ok(()),
+    }
+}
+
+// The same for HTTP handlers
+pub fn check_api() -> Result<(), (Status, Json<Value>)> {
+    check().map_err(|err| api_error(Status::ServiceUnavailable, err.detail))
+}
+
+// Tells every room which banner to show now
+fn announce() {
+    let rooms: Vec<RoomState> = CHAT_STATE.rooms.read().values().cloned().collect();
+    for room in rooms {
+        banner::refresh(&room);
+    }
+}
+
+fn status() -> Value {
+    let mode = MODE.read();
+    json!({
+        "read_only": mode.is_some(),
+        "message": mode.as_ref().map(|mode| &mode.message),
+        "since": mode.as_ref().map(|mode| &mode.since),
+    })
+}
+
+#[derive(Deserialize)]
+struct Enable {
+    message: Option<String>,
+}
+
+#[rocket::get("/maintenance")]
+fn get(_admin: ServerAdmin) -> Json<Value> {
+    Json(status())
+}
+
+#[rocket::put("/maintenance", data = "<enable>")]
+fn put(_admin: ServerAdmin, enable: Option<Json<Enable>>) -> ApiResult {
+    let message = enable
+        .and_then(|enable| enable.into_inner().message)
+        .map(|message| message.trim().to_string())
+        .filter(|message| !message.is_empty())
+        .unwrap_or_else(|| DEFAULT_MESSAGE.to_string());
+    if message.chars().count() > MAX_MESSAGE_LEN {
+        return Err(api_error(Status::BadRequest, format!("Maintenance notices are limited to {} characters", MAX_MESSAGE_LEN)));
+    }
+
+    {
+        let mut mode = MODE.write();
+        // Keeps when it started if only the notice changes
+        let since = mode.as_ref().map_or_else(|| Utc::now().to_rfc3339(), |mode| mode.since.clone());
+        let enabled = Maintenance { message, since };
+        storage::save("maintenance", "mode", &enabled).map_err(|err| api_error(Status::InternalServerError, err))?;
+        *mode = Some(enabled);
+    }
+    announce();
+    Ok(Json(status()))
+}
+
+#[rocket::delete("/maintenance")]
+fn delete(_admin: ServerAdmin) -> ApiResult {
+    {
+        let mut mode = MODE.write();
+        if mode.is_none() {
+            return Err(api_error(Status::NotFound, "The server isn't in maintenance mode"));
+        }
+        storage::remove("maintenance", "mode").map_err(|err| api_error(Status::InternalServerError, err))?;
+        *mode = None;
+    }
+    announce();
+    Ok(Json(status()))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![get, put, delete]
+}
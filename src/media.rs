@@ -0,0 +1,129 @@
+// Expiring signed links to media, so files can sit behind a CDN without
+// permanent public paths. With keys set in the [media] table, links get
+//
+//   ?expires=<unix seconds>&sig=<hex HMAC-SHA256 of "<resource>.<expires>">
+//
+// signed with the first key and checked against all of them, so a new key
+// goes first and the old one stays until its links have run out. Expiry is
+// rounded up to a whole url_ttl_secs, so a link stays the same, and
+// cacheable, for that long. base_url points links at the CDN.
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::{CONFIG, MediaConfig};
+use crate::secrets;
+
+fn mac(key: &str, resource: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(format!("{}.{}", resource, expires).as_bytes());
+    mac
+}
+
+fn signature(key: &str, resource: &str, expires: i64) -> String {
+    hex::encode(mac(key, resource, expires).finalize().into_bytes())
+}
+
+// Whether sig is a current signature of resource under one of the keys
+fn signed_by(keys: &[String], resource: &str, expires: i64, sig: &str, now: i64) -> bool {
+    if expires <= now {
+        return false;
+    }
+    let Ok(sig) = hex::decode(sig) else {
+        return false;
+    };
+    // Checked as MACs so the comparison takes the same time however much matches
+    keys.iter().any(|key| mac(key, resource, expires).verify_slice(&sig).is_ok())
+}
+
+// A link to `path`, signed for `resource` when there are keys
+pub fn link(path: &str, resource: &str) -> String {
+    signed_link(&CONFIG.media, path, resource)
+}
+
+fn signed_link(media: &MediaConfig, path: &str, resource: &str) -> String {
+    let base = media.base_url.as_deref().unwrap_or("").trim_end_matches('/');
+    let Some(key) = media.keys.first().map(|key| secrets::resolve(key)) else {
+        return format!("{}{}", base, path);
+    };
+    let ttl = media.url_ttl_secs.max(1) as i64;
+    let expires = (Utc::now().timestamp() / ttl + 2) * ttl;
+    format!("{}{}?expires={}&sig={}", base, path, expires, signature(&key, resource, expires))
+}
+
+// Whether a link may be served; links without a signature only are when
+// there are no keys
+pub fn verify(resource: &str, expires: Option<i64>, sig: Option<&str>) -> bool {
+    allowed(&CONFIG.media, resource, expires, sig)
+}
+
+fn allowed(media: &MediaConfig, resource: &str, expires: Option<i64>, sig: Option<&str>) -> bool {
+    if media.keys.is_empty() {
+        return true;
+    }
+    let (Some(expires), Some(sig)) = (expires, sig) else {
+        return false;
+    };
+    let keys: Vec<String> = media.keys.iter().flat_map(|key| secrets::accepted(key)).collect();
+    signed_by(&keys, resource, expires, sig, Utc::now().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<String> {
+        vec!["new-key".to_string(), "old-key".to_string()]
+    }
+
+    #[test]
+    fn accepts_its_own_signatures() {
+        let sig = signature("new-key", "abc.png", 2000);
+        assert!(signed_by(&keys(), "abc.png", 2000, &sig, 1000));
+        // Links signed before a rotation still work
+        let sig = signature("old-key", "abc.png", 2000);
+        assert!(signed_by(&keys(), "abc.png", 2000, &sig, 1000));
+    }
+
+    #[test]
+    fn matches_a_known_signature() {
+        assert_eq!(signature("key", "file", 60), "cfe0cf9d724d8bc9b8da0a104aca7b1b8edaaecba2296e7fa77212cbe6c7be5d");
+    }
+
+    #[test]
+    fn rejects_tampered_links() {
+        let sig = signature("new-key", "abc.png", 2000);
+        assert!(!signed_by(&keys(), "other.png", 2000, &sig, 1000));
+        assert!(!signed_by(&keys(), "abc.png", 3000, &sig, 1000));
+        assert!(!signed_by(&keys(), "abc.png", 2000, &sig[2..], 1000));
+        assert!(!signed_by(&keys(), "abc.png", 2000, "not hex", 1000));
+        assert!(!signed_by(&["another-key".to_string()], "abc.png", 2000, &sig, 1000));
+    }
+
+    #[test]
+    fn rejects_expired_links() {
+        let sig = signature("new-key", "abc.png", 2000);
+        assert!(!signed_by(&keys(), "abc.png", 2000, &sig, 2000));
+        assert!(!signed_by(&keys(), "abc.png", 2000, &sig, 2001));
+    }
+
+    #[test]
+    fn links_are_plain_without_keys() {
+        let media = MediaConfig { base_url: Some("https://cdn.example.com/".to_string()), ..MediaConfig::default() };
+        assert_eq!(signed_link(&media, "/attachments/abc.png", "abc.png"), "https://cdn.example.com/attachments/abc.png");
+        assert!(allowed(&media, "abc.png", None, None));
+    }
+
+    #[test]
+    fn signed_links_verify() {
+        let media = MediaConfig { keys: keys(), ..MediaConfig::default() };
+        let link = signed_link(&media, "/attachments/abc.png", "abc.png");
+        let query = link.strip_prefix("/attachments/abc.png?expires=").unwrap();
+        let (expires, sig) = query.split_once("&sig=").unwrap();
+        let expires: i64 = expires.parse().unwrap();
+        assert!(allowed(&media, "abc.png", Some(expires), Some(sig)));
+        assert!(!allowed(&media, "other.png", Some(expires), Some(sig)));
+        assert!(!allowed(&media, "abc.png", None, None));
+    }
+}
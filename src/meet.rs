@@ -0,0 +1,90 @@
+// Calls on an external conference service. `/meet [topic]` posts a call
+// invitation, a message of type "call" carrying
+//
+//   "call": {"url": "<conference link>", "joined": ["alice", ...]}
+//
+// with the link made from the meet_url setting, where {room} is the room id
+// and {slug} a random one per call (Jitsi by default). Clients send
+//
+//   {"type": "join_call", "message_id": "<id>"}
+//
+// when someone clicks through, and the room gets back
+//
+//   {"type": "call_joined", "message_id": ..., "nickname": ..., "joined": [...]}
+//
+// so everyone sees who went. Joins are kept on the invitation and recorded
+// in the room's audit log.
+
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::config::CONFIG;
+use crate::protocol::{self, ErrorCode};
+use crate::{ChatMessage, MessageType, RoomState, User, audit};
+
+const MAX_TOPIC_LEN: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Call {
+    pub url: String,
+    // Nicknames of everyone who clicked join, first join first
+    #[serde(default)]
+    pub joined: Vec<String>,
+}
+
+fn call_url(room_id: &str) -> String {
+    let slug = Uuid::new_v4().simple().to_string();
+    let room: String = url::form_urlencoded::byte_serialize(room_id.as_bytes()).collect();
+    CONFIG.meet_url.replace("{room}", &room).replace("{slug}", &slug[..12])
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("meet", "/meet [topic] - start a call and invite the room", meet);
+}
+
+fn meet(ctx: &CommandContext) -> CommandOutput {
+    let topic = ctx.args.trim();
+    if topic.chars().count() > MAX_TOPIC_LEN {
+        return CommandOutput::error(ErrorCode::InvalidArguments, format!("Topics are limited to {} characters", MAX_TOPIC_LEN));
+    }
+    let call = Call {
+        url: call_url(&ctx.user.room_id),
+        joined: Vec::new(),
+    };
+    let content = match topic {
+        "" => format!("{} started a call: {}", ctx.user.nickname, call.url),
+        topic => format!("{} started a call about {}: {}", ctx.user.nickname, topic, call.url),
+    };
+    let mut msg = ChatMessage::new(&ctx.user.room_id, &ctx.user.nickname, &content, MessageType::Call);
+    msg.account_id = ctx.user.account_id.clone();
+    msg.call = Some(call);
+    CommandOutput::Message(Box::new(msg))
+}
+
+// Records that the user clicked join on the invitation and tells the room
+pub fn join(user: &User, room: &RoomState, message_id: Option<&str>) -> Result<(), protocol::Error> {
+    let message_id = message_id.ok_or(protocol::Error::new(ErrorCode::InvalidFrame, "Joining a call needs a \"message_id\" string"))?;
+    let joined = {
+        let mut messages = room.messages.write();
+        let call = messages
+            .iter_mut()
+            .find(|msg| msg.id == message_id)
+            .and_then(|msg| msg.call.as_mut())
+            .ok_or(protocol::Error::new(ErrorCode::InvalidArguments, "No such call in this room"))?;
+        if !call.joined.contains(&user.nickname) {
+            call.joined.push(user.nickname.clone());
+        }
+        call.joined.clone()
+    };
+
+    audit::record(&room.id, "call_join", &user.nickname, json!({ "message_id": message_id }));
+    room.broadcast(&json!({
+        "type": "call_joined",
+        "message_id": message_id,
+        "nickname": user.nickname,
+        "joined": joined,
+    }).to_string());
+    Ok(())
+}
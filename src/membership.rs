@@ -0,0 +1,213 @@
+// Membership of private rooms, for external systems (an LMS, a ticketing
+// tool) that decide who gets in:
+//
+//   GET    /api/rooms/<room_id>/members
+//   PUT    /api/rooms/<room_id>/members/<username>   {"role": "admin"|"moderator"|null}
+//   DELETE /api/rooms/<room_id>/members/<username>
+//
+// with the admin token or an admin:rooms API token. Adding the first member
+// makes the room private: only member accounts can join it from then on,
+// and the room keeps its member list (and members' roles) across restarts.
+// Removing a member takes away their role and puts them out of the room.
+// DM rooms are managed by their friendship and can't be changed here.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+use ws::CloseCode;
+
+use crate::accounts::ACCOUNTS;
+use crate::admin::{Admin, ApiResult, api_error};
+use crate::rooms::{INVALID_ROOM_ID, valid_room_id};
+use crate::room_core::Event;
+use crate::{CHAT_STATE, Role, RoomConfig, RoomState, audit, storage};
+
+// account id -> room role, None for plain members
+type Members = BTreeMap<String, Option<Role>>;
+
+lazy_static! {
+    // room id -> members
+    static ref MEMBERS: RwLock<HashMap<String, Members>> =
+        RwLock::new(storage::load("memberships", "rooms").unwrap_or_default());
+}
+
+fn save(all: &HashMap<String, Members>) {
+    if let Err(err) = storage::save("memberships", "rooms", all) {
+        eprintln!("Failed to save room memberships: {}", err);
+    }
+}
+
+// Makes a newly loaded room private with its members' roles, if it's managed here
+pub fn restore(room_id: &str, config: &mut RoomConfig) {
+    let all = MEMBERS.read();
+    let Some(members) = all.get(room_id) else {
+        return;
+    };
+    config.members = Some(members.keys().cloned().collect::<BTreeSet<String>>());
+    for (account_id, role) in members {
+        if let Some(role) = role {
+            config.roles.insert(account_id.clone(), *role);
+        }
+    }
+}
+
+// Moves `from`'s managed members to `into`, keeping the roles `into` gave them
+pub fn merge(from: &str, into: &str) {
+    let mut all = MEMBERS.write();
+    let Some(moved) = all.remove(from) else {
+        return;
+    };
+    let members = all.entry(into.to_string()).or_default();
+    for (account_id, role) in moved {
+        members.entry(account_id).or_insert(role);
+    }
+    save(&all);
+}
+
+// Moves the accounts' memberships, with their roles, from one room to another
+pub fn transfer(from: &str, to: &str, account_ids: &[String]) {
+    let mut all = MEMBERS.write();
+    let Some(members) = all.get_mut(from) else {
+        return;
+    };
+    let moved: Members = account_ids
+        .iter()
+        .filter_map(|account_id| members.remove_entry(account_id))
+        .collect();
+    if moved.is_empty() {
+        return;
+    }
+    all.entry(to.to_string()).or_default().extend(moved);
+    save(&all);
+}
+
+fn to_json(room_id: &str, members: &Members) -> Value {
+    let members: Vec<Value> = members
+        .iter()
+        .map(|(account_id, role)| {
+            let username = ACCOUNTS.get(account_id).map(|account| account.username);
+            json!({ "account_id": account_id, "username": username, "role": role })
+        })
+        .collect();
+    json!({ "room_id": room_id, "members": members })
+}
+
+fn check_room_id(room_id: &str) -> Result<(), (Status, Json<Value>)> {
+    if !valid_room_id(room_id) {
+        return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+    }
+    Ok(())
+}
+
+// Takes the users `filter` picks by account out of the room and closes their connections
+fn put_out(room: &RoomState, filter: impl Fn(Option<&str>) -> bool) {
+    let user_ids: Vec<String> = room
+        .users
+        .read()
+        .values()
+        .filter(|user| filter(user.account_id.as_deref()))
+        .map(|user| user.id.clone())
+        .collect();
+    for user_id in user_ids {
+        room.apply(Event::Leave { user_id });
+    }
+    for conn in room.connections.read().iter().filter(|conn| filter(conn.account_id.as_deref())) {
+        let _ = conn.sender.close(CloseCode::Policy);
+    }
+    CHAT_STATE
+        .ws_tickets
+        .write()
+        .retain(|_, ticket| !(*ticket.user.room_id == *room.id && filter(ticket.user.account_id.as_deref())));
+}
+
+#[rocket::get("/<room_id>/members")]
+fn list_members(_admin: Admin, room_id: &str) -> ApiResult {
+    let all = MEMBERS.read();
+    let Some(members) = all.get(room_id) else {
+        return Err(api_error(Status::NotFound, "This room has no managed members"));
+    };
+    Ok(Json(to_json(room_id, members)))
+}
+
+#[derive(Deserialize)]
+struct MemberUpdate {
+    #[serde(default)]
+    role: Option<Role>,
+}
+
+#[rocket::put("/<room_id>/members/<username>", data = "<update>")]
+fn put_member(_admin: Admin, room_id: &str, username: &str, update: Option<Json<MemberUpdate>>) -> ApiResult {
+    check_room_id(room_id)?;
+    let Some(account) = ACCOUNTS.find(username) else {
+        return Err(api_error(Status::NotFound, "No such account"));
+    };
+    let role = update.and_then(|update| update.role);
+
+    let members = {
+        let mut all = MEMBERS.write();
+        let members = all.entry(room_id.to_string()).or_default();
+        members.insert(account.id.clone(), role);
+        let members = members.clone();
+        save(&all);
+        members
+    };
+
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    {
+        let mut config = room.config.write();
+        config.members.get_or_insert_default().insert(account.id.clone());
+        match role {
+            Some(role) => config.roles.insert(account.id.clone(), role),
+            None => config.roles.remove(&account.id),
+        };
+        config.version += 1;
+    }
+    // Whoever was already in a room that just went private has to leave
+    if members.len() == 1 {
+        put_out(&room, |account_id| account_id != Some(account.id.as_str()));
+    }
+    audit::record(room_id, "member_added", "admin", json!({ "account_id": account.id, "role": role }));
+    Ok(Json(to_json(room_id, &members)))
+}
+
+#[rocket::delete("/<room_id>/members/<username>")]
+fn delete_member(_admin: Admin, room_id: &str, username: &str) -> ApiResult {
+    check_room_id(room_id)?;
+    let Some(account) = ACCOUNTS.find(username) else {
+        return Err(api_error(Status::NotFound, "No such account"));
+    };
+    let members = {
+        let mut all = MEMBERS.write();
+        let removed = all.get_mut(room_id).and_then(|members| members.remove(&account.id));
+        if removed.is_none() {
+            return Err(api_error(Status::NotFound, "That account isn't a member of this room"));
+        }
+        let members = all.get(room_id).cloned().unwrap_or_default();
+        save(&all);
+        members
+    };
+
+    // The room stays private, even with nobody left in it
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    {
+        let mut config = room.config.write();
+        if let Some(members) = &mut config.members {
+            members.remove(&account.id);
+        }
+        config.roles.remove(&account.id);
+        config.version += 1;
+    }
+    put_out(&room, |account_id| account_id == Some(account.id.as_str()));
+    audit::record(room_id, "member_removed", "admin", json!({ "account_id": account.id }));
+    Ok(Json(to_json(room_id, &members)))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list_members, put_member, delete_member]
+}
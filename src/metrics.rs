@@ -0,0 +1,134 @@
+// Prometheus metrics at GET /metrics, readable with the admin token or an
+// API token scoped read:metrics.
+//
+// whochat_broadcast_latency_seconds is a histogram per room of how long each
+// connection waited from the start of a broadcast until its frame was queued
+// for sending; lock contention and connections slow to accept a frame show up
+// in the upper buckets. ws writes queued frames on its own thread and doesn't
+// say when they reach the socket, so that last stretch isn't included.
+// Frames that couldn't be queued (the connection's queue is full or it went
+// away) are counted in whochat_broadcast_failures_total.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, Route};
+
+use crate::CHAT_STATE;
+use crate::api_tokens::{Scope, authorize};
+
+// Upper bounds in seconds, from 100µs to 1s
+const BUCKETS: [f64; 10] = [0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.25, 1.0];
+
+#[derive(Default)]
+struct Histogram {
+    // Observations at or below each bucket's bound, not cumulative
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+    failures: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+lazy_static! {
+    // room id -> broadcast latency
+    static ref BROADCASTS: Mutex<HashMap<String, Histogram>> = Mutex::new(HashMap::new());
+}
+
+pub fn record_broadcast(room_id: &str, latencies: &[Duration], failures: u64) {
+    if latencies.is_empty() {
+        return;
+    }
+    let mut broadcasts = BROADCASTS.lock();
+    let histogram = broadcasts.entry(room_id.to_string()).or_default();
+    for &latency in latencies {
+        histogram.observe(latency);
+    }
+    histogram.failures += failures;
+}
+
+// Deleted rooms stop being reported
+pub fn discard(room_id: &str) {
+    BROADCASTS.lock().remove(room_id);
+}
+
+// Escapes a label value per the text exposition format
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn render() -> String {
+    let mut out = String::new();
+    let (rooms, connections) = {
+        let rooms = CHAT_STATE.rooms.read();
+        let connections: usize = rooms.values().map(|room| room.connections.read().len()).sum();
+        (rooms.len(), connections)
+    };
+    let _ = writeln!(out, "# HELP whochat_rooms Rooms currently in memory.");
+    let _ = writeln!(out, "# TYPE whochat_rooms gauge");
+    let _ = writeln!(out, "whochat_rooms {}", rooms);
+    let _ = writeln!(out, "# HELP whochat_connections Open WebSocket connections.");
+    let _ = writeln!(out, "# TYPE whochat_connections gauge");
+    let _ = writeln!(out, "whochat_connections {}", connections);
+
+    let broadcasts = BROADCASTS.lock();
+    let mut room_ids: Vec<&String> = broadcasts.keys().collect();
+    room_ids.sort();
+
+    let _ = writeln!(out, "# HELP whochat_broadcast_latency_seconds Time from the start of a broadcast until a connection's frame was queued.");
+    let _ = writeln!(out, "# TYPE whochat_broadcast_latency_seconds histogram");
+    for room_id in &room_ids {
+        let histogram = &broadcasts[*room_id];
+        let room = label(room_id);
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "whochat_broadcast_latency_seconds_bucket{{room=\"{}\",le=\"{}\"}} {}", room, bound, cumulative);
+        }
+        let _ = writeln!(out, "whochat_broadcast_latency_seconds_bucket{{room=\"{}\",le=\"+Inf\"}} {}", room, histogram.count);
+        let _ = writeln!(out, "whochat_broadcast_latency_seconds_sum{{room=\"{}\"}} {}", room, histogram.sum);
+        let _ = writeln!(out, "whochat_broadcast_latency_seconds_count{{room=\"{}\"}} {}", room, histogram.count);
+    }
+
+    let _ = writeln!(out, "# HELP whochat_broadcast_failures_total Frames that couldn't be queued for a connection.");
+    let _ = writeln!(out, "# TYPE whochat_broadcast_failures_total counter");
+    for room_id in &room_ids {
+        let _ = writeln!(out, "whochat_broadcast_failures_total{{room=\"{}\"}} {}", label(room_id), broadcasts[*room_id].failures);
+    }
+    out
+}
+
+pub struct CanReadMetrics;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CanReadMetrics {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::ReadMetrics).map(|_| CanReadMetrics)
+    }
+}
+
+#[rocket::get("/metrics")]
+fn metrics(_auth: CanReadMetrics) -> (ContentType, String) {
+    (ContentType::new("text", "plain").with_params(("version", "0.0.4")), render())
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![metrics]
+}
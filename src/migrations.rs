@@ -0,0 +1,143 @@
+// Versioned layout of the data directory. The version the data is in is
+// kept in data/schema/version.json; data directories from before it was
+// recorded are version 0. At startup the migrations between that and
+// SCHEMA_VERSION run in order, after a backup of the data directory is
+// written to backup_dir, so an upgrade that changes how something is
+// stored converts what's there instead of ignoring it as corrupt.
+//
+//   who-chat migrate [--dry-run]
+//
+// does the same without starting the server, or with --dry-run only lists
+// what would change. A data directory from a newer version is left alone
+// and the server refuses to start on it.
+//
+// A migration works on the stored JSON (serde_json::Value) rather than the
+// current types, which may no longer read the old format, and reports each
+// change it makes, or would make when dry_run is set.
+
+use std::fs;
+use std::io;
+
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::backup;
+use crate::config::CONFIG;
+use crate::storage;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+struct Migration {
+    // The version the data is in afterwards
+    version: u32,
+    description: &'static str,
+    run: fn(dry_run: bool) -> io::Result<Vec<String>>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Record the schema version of the data directory",
+        run: |_| Ok(Vec::new()),
+    },
+];
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Schema {
+    version: u32,
+}
+
+fn is_fresh() -> bool {
+    fs::read_dir(&CONFIG.data_dir).map_or(true, |mut entries| entries.next().is_none())
+}
+
+// The version the data directory is in
+fn current() -> u32 {
+    match storage::load::<Schema>("schema", "version") {
+        Some(schema) => schema.version,
+        None if is_fresh() => SCHEMA_VERSION,
+        None => 0,
+    }
+}
+
+fn record(version: u32) -> io::Result<()> {
+    storage::save("schema", "version", &Schema { version })
+}
+
+// Brings the data directory up to SCHEMA_VERSION, printing what it does
+pub fn migrate(dry_run: bool) -> Result<(), String> {
+    let from = current();
+    if from > SCHEMA_VERSION {
+        return Err(format!(
+            "{} is in schema version {}, newer than this build knows ({}); run the newer who-chat or restore a backup",
+            CONFIG.data_dir.display(), from, SCHEMA_VERSION
+        ));
+    }
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|migration| migration.version > from).collect();
+    if pending.is_empty() {
+        if !dry_run && storage::load::<Schema>("schema", "version").is_none() {
+            record(SCHEMA_VERSION).map_err(|err| err.to_string())?;
+        }
+        return Ok(());
+    }
+
+    if !dry_run {
+        fs::create_dir_all(&CONFIG.backup_dir).map_err(|err| err.to_string())?;
+        let out = CONFIG.backup_dir.join(format!("before-schema-{}-{}", SCHEMA_VERSION, backup::default_name()));
+        backup::write(&out).map_err(|err| format!("Couldn't back up before migrating: {}", err))?;
+        println!("Backed up {} to {}", CONFIG.data_dir.display(), out.display());
+    }
+    for migration in pending {
+        let verb = if dry_run { "Would migrate" } else { "Migrating" };
+        println!("{} to schema version {}: {}", verb, migration.version, migration.description);
+        let changes = (migration.run)(dry_run)
+            .map_err(|err| format!("Schema version {} failed: {}", migration.version, err))?;
+        for change in changes {
+            println!("  {}", change);
+        }
+        if !dry_run {
+            // Recorded after each step, so a failure resumes where it stopped
+            record(migration.version).map_err(|err| err.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+// At startup, before anything reads the data directory
+pub fn run() {
+    if let Err(err) = migrate(false) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// `who-chat migrate [--dry-run]`
+pub fn run_command(args: impl Iterator<Item = String>) {
+    let mut dry_run = false;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            // Handled by the config loader
+            "--data-dir" => {
+                args.next();
+            },
+            _ if arg.starts_with("--data-dir=") => {},
+            _ => {
+                eprintln!("Unknown option {}", arg);
+                eprintln!("Usage: who-chat migrate [--dry-run]");
+                std::process::exit(2);
+            },
+        }
+    }
+    let from = current();
+    match migrate(dry_run) {
+        Ok(()) if from >= SCHEMA_VERSION => println!("{} is up to date (schema version {})", CONFIG.data_dir.display(), SCHEMA_VERSION),
+        Ok(()) if dry_run => {},
+        Ok(()) => println!("Migrated {} to schema version {}", CONFIG.data_dir.display(), SCHEMA_VERSION),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    }
+}
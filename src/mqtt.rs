@@ -0,0 +1,112 @@
+// Bridges rooms to an MQTT broker, so rooms can serve as alert channels for
+// sensors and other devices. Configured with an [mqtt] table:
+//
+//   [mqtt]
+//   host = "broker.example.com"
+//   subscribe = { "sensors/+/alarm" = "ops" }     # topic filter -> room
+//   publish = { "ops" = "chat/ops" }              # room -> topic
+//
+// Payloads arriving on a subscribed topic are posted to the room under the
+// configured sender name, prefixed with the topic, once they're through the
+// room's filters. Messages people post in a
+// room listed under `publish` go out to its topic as JSON
+// {"id", "room_id", "sender", "content", "timestamp"}. The bridge reconnects
+// (and resubscribes) by itself when the broker goes away.
+
+use std::thread;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use serde_json::json;
+
+use crate::config::{CONFIG, MqttConfig};
+use crate::plugins::{BotReply, Plugin};
+use crate::{CHAT_STATE, ChatMessage, MessageType, maintenance, publish_bot, secrets};
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+// Requests the client may queue before publishing fails
+const QUEUE_CAPACITY: usize = 100;
+
+lazy_static! {
+    static ref CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+}
+
+fn post(config: &MqttConfig, topic: &str, payload: &[u8]) {
+    if maintenance::read_only() {
+        return;
+    }
+    let payload = String::from_utf8_lossy(payload);
+    let content: String = format!("{}: {}", topic, payload.trim()).chars().take(CONFIG.max_message_len).collect();
+    for (filter, room_id) in &config.subscribe {
+        if rumqttc::matches(topic, filter) {
+            let room = CHAT_STATE.get_or_create_room(room_id);
+            if let Err(err) = publish_bot(&room, ChatMessage::new(room_id, &config.sender, &content, MessageType::Bot)) {
+                eprintln!("MQTT payload from {} not posted in {}: {}", topic, room_id, err);
+            }
+        }
+    }
+}
+
+// Connects to the broker on a background thread, if an [mqtt] table is set
+pub fn start() {
+    let Some(config) = CONFIG.mqtt.clone() else {
+        return;
+    };
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, secrets::resolve(password));
+    }
+    let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+    *CLIENT.lock() = Some(client.clone());
+    println!("MQTT bridge connecting to {}:{}", config.host, config.port);
+
+    thread::spawn(move || {
+        for event in connection.iter() {
+            match event {
+                // Subscriptions don't outlive the session, so renew them on every connect
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    for filter in config.subscribe.keys() {
+                        if let Err(err) = client.subscribe(filter, QoS::AtLeastOnce) {
+                            eprintln!("MQTT subscribe to {} failed: {}", filter, err);
+                        }
+                    }
+                },
+                Ok(Event::Incoming(Packet::Publish(publish))) => post(&config, &publish.topic, &publish.payload),
+                Ok(_) => {},
+                Err(err) => {
+                    eprintln!("MQTT connection error: {}", err);
+                    thread::sleep(RETRY_DELAY);
+                },
+            }
+        }
+    });
+}
+
+// Publishes what people post in bridged rooms
+pub struct MqttPlugin;
+
+impl Plugin for MqttPlugin {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    fn on_message_posted(&self, message: &ChatMessage) -> Option<BotReply> {
+        let topic = CONFIG.mqtt.as_ref()?.publish.get(&message.room_id)?;
+        let client = CLIENT.lock().clone()?;
+        let payload = json!({
+            "id": message.id,
+            "room_id": message.room_id,
+            "sender": message.sender,
+            "content": message.content,
+            "timestamp": message.timestamp,
+        });
+        if let Err(err) = client.try_publish(topic, QoS::AtLeastOnce, false, payload.to_string()) {
+            eprintln!("MQTT publish to {} failed: {}", topic, err);
+        }
+        None
+    }
+}
@@ -0,0 +1,563 @@
+// Passkeys (WebAuthn) for registered accounts, so they can sign in without a
+// password. A signed-in account adds one with
+//
+//   POST   /api/account/passkeys/options   -> options for navigator.credentials.create()
+//   POST   /api/account/passkeys           {"id", "client_data_json", "authenticator_data", "public_key", "public_key_algorithm", "name"?}
+//   GET    /api/account/passkeys           {"passkeys": [{"id", "name", "created_at", "last_used_at"}]}
+//   DELETE /api/account/passkeys/<id>
+//
+// sending back what the browser's AuthenticatorAttestationResponse gives
+// through getAuthenticatorData(), getPublicKey() and getPublicKeyAlgorithm(),
+// so no attestation needs decoding; attestation isn't asked for anyway. Then
+//
+//   POST /api/passkeys/options   {"username"?}  -> options for navigator.credentials.get()
+//   POST /api/passkeys/sign-in   {"id", "client_data_json", "authenticator_data", "signature", "user_handle"?, "username"?}
+//
+// signs the account in as a password would, setting the session cookie; the
+// join form then takes its nickname without a password. Without a username
+// the authenticator has to offer a discoverable passkey, whose user handle
+// is the account id. Binary fields are base64url. User verification is
+// required, so a passkey stands in for two-factor authentication too.
+// Passkeys are bound to public_url's host when it's set; otherwise to the
+// host the request came in on, which only a trusted setup should rely on.
+// ES256, EdDSA and RS256 keys are accepted. Each account's passkeys are
+// kept in data/passkeys/<account id>.json.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use data_encoding::BASE64URL_NOPAD;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::Rng;
+use ring::signature::{self, UnparsedPublicKey};
+use rocket::Route;
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::accounts::{ACCOUNTS, Account, AccountSession};
+use crate::admin::{ApiResult, api_error};
+use crate::config::CONFIG;
+use crate::proxy::Origin;
+use crate::sessions::{ClientInfo, SESSIONS};
+use crate::storage;
+
+const CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+const MAX_PASSKEYS: usize = 20;
+const MAX_NAME_LEN: usize = 60;
+
+// COSE algorithm ids
+const ES256: i64 = -7;
+const EDDSA: i64 = -8;
+const RS256: i64 = -257;
+
+// Authenticator data flags
+const USER_PRESENT: u8 = 0x01;
+const USER_VERIFIED: u8 = 0x04;
+const ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Passkey {
+    // Credential id, base64url
+    id: String,
+    name: String,
+    algorithm: i64,
+    // The key as verification takes it (a curve point, PKCS#1 or raw
+    // Ed25519), base64url
+    public_key: String,
+    sign_count: u32,
+    created_at: String,
+    #[serde(default)]
+    last_used_at: Option<String>,
+}
+
+impl Passkey {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "created_at": self.created_at,
+            "last_used_at": self.last_used_at,
+        })
+    }
+}
+
+fn load(account_id: &str) -> Vec<Passkey> {
+    storage::load("passkeys", account_id).unwrap_or_default()
+}
+
+fn save(account_id: &str, passkeys: &[Passkey]) -> Result<(), (Status, Json<Value>)> {
+    storage::save("passkeys", account_id, &passkeys).map_err(|err| api_error(Status::InternalServerError, err))
+}
+
+// Drops the account's passkeys, once it's deleted
+pub fn forget(account_id: &str) {
+    if let Err(err) = storage::remove("passkeys", account_id) {
+        eprintln!("Failed to remove passkeys of {}: {}", account_id, err);
+    }
+}
+
+enum Purpose {
+    // Adding a passkey to this account
+    Register(String),
+    SignIn,
+}
+
+struct Challenge {
+    purpose: Purpose,
+    // The origin it was handed out to
+    origin: String,
+    expires: Instant,
+}
+
+lazy_static! {
+    // challenge, base64url -> what it was handed out for
+    static ref CHALLENGES: Mutex<HashMap<String, Challenge>> = Mutex::new(HashMap::new());
+}
+
+fn new_challenge(purpose: Purpose, origin: &str) -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill(&mut bytes);
+    let challenge = BASE64URL_NOPAD.encode(&bytes);
+    CHALLENGES.lock().insert(challenge.clone(), Challenge {
+        purpose,
+        origin: origin.to_string(),
+        expires: Instant::now() + CHALLENGE_TTL,
+    });
+    challenge
+}
+
+// Forgets challenges nobody answered
+pub fn prune() {
+    let now = Instant::now();
+    CHALLENGES.lock().retain(|_, challenge| challenge.expires > now);
+}
+
+// The origin passkeys are made and used from. The Host header is the
+// client's to choose, so public_url decides when it's set.
+fn site_origin(request_origin: Origin) -> String {
+    CONFIG
+        .public_url
+        .as_deref()
+        .and_then(|url| url::Url::parse(url).ok())
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or(request_origin.0)
+}
+
+// The relying party id: the host passkeys are bound to
+fn rp_id(origin: &str) -> String {
+    url::Url::parse(origin)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, (Status, Json<Value>)> {
+    BASE64URL_NOPAD
+        .decode(value.trim_end_matches('=').as_bytes())
+        .map_err(|_| api_error(Status::BadRequest, format!("{} isn't base64url", field)))
+}
+
+fn refuse(detail: &str) -> (Status, Json<Value>) {
+    api_error(Status::BadRequest, detail)
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+// Checks the client data against a challenge we handed out for the same
+// origin, using the challenge up
+fn take_challenge(client_data_json: &[u8], kind: &str, origin: &str) -> Result<Purpose, (Status, Json<Value>)> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json).map_err(|_| refuse("client_data_json isn't valid"))?;
+    if client_data.kind != kind {
+        return Err(refuse("Wrong kind of WebAuthn response"));
+    }
+    let challenge = CHALLENGES
+        .lock()
+        .remove(&client_data.challenge)
+        .filter(|challenge| challenge.expires > Instant::now())
+        .ok_or_else(|| refuse("Unknown or expired challenge, start again"))?;
+    if client_data.origin != origin || challenge.origin != origin {
+        return Err(refuse("The passkey was used from another origin"));
+    }
+    Ok(challenge.purpose)
+}
+
+struct AuthenticatorData<'a> {
+    sign_count: u32,
+    // Only when a credential was just made
+    credential_id: Option<&'a [u8]>,
+}
+
+fn parse_authenticator_data<'a>(data: &'a [u8], origin: &str) -> Result<AuthenticatorData<'a>, (Status, Json<Value>)> {
+    if data.len() < 37 {
+        return Err(refuse("authenticator_data is too short"));
+    }
+    if data[..32] != *Sha256::digest(rp_id(origin).as_bytes()) {
+        return Err(refuse("The passkey is for another site"));
+    }
+    let flags = data[32];
+    if flags & USER_PRESENT == 0 || flags & USER_VERIFIED == 0 {
+        return Err(refuse("The authenticator didn't verify the user"));
+    }
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+    // AAGUID, then the credential id's length and the id
+    let credential_id = if flags & ATTESTED_CREDENTIAL != 0 {
+        let rest = data.get(37 + 16..).ok_or_else(|| refuse("authenticator_data is too short"))?;
+        let len = u16::from_be_bytes([*rest.first().unwrap_or(&0), *rest.get(1).unwrap_or(&0)]) as usize;
+        Some(rest.get(2..2 + len).ok_or_else(|| refuse("authenticator_data is too short"))?)
+    } else {
+        None
+    };
+    Ok(AuthenticatorData { sign_count, credential_id })
+}
+
+// One DER element: its tag, its contents and whatever follows it
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        (rest[..count].iter().fold(0usize, |len, &byte| len << 8 | byte as usize), &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+// The key inside a SubjectPublicKeyInfo, as getPublicKey() gives it
+fn spki_key(spki: &[u8]) -> Option<Vec<u8>> {
+    let (0x30, info, _) = der_element(spki)? else {
+        return None;
+    };
+    let (0x30, _, rest) = der_element(info)? else {
+        return None;
+    };
+    let (0x03, bits, _) = der_element(rest)? else {
+        return None;
+    };
+    // Leading byte is the count of unused bits, always 0 for keys
+    let (0, key) = bits.split_first()? else {
+        return None;
+    };
+    Some(key.to_vec())
+}
+
+fn verify_signature(passkey: &Passkey, message: &[u8], signature: &[u8]) -> bool {
+    let Ok(key) = BASE64URL_NOPAD.decode(passkey.public_key.as_bytes()) else {
+        return false;
+    };
+    let algorithm: &dyn signature::VerificationAlgorithm = match passkey.algorithm {
+        ES256 => &signature::ECDSA_P256_SHA256_ASN1,
+        EDDSA => &signature::ED25519,
+        RS256 => &signature::RSA_PKCS1_2048_8192_SHA256,
+        _ => return false,
+    };
+    UnparsedPublicKey::new(algorithm, key).verify(message, signature).is_ok()
+}
+
+#[rocket::post("/options")]
+fn register_options(session: AccountSession, origin: Origin) -> Json<Value> {
+    let origin = site_origin(origin);
+    let account = session.0;
+    let challenge = new_challenge(Purpose::Register(account.id.clone()), &origin);
+    let params: Vec<Value> = [ES256, EDDSA, RS256].iter().map(|alg| json!({ "type": "public-key", "alg": alg })).collect();
+    let exclude: Vec<Value> = load(&account.id).iter().map(|passkey| json!({ "type": "public-key", "id": passkey.id })).collect();
+    Json(json!({
+        "challenge": challenge,
+        "rp": { "id": rp_id(&origin), "name": CONFIG.theme.name },
+        "user": {
+            "id": BASE64URL_NOPAD.encode(account.id.as_bytes()),
+            "name": account.username,
+            "displayName": account.username,
+        },
+        "pubKeyCredParams": params,
+        "excludeCredentials": exclude,
+        "authenticatorSelection": { "residentKey": "preferred", "userVerification": "required" },
+        "attestation": "none",
+        "timeout": CHALLENGE_TTL.as_millis() as u64,
+    }))
+}
+
+#[derive(Deserialize)]
+struct Registration {
+    id: String,
+    client_data_json: String,
+    authenticator_data: String,
+    public_key: String,
+    public_key_algorithm: i64,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[rocket::post("/", data = "<request>")]
+fn register(session: AccountSession, origin: Origin, request: Json<Registration>) -> ApiResult {
+    let origin = site_origin(origin);
+    let account = session.0;
+    let client_data_json = decode("client_data_json", &request.client_data_json)?;
+    match take_challenge(&client_data_json, "webauthn.create", &origin)? {
+        Purpose::Register(account_id) if account_id == account.id => {},
+        _ => return Err(refuse("That challenge wasn't for adding a passkey to this account")),
+    }
+    let authenticator_data = decode("authenticator_data", &request.authenticator_data)?;
+    let parsed = parse_authenticator_data(&authenticator_data, &origin)?;
+    let id = decode("id", &request.id)?;
+    if parsed.credential_id != Some(id.as_slice()) {
+        return Err(refuse("authenticator_data doesn't hold the new passkey"));
+    }
+    if ![ES256, EDDSA, RS256].contains(&request.public_key_algorithm) {
+        return Err(refuse("Unsupported key algorithm; use ES256, EdDSA or RS256"));
+    }
+    let public_key = spki_key(&decode("public_key", &request.public_key)?).ok_or_else(|| refuse("public_key isn't a valid SubjectPublicKeyInfo"))?;
+
+    let id = BASE64URL_NOPAD.encode(&id);
+    let mut passkeys = load(&account.id);
+    if passkeys.iter().any(|passkey| passkey.id == id) {
+        return Err(api_error(Status::Conflict, "That passkey is already added"));
+    }
+    if passkeys.len() >= MAX_PASSKEYS {
+        return Err(api_error(Status::Conflict, format!("Accounts can have up to {} passkeys", MAX_PASSKEYS)));
+    }
+    let name = request.name.as_deref().map(str::trim).filter(|name| !name.is_empty()).unwrap_or("Passkey");
+    let passkey = Passkey {
+        id,
+        name: name.chars().take(MAX_NAME_LEN).collect(),
+        algorithm: request.public_key_algorithm,
+        public_key: BASE64URL_NOPAD.encode(&public_key),
+        sign_count: parsed.sign_count,
+        created_at: Utc::now().to_rfc3339(),
+        last_used_at: None,
+    };
+    let added = passkey.to_json();
+    passkeys.push(passkey);
+    save(&account.id, &passkeys)?;
+    Ok(Json(added))
+}
+
+#[rocket::get("/")]
+fn list(session: AccountSession) -> Json<Value> {
+    let passkeys: Vec<Value> = load(&session.0.id).iter().map(Passkey::to_json).collect();
+    Json(json!({ "passkeys": passkeys }))
+}
+
+#[rocket::delete("/<id>")]
+fn remove(session: AccountSession, id: &str) -> ApiResult {
+    let mut passkeys = load(&session.0.id);
+    let before = passkeys.len();
+    passkeys.retain(|passkey| passkey.id != id);
+    if passkeys.len() == before {
+        return Err(api_error(Status::NotFound, "No such passkey"));
+    }
+    save(&session.0.id, &passkeys)?;
+    Ok(Json(json!({ "id": id, "removed": true })))
+}
+
+#[derive(Deserialize)]
+struct SignInOptions {
+    #[serde(default)]
+    username: Option<String>,
+}
+
+#[rocket::post("/options", data = "<request>")]
+fn sign_in_options(origin: Origin, request: Option<Json<SignInOptions>>) -> Json<Value> {
+    let origin = site_origin(origin);
+    let challenge = new_challenge(Purpose::SignIn, &origin);
+    // Unknown usernames get an empty list too, so this doesn't say which exist
+    let allow: Vec<Value> = request
+        .and_then(|request| request.username.as_deref().and_then(|username| ACCOUNTS.find(username)))
+        .map(|account| load(&account.id))
+        .unwrap_or_default()
+        .iter()
+        .map(|passkey| json!({ "type": "public-key", "id": passkey.id }))
+        .collect();
+    Json(json!({
+        "challenge": challenge,
+        "rpId": rp_id(&origin),
+        "allowCredentials": allow,
+        "userVerification": "required",
+        "timeout": CHALLENGE_TTL.as_millis() as u64,
+    }))
+}
+
+#[derive(Deserialize)]
+struct Assertion {
+    id: String,
+    client_data_json: String,
+    authenticator_data: String,
+    signature: String,
+    #[serde(default)]
+    user_handle: Option<String>,
+    #[serde(default)]
+    username: Option<String>,
+}
+
+fn signed_in_account(request: &Assertion) -> Result<Account, (Status, Json<Value>)> {
+    let account = match (&request.user_handle, &request.username) {
+        (Some(handle), _) => String::from_utf8(decode("user_handle", handle)?).ok().and_then(|id| ACCOUNTS.get(&id)),
+        (None, Some(username)) => ACCOUNTS.find(username),
+        (None, None) => return Err(refuse("Send the user_handle, or the username")),
+    };
+    account.filter(|account| !account.deactivated).ok_or_else(|| api_error(Status::Unauthorized, "Unknown passkey"))
+}
+
+#[rocket::post("/sign-in", data = "<request>")]
+fn sign_in(origin: Origin, client: ClientInfo, cookies: &CookieJar<'_>, request: Json<Assertion>) -> ApiResult {
+    let origin = site_origin(origin);
+    let client_data_json = decode("client_data_json", &request.client_data_json)?;
+    let Purpose::SignIn = take_challenge(&client_data_json, "webauthn.get", &origin)? else {
+        return Err(refuse("That challenge wasn't for signing in"));
+    };
+    let authenticator_data = decode("authenticator_data", &request.authenticator_data)?;
+    let parsed = parse_authenticator_data(&authenticator_data, &origin)?;
+    let account = signed_in_account(&request)?;
+
+    let mut passkeys = load(&account.id);
+    let passkey = passkeys
+        .iter_mut()
+        .find(|passkey| passkey.id == request.id.trim_end_matches('='))
+        .ok_or_else(|| api_error(Status::Unauthorized, "Unknown passkey"))?;
+    let message = [authenticator_data.as_slice(), &Sha256::digest(&client_data_json)].concat();
+    if !verify_signature(passkey, &message, &decode("signature", &request.signature)?) {
+        return Err(api_error(Status::Unauthorized, "The passkey's signature doesn't check out"));
+    }
+    // Authenticators that count must count up; otherwise the key may have been cloned
+    if (parsed.sign_count != 0 || passkey.sign_count != 0) && parsed.sign_count <= passkey.sign_count {
+        return Err(api_error(Status::Unauthorized, "The passkey's signature counter went backwards"));
+    }
+    passkey.sign_count = parsed.sign_count;
+    passkey.last_used_at = Some(Utc::now().to_rfc3339());
+    save(&account.id, &passkeys)?;
+
+    let session = SESSIONS.create(&account.id, &client);
+    cookies.add_private(Cookie::new("session_id", session.id));
+    Ok(Json(json!({ "account_id": account.id, "username": account.username })))
+}
+
+// Managing the signed-in account's passkeys
+pub fn routes() -> Vec<Route> {
+    rocket::routes![register_options, register, list, remove]
+}
+
+pub fn sign_in_routes() -> Vec<Route> {
+    rocket::routes![sign_in_options, sign_in]
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair};
+
+    use super::*;
+
+    const ORIGIN: &str = "https://chat.example.com";
+
+    // SubjectPublicKeyInfo headers up to the key's bits
+    const P256_SPKI: &[u8] = &[
+        0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01,
+        0x07, 0x03, 0x42, 0x00,
+    ];
+    const ED25519_SPKI: &[u8] = &[0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+    fn passkey(algorithm: i64, public_key: &[u8]) -> Passkey {
+        Passkey {
+            id: "credential".to_string(),
+            name: "Test key".to_string(),
+            algorithm,
+            public_key: BASE64URL_NOPAD.encode(public_key),
+            sign_count: 0,
+            created_at: Utc::now().to_rfc3339(),
+            last_used_at: None,
+        }
+    }
+
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+        [Sha256::digest(rp_id.as_bytes()).as_slice(), &[flags], &sign_count.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn reads_der_lengths() {
+        assert_eq!(der_element(&[0x04, 0x02, 1, 2, 3]), Some((0x04, &[1, 2][..], &[3][..])));
+        let long = [&[0x04, 0x81, 0x80][..], &[9; 0x80]].concat();
+        assert_eq!(der_element(&long), Some((0x04, &[9; 0x80][..], &[][..])));
+        assert_eq!(der_element(&[0x04, 0x03, 1, 2]), None);
+        assert_eq!(der_element(&[0x04, 0x80]), None);
+        assert_eq!(der_element(&[0x04, 0x85, 0, 0, 0, 0, 1]), None);
+        assert_eq!(der_element(&[0x04]), None);
+    }
+
+    #[test]
+    fn takes_keys_out_of_spki() {
+        let point = [4; 65];
+        assert_eq!(spki_key(&[P256_SPKI, &point].concat()), Some(point.to_vec()));
+        let key = [7; 32];
+        assert_eq!(spki_key(&[ED25519_SPKI, &key].concat()), Some(key.to_vec()));
+        assert_eq!(spki_key(&[ED25519_SPKI, &key[..31]].concat()), None);
+        assert_eq!(spki_key(&key), None);
+    }
+
+    #[test]
+    fn parses_authenticator_data() {
+        let data = authenticator_data("chat.example.com", USER_PRESENT | USER_VERIFIED, 42);
+        let parsed = parse_authenticator_data(&data, ORIGIN).unwrap();
+        assert_eq!(parsed.sign_count, 42);
+        assert!(parsed.credential_id.is_none());
+
+        let attested = [
+            authenticator_data("chat.example.com", USER_PRESENT | USER_VERIFIED | ATTESTED_CREDENTIAL, 0),
+            vec![0; 16],
+            vec![0, 3, 1, 2, 3],
+        ]
+        .concat();
+        assert_eq!(parse_authenticator_data(&attested, ORIGIN).unwrap().credential_id, Some(&[1, 2, 3][..]));
+        assert!(parse_authenticator_data(&attested[..attested.len() - 1], ORIGIN).is_err());
+    }
+
+    #[test]
+    fn refuses_other_sites_and_unverified_users() {
+        let data = authenticator_data("evil.example.com", USER_PRESENT | USER_VERIFIED, 1);
+        assert!(parse_authenticator_data(&data, ORIGIN).is_err());
+        let data = authenticator_data("chat.example.com", USER_PRESENT, 1);
+        assert!(parse_authenticator_data(&data, ORIGIN).is_err());
+        let data = authenticator_data("chat.example.com", USER_PRESENT | USER_VERIFIED, 1);
+        assert!(parse_authenticator_data(&data[..36], ORIGIN).is_err());
+    }
+
+    #[test]
+    fn verifies_es256_signatures() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let key = spki_key(&[P256_SPKI, pair.public_key().as_ref()].concat()).unwrap();
+        let passkey = passkey(ES256, &key);
+
+        let signature = pair.sign(&rng, b"signed data").unwrap();
+        assert!(verify_signature(&passkey, b"signed data", signature.as_ref()));
+        assert!(!verify_signature(&passkey, b"other data", signature.as_ref()));
+    }
+
+    #[test]
+    fn verifies_eddsa_signatures() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let passkey = passkey(EDDSA, pair.public_key().as_ref());
+
+        let signature = pair.sign(b"signed data");
+        assert!(verify_signature(&passkey, b"signed data", signature.as_ref()));
+        assert!(!verify_signature(&passkey, b"other data", signature.as_ref()));
+        // The same key under another algorithm doesn't verify
+        assert!(!verify_signature(&Passkey { algorithm: ES256, ..passkey.clone() }, b"signed data", signature.as_ref()));
+        assert!(!verify_signature(&Passkey { algorithm: -999, ..passkey }, b"signed data", signature.as_ref()));
+    }
+}
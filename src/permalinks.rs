@@ -0,0 +1,49 @@
+// Stable links to single messages,
+//
+//   GET /rooms/<room_id>/message/<message_id>
+//
+// which open the room with the message in view: the page passes the id on
+// as `focus` when it connects, and right after the history replay the
+// connection gets
+//
+//   {"type": "focus", "message_id": "<id>", "found": true|false}
+//
+// where found is false once the message is no longer in the history. Links
+// handed outside the browser, in webhooks and exports, are absolute when
+// public_url is set.
+
+use rocket::response::Redirect;
+use rocket::{Route, uri};
+use serde_json::json;
+
+use crate::config::CONFIG;
+use crate::{ChatMessage, proxy};
+
+pub fn path(room_id: &str, message_id: &str) -> String {
+    proxy::url(format!("/rooms/{}/message/{}", room_id, message_id))
+}
+
+// Absolute with public_url, or failing that `origin`; server-relative otherwise
+pub fn url(origin: Option<&str>, msg: &ChatMessage) -> String {
+    let base = CONFIG.public_url.as_deref().or(origin).unwrap_or("");
+    format!("{}{}", base.trim_end_matches('/'), path(&msg.room_id, &msg.id))
+}
+
+// Sent after the history replay to a connection opened with ?focus=<id>
+pub fn focus_frame(messages: &[ChatMessage], message_id: &str) -> String {
+    json!({
+        "type": "focus",
+        "message_id": message_id,
+        "found": messages.iter().any(|msg| msg.id == message_id),
+    })
+    .to_string()
+}
+
+#[rocket::get("/<room_id>/message/<message_id>")]
+fn open(room_id: &str, message_id: &str) -> Redirect {
+    Redirect::to(proxy::url(uri!(crate::index(Some(room_id), Some(message_id)))))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![open]
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::quota::QuotaPlugin;
+use crate::ranks::RanksPlugin;
+use crate::rules::RulesPlugin;
+use crate::scripting::ScriptPlugin;
+use crate::trivia::TriviaPlugin;
+use crate::translation::TranslationPlugin;
+use crate::webhooks::WebhookPlugin;
+use crate::word_filter::WordFilterPlugin;
+use crate::{ChatMessage, User};
+
+#[cfg(feature = "plugin-logger")]
+mod logger;
+#[cfg(feature = "wasm-plugins")]
+mod wasm;
+
+// Directory scanned for WebAssembly filters at startup
+#[cfg(feature = "wasm-plugins")]
+const WASM_PLUGIN_DIR: &str = "plugins";
+
+// What a plugin decided to do with an incoming message
+pub enum MessageVerdict {
+    Accept,
+    Reject(String),
+}
+
+// A message a plugin wants posted to the room under its own name
+pub struct BotReply {
+    pub sender: String,
+    pub content: String,
+}
+
+// Hooks a plugin can implement; every hook has a no-op default so plugins
+// only override the events they care about
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn on_room_create(&self, _room_id: &str) {}
+
+    // Called when a room is deleted, e.g. an expired burner room
+    fn on_room_destroy(&self, _room_id: &str) {}
+
+    fn on_join(&self, _room_id: &str, _user: &User) {}
+
+    // Called before a message is stored and broadcast. The message may be
+    // rewritten in place, or rejected with a reason shown to the sender.
+    fn on_message(&self, _message: &mut ChatMessage) -> MessageVerdict {
+        MessageVerdict::Accept
+    }
+
+    // Called after a message has been stored and broadcast, so a reply
+    // shows up after it
+    fn on_message_posted(&self, _message: &ChatMessage) -> Option<BotReply> {
+        None
+    }
+
+    // Called for commands the core doesn't know about. Returning a reply
+    // marks the command as handled.
+    fn on_command(&self, _room_id: &str, _user: &User, _command: &str, _args: &str) -> Option<BotReply> {
+        None
+    }
+}
+
+pub struct PluginRegistry {
+    plugins: RwLock<Vec<Arc<dyn Plugin>>>,
+}
+
+impl PluginRegistry {
+    fn new(plugins: Vec<Arc<dyn Plugin>>) -> Self {
+        for plugin in &plugins {
+            println!("Loaded plugin: {}", plugin.name());
+        }
+
+        PluginRegistry {
+            plugins: RwLock::new(plugins),
+        }
+    }
+
+    // Snapshot the plugin list so hooks never run while the registry is locked
+    fn plugins(&self) -> Vec<Arc<dyn Plugin>> {
+        self.plugins.read().clone()
+    }
+
+    pub fn room_created(&self, room_id: &str) {
+        for plugin in self.plugins() {
+            plugin.on_room_create(room_id);
+        }
+    }
+
+    pub fn room_destroyed(&self, room_id: &str) {
+        for plugin in self.plugins() {
+            plugin.on_room_destroy(room_id);
+        }
+    }
+
+    pub fn user_joined(&self, room_id: &str, user: &User) {
+        for plugin in self.plugins() {
+            plugin.on_join(room_id, user);
+        }
+    }
+
+    // Runs the message through every plugin in registration order, stopping
+    // at the first rejection
+    pub fn filter_message(&self, message: &mut ChatMessage) -> MessageVerdict {
+        for plugin in self.plugins() {
+            if let MessageVerdict::Reject(reason) = plugin.on_message(message) {
+                return MessageVerdict::Reject(reason);
+            }
+        }
+        MessageVerdict::Accept
+    }
+
+    pub fn message_posted(&self, message: &ChatMessage) -> Vec<BotReply> {
+        self.plugins()
+            .iter()
+            .filter_map(|plugin| plugin.on_message_posted(message))
+            .collect()
+    }
+
+    // First plugin to answer a command wins
+    pub fn dispatch_command(&self, room_id: &str, user: &User, command: &str, args: &str) -> Option<BotReply> {
+        self.plugins()
+            .iter()
+            .find_map(|plugin| plugin.on_command(room_id, user, command, args))
+    }
+}
+
+// Plugins compiled into this build, selected through cargo features
+fn builtin_plugins() -> Vec<Arc<dyn Plugin>> {
+    vec![
+        // Filter first so other plugins only see what will actually be stored
+        Arc::new(WordFilterPlugin),
+        Arc::new(RulesPlugin),
+        Arc::new(ScriptPlugin),
+        Arc::new(TriviaPlugin),
+        Arc::new(WebhookPlugin),
+        Arc::new(TranslationPlugin),
+        // Last, so messages another built-in rejects don't use up the quota
+        Arc::new(QuotaPlugin),
+        // After the quota, so only messages that get posted count
+        Arc::new(RanksPlugin),
+        #[cfg(feature = "plugin-logger")]
+        Arc::new(logger::EventLogger),
+        #[cfg(feature = "mqtt")]
+        Arc::new(crate::mqtt::MqttPlugin),
+    ]
+}
+
+// Built-in plugins first, then anything loaded at runtime
+fn load_plugins() -> Vec<Arc<dyn Plugin>> {
+    let plugins = builtin_plugins();
+
+    #[cfg(feature = "wasm-plugins")]
+    let plugins = [plugins, wasm::load_dir(std::path::Path::new(WASM_PLUGIN_DIR))].concat();
+
+    plugins
+}
+
+lazy_static! {
+    pub static ref PLUGINS: PluginRegistry = PluginRegistry::new(load_plugins());
+}
@@ -0,0 +1,24 @@
+use crate::plugins::{MessageVerdict, Plugin};
+use crate::{ChatMessage, User};
+
+// Example plugin that logs room activity to stdout
+pub struct EventLogger;
+
+impl Plugin for EventLogger {
+    fn name(&self) -> &str {
+        "event-logger"
+    }
+
+    fn on_room_create(&self, room_id: &str) {
+        println!("[event-logger] room created: {}", room_id);
+    }
+
+    fn on_join(&self, room_id: &str, user: &User) {
+        println!("[event-logger] {} joined {}", user.nickname, room_id);
+    }
+
+    fn on_message(&self, message: &mut ChatMessage) -> MessageVerdict {
+        println!("[event-logger] {} in {}: {}", message.sender, message.room_id, message.content);
+        MessageVerdict::Accept
+    }
+}
@@ -0,0 +1,158 @@
+// WebAssembly message filters.
+//
+// A filter module must export:
+//   memory                         - its linear memory
+//   alloc(len: i32) -> i32         - reserve `len` bytes for the input
+//   filter(ptr: i32, len: i32) -> i64
+//
+// The host writes a UTF-8 JSON object `{"room_id", "sender", "content"}` into
+// the allocated buffer and calls `filter`. The return value means:
+//   0         keep the message unchanged
+//   negative  reject the message
+//   positive  replace the content with the UTF-8 string at
+//             (ptr << 32 | len) in the module's memory
+//
+// Modules get no imports, a fixed fuel budget and a memory limit per call,
+// and replacements longer than max_message_len bytes are refused, so a broken
+// or malicious filter can't hang the chat server or run it out of memory.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde_json::json;
+use wasmtime::{Config, Engine, Error, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::ChatMessage;
+use crate::config::CONFIG;
+use crate::plugins::{MessageVerdict, Plugin};
+
+const FUEL_PER_CALL: u64 = 10_000_000;
+// Linear memory a module may grow to during a call
+const MAX_MEMORY_BYTES: usize = 16 << 20;
+
+enum FilterOutcome {
+    Keep,
+    Replace(String),
+    Reject,
+}
+
+pub struct WasmFilter {
+    name: String,
+    engine: Engine,
+    module: Module,
+}
+
+impl WasmFilter {
+    fn load(engine: &Engine, path: &Path) -> wasmtime::Result<Self> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "wasm".to_string());
+
+        Ok(WasmFilter {
+            name: format!("wasm:{}", name),
+            engine: engine.clone(),
+            module: Module::from_file(engine, path)?,
+        })
+    }
+
+    fn run(&self, input: &[u8]) -> wasmtime::Result<FilterOutcome> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build();
+        let mut store: Store<StoreLimits> = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+
+        let instance = Instance::new(&mut store, &self.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| Error::msg("module does not export `memory`"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let filter = instance.get_typed_func::<(i32, i32), i64>(&mut store, "filter")?;
+
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as usize, input)?;
+
+        let result = filter.call(&mut store, (ptr, len))?;
+        if result == 0 {
+            return Ok(FilterOutcome::Keep);
+        }
+        if result < 0 {
+            return Ok(FilterOutcome::Reject);
+        }
+
+        let out_ptr = (result >> 32) as usize;
+        let out_len = (result & 0xffff_ffff) as usize;
+        if out_len > CONFIG.max_message_len {
+            return Err(Error::msg(format!("replacement is {} bytes, over max_message_len", out_len)));
+        }
+        if out_ptr.checked_add(out_len).is_none_or(|end| end > memory.data_size(&store)) {
+            return Err(Error::msg("replacement lies outside the module's memory"));
+        }
+        let mut output = vec![0; out_len];
+        memory.read(&store, out_ptr, &mut output)?;
+        Ok(FilterOutcome::Replace(String::from_utf8(output)?))
+    }
+}
+
+impl Plugin for WasmFilter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_message(&self, message: &mut ChatMessage) -> MessageVerdict {
+        let input = json!({
+            "room_id": message.room_id,
+            "sender": message.sender,
+            "content": message.content,
+        }).to_string();
+
+        match self.run(input.as_bytes()) {
+            Ok(FilterOutcome::Keep) => MessageVerdict::Accept,
+            Ok(FilterOutcome::Replace(content)) => {
+                message.content = content;
+                MessageVerdict::Accept
+            },
+            Ok(FilterOutcome::Reject) => MessageVerdict::Reject(format!("blocked by {}", self.name)),
+            Err(err) => {
+                // A failing filter shouldn't take the room down with it
+                eprintln!("{} failed, letting message through: {}", self.name, err);
+                MessageVerdict::Accept
+            }
+        }
+    }
+}
+
+// Load every `.wasm` module in `dir`, in file name order so operators can
+// control the filter chain by naming files
+pub fn load_dir(dir: &Path) -> Vec<Arc<dyn Plugin>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    paths.sort();
+
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = match Engine::new(&config) {
+        Ok(engine) => engine,
+        Err(err) => {
+            eprintln!("Failed to start WebAssembly engine: {}", err);
+            return Vec::new();
+        }
+    };
+
+    let mut plugins: Vec<Arc<dyn Plugin>> = Vec::new();
+    for path in paths {
+        match WasmFilter::load(&engine, &path) {
+            Ok(filter) => plugins.push(Arc::new(filter)),
+            Err(err) => eprintln!("Failed to load {}: {}", path.display(), err),
+        }
+    }
+    plugins
+}
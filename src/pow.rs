@@ -0,0 +1,125 @@
+// Proof-of-work for guest joins, to make joining with thousands of bots
+// cost real CPU time on open deployments. With guest_pow_difficulty set,
+// joining as a guest or registering a nickname needs a nonce such that
+//
+//   sha256(challenge + nonce)
+//
+// starts with that many zero bits. The login page hands out a challenge and
+// static/pow.js finds the nonce before the form is sent; other clients get
+// one from
+//
+//   GET /api/pow    {"challenge": "...", "difficulty": 16}    404 when off
+//
+// and send it back as the `pow_challenge` and `pow_nonce` form fields. Each
+// challenge is good for one join within CHALLENGE_TTL. Challenges are
+// signed rather than stored, so handing them out costs nothing; only solved
+// ones are remembered until they expire. Signing in to an existing account
+// needs no proof, and the no-JavaScript view can only do that while this is
+// on. Every extra bit doubles the work: 16 takes a fraction of a second in a
+// browser, 20 a few seconds.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::Rng;
+use rocket::Route;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::config::CONFIG;
+
+// How long a challenge may take to solve and use, in seconds
+const CHALLENGE_TTL: i64 = 10 * 60;
+// Beyond this, joining would take browsers minutes
+pub const MAX_DIFFICULTY: u32 = 28;
+
+lazy_static! {
+    // Signs challenges; a restart only means solving a new one
+    static ref KEY: [u8; 32] = rand::rng().random();
+    // Solved challenge -> when it expires
+    static ref USED: Mutex<HashMap<String, i64>> = Mutex::new(HashMap::new());
+}
+
+// Zero bits asked for, or None when joins need no proof
+pub fn difficulty() -> Option<u32> {
+    Some(CONFIG.guest_pow_difficulty.min(MAX_DIFFICULTY)).filter(|&bits| bits > 0)
+}
+
+fn mac(payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&*KEY).expect("HMAC takes keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn signed(payload: &str, signature: &str) -> bool {
+    hex::decode(signature).is_ok_and(|signature| mac(payload).verify_slice(&signature).is_ok())
+}
+
+// A fresh challenge and its difficulty, for the login page
+pub fn challenge() -> Option<Value> {
+    let difficulty = difficulty()?;
+    let nonce: [u8; 16] = rand::rng().random();
+    let payload = format!("{}.{}", Utc::now().timestamp(), hex::encode(nonce));
+    let challenge = format!("{}.{}", payload, hex::encode(mac(&payload).finalize().into_bytes()));
+    Some(json!({ "challenge": challenge, "difficulty": difficulty }))
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+// Ok if joins need no proof or the nonce solves a fresh, unused challenge
+pub fn verify(challenge: Option<&str>, nonce: Option<&str>) -> Result<(), &'static str> {
+    let Some(difficulty) = difficulty() else {
+        return Ok(());
+    };
+    let (Some(challenge), Some(nonce)) = (challenge, nonce.filter(|nonce| !nonce.is_empty())) else {
+        return Err("Joining needs a proof of work from your browser; turn on JavaScript, or sign in to a registered nickname");
+    };
+    let issued = challenge
+        .rsplit_once('.')
+        .filter(|(payload, signature)| signed(payload, signature))
+        .and_then(|(payload, _)| payload.split_once('.'))
+        .and_then(|(issued, _)| issued.parse::<i64>().ok());
+    let issued = match issued {
+        Some(issued) if Utc::now().timestamp() - issued <= CHALLENGE_TTL => issued,
+        _ => return Err("The join page has expired, try again"),
+    };
+    let hash = Sha256::new().chain_update(challenge).chain_update(nonce).finalize();
+    if leading_zero_bits(&hash) < difficulty {
+        return Err("The proof of work doesn't check out, try again");
+    }
+
+    let mut used = USED.lock();
+    if used.contains_key(challenge) {
+        return Err("The join page has already been used, try again");
+    }
+    used.insert(challenge.to_string(), issued + CHALLENGE_TTL);
+    Ok(())
+}
+
+// Forgets solved challenges that have expired anyway
+pub fn prune() {
+    let now = Utc::now().timestamp();
+    USED.lock().retain(|_, expires| *expires > now);
+}
+
+#[rocket::get("/")]
+fn get() -> Option<Json<Value>> {
+    challenge().map(Json)
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![get]
+}
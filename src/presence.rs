@@ -0,0 +1,148 @@
+// Presence for integrations that don't hold a WebSocket open, like
+// dashboards and bots posting through the message API. They heartbeat with
+//
+//   PUT    /api/presence     {"room_id": "...", "name": "...", "ttl": <secs>}
+//   DELETE /api/presence     {"room_id": "...", "name": "..."}
+//   GET    /api/presence?room_id=<id>     who's in the room
+//
+// using a token with post:messages (read:messages to GET). The first
+// heartbeat joins the room under the name like anyone else, so it shows in
+// the roster and user counts; each one after pushes the expiry back, and once
+// the TTL runs out without one the integration leaves the room.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::accounts::ACCOUNTS;
+use crate::admin::{ApiResult, api_error};
+use crate::api_tokens::{CanPostMessages, CanReadMessages};
+use crate::room_core::{Effect, Event};
+use crate::rooms::public_room;
+use crate::{CHAT_STATE, User};
+
+const DEFAULT_TTL_SECS: i64 = 60;
+const MAX_TTL_SECS: i64 = 3600;
+const MAX_NAME_LEN: usize = 32;
+
+lazy_static! {
+    // (room id, user id) -> when the integration's presence runs out
+    static ref PRESENT: RwLock<HashMap<(String, String), DateTime<Utc>>> = RwLock::new(HashMap::new());
+}
+
+// Integrations get a fixed user id per name so heartbeats find them again
+fn user_id(name: &str) -> String {
+    format!("integration:{}", name.to_lowercase())
+}
+
+#[derive(Deserialize)]
+struct Heartbeat {
+    room_id: String,
+    name: String,
+    ttl: Option<i64>,
+}
+
+#[rocket::put("/", data = "<heartbeat>")]
+fn heartbeat(_token: CanPostMessages, heartbeat: Json<Heartbeat>) -> ApiResult {
+    let name = heartbeat.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(api_error(Status::BadRequest, format!("Names are 1 to {} characters", MAX_NAME_LEN)));
+    }
+    if ACCOUNTS.find(name).is_some() {
+        return Err(api_error(Status::Conflict, "That name belongs to a registered account"));
+    }
+    let ttl = heartbeat.ttl.unwrap_or(DEFAULT_TTL_SECS);
+    if !(1..=MAX_TTL_SECS).contains(&ttl) {
+        return Err(api_error(Status::BadRequest, format!("ttl must be 1 to {} seconds", MAX_TTL_SECS)));
+    }
+
+    let room = public_room(&heartbeat.room_id)?;
+    let user = User {
+        id: user_id(name),
+        nickname: name.to_string(),
+        room_id: heartbeat.room_id.clone(),
+        account_id: None,
+        session_id: None,
+    };
+    // Joining again while present does nothing
+    for effect in room.apply(Event::Join(user.clone())) {
+        if let Effect::Reject(error) = effect {
+            return Err(api_error(Status::Conflict, error.detail));
+        }
+    }
+    let expires_at = Utc::now() + Duration::seconds(ttl);
+    PRESENT.write().insert((heartbeat.room_id.clone(), user.id), expires_at);
+    Ok(Json(json!({
+        "room_id": heartbeat.room_id,
+        "name": name,
+        "expires_at": expires_at.to_rfc3339(),
+    })))
+}
+
+#[derive(Deserialize)]
+struct Departure {
+    room_id: String,
+    name: String,
+}
+
+#[rocket::delete("/", data = "<departure>")]
+fn leave(_token: CanPostMessages, departure: Json<Departure>) -> ApiResult {
+    let user_id = user_id(departure.name.trim());
+    if PRESENT.write().remove(&(departure.room_id.clone(), user_id.clone())).is_none() {
+        return Err(api_error(Status::NotFound, "That integration isn't present in the room"));
+    }
+    let room = CHAT_STATE.rooms.read().get(&departure.room_id).cloned();
+    if let Some(room) = room {
+        room.apply(Event::Leave { user_id });
+    }
+    Ok(Json(json!({ "room_id": departure.room_id, "name": departure.name.trim() })))
+}
+
+#[rocket::get("/?<room_id>")]
+fn roster(_token: CanReadMessages, room_id: &str) -> ApiResult {
+    let room = public_room(room_id)?;
+    let present = PRESENT.read();
+    let mut users: Vec<Value> = room
+        .users
+        .read()
+        .values()
+        .map(|user| {
+            let expires_at = present.get(&(room_id.to_string(), user.id.clone()));
+            json!({
+                "nickname": user.nickname,
+                "integration": expires_at.is_some(),
+                "expires_at": expires_at.map(DateTime::to_rfc3339),
+            })
+        })
+        .collect();
+    users.sort_by_key(|user| user["nickname"].as_str().map(str::to_lowercase));
+    Ok(Json(json!({ "room_id": room_id, "users": users })))
+}
+
+// Takes integrations whose TTL ran out out of their rooms; run from the task loop
+pub fn expire() {
+    let now = Utc::now();
+    let expired: Vec<(String, String)> = {
+        let mut present = PRESENT.write();
+        let expired = present.iter().filter(|(_, at)| **at <= now).map(|(key, _)| key.clone()).collect();
+        present.retain(|_, at| *at > now);
+        expired
+    };
+    for (room_id, user_id) in expired {
+        let room = CHAT_STATE.rooms.read().get(&room_id).cloned();
+        if let Some(room) = room {
+            room.apply(Event::Leave { user_id });
+        }
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![heartbeat, leave, roster]
+}
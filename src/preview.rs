@@ -0,0 +1,114 @@
+// Read-only look into a public room before joining, for visitors who want
+// to see what's being talked about before picking a nickname:
+//
+//   GET /rooms/<room_id>/preview
+//
+// shows the last preview_messages messages, without presence notices, and
+// a link to join. Only listed rooms (see seo.rs) that don't take knocking
+// can be previewed, and each address gets preview_rate_limit page views a
+// minute. Setting preview_messages to 0 turns previews off.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rocket::Route;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_dyn_templates::{Template, context};
+use serde_json::Value;
+
+use crate::appearance;
+use crate::config::CONFIG;
+use crate::rooms::valid_room_id;
+use crate::{CHAT_STATE, basic, proxy, seo};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    // Client address -> preview times within the window
+    static ref RECENT: Mutex<HashMap<IpAddr, VecDeque<Instant>>> = Mutex::new(HashMap::new());
+}
+
+// Whether the room can be previewed; rooms that don't exist yet can, and
+// show as empty
+pub fn available(room_id: &str) -> bool {
+    if CONFIG.preview_messages == 0 {
+        return false;
+    }
+    match CHAT_STATE.rooms.read().get(room_id) {
+        Some(room) => {
+            let config = room.config.read();
+            seo::listed(&config) && !config.knock
+        },
+        None => valid_room_id(room_id),
+    }
+}
+
+// Counts a view, or says no when the address has had its share
+fn allow(ip: IpAddr) -> bool {
+    let now = Instant::now();
+    let mut recent = RECENT.lock();
+    let views = recent.entry(ip).or_default();
+    while views.front().is_some_and(|viewed| now.duration_since(*viewed) >= WINDOW) {
+        views.pop_front();
+    }
+    if views.len() >= CONFIG.preview_rate_limit {
+        return false;
+    }
+    views.push_back(now);
+    true
+}
+
+// Forgets addresses whose window has passed
+pub fn prune() {
+    let now = Instant::now();
+    RECENT
+        .lock()
+        .retain(|_, views| views.back().is_some_and(|viewed| now.duration_since(*viewed) < WINDOW));
+}
+
+pub struct Visitor(Option<IpAddr>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Visitor {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Visitor(proxy::client_ip(request)))
+    }
+}
+
+#[rocket::get("/<room_id>/preview")]
+fn preview(room_id: &str, visitor: Visitor) -> Result<(ContentType, Template), Status> {
+    if !available(room_id) {
+        return Err(Status::NotFound);
+    }
+    if !visitor.0.is_some_and(allow) {
+        return Err(Status::TooManyRequests);
+    }
+
+    // Looked up without creating the room, so previews never open one
+    let room = CHAT_STATE.rooms.read().get(room_id).cloned();
+    let appearance = room.as_ref().map(|room| appearance::json(&room.config.read()));
+    let messages: Vec<Value> = room.map_or_else(Vec::new, |room| {
+        let messages = room.messages.read();
+        let shown: Vec<_> = messages.iter().filter(|msg| !msg.presence).collect();
+        shown[shown.len().saturating_sub(CONFIG.preview_messages)..].iter().map(|msg| basic::entry(msg)).collect()
+    });
+
+    Ok((ContentType::HTML, Template::render("preview", context! {
+        room_id,
+        title: format!("#{}", room_id),
+        messages,
+        room: appearance,
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![preview]
+}
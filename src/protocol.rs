@@ -0,0 +1,106 @@
+// Error codes on the WebSocket protocol. Anything the server turns down is
+// reported with a stable code clients can act on and a detail to show:
+//
+//   {"type": "error", "code": "UNKNOWN_COMMAND", "detail": "Unknown command: /foo"}
+//
+// Chat messages sent with a `client_id` are answered with a `nack` carrying
+// the same code and detail instead, see ChatSocketHandler::reject.
+//
+//   INVALID_FRAME         not JSON, or missing a field the frame type needs
+//   UNKNOWN_COMMAND       no built-in or plugin command by that name
+//   INVALID_ARGUMENTS     a command's arguments don't fit its usage
+//   FORBIDDEN             the caller's role doesn't allow it
+//   ACCOUNT_REQUIRED      only registered accounts can do it
+//   UNKNOWN_ACTION        an `action` frame with an action we don't know
+//   ROOM_LOCKED           the (burner) room has expired
+//   MUTED                 a moderator muted the sender
+//   BANNED                an admin banned the user from the room, see reports.rs
+//   TOO_LONG              over `max_message_len` characters
+//   RATE_LIMITED          over `rate_limit_messages` within `rate_limit_secs`
+//   REJECTED              a plugin or word filter refused the message
+//   INVALID_LOCATION      coordinates out of range
+//   INVALID_WHITEBOARD    a whiteboard event that doesn't validate
+//   NICKNAME_TAKEN        someone else in the room goes by that nickname
+//   UNKNOWN_UPLOAD        an `upload` that isn't a finished upload of the sender's
+//   KNOCK_REQUIRED        the room only takes people a moderator let in
+//   FEATURE_DISABLED      an operator turned the feature off, see flags.rs
+//   READ_ONLY             the server is in maintenance mode, see maintenance.rs
+//
+// Errors, command results, welcome messages, reminders and bot hints go to
+// one connection only. They're marked ephemeral, are never stored in the
+// room's history and so don't come back on reconnect:
+//
+//   {"type": "system", "content": "...", "ephemeral": true}
+//   {"type": "system", "sender": "TriviaBot", "content": "...", "ephemeral": true}
+//   {"type": "error", "code": ..., "detail": ..., "ephemeral": true}
+//
+// Moderators also get abuse alerts this way, see escalation.rs.
+
+use rocket::serde::Serialize;
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidFrame,
+    UnknownCommand,
+    InvalidArguments,
+    Forbidden,
+    AccountRequired,
+    UnknownAction,
+    RoomLocked,
+    Muted,
+    Banned,
+    TooLong,
+    RateLimited,
+    Rejected,
+    InvalidLocation,
+    InvalidWhiteboard,
+    NicknameTaken,
+    UnknownUpload,
+    KnockRequired,
+    FeatureDisabled,
+    ReadOnly,
+}
+
+// Something turned down, with the code for clients and the detail for people
+#[derive(Debug)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub detail: String,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Error { code, detail: detail.into() }
+    }
+
+    pub fn frame(&self) -> String {
+        json!({
+            "type": "error",
+            "code": self.code,
+            "detail": self.detail,
+            "ephemeral": true,
+        }).to_string()
+    }
+}
+
+// A notice for one connection; callers may add fields before sending
+pub fn ephemeral(content: &str) -> Value {
+    json!({
+        "type": "system",
+        "content": content,
+        "ephemeral": true,
+    })
+}
+
+// A bot's reply for one connection, e.g. a game's scores asked for by one
+// player
+pub fn hint(sender: &str, content: &str) -> Value {
+    json!({
+        "type": "system",
+        "sender": sender,
+        "content": content,
+        "ephemeral": true,
+    })
+}
@@ -0,0 +1,106 @@
+// Running behind a reverse proxy: client addresses come from X-Forwarded-For
+// when the connection is from a trusted proxy, and everything can be served
+// under a path prefix such as /chat, with the proxy passing the full path on.
+
+use std::fmt::Display;
+use std::net::IpAddr;
+
+use lazy_static::lazy_static;
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome};
+use ws::Handshake;
+
+use crate::config::CONFIG;
+
+lazy_static! {
+    // The configured prefix as "/chat", or "" when serving from the root
+    static ref PREFIX: String = {
+        let prefix = CONFIG.path_prefix.trim_matches('/');
+        if prefix.is_empty() { String::new() } else { format!("/{}", prefix) }
+    };
+}
+
+pub fn prefix() -> &'static str {
+    &PREFIX
+}
+
+// A server-relative URL with the prefix in front, for links and redirects
+pub fn url(path: impl Display) -> String {
+    format!("{}{}", prefix(), path)
+}
+
+// Path as the app sees it, with the prefix removed
+pub fn strip_prefix(path: &str) -> &str {
+    path.strip_prefix(prefix()).unwrap_or(path)
+}
+
+fn is_trusted(ip: &IpAddr) -> bool {
+    CONFIG.trusted_proxies.contains(ip)
+}
+
+// Whether the request came through one of our reverse proxies
+pub fn behind_proxy(request: &Request<'_>) -> bool {
+    request.remote().is_some_and(|remote| is_trusted(&remote.ip()))
+}
+
+// Whether the client reached us over HTTPS, directly or through the proxy
+pub fn is_https(request: &Request<'_>) -> bool {
+    let proxied_https = behind_proxy(request)
+        && request
+            .headers()
+            .get_one("X-Forwarded-Proto")
+            .is_some_and(|proto| proto.eq_ignore_ascii_case("https"));
+    proxied_https || CONFIG.http.tls_cert.is_some()
+}
+
+// Scheme and host the client used, e.g. "https://chat.example.com", for
+// absolute links that leave the browser such as QR codes
+pub fn origin(request: &Request<'_>) -> String {
+    let headers = request.headers();
+    let forwarded_host = headers.get_one("X-Forwarded-Host").filter(|_| behind_proxy(request));
+    let host = forwarded_host
+        .or_else(|| headers.get_one("Host"))
+        .unwrap_or("localhost");
+    format!("{}://{}", if is_https(request) { "https" } else { "http" }, host)
+}
+
+// Request guard for the request's origin
+pub struct Origin(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Origin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Origin(origin(request)))
+    }
+}
+
+// The client's address. Forwarded headers are only believed from trusted
+// proxies, and the rightmost untrusted hop wins since anything further left
+// could have been made up by the client.
+pub fn client_ip(request: &Request<'_>) -> Option<IpAddr> {
+    let peer = request.remote()?.ip();
+    Some(forwarded_client(peer, request.headers().get("X-Forwarded-For")))
+}
+
+// The same for WebSocket connections
+pub fn ws_client_ip(handshake: &Handshake) -> Option<IpAddr> {
+    let peer = handshake.peer_addr?.ip();
+    let forwarded = handshake
+        .request
+        .header("X-Forwarded-For")
+        .and_then(|value| std::str::from_utf8(value).ok());
+    Some(forwarded_client(peer, forwarded.into_iter()))
+}
+
+fn forwarded_client<'a>(peer: IpAddr, forwarded_for: impl Iterator<Item = &'a str>) -> IpAddr {
+    if !is_trusted(&peer) {
+        return peer;
+    }
+    let forwarded: Vec<IpAddr> = forwarded_for
+        .flat_map(|value| value.split(','))
+        .filter_map(|hop| hop.trim().parse().ok())
+        .collect();
+    forwarded.into_iter().rev().find(|ip| !is_trusted(ip)).unwrap_or(peer)
+}
@@ -0,0 +1,105 @@
+// Installing the chat as a web app: a manifest built from the [pwa] and
+// [theme] config, and a service worker that keeps an offline page and the
+// static files cached and shows notifications. Pages link the manifest and
+// chat.js registers the worker when the manifest link is there.
+
+use rocket::Route;
+use rocket::http::{ContentType, Header};
+use rocket::serde::json::Value;
+use rocket_dyn_templates::{Template, context};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::config::CONFIG;
+use crate::{assets, proxy};
+
+// Files the worker caches on install, so the offline page can render
+const PRECACHE: [&str; 3] = ["chat.css", "chat.js", "login.css"];
+
+#[derive(rocket::Responder)]
+pub struct Worker {
+    body: Template,
+    content_type: ContentType,
+    cache_control: Header<'static>,
+    // Lets a worker served under the path prefix control the whole app
+    scope: Header<'static>,
+}
+
+pub fn icon_url() -> String {
+    CONFIG
+        .pwa
+        .icon_url
+        .clone()
+        .or_else(|| CONFIG.theme.logo_url.clone())
+        .unwrap_or_else(|| assets::url("icon.svg"))
+}
+
+fn icon_type(url: &str) -> &'static str {
+    match url.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).as_deref() {
+        Some("svg") => "image/svg+xml",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        _ => "image/png",
+    }
+}
+
+#[rocket::get("/manifest.json")]
+fn manifest() -> Option<(ContentType, String)> {
+    if !CONFIG.pwa.enabled {
+        return None;
+    }
+    let theme = &CONFIG.theme;
+    let icon = icon_url();
+    let manifest: Value = json!({
+        "name": theme.name,
+        "short_name": CONFIG.pwa.short_name.as_deref().unwrap_or(&theme.name),
+        "description": CONFIG.pwa.description,
+        "start_url": proxy::url("/"),
+        "scope": proxy::url("/"),
+        "display": "standalone",
+        "background_color": theme.background_color,
+        "theme_color": CONFIG.pwa.theme_color.as_deref().unwrap_or(&theme.primary_color),
+        "icons": [{ "src": icon, "sizes": "any", "type": icon_type(&icon), "purpose": "any" }],
+    });
+    Some((ContentType::new("application", "manifest+json"), manifest.to_string()))
+}
+
+#[rocket::get("/sw.js")]
+fn service_worker() -> Option<Worker> {
+    if !CONFIG.pwa.enabled {
+        return None;
+    }
+    let mut precache: Vec<String> = PRECACHE.iter().map(|name| assets::url(name)).collect();
+    precache.push(proxy::url("/static/theme.css"));
+    precache.push(proxy::url("/offline"));
+    // New asset hashes or theme settings mean a new cache, so stale files get dropped
+    let theme = serde_json::to_string(&CONFIG.theme).unwrap_or_default();
+    let version = hex::encode(&Sha256::digest(format!("{} {}", precache.join(" "), theme).as_bytes())[..4]);
+
+    Some(Worker {
+        body: Template::render("sw", context! {
+            version,
+            precache: json!(precache).to_string(),
+            offline: proxy::url("/offline"),
+            icon: icon_url(),
+            start: proxy::url("/"),
+        }),
+        content_type: ContentType::JavaScript,
+        cache_control: Header::new("Cache-Control", "no-cache"),
+        scope: Header::new("Service-Worker-Allowed", proxy::url("/")),
+    })
+}
+
+// Shown by the worker when a page can't be loaded
+#[rocket::get("/offline")]
+fn offline() -> (ContentType, Template) {
+    (ContentType::HTML, Template::render("offline", context! {
+        title: "Offline",
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![manifest, service_worker, offline]
+}
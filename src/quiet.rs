@@ -0,0 +1,86 @@
+// Opting out of a room's join and leave notices:
+//
+//   /quiet on      /quiet off      /quiet
+//
+// The server leaves the notices out of what it sends that user, both live
+// and in the history replay, so a busy room's comings and goings don't
+// bury the conversation. Other system messages still come through. Like
+// keywords, the setting belongs to the account, or to the connection for
+// guests; only accounts' are saved.
+
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::ErrorCode;
+use crate::storage;
+
+lazy_static! {
+    // "account:<id>:<room>" or "user:<id>:<room>"
+    static ref QUIET: RwLock<HashSet<String>> =
+        RwLock::new(storage::load("quiet", "rooms").unwrap_or_default());
+}
+
+fn key(room_id: &str, user_id: &str, account_id: Option<&str>) -> String {
+    match account_id {
+        Some(account_id) => format!("account:{}:{}", account_id, room_id),
+        None => format!("user:{}:{}", user_id, room_id),
+    }
+}
+
+fn save(all: &HashSet<String>) {
+    let saved: HashSet<&String> = all.iter().filter(|key| key.starts_with("account:")).collect();
+    if let Err(err) = storage::save("quiet", "rooms", &saved) {
+        eprintln!("Failed to save quiet rooms: {}", err);
+    }
+}
+
+// Whether the user has turned off join and leave notices in the room
+pub fn hides_presence(room_id: &str, user_id: &str, account_id: Option<&str>) -> bool {
+    QUIET.read().contains(&key(room_id, user_id, account_id))
+}
+
+// Hands a guest's quiet rooms over to the account they registered, see upgrade.rs
+pub fn adopt(user_id: &str, account_id: &str) {
+    let mut all = QUIET.write();
+    let prefix = format!("user:{}:", user_id);
+    let keys: Vec<String> = all.iter().filter(|key| key.starts_with(&prefix)).cloned().collect();
+    for key in keys {
+        all.remove(&key);
+        all.insert(format!("account:{}:{}", account_id, &key[prefix.len()..]));
+    }
+    save(&all);
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("quiet", "/quiet on|off - hide or show join and leave notices in this room", quiet);
+}
+
+fn quiet(ctx: &CommandContext) -> CommandOutput {
+    let user = ctx.user;
+    let key = key(&user.room_id, &user.id, user.account_id.as_deref());
+    let mut all = QUIET.write();
+    let reply = match ctx.args.trim() {
+        "" => {
+            return CommandOutput::Reply(match all.contains(&key) {
+                true => "Join and leave notices are hidden here".to_string(),
+                false => "Join and leave notices are shown here".to_string(),
+            });
+        },
+        "on" => {
+            all.insert(key);
+            "You won't see join and leave notices in this room any more"
+        },
+        "off" => {
+            all.remove(&key);
+            "Join and leave notices are back on for this room"
+        },
+        _ => return CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /quiet on|off"),
+    };
+    if user.account_id.is_some() {
+        save(&all);
+    }
+    CommandOutput::Reply(reply.to_string())
+}
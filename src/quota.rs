@@ -0,0 +1,100 @@
+// Optional daily message quota per nickname. Counts reset at midnight UTC
+// and are saved to the data directory so a restart doesn't hand out a fresh
+// allowance.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveTime, Utc};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rocket::Route;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::CONFIG;
+use crate::plugins::{MessageVerdict, Plugin};
+use crate::{ChatMessage, MessageType, UserSession, storage};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Usage {
+    // UTC date the counts belong to
+    day: String,
+    // lowercased nickname -> messages sent that day
+    counts: HashMap<String, u32>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Usage {
+    fn roll_over(&mut self) {
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        if self.day != today {
+            self.day = today;
+            self.counts.clear();
+            self.dirty = true;
+        }
+    }
+
+    fn used(&mut self, nickname: &str) -> u32 {
+        self.roll_over();
+        self.counts.get(&nickname.to_lowercase()).copied().unwrap_or(0)
+    }
+}
+
+lazy_static! {
+    static ref USAGE: Mutex<Usage> = Mutex::new(storage::load("quotas", "usage").unwrap_or_default());
+}
+
+fn resets_at() -> String {
+    let tomorrow = Utc::now().date_naive().succ_opt().unwrap_or_default();
+    tomorrow.and_time(NaiveTime::MIN).and_utc().to_rfc3339()
+}
+
+pub struct QuotaPlugin;
+
+impl Plugin for QuotaPlugin {
+    fn name(&self) -> &str {
+        "quota"
+    }
+
+    fn on_message(&self, message: &mut ChatMessage) -> MessageVerdict {
+        // Integrations posting through publish_bot aren't people with a quota
+        let Some(limit) = CONFIG.daily_message_quota.filter(|_| message.message_type != MessageType::Bot) else {
+            return MessageVerdict::Accept;
+        };
+
+        let mut usage = USAGE.lock();
+        if usage.used(&message.sender) >= limit {
+            return MessageVerdict::Reject(format!(
+                "you've sent all {} of your messages for today. Your quota resets at midnight UTC.",
+                limit
+            ));
+        }
+        *usage.counts.entry(message.sender.to_lowercase()).or_insert(0) += 1;
+        usage.dirty = true;
+        MessageVerdict::Accept
+    }
+}
+
+pub fn save_usage() {
+    storage::save_if_dirty("quotas", "usage", &mut *USAGE.lock(), |usage| &mut usage.dirty, "message quotas");
+}
+
+// Quota status for the signed-in user; limit and remaining are null when
+// there is no quota
+#[rocket::get("/")]
+fn status(session: UserSession) -> Json<Value> {
+    let used = USAGE.lock().used(&session.nickname);
+    let limit = CONFIG.daily_message_quota;
+    Json(json!({
+        "limit": limit,
+        "used": used,
+        "remaining": limit.map(|limit| limit.saturating_sub(used)),
+        "resets_at": resets_at(),
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![status]
+}
@@ -0,0 +1,125 @@
+// Activity ranks per room. Every message counts towards the sender's total
+// in that room, and the first one starts their tenure; together they earn a
+// rank from the [[ranks]] config, e.g.
+//
+//   [[ranks]]
+//   name = "regular"
+//   min_messages = 50
+//   min_days = 7
+//
+// listed lowest first, the last one reached winning. Messages carry the
+// sender's rank as "rank" for clients to show as a badge, and
+//
+//   /rank [nickname]
+//
+// says where someone stands. Like quotas, counts are per nickname and saved
+// to the data directory. With no ranks configured nothing is tracked.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::{ChatMessage, MessageType};
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::config::{CONFIG, RankConfig};
+use crate::plugins::{MessageVerdict, Plugin};
+use crate::storage;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Record {
+    messages: u64,
+    // RFC 3339
+    first_seen: String,
+}
+
+impl Record {
+    // Whole days since the first message
+    fn days(&self) -> u64 {
+        DateTime::parse_from_rfc3339(&self.first_seen)
+            .map_or(0, |first| (Utc::now() - first.with_timezone(&Utc)).num_days().max(0) as u64)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Activity {
+    // "<room>\n<lowercased nickname>" -> record
+    records: HashMap<String, Record>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+lazy_static! {
+    static ref ACTIVITY: Mutex<Activity> = Mutex::new(storage::load("ranks", "activity").unwrap_or_default());
+}
+
+fn key(room_id: &str, nickname: &str) -> String {
+    format!("{}\n{}", room_id, nickname.to_lowercase())
+}
+
+fn rank(record: &Record) -> Option<&'static RankConfig> {
+    let days = record.days();
+    CONFIG
+        .ranks
+        .iter()
+        .rfind(|rank| record.messages >= rank.min_messages && days >= rank.min_days)
+}
+
+pub struct RanksPlugin;
+
+impl Plugin for RanksPlugin {
+    fn name(&self) -> &str {
+        "ranks"
+    }
+
+    fn on_message(&self, message: &mut ChatMessage) -> MessageVerdict {
+        // Ranks are for people, not integrations posting through publish_bot
+        if CONFIG.ranks.is_empty() || message.message_type == MessageType::Bot {
+            return MessageVerdict::Accept;
+        }
+        let mut activity = ACTIVITY.lock();
+        let record = activity
+            .records
+            .entry(key(&message.room_id, &message.sender))
+            .or_insert_with(|| Record { messages: 0, first_seen: Utc::now().to_rfc3339() });
+        record.messages += 1;
+        message.rank = rank(record).map(|rank| rank.name.clone());
+        activity.dirty = true;
+        MessageVerdict::Accept
+    }
+}
+
+pub fn save_activity() {
+    storage::save_if_dirty("ranks", "activity", &mut *ACTIVITY.lock(), |activity| &mut activity.dirty, "activity ranks");
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("rank", "/rank [nickname] - show someone's activity rank in the room", show_rank);
+}
+
+fn show_rank(ctx: &CommandContext) -> CommandOutput {
+    if CONFIG.ranks.is_empty() {
+        return CommandOutput::Reply("Ranks aren't turned on here".to_string());
+    }
+    let nickname = if ctx.args.is_empty() { ctx.user.nickname.as_str() } else { ctx.args };
+    let record = ACTIVITY.lock().records.get(&key(&ctx.user.room_id, nickname)).cloned();
+    let Some(record) = record else {
+        return CommandOutput::Reply(format!("{} hasn't posted in #{} yet", nickname, ctx.user.room_id));
+    };
+    let days = record.days();
+    let standing = match rank(&record) {
+        Some(rank) => format!("{}: {}", nickname, rank.name),
+        None => format!("{}: no rank yet", nickname),
+    };
+    let next = CONFIG.ranks.iter().find(|rank| record.messages < rank.min_messages || days < rank.min_days);
+    let mut reply = format!("{} ({} messages over {} days)", standing, record.messages, days);
+    if let Some(next) = next {
+        reply.push_str(&format!(
+            ". Next: {} at {} messages and {} days",
+            next.name, next.min_messages, next.min_days
+        ));
+    }
+    CommandOutput::Reply(reply)
+}
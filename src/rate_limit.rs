@@ -0,0 +1,43 @@
+// Short-term flood protection: each nickname may send `rate_limit_messages`
+// messages per room within a sliding window of `rate_limit_secs`.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+
+use crate::config::CONFIG;
+
+lazy_static! {
+    // "<room>\n<lowercased nickname>" -> send times within the window
+    static ref RECENT: Mutex<HashMap<String, VecDeque<Instant>>> = Mutex::new(HashMap::new());
+}
+
+fn window() -> Duration {
+    Duration::from_secs(CONFIG.rate_limit_secs)
+}
+
+// Records a send, or returns how long until the next one is allowed
+pub fn check(room_id: &str, nickname: &str) -> Result<(), Duration> {
+    let now = Instant::now();
+    let mut recent = RECENT.lock();
+    let sends = recent.entry(format!("{}\n{}", room_id, nickname.to_lowercase())).or_default();
+    while sends.front().is_some_and(|sent| now.duration_since(*sent) >= window()) {
+        sends.pop_front();
+    }
+    if sends.len() >= CONFIG.rate_limit_messages {
+        let oldest = sends.front().copied().unwrap_or(now);
+        return Err(window().saturating_sub(now.duration_since(oldest)));
+    }
+    sends.push_back(now);
+    Ok(())
+}
+
+// Forgets senders whose window has passed
+pub fn prune() {
+    let now = Instant::now();
+    RECENT
+        .lock()
+        .retain(|_, sends| sends.back().is_some_and(|sent| now.duration_since(*sent) < window()));
+}
@@ -0,0 +1,230 @@
+// Opt-in recording of WebSocket sessions, for reproducing reported bugs.
+// A server admin arms recording for a user in a room, and every connection
+// they make there is written frame by frame to data/recordings/<name>.jsonl
+// until it's disarmed:
+//
+//   PUT    /api/admin/rooms/<room_id>/recording/<nickname>   arm
+//   DELETE /api/admin/rooms/<room_id>/recording/<nickname>   disarm
+//   GET    /api/admin/recordings                             names of recordings
+//   GET    /api/admin/recordings/<name>                      a recording's lines
+//   POST   /api/admin/recordings/<name>/replay               replay it
+//
+// The first line describes the session, then one line per text frame:
+//   {"at_ms": <since connecting>, "dir": "in"|"out", "frame": <JSON or text>}
+// Fields named in `recording_redact` (tickets, tokens and the like by
+// default) are replaced with "[redacted]" anywhere in a frame.
+//
+// Replaying connects a new session as the recorded nickname, by default to
+// the scratch room "replay-<name>" so nobody else sees it, and sends the
+// recorded inbound frames with their original spacing (gaps capped at
+// MAX_REPLAY_GAP_MS). The replayed session is itself recorded, with
+// "replay_of" in its header, so the two recordings can be compared.
+
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::Instant;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+use uuid::Uuid;
+use ws::util::Token;
+use ws::{CloseCode, Handler, Handshake, Sender};
+
+use crate::admin::{ApiResult, ServerAdmin, api_error};
+use crate::config::CONFIG;
+use crate::rooms::{INVALID_ROOM_ID, valid_room_id};
+use crate::{CHAT_STATE, User, storage};
+
+const MAX_REPLAY_GAP_MS: u64 = 5_000;
+// How long a replay stays connected after its last frame, to catch replies
+const REPLAY_SETTLE_MS: u64 = 2_000;
+
+lazy_static! {
+    // (room id, lowercased nickname) pairs being recorded
+    static ref ARMED: RwLock<HashSet<(String, String)>> = RwLock::new(HashSet::new());
+    // User id of a replay connection -> the recording it replays
+    static ref REPLAYS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if CONFIG.recording_redact.iter().any(|name| name == key) {
+                    *field = json!("[redacted]");
+                } else {
+                    redact(field);
+                }
+            }
+        },
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {},
+    }
+}
+
+pub struct Recorder {
+    name: String,
+    started: Instant,
+}
+
+impl Recorder {
+    // A recorder for the connection if it's armed or a replay
+    pub fn start(user: &User) -> Option<Recorder> {
+        let replay_of = REPLAYS.lock().remove(&user.id);
+        let armed = ARMED.read().contains(&(user.room_id.clone(), user.nickname.to_lowercase()));
+        if replay_of.is_none() && !armed {
+            return None;
+        }
+
+        let started_at = Utc::now();
+        let recorder = Recorder {
+            name: format!("{}-{}", started_at.format("%Y%m%dT%H%M%S"), &Uuid::new_v4().simple().to_string()[..8]),
+            started: Instant::now(),
+        };
+        recorder.write(json!({
+            "room_id": user.room_id,
+            "nickname": user.nickname,
+            "started_at": started_at.to_rfc3339(),
+            "replay_of": replay_of,
+        }));
+        Some(recorder)
+    }
+
+    fn write(&self, line: Value) {
+        if let Err(err) = storage::append_line("recordings", &self.name, &line.to_string()) {
+            eprintln!("Failed to write recording {}: {}", self.name, err);
+        }
+    }
+
+    // `dir` is "in" for frames from the client, "out" for frames to it
+    pub fn record(&self, dir: &str, text: &str) {
+        let mut frame = serde_json::from_str(text).unwrap_or_else(|_| json!(text));
+        redact(&mut frame);
+        self.write(json!({
+            "at_ms": self.started.elapsed().as_millis() as u64,
+            "dir": dir,
+            "frame": frame,
+        }));
+    }
+}
+
+fn read(name: &str) -> Result<Vec<Value>, (Status, Json<Value>)> {
+    let lines = storage::read_lines("recordings", name).map_err(|err| api_error(Status::InternalServerError, err))?;
+    if lines.is_empty() {
+        return Err(api_error(Status::NotFound, "No such recording"));
+    }
+    Ok(lines.iter().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+// Sends the recorded frames on a timer, then hangs up
+struct ReplayClient {
+    out: Sender,
+    // Milliseconds after connecting, and the frame
+    frames: Vec<(u64, String)>,
+}
+
+impl Handler for ReplayClient {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        for (index, (at_ms, _)) in self.frames.iter().enumerate() {
+            self.out.timeout(*at_ms, Token(index))?;
+        }
+        let last = self.frames.last().map_or(0, |(at_ms, _)| *at_ms);
+        self.out.timeout(last + REPLAY_SETTLE_MS, Token(self.frames.len()))
+    }
+
+    fn on_timeout(&mut self, event: Token) -> ws::Result<()> {
+        match self.frames.get(event.0) {
+            Some((_, frame)) => self.out.send(frame.as_str()),
+            None => self.out.close(CloseCode::Normal),
+        }
+    }
+}
+
+// Inbound frames with their timing squeezed down to at most MAX_REPLAY_GAP_MS apart
+fn inbound_frames(lines: &[Value]) -> Vec<(u64, String)> {
+    let mut frames = Vec::new();
+    let (mut previous, mut at) = (0, 0);
+    for line in lines.iter().filter(|line| line["dir"] == "in") {
+        let recorded = line["at_ms"].as_u64().unwrap_or(previous);
+        at += recorded.saturating_sub(previous).min(MAX_REPLAY_GAP_MS);
+        previous = recorded;
+        let frame = match &line["frame"] {
+            Value::String(text) => text.clone(),
+            frame => frame.to_string(),
+        };
+        frames.push((at, frame));
+    }
+    frames
+}
+
+#[rocket::put("/rooms/<room_id>/recording/<nickname>")]
+fn arm(_admin: ServerAdmin, room_id: &str, nickname: &str) -> Json<Value> {
+    ARMED.write().insert((room_id.to_string(), nickname.to_lowercase()));
+    Json(json!({ "room_id": room_id, "nickname": nickname, "recording": true }))
+}
+
+#[rocket::delete("/rooms/<room_id>/recording/<nickname>")]
+fn disarm(_admin: ServerAdmin, room_id: &str, nickname: &str) -> ApiResult {
+    if !ARMED.write().remove(&(room_id.to_string(), nickname.to_lowercase())) {
+        return Err(api_error(Status::NotFound, "That user isn't being recorded"));
+    }
+    Ok(Json(json!({ "room_id": room_id, "nickname": nickname, "recording": false })))
+}
+
+#[rocket::get("/recordings")]
+fn list(_admin: ServerAdmin) -> ApiResult {
+    let names = storage::log_keys("recordings").map_err(|err| api_error(Status::InternalServerError, err))?;
+    Ok(Json(json!({ "recordings": names })))
+}
+
+#[rocket::get("/recordings/<name>")]
+fn get(_admin: ServerAdmin, name: &str) -> ApiResult {
+    Ok(Json(json!({ "name": name, "lines": read(name)? })))
+}
+
+#[derive(Deserialize)]
+struct ReplayOptions {
+    room_id: Option<String>,
+}
+
+#[rocket::post("/recordings/<name>/replay", data = "<options>")]
+fn replay(_admin: ServerAdmin, name: &str, options: Option<Json<ReplayOptions>>) -> ApiResult {
+    let lines = read(name)?;
+    let nickname = lines[0]["nickname"].as_str().unwrap_or("replay").to_string();
+    let room_id = options
+        .and_then(|options| options.into_inner().room_id)
+        .unwrap_or_else(|| format!("replay-{}", name));
+    if !valid_room_id(&room_id) {
+        return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+    }
+    let frames = inbound_frames(&lines);
+    let count = frames.len();
+
+    let user = User {
+        id: Uuid::new_v4().to_string(),
+        nickname,
+        room_id: room_id.clone(),
+        account_id: None,
+        session_id: None,
+    };
+    REPLAYS.lock().insert(user.id.clone(), name.to_string());
+    let ticket = CHAT_STATE.issue_ws_ticket(user);
+    let url = format!("ws://127.0.0.1:{}/{}?ticket={}", CONFIG.ws_port, room_id, ticket);
+    thread::spawn(move || {
+        if let Err(err) = ws::connect(url, |out| ReplayClient { out, frames: frames.clone() }) {
+            eprintln!("Replay failed: {}", err);
+        }
+    });
+
+    Ok(Json(json!({ "room_id": room_id, "frames": count })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![arm, disarm, list, get, replay]
+}
@@ -0,0 +1,263 @@
+// Reminders set with
+//
+//   /remind me in 20m to check the build      private, back to whoever set it
+//   /remind here in 1h30m to start the retro  posted to the room
+//   /remind list
+//   /remind cancel <id>
+//
+// Delays are made of <n>s, <n>m, <n>h and <n>d parts. Reminders are saved so
+// they survive restarts. Private ones go to the requester's connections in
+// the room they were set in, or failing that any room they're in; if they
+// aren't connected anywhere the reminder waits for them to come back, for up
+// to a day.
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::protocol::{self, ErrorCode};
+use crate::{CHAT_STATE, ChatMessage, Connection, MessageType, RoomState, User, storage};
+
+const MAX_PENDING: usize = 25;
+const MAX_TEXT_LEN: usize = 500;
+const MAX_DELAY_DAYS: i64 = 30;
+// How long a private reminder waits for its owner to reconnect
+const UNDELIVERED_HOURS: i64 = 24;
+// Event and calendar reminders this late aren't worth sending, e.g. after a
+// restart
+pub const LATE_REMINDER_SECS: i64 = 5 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Reminder {
+    id: String,
+    room_id: String,
+    // Posted to the room rather than sent to the requester
+    here: bool,
+    user_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    account_id: Option<String>,
+    nickname: String,
+    text: String,
+    // RFC 3339
+    due_at: String,
+}
+
+impl Reminder {
+    fn due(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.due_at).ok().map(|at| at.with_timezone(&Utc))
+    }
+
+    fn owned_by(&self, user: &User) -> bool {
+        match &user.account_id {
+            Some(account_id) => self.account_id.as_ref() == Some(account_id),
+            None => self.account_id.is_none() && self.user_id == user.id,
+        }
+    }
+
+    fn reaches(&self, conn: &Connection) -> bool {
+        match &self.account_id {
+            Some(account_id) => conn.account_id.as_ref() == Some(account_id),
+            None => conn.user_id == self.user_id,
+        }
+    }
+}
+
+lazy_static! {
+    static ref REMINDERS: RwLock<Vec<Reminder>> =
+        RwLock::new(storage::load("reminders", "reminders").unwrap_or_default());
+}
+
+fn save(reminders: &[Reminder]) {
+    if let Err(err) = storage::save("reminders", "reminders", &reminders) {
+        eprintln!("Failed to save reminders: {}", err);
+    }
+}
+
+// "20m", "1h30m", "2d", ...
+fn parse_delay(text: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: i64 = digits.parse().ok()?;
+        digits.clear();
+        total += match c {
+            's' => Duration::seconds(n),
+            'm' => Duration::minutes(n),
+            'h' => Duration::hours(n),
+            'd' => Duration::days(n),
+            _ => return None,
+        };
+    }
+    (digits.is_empty() && total > Duration::zero()).then_some(total)
+}
+
+fn describe_delay(delay: Duration) -> String {
+    let minutes = (delay.num_seconds() + 59) / 60;
+    match minutes {
+        1 => "1 minute".to_string(),
+        m if m < 120 => format!("{} minutes", m),
+        m if m < 48 * 60 => format!("{} hours", m / 60),
+        m => format!("{} days", m / (24 * 60)),
+    }
+}
+
+// Sends a private reminder; false if none of the owner's connections got it
+fn deliver_privately(reminder: &Reminder) -> bool {
+    let mut frame = protocol::ephemeral(&format!("Reminder: {}", reminder.text));
+    frame["reminder"] = json!(reminder.id);
+    let frame = frame.to_string();
+    let connected = |room: &RoomState| room.connections.read().iter().any(|conn| reminder.reaches(conn));
+
+    let rooms = CHAT_STATE.rooms.read();
+    let room = rooms
+        .get(&reminder.room_id)
+        .filter(|room| connected(room))
+        .or_else(|| rooms.values().find(|room| connected(room)))
+        .cloned();
+    drop(rooms);
+    match room {
+        Some(room) => {
+            room.send_where(&frame, |conn| reminder.reaches(conn));
+            true
+        },
+        None => false,
+    }
+}
+
+// Posts to the room as System, if the room is open; also used for event and
+// calendar reminders
+pub fn announce(room_id: &str, content: &str) {
+    let room = CHAT_STATE.rooms.read().get(room_id).cloned();
+    if let Some(room) = room {
+        room.post(ChatMessage::new(room_id, "System", content, MessageType::SystemMessage));
+    }
+}
+
+fn deliver_here(reminder: &Reminder) {
+    announce(&reminder.room_id, &format!("Reminder from {}: {}", reminder.nickname, reminder.text));
+}
+
+// Sends due reminders; run from the task loop
+pub fn fire() {
+    let now = Utc::now();
+    let due: Vec<Reminder> = REMINDERS
+        .read()
+        .iter()
+        .filter(|reminder| reminder.due().is_none_or(|at| at <= now))
+        .cloned()
+        .collect();
+
+    let mut done = Vec::new();
+    for reminder in due {
+        let delivered = if reminder.here {
+            deliver_here(&reminder);
+            true
+        } else {
+            deliver_privately(&reminder)
+        };
+        let stale = reminder.due().is_none_or(|at| now - at > Duration::hours(UNDELIVERED_HOURS));
+        if delivered || stale {
+            done.push(reminder.id);
+        }
+    }
+    if !done.is_empty() {
+        let mut reminders = REMINDERS.write();
+        reminders.retain(|reminder| !done.contains(&reminder.id));
+        save(&reminders);
+    }
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("remind", "/remind me|here in <20m|2h|1d> to <text>, /remind list or /remind cancel <id>", remind);
+}
+
+const USAGE: &str = "Usage: /remind me|here in <20m|2h|1d> to <text>";
+
+fn remind(ctx: &CommandContext) -> CommandOutput {
+    let user = ctx.user;
+    let (action, rest) = ctx.args.split_once(' ').unwrap_or((ctx.args, ""));
+    match action {
+        "list" => {
+            let now = Utc::now();
+            let lines: Vec<String> = REMINDERS
+                .read()
+                .iter()
+                .filter(|reminder| reminder.owned_by(user))
+                .map(|reminder| {
+                    let left = reminder.due().map(|at| describe_delay(at - now)).unwrap_or_default();
+                    let place = if reminder.here { format!(" in #{}", reminder.room_id) } else { String::new() };
+                    format!("[{}] in {}{}: {}", reminder.id, left, place, reminder.text)
+                })
+                .collect();
+            if lines.is_empty() {
+                return CommandOutput::Reply("You have no reminders".to_string());
+            }
+            CommandOutput::Reply(format!("Your reminders:\n{}", lines.join("\n")))
+        },
+        "cancel" => {
+            let id = rest.trim();
+            let mut reminders = REMINDERS.write();
+            let before = reminders.len();
+            reminders.retain(|reminder| !(reminder.id == id && reminder.owned_by(user)));
+            if reminders.len() == before {
+                return CommandOutput::error(ErrorCode::InvalidArguments, format!("You have no reminder {}", id));
+            }
+            save(&reminders);
+            CommandOutput::Reply(format!("Cancelled reminder {}", id))
+        },
+        "me" | "here" => {
+            let mut words = rest.trim().splitn(3, ' ');
+            let (Some("in"), Some(delay), Some(text)) = (words.next(), words.next(), words.next()) else {
+                return CommandOutput::error(ErrorCode::InvalidArguments, USAGE);
+            };
+            let Some(delay) = parse_delay(delay).filter(|delay| *delay <= Duration::days(MAX_DELAY_DAYS)) else {
+                return CommandOutput::error(
+                    ErrorCode::InvalidArguments,
+                    format!("Delays look like 20m, 1h30m or 2d, up to {} days", MAX_DELAY_DAYS),
+                );
+            };
+            let text = text.strip_prefix("to ").unwrap_or(text).trim();
+            if text.is_empty() {
+                return CommandOutput::error(ErrorCode::InvalidArguments, USAGE);
+            }
+            if text.chars().count() > MAX_TEXT_LEN {
+                return CommandOutput::error(
+                    ErrorCode::InvalidArguments,
+                    format!("Reminders are limited to {} characters", MAX_TEXT_LEN),
+                );
+            }
+
+            let mut reminders = REMINDERS.write();
+            if reminders.iter().filter(|reminder| reminder.owned_by(user)).count() >= MAX_PENDING {
+                return CommandOutput::error(
+                    ErrorCode::InvalidArguments,
+                    format!("You can have at most {} reminders", MAX_PENDING),
+                );
+            }
+            let reminder = Reminder {
+                id: Uuid::new_v4().simple().to_string()[..8].to_string(),
+                room_id: user.room_id.clone(),
+                here: action == "here",
+                user_id: user.id.clone(),
+                account_id: user.account_id.clone(),
+                nickname: user.nickname.clone(),
+                text: text.to_string(),
+                due_at: (Utc::now() + delay).to_rfc3339(),
+            };
+            let whom = if reminder.here { "the room" } else { "you" };
+            let reply = format!("I'll remind {} in {} [{}]", whom, describe_delay(delay), reminder.id);
+            reminders.push(reminder);
+            save(&reminders);
+            CommandOutput::Reply(reply)
+        },
+        _ => CommandOutput::error(ErrorCode::InvalidArguments, USAGE),
+    }
+}
@@ -0,0 +1,295 @@
+// Abuse reports and the queue server admins work through. Anyone in a room
+// can report a message there, with an optional reason:
+//
+//   {"type": "report", "message_id": "<id>", "reason": "..."}
+//
+// and gets a private thank-you. Reports keep a copy of the message as it was,
+// so they still make sense once it's gone. Admins review them at
+// /admin/reports, which asks for the admin token, or through
+//
+//   GET  /api/admin/reports?status=open|resolved|all     open ones by default
+//        {"reports": [{"id", "room_id", "message_id", "sender", "content", "sent_at",
+//                      "reporter", "reason", "created_at", "resolution", "context"}]}
+//   POST /api/admin/reports/<id>/resolve
+//        {"action": "delete" | "mute" | "ban" | "dismiss", "minutes": <mute length>, "note": "..."}
+//
+// `context` is the messages around the reported one while they're still in
+// the room's history. Resolving carries out the action and closes every
+// open report on the same message:
+//
+//   delete    takes the message out of the room's history; clients get
+//             {"type": "delete", "message_id"}. Not in compliance rooms.
+//   mute      the sender can't post in the room for `minutes` (60 by default)
+//   ban       puts the sender out of the room and keeps their nickname, and
+//             account if they have one, out of it
+//   dismiss   nothing, the report was unfounded
+//
+// Enough open reports against one sender alert the room's moderators, see
+// escalation.rs.
+//
+// In anonymous rooms mute and ban go to the real sender behind the pseudonym.
+
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use rocket_dyn_templates::{Template, context};
+use serde_json::json;
+use uuid::Uuid;
+use ws::CloseCode;
+
+use crate::accounts::ACCOUNTS;
+use crate::admin::{Admin, ApiResult, api_error};
+use crate::config::CONFIG;
+use crate::protocol::{self, ErrorCode};
+use crate::{CHAT_STATE, Event, MessageType, RoomState, User, anonymous, audit, escalation, proxy, storage};
+
+const MAX_REASON_LEN: usize = 500;
+// Messages shown on either side of the reported one
+const CONTEXT: usize = 3;
+const DEFAULT_MUTE_MINS: i64 = 60;
+const MAX_MUTE_MINS: i64 = 30 * 24 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Delete,
+    Mute,
+    Ban,
+    Dismiss,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Resolution {
+    action: Action,
+    note: Option<String>,
+    // RFC 3339
+    resolved_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Report {
+    id: String,
+    room_id: String,
+    message_id: String,
+    // The message as it was reported
+    sender: String,
+    content: String,
+    sent_at: String,
+    reporter: String,
+    reason: Option<String>,
+    // RFC 3339
+    created_at: String,
+    resolution: Option<Resolution>,
+}
+
+lazy_static! {
+    // Oldest first
+    static ref REPORTS: RwLock<Vec<Report>> = RwLock::new(storage::load("reports", "reports").unwrap_or_default());
+}
+
+fn save(reports: &[Report]) {
+    if let Err(err) = storage::save("reports", "reports", &reports) {
+        eprintln!("Failed to save reports: {}", err);
+    }
+}
+
+// Handles a `report` frame, returning the reply for the reporter
+pub fn submit(user: &User, room: &RoomState, message_id: Option<&str>, reason: Option<&str>) -> Result<String, protocol::Error> {
+    let Some(message_id) = message_id else {
+        return Err(protocol::Error::new(ErrorCode::InvalidFrame, "Reports need a \"message_id\" string"));
+    };
+    let reason = reason.map(str::trim).filter(|reason| !reason.is_empty());
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_REASON_LEN) {
+        return Err(protocol::Error::new(ErrorCode::TooLong, format!("Keep the reason under {} characters", MAX_REASON_LEN)));
+    }
+    let msg = room
+        .messages
+        .read()
+        .iter()
+        .find(|msg| msg.id == message_id && msg.message_type != MessageType::SystemMessage)
+        .cloned()
+        .ok_or(protocol::Error::new(ErrorCode::InvalidArguments, "No such message in this room"))?;
+    if msg.sender.eq_ignore_ascii_case(&user.nickname) {
+        return Err(protocol::Error::new(ErrorCode::InvalidArguments, "You can't report your own messages"));
+    }
+
+    let mut reports = REPORTS.write();
+    let already = reports.iter().any(|report| {
+        report.resolution.is_none() && report.message_id == message_id && report.reporter.eq_ignore_ascii_case(&user.nickname)
+    });
+    if !already {
+        let sender = msg.sender.clone();
+        reports.push(Report {
+            id: Uuid::new_v4().to_string(),
+            room_id: room.id.to_string(),
+            message_id: message_id.to_string(),
+            sender: msg.sender,
+            content: msg.content,
+            sent_at: msg.timestamp,
+            reporter: user.nickname.clone(),
+            reason: reason.map(str::to_string),
+            created_at: Utc::now().to_rfc3339(),
+            resolution: None,
+        });
+        save(&reports);
+        let open = reports
+            .iter()
+            .filter(|report| report.resolution.is_none() && report.room_id == *room.id && report.sender == sender)
+            .count();
+        drop(reports);
+        escalation::reported(&room.id, &sender, open);
+    }
+    Ok(protocol::ephemeral("Thanks for the report, the moderators will take a look").to_string())
+}
+
+// The messages around the reported one, if it's still in the room
+fn context(report: &Report) -> Vec<Value> {
+    let Some(room) = CHAT_STATE.rooms.read().get(&report.room_id).cloned() else {
+        return Vec::new();
+    };
+    let messages = room.messages.read();
+    let Some(at) = messages.iter().position(|msg| msg.id == report.message_id) else {
+        return Vec::new();
+    };
+    messages[at.saturating_sub(CONTEXT)..(at + CONTEXT + 1).min(messages.len())]
+        .iter()
+        .map(|msg| msg.to_frame())
+        .collect()
+}
+
+#[rocket::get("/reports?<status>")]
+fn list(_admin: Admin, status: Option<&str>) -> ApiResult {
+    let wanted: fn(&Report) -> bool = match status.unwrap_or("open") {
+        "open" => |report| report.resolution.is_none(),
+        "resolved" => |report| report.resolution.is_some(),
+        "all" => |_| true,
+        _ => return Err(api_error(Status::BadRequest, "status must be open, resolved or all")),
+    };
+    let listed: Vec<Value> = REPORTS
+        .read()
+        .iter()
+        .rev()
+        .filter(|report| wanted(report))
+        .map(|report| {
+            let mut entry = json!(report);
+            entry["context"] = json!(context(report));
+            entry
+        })
+        .collect();
+    Ok(Json(json!({ "reports": listed })))
+}
+
+#[derive(Deserialize)]
+struct Decision {
+    action: Action,
+    minutes: Option<i64>,
+    note: Option<String>,
+}
+
+// Takes the message out of the room's history
+fn delete(room: &RoomState, message_id: &str) -> Result<(), (Status, Json<Value>)> {
+    if room.config.read().compliance {
+        return Err(api_error(Status::Conflict, "Messages can't be deleted in compliance mode"));
+    }
+    room.messages.write().retain(|msg| msg.id != message_id);
+    room.broadcast(&json!({ "type": "delete", "message_id": message_id }).to_string());
+    Ok(())
+}
+
+// Puts everyone going by the nickname out of the room and keeps them out
+fn ban(room: &RoomState, nickname: &str) {
+    let account_id = ACCOUNTS.find(nickname).map(|account| account.id);
+    {
+        let mut config = room.config.write();
+        config.banned.insert(nickname.to_lowercase());
+        config.banned.extend(account_id.clone());
+    }
+    let banned = |name: &str, account: Option<&str>| {
+        name.eq_ignore_ascii_case(nickname) || (account.is_some() && account == account_id.as_deref())
+    };
+    for connection in room.connections.read().iter() {
+        if banned(&connection.nickname, connection.account_id.as_deref()) {
+            let _ = connection.sender.close(CloseCode::Policy);
+        }
+    }
+    let user_ids: Vec<String> = room
+        .users
+        .read()
+        .values()
+        .filter(|user| banned(&user.nickname, user.account_id.as_deref()))
+        .map(|user| user.id.clone())
+        .collect();
+    for user_id in user_ids {
+        room.apply(Event::Leave { user_id });
+    }
+}
+
+#[rocket::post("/reports/<id>/resolve", data = "<decision>")]
+fn resolve(_admin: Admin, id: &str, decision: Json<Decision>) -> ApiResult {
+    let report = REPORTS
+        .read()
+        .iter()
+        .find(|report| report.id == id)
+        .cloned()
+        .ok_or_else(|| api_error(Status::NotFound, "No such report"))?;
+    if report.resolution.is_some() {
+        return Err(api_error(Status::Conflict, "That report has already been resolved"));
+    }
+    let minutes = decision.minutes.unwrap_or(DEFAULT_MUTE_MINS);
+    if !(1..=MAX_MUTE_MINS).contains(&minutes) {
+        return Err(api_error(Status::BadRequest, format!("minutes must be between 1 and {}", MAX_MUTE_MINS)));
+    }
+
+    let room = CHAT_STATE.rooms.read().get(&report.room_id).cloned();
+    let sender = anonymous::real_sender(&report.room_id, &report.message_id).unwrap_or_else(|| report.sender.clone());
+    match (decision.action, &room) {
+        (Action::Dismiss, _) => {},
+        (_, None) => return Err(api_error(Status::Gone, "The room is gone; dismiss the report instead")),
+        (Action::Delete, Some(room)) => delete(room, &report.message_id)?,
+        (Action::Mute, Some(room)) => {
+            room.config.write().muted.insert(sender.to_lowercase(), Utc::now() + Duration::minutes(minutes));
+        },
+        (Action::Ban, Some(room)) => ban(room, &sender),
+    }
+    audit::record(&report.room_id, "report_resolved", "admin", json!({
+        "message_id": report.message_id,
+        "sender": sender,
+        "action": decision.action,
+    }));
+
+    let note = decision.note.as_deref().map(str::trim).filter(|note| !note.is_empty()).map(str::to_string);
+    let resolution = Resolution { action: decision.action, note, resolved_at: Utc::now().to_rfc3339() };
+    let mut reports = REPORTS.write();
+    let mut resolved = 0;
+    for other in reports.iter_mut() {
+        if other.resolution.is_none() && other.room_id == report.room_id && other.message_id == report.message_id {
+            other.resolution = Some(resolution.clone());
+            resolved += 1;
+        }
+    }
+    save(&reports);
+    Ok(Json(json!({ "id": id, "resolution": resolution, "resolved": resolved })))
+}
+
+// The review queue; reports.js asks for the admin token and uses the API
+#[rocket::get("/reports")]
+fn page() -> (ContentType, Template) {
+    (ContentType::HTML, Template::render("reports", context! {
+        title: "Reports",
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, resolve]
+}
+
+pub fn page_routes() -> Vec<Route> {
+    rocket::routes![page]
+}
+
@@ -0,0 +1,90 @@
+// Drops old history, so rooms stay focused and storage stays small. The
+// server's [retention] table sets how many hours each kind of message is
+// kept, and a room's own `retention` setting (PATCH /api/admin/rooms/<id>/settings)
+// overrides it per kind, 0 keeping that kind for good:
+//
+//   messages_hours      what people send, including locations and calls
+//   system_hours        join and leave notices and other system messages
+//   bot_hours           bot replies
+//   attachments_hours   attached files; a message that was only files goes
+//                       with them, otherwise it stays without them
+//
+// Expired messages leave the stored history; clients that already have them
+// aren't told. Compliance rooms keep everything.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::config::{CONFIG, RetentionConfig};
+use crate::tasks::Every;
+use crate::{CHAT_STATE, ChatMessage, MessageType, attachments};
+
+static SWEEPS: Every = Every::new(Duration::from_secs(60));
+
+// The room's setting for a kind, else the server's; None keeps it
+fn hours(room: Option<u64>, server: Option<u64>) -> Option<chrono::Duration> {
+    room.or(server).filter(|&hours| hours > 0).map(|hours| chrono::Duration::hours(hours as i64))
+}
+
+fn lifetime(retention: &RetentionConfig, msg: &ChatMessage) -> Option<chrono::Duration> {
+    let server = &CONFIG.retention;
+    match msg.message_type {
+        MessageType::SystemMessage => hours(retention.system_hours, server.system_hours),
+        MessageType::Bot => hours(retention.bot_hours, server.bot_hours),
+        _ => hours(retention.messages_hours, server.messages_hours),
+    }
+}
+
+fn older_than(msg: &ChatMessage, lifetime: Option<chrono::Duration>, now: DateTime<Utc>) -> bool {
+    lifetime.is_some_and(|lifetime| {
+        DateTime::parse_from_rfc3339(&msg.timestamp).is_ok_and(|sent| sent.with_timezone(&Utc) + lifetime <= now)
+    })
+}
+
+fn attached_anywhere(id: &str) -> bool {
+    CHAT_STATE.rooms.read().values().any(|room| {
+        room.messages.read().iter().any(|msg| msg.attachments.iter().any(|attachment| attachment.id == id))
+    })
+}
+
+// Runs from the task loop, sweeping once a minute
+pub fn sweep() {
+    if !SWEEPS.due() {
+        return;
+    }
+    let now = Utc::now();
+    let rooms: Vec<_> = CHAT_STATE.rooms.read().values().cloned().collect();
+    for room in rooms {
+        let retention = {
+            let config = room.config.read();
+            if config.compliance {
+                continue;
+            }
+            config.retention.clone()
+        };
+        let attachments_lifetime = hours(retention.attachments_hours, CONFIG.retention.attachments_hours);
+        let mut files = Vec::new();
+        {
+            let mut messages = room.messages.write();
+            messages.retain_mut(|msg| {
+                if older_than(msg, lifetime(&retention, msg), now) {
+                    files.extend(msg.attachments.drain(..).map(|attachment| attachment.id));
+                    return false;
+                }
+                if !msg.attachments.is_empty() && older_than(msg, attachments_lifetime, now) {
+                    files.extend(msg.attachments.drain(..).map(|attachment| attachment.id));
+                    return !msg.content.is_empty();
+                }
+                true
+            });
+        }
+        // Rooms split off this one may have copies of the message
+        files.retain(|id| !attached_anywhere(id));
+        for id in files {
+            if let Err(err) = attachments::remove(&id) {
+                eprintln!("Failed to delete expired attachment {}: {}", id, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+// The rules for what happens in a room, kept apart from locks, sockets,
+// storage and the clock. `apply` takes the room's members and settings plus
+// an event and returns the effects for the caller to carry out
+// (RoomState::apply does that for real), so a sequence of joins, leaves,
+// messages and commands always gives the same result and can be checked
+// without a server running.
+//
+// Anything random or time-dependent is passed in: events carry fully built
+// users and messages, and the current time comes in with the room.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+
+use crate::protocol::{self, ErrorCode};
+use crate::{ChatMessage, RoomConfig, User, knock};
+
+pub const BANNED: &str = "You are banned from this room";
+
+// The parts of a room the rules look at
+pub struct Room<'a> {
+    // user id -> user
+    pub users: &'a mut HashMap<String, User>,
+    pub config: &'a RoomConfig,
+    pub now: DateTime<Utc>,
+    pub max_message_len: usize,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    // Someone enters the room, from the login form or a new connection
+    Join(User),
+    // A user logged out or closed their last connection
+    Leave { user_id: String },
+    // A chat message to publish
+    Post(Box<ChatMessage>),
+    // A slash command, including the '/'
+    Command { user: User, command: String },
+}
+
+#[derive(Debug)]
+pub enum Effect {
+    // A system message to store and show everyone
+    Notice(String),
+    // An entry for the audit log
+    Audit { event: &'static str, actor: String, data: Value },
+    // Plugins hear about a user joining
+    Joined(User),
+    // The message passed the room's rules and goes on to rate limiting,
+    // plugins and the history
+    Publish(Box<ChatMessage>),
+    // The command goes to the command registry and plugins
+    RunCommand { user: User, command: String },
+    // The event was turned down and nothing changed
+    Reject(protocol::Error),
+}
+
+// Whether the account (or a guest) may come in at all
+pub fn admit(config: &RoomConfig, account_id: Option<&str>) -> Result<(), protocol::Error> {
+    if !config.admits(account_id) {
+        return Err(protocol::Error::new(ErrorCode::Forbidden, "This room is private"));
+    }
+    if config.locked {
+        return Err(protocol::Error::new(ErrorCode::RoomLocked, "This room has expired"));
+    }
+    Ok(())
+}
+
+// Whether the user could join, without changing anything
+pub fn check_join(config: &RoomConfig, users: &HashMap<String, User>, user: &User) -> Result<(), protocol::Error> {
+    admit(config, user.account_id.as_deref())?;
+    check_ban(config, user)?;
+    let taken = users
+        .values()
+        .any(|other| other.id != user.id && other.nickname == user.nickname);
+    if taken {
+        return Err(protocol::Error::new(ErrorCode::NicknameTaken, "That nickname is already in use in this room"));
+    }
+    check_knock(config, user)
+}
+
+// Banned nicknames and accounts stay out, see reports.rs
+pub fn check_ban(config: &RoomConfig, user: &User) -> Result<(), protocol::Error> {
+    if config.bans(&user.nickname, user.account_id.as_deref()) {
+        return Err(protocol::Error::new(ErrorCode::Banned, BANNED));
+    }
+    Ok(())
+}
+
+// Knock rooms only take moderators and people they approved
+pub fn check_knock(config: &RoomConfig, user: &User) -> Result<(), protocol::Error> {
+    if config.knock && !config.is_moderator(user.account_id.as_deref()) && !knock::approved(config, user) {
+        return Err(protocol::Error::new(ErrorCode::KnockRequired, "A moderator has to let you into this room"));
+    }
+    Ok(())
+}
+
+fn check_post(room: &Room, msg: &ChatMessage) -> Result<(), protocol::Error> {
+    if room.config.locked {
+        return Err(protocol::Error::new(ErrorCode::RoomLocked, "This room has expired and is locked"));
+    }
+    let muted = room
+        .config
+        .muted
+        .get(&msg.sender.to_lowercase())
+        .is_some_and(|until| *until > room.now);
+    if muted {
+        return Err(protocol::Error::new(ErrorCode::Muted, "You are muted in this room"));
+    }
+    if msg.content.chars().count() > room.max_message_len {
+        return Err(protocol::Error::new(
+            ErrorCode::TooLong,
+            format!("Messages are limited to {} characters", room.max_message_len),
+        ));
+    }
+    Ok(())
+}
+
+pub fn apply(room: &mut Room, event: Event) -> Vec<Effect> {
+    match event {
+        Event::Join(user) => {
+            // Another connection of someone already here
+            if room.users.contains_key(&user.id) {
+                return Vec::new();
+            }
+            if let Err(error) = check_join(room.config, room.users, &user) {
+                return vec![Effect::Reject(error)];
+            }
+            room.users.insert(user.id.clone(), user.clone());
+            vec![
+                Effect::Notice(format!("{} has joined the room", user.nickname)),
+                Effect::Joined(user.clone()),
+                Effect::Audit { event: "join", actor: user.nickname, data: json!({ "user_id": user.id }) },
+            ]
+        },
+        Event::Leave { user_id } => match room.users.remove(&user_id) {
+            Some(user) => vec![
+                Effect::Notice(format!("{} has left the room", user.nickname)),
+                Effect::Audit { event: "leave", actor: user.nickname, data: json!({ "user_id": user.id }) },
+            ],
+            // Never joined, e.g. turned away when connecting
+            None => Vec::new(),
+        },
+        Event::Post(msg) => match check_post(room, &msg) {
+            Ok(()) => vec![Effect::Publish(msg)],
+            Err(error) => vec![Effect::Reject(error)],
+        },
+        Event::Command { user, command } => vec![
+            Effect::Audit { event: "command", actor: user.nickname.clone(), data: json!({ "command": command }) },
+            Effect::RunCommand { user, command },
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::MessageType;
+
+    const MAX_LEN: usize = 20;
+    // Ids 0 and 4 share a nickname, as do 1 and 5, and so on
+    const NICKNAMES: [&str; 4] = ["alice", "bob", "mallory", "quiet"];
+    const USERS: usize = 8;
+    const BANNED_NICKNAME: &str = "mallory";
+    const MUTED_NICKNAME: &str = "quiet";
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Join(usize),
+        Leave(usize),
+        Post(usize, usize),
+        Command(usize),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..USERS).prop_map(Op::Join),
+            (0..USERS).prop_map(Op::Leave),
+            (0..USERS, 0..MAX_LEN + 5).prop_map(|(user, len)| Op::Post(user, len)),
+            (0..USERS).prop_map(Op::Command),
+        ]
+    }
+
+    fn user(index: usize) -> User {
+        User {
+            id: format!("user-{}", index),
+            nickname: NICKNAMES[index % NICKNAMES.len()].to_string(),
+            room_id: "lobby".to_string(),
+            account_id: None,
+            session_id: None,
+        }
+    }
+
+    fn config(now: DateTime<Utc>) -> RoomConfig {
+        let mut config = RoomConfig::default();
+        config.banned.insert(BANNED_NICKNAME.to_string());
+        config.muted.insert(MUTED_NICKNAME.to_string(), now + Duration::hours(1));
+        config
+    }
+
+    fn rejected(effects: &[Effect], code: ErrorCode) -> bool {
+        matches!(effects, [Effect::Reject(error)] if error.code == code)
+    }
+
+    proptest! {
+        // Runs random sequences against a model of who should be in the room
+        #[test]
+        fn events_keep_members_consistent(ops in prop::collection::vec(op(), 0..60)) {
+            let now = Utc::now();
+            let config = config(now);
+            let mut users = HashMap::new();
+            let mut model: HashMap<String, String> = HashMap::new();
+
+            for op in ops {
+                let mut room = Room { users: &mut users, config: &config, now, max_message_len: MAX_LEN };
+                match op {
+                    Op::Join(index) => {
+                        let user = user(index);
+                        let effects = apply(&mut room, Event::Join(user.clone()));
+                        if model.contains_key(&user.id) {
+                            prop_assert!(effects.is_empty());
+                        } else if user.nickname == BANNED_NICKNAME {
+                            prop_assert!(rejected(&effects, ErrorCode::Banned));
+                        } else if model.values().any(|nickname| *nickname == user.nickname) {
+                            prop_assert!(rejected(&effects, ErrorCode::NicknameTaken));
+                        } else {
+                            prop_assert!(matches!(effects.as_slice(), [Effect::Notice(_), Effect::Joined(_), Effect::Audit { .. }]), "{:?}", effects);
+                            model.insert(user.id, user.nickname);
+                        }
+                    },
+                    Op::Leave(index) => {
+                        let user = user(index);
+                        let effects = apply(&mut room, Event::Leave { user_id: user.id.clone() });
+                        match model.remove(&user.id) {
+                            Some(_) => prop_assert!(matches!(effects.as_slice(), [Effect::Notice(_), Effect::Audit { .. }]), "{:?}", effects),
+                            None => prop_assert!(effects.is_empty()),
+                        }
+                    },
+                    Op::Post(index, len) => {
+                        let user = user(index);
+                        let msg = ChatMessage::new("lobby", &user.nickname, &"x".repeat(len), MessageType::UserMessage);
+                        let effects = apply(&mut room, Event::Post(Box::new(msg)));
+                        if user.nickname == MUTED_NICKNAME {
+                            prop_assert!(rejected(&effects, ErrorCode::Muted));
+                        } else if len > MAX_LEN {
+                            prop_assert!(rejected(&effects, ErrorCode::TooLong));
+                        } else {
+                            prop_assert!(matches!(effects.as_slice(), [Effect::Publish(_)]));
+                        }
+                    },
+                    Op::Command(index) => {
+                        let effects = apply(&mut room, Event::Command { user: user(index), command: "/help".to_string() });
+                        prop_assert!(matches!(effects.as_slice(), [Effect::Audit { .. }, Effect::RunCommand { .. }]), "{:?}", effects);
+                    },
+                }
+
+                let mut members: Vec<(&String, &String)> = users.iter().map(|(id, user)| (id, &user.nickname)).collect();
+                let mut expected: Vec<(&String, &String)> = model.iter().collect();
+                members.sort();
+                expected.sort();
+                prop_assert_eq!(members, expected);
+                // Nicknames stay unique among members
+                let mut nicknames: Vec<&String> = users.values().map(|user| &user.nickname).collect();
+                nicknames.sort();
+                nicknames.dedup();
+                prop_assert_eq!(nicknames.len(), users.len());
+            }
+        }
+
+        // However the room got where it is, a banned user never gets in
+        #[test]
+        fn banned_users_are_always_rejected(ops in prop::collection::vec(op(), 0..30), account in prop::option::of("[a-z]{1,8}")) {
+            let now = Utc::now();
+            let mut config = config(now);
+            let mut users = HashMap::new();
+            for op in ops {
+                let mut room = Room { users: &mut users, config: &config, now, max_message_len: MAX_LEN };
+                let event = match op {
+                    Op::Join(index) => Event::Join(user(index)),
+                    Op::Leave(index) => Event::Leave { user_id: user(index).id },
+                    Op::Post(index, len) => Event::Post(Box::new(ChatMessage::new("lobby", NICKNAMES[index % NICKNAMES.len()], &"x".repeat(len), MessageType::UserMessage))),
+                    Op::Command(index) => Event::Command { user: user(index), command: "/help".to_string() },
+                };
+                apply(&mut room, event);
+            }
+
+            // Banned by nickname, and by account for a nickname nobody banned
+            let mut banned = user(2);
+            banned.id = "newcomer".to_string();
+            banned.account_id = account.clone();
+            if let Some(account_id) = &account {
+                config.banned.insert(account_id.clone());
+            }
+            let mut by_account = banned.clone();
+            by_account.nickname = "someone-new".to_string();
+
+            let mut room = Room { users: &mut users, config: &config, now, max_message_len: MAX_LEN };
+            prop_assert!(rejected(&apply(&mut room, Event::Join(banned)), ErrorCode::Banned));
+            if account.is_some() {
+                prop_assert!(rejected(&apply(&mut room, Event::Join(by_account)), ErrorCode::Banned));
+            }
+            prop_assert!(!users.contains_key("newcomer"));
+        }
+    }
+}
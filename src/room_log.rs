@@ -0,0 +1,86 @@
+// Read-only log of a room's history, for rooms an admin has set public_log
+// on, e.g. an open-source project's support channel:
+//
+//   GET /rooms/<room_id>/log?date=YYYY-MM-DD&page=<n>
+//
+// shows one UTC day (today when no date is given), PAGE_SIZE messages a
+// page, with links to the days around it. Each message has an anchor, so
+// /rooms/<room_id>/log?date=...&page=...#m-<message_id> is a permalink to it.
+// System notices aren't logged, and DMs never are.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rocket::Route;
+use rocket::http::{ContentType, Status};
+use rocket_dyn_templates::{Template, context};
+use serde_json::Value;
+
+use crate::appearance;
+use crate::config::CONFIG;
+use crate::{CHAT_STATE, ChatMessage, MessageType, basic, proxy};
+
+const PAGE_SIZE: usize = 100;
+
+fn day_of(msg: &ChatMessage) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(&msg.timestamp).ok().map(|at| at.with_timezone(&Utc).date_naive())
+}
+
+fn link(room_id: &str, date: NaiveDate, page: usize) -> String {
+    let mut url = proxy::url(format!("/rooms/{}/log?date={}", room_id, date.format("%Y-%m-%d")));
+    if page > 1 {
+        url.push_str(&format!("&page={}", page));
+    }
+    url
+}
+
+#[rocket::get("/<room_id>/log?<date>&<page>")]
+fn log(room_id: &str, date: Option<&str>, page: Option<usize>) -> Result<(ContentType, Template), Status> {
+    let room = CHAT_STATE
+        .rooms
+        .read()
+        .get(room_id)
+        .filter(|room| {
+            let config = room.config.read();
+            config.public_log && config.members.is_none()
+        })
+        .cloned()
+        .ok_or(Status::NotFound)?;
+    let today = Utc::now().date_naive();
+    let date = match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| Status::BadRequest)?,
+        None => today,
+    };
+
+    let day: Vec<Value> = room
+        .messages
+        .read()
+        .iter()
+        .filter(|msg| msg.message_type != MessageType::SystemMessage && day_of(msg) == Some(date))
+        .map(basic::entry)
+        .collect();
+    let pages = day.len().div_ceil(PAGE_SIZE).max(1);
+    let page = page.unwrap_or(1).clamp(1, pages);
+    let messages: Vec<Value> = day.into_iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE).collect();
+    let permalink = |page: usize| link(room_id, date, page);
+    let appearance = appearance::json(&room.config.read());
+
+    Ok((ContentType::HTML, Template::render("log", context! {
+        room_id,
+        title: format!("#{} log for {}", room_id, date.format("%Y-%m-%d")),
+        date: date.format("%Y-%m-%d").to_string(),
+        messages,
+        page,
+        pages,
+        page_url: permalink(page),
+        previous_page: (page > 1).then(|| permalink(page - 1)),
+        next_page: (page < pages).then(|| permalink(page + 1)),
+        previous_day: link(room_id, date - Duration::days(1), 1),
+        next_day: (date < today).then(|| link(room_id, date + Duration::days(1), 1)),
+        room: appearance,
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![log]
+}
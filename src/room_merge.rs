@@ -0,0 +1,204 @@
+// Merging and splitting rooms, for when a community outgrows its layout:
+//
+//   POST /api/admin/rooms/<room_id>/merge    {"from": "<room_id>"}
+//   POST /api/admin/rooms/<room_id>/split    {"to": "<room_id>", "members": ["<nickname>", ...], "since": "<message id>"}
+//
+// Merging folds `from` into the room: its history is interleaved with the
+// room's by timestamp, its managed members (see membership.rs) and roles
+// join the room's, keeping the roles the room already gave, and `from` is
+// torn down. Splitting moves the named people, guests by nickname and
+// accounts by username, to `to`, a room nobody is using yet, which takes
+// over the room's settings; with `since`, a copy of the history from that
+// message on goes along. Either way whoever is moved and still connected is
+// sent
+//
+//   {"type": "moved", "room_id": "<room_id>"}
+//
+// and has to join the other room. A public room and a private one can't be
+// merged, so neither one's history ends up where it wasn't meant to be.
+// Stars, karma, whiteboards and the like stay with the room they were in.
+
+use chrono::{DateTime, Utc};
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+use uuid::Uuid;
+use ws::CloseCode;
+
+use crate::accounts::ACCOUNTS;
+use crate::admin::{Admin, ApiResult, api_error};
+use crate::room_core::Event;
+use crate::rooms::{INVALID_ROOM_ID, valid_room_id};
+use crate::{CHAT_STATE, ChatMessage, RoomState, audit, membership};
+
+fn check_room_ids(room_id: &str, other: &str) -> Result<(), (Status, Json<Value>)> {
+    if !valid_room_id(room_id) || !valid_room_id(other) {
+        return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+    }
+    if room_id == other {
+        return Err(api_error(Status::BadRequest, "Pick two different rooms"));
+    }
+    Ok(())
+}
+
+fn sent_at(msg: &ChatMessage) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(&msg.timestamp).map_or(DateTime::<Utc>::MIN_UTC, |at| at.with_timezone(&Utc))
+}
+
+// Points the people `filter` picks at the other room and takes them out of this one
+fn move_out(room: &RoomState, to: &str, filter: impl Fn(&str, Option<&str>) -> bool) {
+    let frame = json!({ "type": "moved", "room_id": to }).to_string();
+    room.send_where(&frame, |conn| filter(&conn.nickname, conn.account_id.as_deref()));
+    let user_ids: Vec<String> = room
+        .users
+        .read()
+        .values()
+        .filter(|user| filter(&user.nickname, user.account_id.as_deref()))
+        .map(|user| user.id.clone())
+        .collect();
+    for user_id in user_ids {
+        room.apply(Event::Leave { user_id });
+    }
+    for conn in room.connections.read().iter().filter(|conn| filter(&conn.nickname, conn.account_id.as_deref())) {
+        let _ = conn.sender.close(CloseCode::Away);
+    }
+    CHAT_STATE
+        .ws_tickets
+        .write()
+        .retain(|_, ticket| !(*ticket.user.room_id == *room.id && filter(&ticket.user.nickname, ticket.user.account_id.as_deref())));
+}
+
+#[derive(Deserialize)]
+struct Merge {
+    from: String,
+}
+
+#[rocket::post("/rooms/<room_id>/merge", data = "<merge>")]
+fn merge(_admin: Admin, room_id: &str, merge: Json<Merge>) -> ApiResult {
+    let from = merge.from.as_str();
+    check_room_ids(room_id, from)?;
+    let Some(source) = CHAT_STATE.rooms.read().get(from).cloned() else {
+        return Err(api_error(Status::NotFound, "No such room to merge"));
+    };
+    let room = CHAT_STATE.get_or_create_room(room_id);
+    // One room's lock at a time, as upgrades hold every room's at once
+    let source_private = source.config.read().members.is_some();
+    if source_private != room.config.read().members.is_some() {
+        return Err(api_error(Status::Conflict, "A public room and a private one can't be merged"));
+    }
+
+    move_out(&source, room_id, |_, _| true);
+    let moved: Vec<ChatMessage> = std::mem::take(&mut *source.messages.write());
+    let merged = moved.len();
+    {
+        let mut messages = room.messages.write();
+        messages.extend(moved.into_iter().map(|mut msg| {
+            msg.room_id = room_id.to_string();
+            msg
+        }));
+        // Stable, so messages sent at the same moment keep their order
+        messages.sort_by_key(sent_at);
+    }
+    {
+        let source_config = source.config.read().clone();
+        let mut config = room.config.write();
+        for (account_id, role) in &source_config.roles {
+            config.roles.entry(account_id.clone()).or_insert(*role);
+        }
+        if let (Some(members), Some(moved)) = (&mut config.members, &source_config.members) {
+            members.extend(moved.iter().cloned());
+        }
+        config.version += 1;
+    }
+    membership::merge(from, room_id);
+    CHAT_STATE.remove_room(from);
+
+    audit::record(room_id, "room_merged", "admin", json!({ "from": from, "messages": merged }));
+    Ok(Json(json!({ "room_id": room_id, "merged": from, "messages": merged })))
+}
+
+#[derive(Deserialize)]
+struct Split {
+    to: String,
+    members: Vec<String>,
+    since: Option<String>,
+}
+
+#[rocket::post("/rooms/<room_id>/split", data = "<split>")]
+fn split(_admin: Admin, room_id: &str, split: Json<Split>) -> ApiResult {
+    let to = split.to.as_str();
+    check_room_ids(room_id, to)?;
+    if split.members.is_empty() {
+        return Err(api_error(Status::BadRequest, "Name at least one member to move"));
+    }
+    let Some(room) = CHAT_STATE.rooms.read().get(room_id).cloned() else {
+        return Err(api_error(Status::NotFound, "No such room"));
+    };
+    let in_use = |target: &RoomState| !target.users.read().is_empty() || !target.messages.read().is_empty();
+    if CHAT_STATE.rooms.read().get(to).is_some_and(in_use) {
+        return Err(api_error(Status::Conflict, "Split into a room nobody is using yet"));
+    }
+
+    let copied: Vec<ChatMessage> = match &split.since {
+        Some(since) => {
+            let messages = room.messages.read();
+            let Some(at) = messages.iter().position(|msg| msg.id == *since) else {
+                return Err(api_error(Status::NotFound, "No such message in this room"));
+            };
+            messages[at..]
+                .iter()
+                .cloned()
+                .map(|mut msg| {
+                    msg.id = Uuid::new_v4().to_string();
+                    msg.room_id = to.to_string();
+                    msg
+                })
+                .collect()
+        },
+        None => Vec::new(),
+    };
+
+    let nicknames: Vec<String> = split.members.iter().map(|name| name.to_lowercase()).collect();
+    let account_ids: Vec<String> = split
+        .members
+        .iter()
+        .filter_map(|name| ACCOUNTS.find(name))
+        .map(|account| account.id)
+        .collect();
+    let picked = |nickname: &str, account_id: Option<&str>| {
+        nicknames.contains(&nickname.to_lowercase()) || account_id.is_some_and(|id| account_ids.iter().any(|moved| moved == id))
+    };
+
+    let target = CHAT_STATE.get_or_create_room(to);
+    {
+        let source_config = room.config.read().clone();
+        let mut config = target.config.write();
+        config.apply_template(&source_config);
+        config.roles.retain(|account_id, _| account_ids.contains(account_id));
+        if let Some(members) = &source_config.members {
+            let moved = members.iter().filter(|id| account_ids.contains(id)).cloned();
+            config.members.get_or_insert_default().extend(moved);
+        }
+    }
+    {
+        let mut config = room.config.write();
+        config.roles.retain(|account_id, _| !account_ids.contains(account_id));
+        if let Some(members) = &mut config.members {
+            members.retain(|id| !account_ids.contains(id));
+        }
+        config.version += 1;
+    }
+    membership::transfer(room_id, to, &account_ids);
+    let copied_count = copied.len();
+    target.messages.write().extend(copied);
+    move_out(&room, to, picked);
+
+    audit::record(room_id, "room_split", "admin", json!({ "to": to, "members": split.members, "messages": copied_count }));
+    Ok(Json(json!({ "room_id": room_id, "to": to, "members": split.members, "messages": copied_count })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![merge, split]
+}
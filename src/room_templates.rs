@@ -0,0 +1,55 @@
+// Named room templates: the copyable part of a room's config (roles, rules,
+// word filters, flags and welcome message) saved so new rooms can start from
+// it. Message history is never part of a template.
+
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::{RoomConfig, storage};
+
+pub struct TemplateStore {
+    templates: RwLock<BTreeMap<String, RoomConfig>>,
+}
+
+impl TemplateStore {
+    fn load() -> Self {
+        TemplateStore {
+            templates: RwLock::new(storage::load("room_templates", "templates").unwrap_or_default()),
+        }
+    }
+
+    fn save(templates: &BTreeMap<String, RoomConfig>) {
+        if let Err(err) = storage::save("room_templates", "templates", templates) {
+            eprintln!("Failed to save room templates: {}", err);
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.templates.read().keys().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<RoomConfig> {
+        self.templates.read().get(name).cloned()
+    }
+
+    pub fn put(&self, name: &str, template: RoomConfig) {
+        let mut templates = self.templates.write();
+        templates.insert(name.to_string(), template);
+        Self::save(&templates);
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        let mut templates = self.templates.write();
+        let removed = templates.remove(name).is_some();
+        if removed {
+            Self::save(&templates);
+        }
+        removed
+    }
+}
+
+lazy_static! {
+    pub static ref TEMPLATES: TemplateStore = TemplateStore::load();
+}
@@ -0,0 +1,385 @@
+// Public room API: discovery of the rooms people can join, and burner rooms
+// that delete themselves after a time-to-live. Creating a room takes the
+// admin token or an admin:rooms API token, so nobody else can claim ids.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Duration, Utc};
+use qrcode::{Color, QrCode};
+use rocket::Route;
+use rocket::http::{ContentType, Status};
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::admin::{Admin, ApiResult, api_error};
+use crate::appearance;
+use crate::api_tokens::{CanPostMessages, CanReadMessages};
+use crate::config::CONFIG;
+use crate::flags::{self, Flag};
+use crate::trace::{TraceContext, TraceParent};
+use crate::uploads::{self, Uploader};
+use crate::{CHAT_STATE, ChatMessage, MessageType, RoomState, archive, maintenance, proxy, publish_bot, stats};
+
+const MAX_ROOM_ID_LEN: usize = 64;
+const DEFAULT_HISTORY: usize = 50;
+const MAX_HISTORY: usize = 500;
+pub const INVALID_ROOM_ID: &str = "Room ids are up to 64 letters, digits, '-' or '_'";
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LEN: usize = 32;
+const INVALID_TAG: &str = "Tags are up to 32 letters, digits, '-' or '_'";
+// Countdown warnings, in seconds before expiry
+const EXPIRY_WARNINGS: [i64; 4] = [600, 300, 60, 10];
+// How long an expired room stays locked before it is deleted
+const LOCKED_GRACE_SECS: i64 = 30;
+// QR code pixels per module, and the blank border around it in modules
+const DEFAULT_QR_SCALE: u32 = 8;
+const MAX_QR_SCALE: u32 = 32;
+const QR_QUIET_ZONE: u32 = 4;
+
+// A room as listed in discovery
+pub struct Listing {
+    pub id: String,
+    pub users: usize,
+    pub nsfw: bool,
+    pub topic: Option<String>,
+    pub tags: BTreeSet<String>,
+    // Icon and accent color, see appearance.rs
+    pub appearance: Value,
+}
+
+impl Listing {
+    pub fn to_json(&self) -> Value {
+        let mut listing = json!({ "id": self.id, "users": self.users, "nsfw": self.nsfw, "topic": self.topic, "tags": self.tags });
+        if let (Some(listing), Value::Object(appearance)) = (listing.as_object_mut(), &self.appearance) {
+            listing.extend(appearance.clone());
+        }
+        listing
+    }
+}
+
+// Checks and normalizes tags for a room's settings
+pub fn parse_tags(tags: &[String]) -> Result<BTreeSet<String>, String> {
+    let tags: BTreeSet<String> = tags.iter().map(|tag| tag.trim().to_lowercase()).collect();
+    if tags.len() > MAX_TAGS {
+        return Err(format!("Rooms can have at most {} tags", MAX_TAGS));
+    }
+    if !tags.iter().all(|tag| valid_tag(tag)) {
+        return Err(INVALID_TAG.to_string());
+    }
+    Ok(tags)
+}
+
+fn valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && tag.len() <= MAX_TAG_LEN && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Tags asked for as `tag=a,b`, all of which a room needs to be listed
+pub fn tag_filter(tag: Option<&str>) -> BTreeSet<String> {
+    tag.unwrap_or_default()
+        .split(',')
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+// Public rooms, busiest first. Private (DM) rooms are never listed and NSFW
+// rooms only when asked for.
+pub fn discoverable(include_nsfw: bool, tags: &BTreeSet<String>) -> Vec<Listing> {
+    let rooms = CHAT_STATE.rooms.read();
+    let mut listed: Vec<Listing> = rooms
+        .iter()
+        .filter_map(|(room_id, room)| {
+            let config = room.config.read();
+            if config.members.is_some() || (config.nsfw && !include_nsfw) || !tags.is_subset(&config.tags) {
+                return None;
+            }
+            Some(Listing {
+                id: room_id.clone(),
+                users: room.users.read().len(),
+                nsfw: config.nsfw,
+                topic: config.topic.clone(),
+                tags: config.tags.clone(),
+                appearance: appearance::json(&config),
+            })
+        })
+        .collect();
+    listed.sort_by(|a, b| b.users.cmp(&a.users).then_with(|| a.id.cmp(&b.id)));
+    listed
+}
+
+// Every tag in use on a listed room, with how many rooms have it
+pub fn tag_counts(include_nsfw: bool) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for listing in discoverable(include_nsfw, &BTreeSet::new()) {
+        for tag in listing.tags {
+            *counts.entry(tag).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+// Lists public rooms, busiest first, including NSFW ones with `?nsfw=true`
+// and only those with all the given tags with `?tag=games,eu`
+#[rocket::get("/?<nsfw>&<tag>")]
+fn list(nsfw: Option<bool>, tag: Option<&str>) -> Json<Value> {
+    let listed: Vec<Value> = discoverable(nsfw.unwrap_or(false), &tag_filter(tag))
+        .iter()
+        .map(Listing::to_json)
+        .collect();
+    Json(json!({ "rooms": listed }))
+}
+
+// Tags of listed rooms and how many rooms have each
+#[rocket::get("/tags?<nsfw>")]
+fn tags(nsfw: Option<bool>) -> Json<Value> {
+    let tags: Vec<Value> = tag_counts(nsfw.unwrap_or(false))
+        .into_iter()
+        .map(|(tag, rooms)| json!({ "tag": tag, "rooms": rooms }))
+        .collect();
+    Json(json!({ "tags": tags }))
+}
+
+// Rooms with the most messages in the last hour, plus how many people are
+// online across all rooms
+#[rocket::get("/trending?<limit>")]
+fn trending(limit: Option<usize>) -> Json<Value> {
+    let limit = limit.unwrap_or(stats::LANDING_TRENDING).min(stats::MAX_TRENDING);
+    Json(json!({
+        "rooms": stats::trending(limit),
+        "online_users": stats::online_users(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct NewRoom {
+    // Random when omitted
+    id: Option<String>,
+    // Seconds until the room is locked and deleted
+    ttl: Option<u64>,
+}
+
+pub fn valid_room_id(room_id: &str) -> bool {
+    !room_id.is_empty()
+        && room_id.len() <= MAX_ROOM_ID_LEN
+        && !room_id.starts_with("dm_")
+        && room_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[rocket::post("/", data = "<new_room>")]
+fn create(_admin: Admin, new_room: Json<NewRoom>) -> ApiResult {
+    let room_id = match &new_room.id {
+        Some(room_id) if !valid_room_id(room_id) => {
+            return Err(api_error(Status::BadRequest, INVALID_ROOM_ID));
+        },
+        Some(room_id) => room_id.clone(),
+        None => format!("burner-{}", &Uuid::new_v4().simple().to_string()[..8]),
+    };
+
+    let expires_at = match new_room.ttl {
+        Some(ttl) if ttl == 0 || ttl > CONFIG.max_room_ttl_secs => {
+            return Err(api_error(
+                Status::BadRequest,
+                format!("ttl must be between 1 and {} seconds", CONFIG.max_room_ttl_secs),
+            ));
+        },
+        Some(ttl) => Some(Utc::now() + Duration::seconds(ttl as i64)),
+        None => None,
+    };
+
+    if CHAT_STATE.rooms.read().contains_key(&room_id) {
+        return Err(api_error(Status::Conflict, "A room with that id already exists"));
+    }
+    let room_state = CHAT_STATE.get_or_create_room(&room_id);
+    room_state.config.write().expires_at = expires_at;
+
+    Ok(Json(json!({
+        "id": room_id,
+        "url": proxy::url(format!("/?rid={}", room_id)),
+        "expires_at": expires_at.map(|at| at.to_rfc3339()),
+    })))
+}
+
+fn describe_secs(secs: i64) -> String {
+    match secs {
+        s if s >= 120 => format!("{} minutes", (s + 59) / 60),
+        s if s >= 60 => "1 minute".to_string(),
+        1 => "1 second".to_string(),
+        s => format!("{} seconds", s),
+    }
+}
+
+// Counts burner rooms down, locks them when their time is up and deletes
+// them after a short grace period
+pub fn expire() {
+    let now = Utc::now();
+    let burners: Vec<_> = CHAT_STATE
+        .rooms
+        .read()
+        .iter()
+        .filter(|(_, room)| room.config.read().expires_at.is_some())
+        .map(|(room_id, room)| (room_id.clone(), room.clone()))
+        .collect();
+
+    for (room_id, room) in burners {
+        let mut config = room.config.write();
+        let Some(expires_at) = config.expires_at else { continue };
+        let remaining = (expires_at - now).num_seconds();
+
+        let announcement = if remaining <= -LOCKED_GRACE_SECS {
+            drop(config);
+            CHAT_STATE.remove_room(&room_id);
+            continue;
+        } else if remaining <= 0 {
+            if config.locked {
+                continue;
+            }
+            config.locked = true;
+            format!(
+                "This room has expired and is now locked. It will be deleted in {}.",
+                describe_secs(LOCKED_GRACE_SECS)
+            )
+        } else {
+            // Announce the closest threshold once, however late we are to it
+            let Some(threshold) = EXPIRY_WARNINGS.iter().copied().filter(|&t| remaining <= t).min() else {
+                continue;
+            };
+            if config.expiry_warning.is_some_and(|warned| warned <= threshold) {
+                continue;
+            }
+            config.expiry_warning = Some(threshold);
+            format!("This room expires in {}.", describe_secs(remaining))
+        };
+        drop(config);
+
+        room.post(ChatMessage::new(&room_id, "System", &announcement, MessageType::SystemMessage));
+    }
+}
+
+// Existing public rooms only; the message API never reaches into DMs
+pub fn public_room(room_id: &str) -> Result<RoomState, (Status, Json<Value>)> {
+    CHAT_STATE
+        .rooms
+        .read()
+        .get(room_id)
+        .filter(|room| room.config.read().members.is_none())
+        .cloned()
+        .ok_or_else(|| api_error(Status::NotFound, "No such room"))
+}
+
+// Most recent messages, oldest first; with `before`, the ones before that
+// message, going on into the archive (see archive.rs)
+#[rocket::get("/<room_id>/messages?<limit>&<before>")]
+fn messages(_token: CanReadMessages, room_id: &str, limit: Option<usize>, before: Option<&str>) -> ApiResult {
+    let room = public_room(room_id)?;
+    let limit = limit.unwrap_or(DEFAULT_HISTORY).min(MAX_HISTORY);
+    let Some(before) = before else {
+        let messages = room.messages.read();
+        let recent: Vec<Value> = messages
+            .iter()
+            .skip(messages.len().saturating_sub(limit))
+            .map(|msg| msg.to_frame())
+            .collect();
+        return Ok(Json(json!({ "messages": recent })));
+    };
+
+    let (mut page, in_memory) = {
+        let messages = room.messages.read();
+        match messages.iter().position(|msg| msg.id == before) {
+            Some(at) => (messages[at.saturating_sub(limit)..at].to_vec(), true),
+            None => (Vec::new(), false),
+        }
+    };
+    if page.len() < limit {
+        let archived = archive::before(room_id, (!in_memory).then_some(before), limit - page.len())
+            .map_err(|err| api_error(Status::InternalServerError, err))?
+            .ok_or_else(|| api_error(Status::NotFound, "No such message in this room"))?;
+        page.splice(0..0, archived);
+    }
+    let page: Vec<Value> = page.iter().map(|msg| msg.to_frame()).collect();
+    Ok(Json(json!({ "messages": page })))
+}
+
+#[derive(Deserialize)]
+struct NewMessage {
+    content: String,
+    // Finished chunked upload to attach, see uploads.rs
+    upload: Option<String>,
+}
+
+// Posts as a bot named after the token, e.g. for integrations announcing
+// builds or alerts; content past max_message_len is cut off, and the rest
+// goes through the same filters as chat messages
+#[rocket::post("/<room_id>/messages", data = "<message>")]
+fn post_message(token: CanPostMessages, traceparent: TraceParent, room_id: &str, message: Json<NewMessage>) -> ApiResult {
+    let room = public_room(room_id)?;
+    if room.config.read().locked {
+        return Err(api_error(Status::Conflict, "This room has expired and is locked"));
+    }
+    if message.content.trim().is_empty() {
+        return Err(api_error(Status::BadRequest, "Messages need content"));
+    }
+
+    flags::check(Flag::Bots, Some(room_id)).map_err(|err| api_error(Status::Forbidden, err.detail))?;
+    maintenance::check_api()?;
+    let uploader = Uploader::api(token.0.as_ref());
+    let sender = token.0.map(|token| token.name).unwrap_or_else(|| "API".to_string());
+    let content: String = message.content.chars().take(CONFIG.max_message_len).collect();
+    let mut msg = ChatMessage::new(room_id, &sender, &content, MessageType::Bot);
+    msg.trace = Some(TraceContext::continue_from(traceparent.0.as_deref()));
+    if let Some(upload) = &message.upload {
+        let attachment = uploads::take(&uploader, upload, room_id).map_err(|err| api_error(Status::BadRequest, err.detail))?;
+        msg.attachments.push(attachment);
+    }
+    let msg = publish_bot(&room, msg).map_err(|err| api_error(Status::UnprocessableEntity, err))?;
+    let mut frame = msg.to_frame();
+    frame["trace_id"] = json!(msg.trace.as_ref().map(|trace| &trace.trace_id));
+    Ok(Json(frame))
+}
+
+// Black-on-white grayscale PNG of the data as a QR code
+fn qr_png(data: &str, scale: u32) -> Result<Vec<u8>, String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|err| err.to_string())?;
+    let modules = code.width() as u32;
+    let colors = code.to_colors();
+    let size = (modules + 2 * QR_QUIET_ZONE) * scale;
+
+    let mut pixels = vec![255u8; (size * size) as usize];
+    for (i, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let x = (i as u32 % modules + QR_QUIET_ZONE) * scale;
+        let y = (i as u32 / modules + QR_QUIET_ZONE) * scale;
+        for row in y..y + scale {
+            let start = (row * size + x) as usize;
+            pixels[start..start + scale as usize].fill(0);
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, size, size);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|err| err.to_string())?;
+    Ok(png)
+}
+
+// QR code of the room's join link, for pulling in people who are in the
+// same place. `scale` is the size of a module in pixels.
+#[rocket::get("/<room_id>/qr.png?<scale>")]
+fn qr_code(room_id: &str, scale: Option<u32>, origin: proxy::Origin) -> Result<(ContentType, Vec<u8>), (Status, Json<Value>)> {
+    public_room(room_id)?;
+    let scale = scale.unwrap_or(DEFAULT_QR_SCALE).clamp(1, MAX_QR_SCALE);
+    let url = format!("{}{}", origin.0, proxy::url(format!("/?rid={}", room_id)));
+    let png = qr_png(&url, scale).map_err(|err| api_error(Status::InternalServerError, err))?;
+    Ok((ContentType::PNG, png))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, tags, trending, create, messages, post_message, qr_code]
+}
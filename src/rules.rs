@@ -0,0 +1,119 @@
+// Per-room auto-responder rules: a case-insensitive regex and an action,
+// evaluated against every user message.
+
+use regex::{Regex, RegexBuilder};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::plugins::{BotReply, MessageVerdict, Plugin};
+use crate::{CHAT_STATE, ChatMessage, RoomState};
+
+const RESPONDER_NAME: &str = "AutoResponder";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RuleAction {
+    // Post a bot reply, e.g. to answer a FAQ
+    Respond { response: String },
+    // Privately notify the room's moderators
+    AlertModerators,
+    // Attach a tag to the message
+    Tag { tag: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleSpec {
+    pub pattern: String,
+    #[serde(flatten)]
+    pub action: RuleAction,
+}
+
+// A rule with its pattern compiled; (de)serialized as a RuleSpec
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RuleSpec", into = "RuleSpec")]
+pub struct Rule {
+    spec: RuleSpec,
+    regex: Regex,
+}
+
+impl TryFrom<RuleSpec> for Rule {
+    type Error = regex::Error;
+
+    fn try_from(spec: RuleSpec) -> Result<Self, Self::Error> {
+        let regex = RegexBuilder::new(&spec.pattern)
+            .case_insensitive(true)
+            .size_limit(1 << 20)
+            .build()?;
+        Ok(Rule { spec, regex })
+    }
+}
+
+impl From<Rule> for RuleSpec {
+    fn from(rule: Rule) -> Self {
+        rule.spec
+    }
+}
+
+impl Rule {
+    fn matches(&self, content: &str) -> bool {
+        self.regex.is_match(content)
+    }
+}
+
+fn alert_moderators(room: &RoomState, message: &ChatMessage, pattern: &str) {
+    let frame = json!({
+        "type": "alert",
+        "room_id": message.room_id,
+        "message_id": message.id,
+        "sender": message.sender,
+        "content": message.content,
+        "pattern": pattern,
+    }).to_string();
+
+    let config = room.config.read();
+    room.send_where(&frame, |conn| config.is_moderator(conn.account_id.as_deref()));
+}
+
+pub struct RulesPlugin;
+
+impl Plugin for RulesPlugin {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    // Tags have to be attached before the message is stored
+    fn on_message(&self, message: &mut ChatMessage) -> MessageVerdict {
+        let room = CHAT_STATE.get_or_create_room(&message.room_id);
+        let config = room.config.read();
+
+        for rule in config.rules.iter().filter(|rule| rule.matches(&message.content)) {
+            if let RuleAction::Tag { tag } = &rule.spec.action
+                && !message.tags.contains(tag)
+            {
+                message.tags.push(tag.clone());
+            }
+        }
+        MessageVerdict::Accept
+    }
+
+    // Alerts and responses go out once the message itself is visible
+    fn on_message_posted(&self, message: &ChatMessage) -> Option<BotReply> {
+        let room = CHAT_STATE.get_or_create_room(&message.room_id);
+        let rules = room.config.read().rules.clone();
+
+        let mut response = None;
+        for rule in rules.iter().filter(|rule| rule.matches(&message.content)) {
+            match &rule.spec.action {
+                RuleAction::AlertModerators => alert_moderators(&room, message, &rule.spec.pattern),
+                // Only the first matching response is posted
+                RuleAction::Respond { response: text } if response.is_none() => response = Some(text.clone()),
+                _ => {}
+            }
+        }
+
+        response.map(|content| BotReply {
+            sender: RESPONDER_NAME.to_string(),
+            content,
+        })
+    }
+}
@@ -0,0 +1,155 @@
+// Just enough of the S3 API to keep attachments in a bucket, on AWS or an
+// S3-compatible server such as MinIO: objects are put and deleted with
+// AWS Signature Version 4, and clients fetch them through presigned GET URLs
+// that run out after url_ttl_secs.
+
+use std::io;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::config::S3Config;
+use crate::secrets;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Percent-encodes everything but unreserved characters, and '/' when asked
+fn encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') || (keep_slash && byte == b'/') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+pub struct Bucket {
+    config: S3Config,
+    agent: ureq::Agent,
+}
+
+// Where an object is, as seen from one endpoint
+struct Location {
+    scheme: String,
+    host: String,
+    path: String,
+}
+
+impl Bucket {
+    pub fn new(config: S3Config) -> Self {
+        Bucket {
+            config,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+        }
+    }
+
+    fn locate(&self, endpoint: &str, key: &str) -> io::Result<Location> {
+        let url = Url::parse(endpoint).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let host = url.host_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "S3 endpoint without a host"))?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let key = encode(&format!("{}{}", self.config.prefix, key), true);
+        Ok(if self.config.path_style {
+            Location { scheme: url.scheme().to_string(), host, path: format!("/{}/{}", encode(&self.config.bucket, false), key) }
+        } else {
+            Location { scheme: url.scheme().to_string(), host: format!("{}.{}", self.config.bucket, host), path: format!("/{}", key) }
+        })
+    }
+
+    fn scope(&self, date: &str) -> String {
+        format!("{}/{}/s3/aws4_request", date, self.config.region)
+    }
+
+    fn signature(&self, date: &str, timestamp: &str, canonical_request: &str) -> String {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            self.scope(date),
+            hex::encode(Sha256::digest(canonical_request.as_bytes())),
+        );
+        let key = hmac(format!("AWS4{}", secrets::resolve(&self.config.secret_key)).as_bytes(), date);
+        let key = hmac(&key, &self.config.region);
+        let key = hmac(&key, "s3");
+        let key = hmac(&key, "aws4_request");
+        hex::encode(hmac(&key, &string_to_sign))
+    }
+
+    // Sends a request signed in the Authorization header
+    fn send(&self, method: &str, key: &str, headers: &[(&str, &str)], body: &[u8]) -> io::Result<()> {
+        let location = self.locate(&self.config.endpoint, key)?;
+        let now = Utc::now();
+        let (date, timestamp) = (now.format("%Y%m%d").to_string(), now.format("%Y%m%dT%H%M%SZ").to_string());
+        let payload_hash = hex::encode(Sha256::digest(body));
+
+        let mut signed: Vec<(String, String)> = headers.iter().map(|(name, value)| (name.to_lowercase(), value.trim().to_string())).collect();
+        signed.push(("host".to_string(), location.host.clone()));
+        signed.push(("x-amz-content-sha256".to_string(), payload_hash.clone()));
+        signed.push(("x-amz-date".to_string(), timestamp.clone()));
+        signed.sort();
+        let canonical_headers: String = signed.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect();
+        let signed_headers = signed.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method, location.path, canonical_headers, signed_headers, payload_hash);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            secrets::resolve(&self.config.access_key),
+            self.scope(&date),
+            signed_headers,
+            self.signature(&date, &timestamp, &canonical_request),
+        );
+
+        let mut request = self
+            .agent
+            .request(method, &format!("{}://{}{}", location.scheme, location.host, location.path))
+            .set("Authorization", &authorization);
+        for (name, value) in signed.iter().filter(|(name, _)| name != "host") {
+            request = request.set(name, value);
+        }
+        match request.send_bytes(body) {
+            Ok(_) => Ok(()),
+            // Deleting what's already gone is fine
+            Err(ureq::Error::Status(404, _)) if method == "DELETE" => Ok(()),
+            Err(err) => Err(io::Error::other(err.to_string())),
+        }
+    }
+
+    pub fn put(&self, key: &str, data: &[u8], content_type: &str, disposition: &str) -> io::Result<()> {
+        self.send("PUT", key, &[("Content-Type", content_type), ("Content-Disposition", disposition)], data)
+    }
+
+    pub fn delete(&self, key: &str) -> io::Result<()> {
+        self.send("DELETE", key, &[], &[])
+    }
+
+    // A GET URL anyone can use until it runs out
+    pub fn presign(&self, key: &str) -> io::Result<String> {
+        let endpoint = self.config.public_endpoint.as_deref().unwrap_or(&self.config.endpoint);
+        let location = self.locate(endpoint, key)?;
+        let now = Utc::now();
+        let (date, timestamp) = (now.format("%Y%m%d").to_string(), now.format("%Y%m%dT%H%M%SZ").to_string());
+        // Already in sorted order
+        let query = format!(
+            "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
+            encode(&format!("{}/{}", secrets::resolve(&self.config.access_key), self.scope(&date)), false),
+            timestamp,
+            self.config.url_ttl_secs,
+        );
+        let canonical_request = format!("GET\n{}\n{}\nhost:{}\n\nhost\n{}", location.path, query, location.host, UNSIGNED_PAYLOAD);
+        let signature = self.signature(&date, &timestamp, &canonical_request);
+        Ok(format!("{}://{}{}?{}&X-Amz-Signature={}", location.scheme, location.host, location.path, query, signature))
+    }
+}
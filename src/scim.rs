@@ -0,0 +1,270 @@
+// A minimal SCIM 2.0 Users endpoint (RFC 7644) so an identity provider can
+// create and deactivate accounts on its own:
+//
+//   GET    /scim/v2/Users?filter=userName eq "<name>"&startIndex=<n>&count=<n>
+//   GET    /scim/v2/Users/<id>
+//   POST   /scim/v2/Users           {"userName", "externalId", "active", "password"}
+//   PUT    /scim/v2/Users/<id>      same fields; userName can't change
+//   PATCH  /scim/v2/Users/<id>      {"Operations": [{"op": "replace", "path": "active", "value": false}]}
+//   DELETE /scim/v2/Users/<id>
+//
+// with the admin token or an API token scoped provision:users. New accounts
+// belong to the configured directory provider (or are local, signing in with
+// the password given, if there is none). Deactivating an account signs it
+// out everywhere and takes it out of every room; nothing signs it in again
+// until it's reactivated.
+
+use rocket::form::FromForm;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use rocket::{Request, Route};
+use serde_json::json;
+
+use crate::accounts::{ACCOUNTS, Account};
+use crate::api_tokens::{Scope, authorize};
+use crate::room_core::Event;
+use crate::sessions::SESSIONS;
+use crate::{CHAT_STATE, auth, proxy};
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const PATCH_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+const MAX_PAGE: usize = 200;
+
+pub struct CanProvisionUsers;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CanProvisionUsers {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::ProvisionUsers).map(|_| CanProvisionUsers)
+    }
+}
+
+// A SCIM response body, sent as application/scim+json
+pub struct Scim(Status, Value);
+
+impl<'r> Responder<'r, 'static> for Scim {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let scim_json = ContentType::new("application", "scim+json");
+        (self.0, (scim_json, Json(self.1))).respond_to(request)
+    }
+}
+
+fn scim_error(status: Status, detail: impl ToString) -> Scim {
+    Scim(status, json!({
+        "schemas": [ERROR_SCHEMA],
+        "status": status.code.to_string(),
+        "detail": detail.to_string(),
+    }))
+}
+
+type ScimResult = Result<Scim, Scim>;
+
+fn to_scim(account: &Account) -> Value {
+    json!({
+        "schemas": [USER_SCHEMA],
+        "id": account.id,
+        "externalId": account.external_id,
+        "userName": account.username,
+        "active": !account.deactivated,
+        "meta": {
+            "resourceType": "User",
+            "created": account.created_at,
+            "location": proxy::url(format!("/scim/v2/Users/{}", account.id)),
+        },
+    })
+}
+
+fn find(id: &str) -> Result<Account, Scim> {
+    ACCOUNTS.get(id).ok_or_else(|| scim_error(Status::NotFound, "No such user"))
+}
+
+// Signs the account out everywhere and takes it out of every room
+fn put_out(account_id: &str) {
+    SESSIONS.revoke_account(account_id);
+    let rooms: Vec<_> = CHAT_STATE.rooms.read().values().cloned().collect();
+    for room in rooms {
+        let user_ids: Vec<String> = room
+            .users
+            .read()
+            .values()
+            .filter(|user| user.account_id.as_deref() == Some(account_id))
+            .map(|user| user.id.clone())
+            .collect();
+        for user_id in user_ids {
+            room.apply(Event::Leave { user_id });
+        }
+    }
+    CHAT_STATE
+        .ws_tickets
+        .write()
+        .retain(|_, ticket| ticket.user.account_id.as_deref() != Some(account_id));
+}
+
+// Applies the new state, putting the account out if it was just deactivated
+fn set_active(id: &str, active: bool, external_id: Option<Option<String>>) -> Result<Account, Scim> {
+    let (account, deactivated) = ACCOUNTS.update(|accounts| {
+        let account = accounts.get_mut(id)?;
+        let deactivated = !active && !account.deactivated;
+        account.deactivated = !active;
+        if let Some(external_id) = external_id {
+            account.external_id = external_id;
+        }
+        Some((account.clone(), deactivated))
+    }).ok_or_else(|| scim_error(Status::NotFound, "No such user"))?;
+    if deactivated {
+        put_out(id);
+    }
+    Ok(account)
+}
+
+// Only `userName eq "<value>"` filters are supported
+fn parse_filter(filter: &str) -> Option<String> {
+    let mut parts = filter.trim().splitn(3, ' ');
+    let (Some(attribute), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    if !attribute.eq_ignore_ascii_case("userName") || !op.eq_ignore_ascii_case("eq") {
+        return None;
+    }
+    value.strip_prefix('"')?.strip_suffix('"').map(str::to_string)
+}
+
+#[derive(FromForm)]
+struct ListQuery {
+    filter: Option<String>,
+    #[field(name = "startIndex")]
+    start_index: Option<usize>,
+    count: Option<usize>,
+}
+
+#[rocket::get("/Users?<query..>")]
+fn list_users(_auth: CanProvisionUsers, query: ListQuery) -> ScimResult {
+    let username = match query.filter.as_deref() {
+        Some(filter) => Some(
+            parse_filter(filter).ok_or_else(|| scim_error(Status::BadRequest, "Only userName eq \"...\" filters are supported"))?,
+        ),
+        None => None,
+    };
+    let mut accounts: Vec<Account> = match username {
+        Some(username) => ACCOUNTS.find(&username).into_iter().collect(),
+        None => ACCOUNTS.list(),
+    };
+    accounts.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+    // startIndex is 1-based
+    let start = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(MAX_PAGE).min(MAX_PAGE);
+    let page: Vec<Value> = accounts.iter().skip(start - 1).take(count).map(to_scim).collect();
+    Ok(Scim(Status::Ok, json!({
+        "schemas": [LIST_SCHEMA],
+        "totalResults": accounts.len(),
+        "startIndex": start,
+        "itemsPerPage": page.len(),
+        "Resources": page,
+    })))
+}
+
+#[rocket::get("/Users/<id>")]
+fn get_user(_auth: CanProvisionUsers, id: &str) -> ScimResult {
+    Ok(Scim(Status::Ok, to_scim(&find(id)?)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScimUser {
+    user_name: String,
+    external_id: Option<String>,
+    active: Option<bool>,
+    password: Option<String>,
+}
+
+#[rocket::post("/Users", data = "<user>")]
+fn create_user(_auth: CanProvisionUsers, user: Json<ScimUser>) -> ScimResult {
+    if ACCOUNTS.find(user.user_name.trim()).is_some() {
+        return Err(scim_error(Status::Conflict, "userName is already taken"));
+    }
+    let provider = auth::directory_provider().unwrap_or(auth::LOCAL);
+    let account = ACCOUNTS
+        .provision(&user.user_name, user.password.as_deref(), provider, user.external_id.clone())
+        .map_err(|err| scim_error(Status::BadRequest, err))?;
+    let account = match user.active {
+        Some(false) => set_active(&account.id, false, None)?,
+        _ => account,
+    };
+    Ok(Scim(Status::Created, to_scim(&account)))
+}
+
+#[rocket::put("/Users/<id>", data = "<user>")]
+fn replace_user(_auth: CanProvisionUsers, id: &str, user: Json<ScimUser>) -> ScimResult {
+    let account = find(id)?;
+    if !account.username.eq_ignore_ascii_case(user.user_name.trim()) {
+        return Err(scim_error(Status::BadRequest, "userName can't be changed"));
+    }
+    let account = set_active(id, user.active.unwrap_or(true), Some(user.external_id.clone()))?;
+    Ok(Scim(Status::Ok, to_scim(&account)))
+}
+
+#[derive(Deserialize)]
+struct PatchOp {
+    schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    operations: Vec<Operation>,
+}
+
+#[derive(Deserialize)]
+struct Operation {
+    op: String,
+    path: Option<String>,
+    value: Value,
+}
+
+// The new `active` value from a replace of the attribute, or of the user
+// with {"active": ...}; Azure AD sends it as a string
+fn patched_active(operation: &Operation) -> Option<bool> {
+    if !operation.op.eq_ignore_ascii_case("replace") {
+        return None;
+    }
+    let value = match operation.path.as_deref() {
+        Some(path) if path.eq_ignore_ascii_case("active") => &operation.value,
+        Some(_) => return None,
+        None => operation.value.get("active")?,
+    };
+    match value {
+        Value::Bool(active) => Some(*active),
+        Value::String(active) => active.to_lowercase().parse().ok(),
+        _ => None,
+    }
+}
+
+#[rocket::patch("/Users/<id>", data = "<patch>")]
+fn patch_user(_auth: CanProvisionUsers, id: &str, patch: Json<PatchOp>) -> ScimResult {
+    let mut account = find(id)?;
+    if !patch.schemas.iter().any(|schema| schema == PATCH_SCHEMA) {
+        return Err(scim_error(Status::BadRequest, "Expected a PatchOp"));
+    }
+    for operation in &patch.operations {
+        let Some(active) = patched_active(operation) else {
+            return Err(scim_error(Status::BadRequest, "Only replacing active is supported"));
+        };
+        account = set_active(id, active, None)?;
+    }
+    Ok(Scim(Status::Ok, to_scim(&account)))
+}
+
+#[rocket::delete("/Users/<id>")]
+fn delete_user(_auth: CanProvisionUsers, id: &str) -> Result<Status, Scim> {
+    let account = ACCOUNTS.remove(id).ok_or_else(|| scim_error(Status::NotFound, "No such user"))?;
+    put_out(&account.id);
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list_users, get_user, create_user, replace_user, patch_user, delete_user]
+}
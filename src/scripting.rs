@@ -0,0 +1,192 @@
+// Per-room Lua scripts.
+//
+// A script may define any of these globals:
+//   on_command(command, args, nickname) -> string | nil
+//   on_message(content, nickname)       -> string | nil
+// A returned string is posted to the room as a bot message named after the
+// script, so names can't be "System" or a registered nickname. Scripts only
+// get the table, string and math libraries, and every call runs under an
+// instruction budget and a memory cap. Sources are saved and compiled again
+// on startup.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use lazy_static::lazy_static;
+use mlua::{Function, HookTriggers, Lua, LuaOptions, StdLib, VmState};
+use parking_lot::{Mutex, RwLock};
+
+use crate::accounts::ACCOUNTS;
+use crate::plugins::{BotReply, Plugin};
+use crate::{ChatMessage, User, storage};
+
+const MEMORY_LIMIT: usize = 4 * 1024 * 1024;
+// The hook fires every HOOK_INTERVAL instructions, so a call may run at most
+// HOOK_INTERVAL * MAX_HOOK_CALLS instructions
+const HOOK_INTERVAL: u32 = 1_000;
+const MAX_HOOK_CALLS: u32 = 1_000;
+
+struct Script {
+    lua: Mutex<Lua>,
+    budget: Arc<AtomicU32>,
+    source: String,
+}
+
+impl Script {
+    fn compile(name: &str, source: &str) -> mlua::Result<Self> {
+        let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH, LuaOptions::new())?;
+        lua.set_memory_limit(MEMORY_LIMIT)?;
+
+        let budget = Arc::new(AtomicU32::new(0));
+        let hook_budget = budget.clone();
+        lua.set_hook(HookTriggers::new().every_nth_instruction(HOOK_INTERVAL), move |_, _| {
+            if hook_budget.fetch_add(1, Ordering::Relaxed) >= MAX_HOOK_CALLS {
+                Err(mlua::Error::runtime("script exceeded its CPU budget"))
+            } else {
+                Ok(VmState::Continue)
+            }
+        });
+
+        // Running the chunk defines the script's handlers
+        lua.load(source).set_name(name).exec()?;
+
+        Ok(Script {
+            lua: Mutex::new(lua),
+            budget,
+            source: source.to_string(),
+        })
+    }
+
+    // Call a global handler if the script defines it
+    fn call(&self, handler: &str, args: impl mlua::IntoLuaMulti) -> mlua::Result<Option<String>> {
+        let lua = self.lua.lock();
+        let Some(function) = lua.globals().get::<Option<Function>>(handler)? else {
+            return Ok(None);
+        };
+
+        self.budget.store(0, Ordering::Relaxed);
+        function.call::<Option<String>>(args)
+    }
+}
+
+pub struct ScriptRegistry {
+    // room id -> script name -> script
+    rooms: RwLock<HashMap<String, HashMap<String, Arc<Script>>>>,
+}
+
+impl ScriptRegistry {
+    fn load() -> Self {
+        let sources: HashMap<String, HashMap<String, String>> = storage::load("scripts", "scripts").unwrap_or_default();
+        let mut rooms: HashMap<String, HashMap<String, Arc<Script>>> = HashMap::new();
+        for (room_id, scripts) in sources {
+            for (name, source) in scripts {
+                match Script::compile(&name, &source) {
+                    Ok(script) => {
+                        rooms.entry(room_id.clone()).or_default().insert(name, Arc::new(script));
+                    },
+                    Err(err) => eprintln!("Failed to load script {} in room {}: {}", name, room_id, err),
+                }
+            }
+        }
+        ScriptRegistry {
+            rooms: RwLock::new(rooms),
+        }
+    }
+
+    fn save(rooms: &HashMap<String, HashMap<String, Arc<Script>>>) {
+        let sources: HashMap<&String, HashMap<&String, &String>> = rooms
+            .iter()
+            .map(|(room_id, scripts)| (room_id, scripts.iter().map(|(name, script)| (name, &script.source)).collect()))
+            .collect();
+        if let Err(err) = storage::save("scripts", "scripts", &sources) {
+            eprintln!("Failed to save scripts: {}", err);
+        }
+    }
+
+    pub fn list(&self, room_id: &str) -> Vec<String> {
+        let rooms = self.rooms.read();
+        let mut names: Vec<String> = rooms
+            .get(room_id)
+            .map(|scripts| scripts.keys().cloned().collect())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    // Compile and install a script, replacing any script with the same name
+    pub fn install(&self, room_id: &str, name: &str, source: &str) -> Result<(), String> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err("Script names may only contain letters, digits, '-' and '_'".to_string());
+        }
+        // Replies are posted under the name, which mustn't pass for the server or a person
+        if name.eq_ignore_ascii_case("system") || ACCOUNTS.find(name).is_some() {
+            return Err("Script names can't be \"System\" or a registered nickname".to_string());
+        }
+
+        let script = Script::compile(name, source).map_err(|err| err.to_string())?;
+        let mut rooms = self.rooms.write();
+        rooms.entry(room_id.to_string()).or_default().insert(name.to_string(), Arc::new(script));
+        Self::save(&rooms);
+        Ok(())
+    }
+
+    pub fn remove(&self, room_id: &str, name: &str) -> bool {
+        let mut rooms = self.rooms.write();
+        let Some(scripts) = rooms.get_mut(room_id) else {
+            return false;
+        };
+        let removed = scripts.remove(name).is_some();
+        if scripts.is_empty() {
+            rooms.remove(room_id);
+        }
+        if removed {
+            Self::save(&rooms);
+        }
+        removed
+    }
+
+    fn scripts(&self, room_id: &str) -> Vec<(String, Arc<Script>)> {
+        let rooms = self.rooms.read();
+        let mut scripts: Vec<_> = rooms
+            .get(room_id)
+            .map(|scripts| scripts.iter().map(|(name, script)| (name.clone(), script.clone())).collect())
+            .unwrap_or_default();
+        scripts.sort_by(|a, b| a.0.cmp(&b.0));
+        scripts
+    }
+
+    // Run `handler` in each of the room's scripts until one replies
+    fn first_reply(&self, room_id: &str, handler: &str, args: impl mlua::IntoLuaMulti + Clone) -> Option<BotReply> {
+        self.scripts(room_id).into_iter().find_map(|(name, script)| {
+            match script.call(handler, args.clone()) {
+                Ok(reply) => reply.map(|content| BotReply { sender: name, content }),
+                Err(err) => {
+                    eprintln!("Script {} in room {} failed: {}", name, room_id, err);
+                    None
+                }
+            }
+        })
+    }
+}
+
+lazy_static! {
+    pub static ref SCRIPTS: ScriptRegistry = ScriptRegistry::load();
+}
+
+// Exposes room scripts through the plugin hooks
+pub struct ScriptPlugin;
+
+impl Plugin for ScriptPlugin {
+    fn name(&self) -> &str {
+        "lua-scripts"
+    }
+
+    fn on_command(&self, room_id: &str, user: &User, command: &str, args: &str) -> Option<BotReply> {
+        SCRIPTS.first_reply(room_id, "on_command", (command.to_string(), args.to_string(), user.nickname.clone()))
+    }
+
+    fn on_message_posted(&self, message: &ChatMessage) -> Option<BotReply> {
+        SCRIPTS.first_reply(&message.room_id, "on_message", (message.content.clone(), message.sender.clone()))
+    }
+}
@@ -0,0 +1,103 @@
+// Searching every room the caller is in at once:
+//
+//   GET /api/search?q=<words>&limit=<n>
+//
+// Accounts search the rooms they're connected to plus their DM rooms;
+// guests only the room their session is for. Private rooms are searched only
+// if they'd admit the caller, and messages from nicknames the account blocked
+// are left out. A message matches when it contains every word (ignoring
+// case); results are ranked by how often the words appear, then newest
+// first, and each comes with its room and the messages around it:
+//
+//   {"query": ..., "results": [{"room_id", "message": {...}, "score",
+//                              "context": {"before": {...}|null, "after": {...}|null}}, ...]}
+
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::accounts::{ACCOUNTS, AccountSession};
+use crate::admin::{ApiResult, api_error};
+use crate::{CHAT_STATE, ChatMessage, MessageType, RoomState, UserSession, friends};
+
+const DEFAULT_RESULTS: usize = 20;
+const MAX_RESULTS: usize = 100;
+const MIN_QUERY_LEN: usize = 2;
+
+// Rooms the caller may search, by account or by guest session
+pub fn searchable_rooms(account_id: Option<&str>, session: Option<&UserSession>) -> Vec<RoomState> {
+    let rooms = CHAT_STATE.rooms.read();
+    rooms
+        .iter()
+        .filter(|(room_id, room)| {
+            let member = match account_id {
+                Some(account_id) => {
+                    friends::dm_members(room_id).is_some_and(|members| members.contains(account_id))
+                        || room.users.read().values().any(|user| user.account_id.as_deref() == Some(account_id))
+                },
+                None => session.is_some_and(|session| session.room_id == **room_id),
+            };
+            member && room.config.read().admits(account_id)
+        })
+        .map(|(_, room)| room.clone())
+        .collect()
+}
+
+// How often the words appear in the message, or None unless all of them do
+fn score(msg: &ChatMessage, words: &[String]) -> Option<usize> {
+    let content = msg.content.to_lowercase();
+    let counts: Vec<usize> = words.iter().map(|word| content.matches(word.as_str()).count()).collect();
+    counts.iter().all(|count| *count > 0).then(|| counts.iter().sum())
+}
+
+fn context_entry(msg: Option<&ChatMessage>) -> Value {
+    msg.map_or(Value::Null, |msg| json!({ "id": msg.id, "sender": msg.sender, "content": msg.content }))
+}
+
+#[rocket::get("/?<q>&<limit>")]
+fn search(q: &str, limit: Option<usize>, account: Option<AccountSession>, session: Option<UserSession>) -> ApiResult {
+    let account_id = account.map(|account| account.0.id);
+    if account_id.is_none() && session.is_none() {
+        return Err(api_error(Status::Unauthorized, "Sign in or join a room to search"));
+    }
+    let words: Vec<String> = q.split_whitespace().map(str::to_lowercase).collect();
+    if words.iter().map(|word| word.chars().count()).sum::<usize>() < MIN_QUERY_LEN {
+        return Err(api_error(Status::BadRequest, format!("Searches need at least {} characters", MIN_QUERY_LEN)));
+    }
+    let limit = limit.unwrap_or(DEFAULT_RESULTS).clamp(1, MAX_RESULTS);
+    let blocked = |sender: &str| account_id.as_deref().is_some_and(|id| ACCOUNTS.has_blocked(id, sender));
+
+    // (score, timestamp, result)
+    let mut results: Vec<(usize, String, Value)> = Vec::new();
+    for room in searchable_rooms(account_id.as_deref(), session.as_ref()) {
+        let messages = room.messages.read();
+        for (index, msg) in messages.iter().enumerate() {
+            if msg.message_type == MessageType::SystemMessage || blocked(&msg.sender) {
+                continue;
+            }
+            let Some(score) = score(msg, &words) else {
+                continue;
+            };
+            let before = index.checked_sub(1).and_then(|before| messages.get(before));
+            results.push((score, msg.timestamp.clone(), json!({
+                "room_id": room.id.as_ref(),
+                "message": msg.to_frame(),
+                "score": score,
+                "context": {
+                    "before": context_entry(before),
+                    "after": context_entry(messages.get(index + 1)),
+                },
+            })));
+        }
+    }
+    results.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+    results.truncate(limit);
+
+    let results: Vec<Value> = results.into_iter().map(|(_, _, result)| result).collect();
+    Ok(Json(json!({ "query": q, "results": results })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![search]
+}
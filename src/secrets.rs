@@ -0,0 +1,163 @@
+// Secrets kept out of the config file. Any of the config's keys, tokens and
+// passwords can be given as a reference instead of the value itself:
+//
+//   admin_token = "secret:admin_token"
+//
+// which is looked up through the provider picked in the [secrets] table:
+// environment variables (ADMIN_TOKEN), files in a directory such as Docker's
+// /run/secrets (admin_token), or a field of a Vault KV v2 secret. Values are
+// read again every refresh_secs, so a rotated secret is picked up without a
+// restart; http.secret_key is only read at startup. To rotate without
+// cutting everyone off at once, put the old value under <name>_previous
+// while the new one goes in: checks (admin token, media link and email
+// signatures, session cookies) accept either, and only the current one is used to sign. If
+// the provider can't be reached, the last values read stay in use.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use serde_json::Value;
+
+use crate::config::{CONFIG, SecretsConfig, SecretsProviderKind};
+
+const PREFIX: &str = "secret:";
+const PREVIOUS_SUFFIX: &str = "_previous";
+const VAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub trait SecretsProvider: Send + Sync {
+    // The secret's value; None when it isn't set
+    fn read(&self, name: &str) -> io::Result<Option<String>>;
+}
+
+// ADMIN_TOKEN for admin_token
+struct EnvSecrets;
+
+impl SecretsProvider for EnvSecrets {
+    fn read(&self, name: &str) -> io::Result<Option<String>> {
+        let var: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+        Ok(std::env::var(var).ok())
+    }
+}
+
+// One file per secret, trailing newline dropped
+struct FileSecrets {
+    dir: std::path::PathBuf,
+}
+
+impl SecretsProvider for FileSecrets {
+    fn read(&self, name: &str) -> io::Result<Option<String>> {
+        if name.starts_with('.') || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("bad secret name {:?}", name)));
+        }
+        match std::fs::read_to_string(self.dir.join(Path::new(name))) {
+            Ok(value) => Ok(Some(value.trim_end_matches(['\r', '\n']).to_string())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+// Fields of the KV v2 secret at vault_path, e.g. "secret/data/who-chat"
+struct VaultSecrets {
+    url: String,
+    token: String,
+    path: String,
+    agent: ureq::Agent,
+}
+
+impl SecretsProvider for VaultSecrets {
+    fn read(&self, name: &str) -> io::Result<Option<String>> {
+        let url = format!("{}/v1/{}", self.url.trim_end_matches('/'), self.path.trim_start_matches('/'));
+        let response = match self.agent.get(&url).set("X-Vault-Token", &self.token).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(err) => return Err(io::Error::other(err.to_string())),
+        };
+        let body: Value = serde_json::from_str(&response.into_string()?)?;
+        Ok(body["data"]["data"][name].as_str().map(str::to_string))
+    }
+}
+
+fn provider(config: &SecretsConfig) -> Box<dyn SecretsProvider> {
+    match config.provider {
+        SecretsProviderKind::Env => Box::new(EnvSecrets),
+        SecretsProviderKind::File => Box::new(FileSecrets { dir: config.dir.clone() }),
+        SecretsProviderKind::Vault => Box::new(VaultSecrets {
+            url: config.vault_url.clone(),
+            // Vault's own variable, so the token needn't be in the config either
+            token: config.vault_token.clone().or_else(|| std::env::var("VAULT_TOKEN").ok()).unwrap_or_default(),
+            path: config.vault_path.clone(),
+            agent: ureq::AgentBuilder::new().timeout(VAULT_TIMEOUT).build(),
+        }),
+    }
+}
+
+lazy_static! {
+    static ref PROVIDER: Box<dyn SecretsProvider> = provider(&CONFIG.secrets);
+    // name -> value as last read; every name asked for is kept fresh
+    static ref VALUES: RwLock<HashMap<String, Option<String>>> = RwLock::new(HashMap::new());
+    static ref LAST_REFRESH: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+fn lookup(name: &str) -> Option<String> {
+    if let Some(value) = VALUES.read().get(name) {
+        return value.clone();
+    }
+    let value = PROVIDER.read(name).unwrap_or_else(|err| {
+        eprintln!("Failed to read secret {}: {}", name, err);
+        None
+    });
+    VALUES.write().insert(name.to_string(), value.clone());
+    value
+}
+
+// A config value with any reference looked up; references to secrets that
+// aren't set come out empty
+pub fn resolve(configured: &str) -> String {
+    match configured.strip_prefix(PREFIX) {
+        Some(name) => lookup(name).unwrap_or_default(),
+        None => configured.to_string(),
+    }
+}
+
+// The values accepted for a config value: the current one, then the previous
+// one while a rotation is under way
+pub fn accepted(configured: &str) -> Vec<String> {
+    let Some(name) = configured.strip_prefix(PREFIX) else {
+        return vec![configured.to_string()];
+    };
+    [lookup(name), lookup(&format!("{}{}", name, PREVIOUS_SUFFIX))]
+        .into_iter()
+        .flatten()
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+// Runs from the task loop; reads every secret in use again once refresh_secs
+// have passed
+pub fn refresh() {
+    {
+        let mut last = LAST_REFRESH.lock();
+        match *last {
+            None => {
+                *last = Some(Instant::now());
+                return;
+            },
+            Some(at) if at.elapsed() < Duration::from_secs(CONFIG.secrets.refresh_secs.max(1)) => return,
+            Some(_) => *last = Some(Instant::now()),
+        }
+    }
+    let names: Vec<String> = VALUES.read().keys().cloned().collect();
+    for name in names {
+        match PROVIDER.read(&name) {
+            Ok(value) => {
+                VALUES.write().insert(name, value);
+            },
+            Err(err) => eprintln!("Failed to refresh secret {}, keeping the old value: {}", name, err),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+// Security headers added to every response: Content-Security-Policy,
+// X-Frame-Options, Referrer-Policy and, over HTTPS, Strict-Transport-Security.
+// Each can be switched off or changed in the [security] config; routes that
+// set one of these headers themselves keep their own.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::shield::{Frame, Shield};
+use rocket::{Request, Response};
+
+use crate::config::CONFIG;
+use crate::proxy;
+
+pub struct SecurityHeaders;
+
+// Rocket's default shield, minus the X-Frame-Options it would always send;
+// framing is decided below so embed paths can be exempted
+pub fn shield() -> Shield {
+    Shield::default().disable::<Frame>()
+}
+
+// Pages meant to be framed by other sites, such as embeds
+fn frameable(request: &Request<'_>) -> bool {
+    let path = proxy::strip_prefix(request.uri().path().as_str());
+    CONFIG
+        .security
+        .frame_exempt_paths
+        .iter()
+        .any(|exempt| path.starts_with(exempt.as_str()))
+}
+
+fn set_default(response: &mut Response<'_>, name: &'static str, value: String) {
+    if !response.headers().contains(name) {
+        response.set_header(Header::new(name, value));
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let config = &CONFIG.security;
+        if let Some(policy) = config.content_security_policy.as_ref().filter(|policy| !policy.is_empty()) {
+            set_default(response, "Content-Security-Policy", policy.clone());
+        }
+        if config.deny_framing && !frameable(request) {
+            set_default(response, "X-Frame-Options", "DENY".to_string());
+        }
+        if let Some(policy) = config.referrer_policy.as_ref().filter(|policy| !policy.is_empty()) {
+            set_default(response, "Referrer-Policy", policy.clone());
+        }
+        if let Some(max_age) = config.hsts_max_age_secs
+            && proxy::is_https(request)
+        {
+            set_default(response, "Strict-Transport-Security", format!("max-age={}; includeSubDomains", max_age));
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// Making public rooms findable and their links unfurl nicely: OpenGraph
+// tags on a room's landing page, and, with `sitemap` on, a sitemap of the
+// listed rooms. Private, NSFW and burner rooms are never described.
+
+use rocket::Route;
+use rocket::http::ContentType;
+use serde_json::{Value, json};
+
+use crate::config::CONFIG;
+use crate::rooms::valid_room_id;
+use crate::{CHAT_STATE, RoomConfig, proxy, pwa};
+
+pub fn listed(config: &RoomConfig) -> bool {
+    config.members.is_none() && !config.nsfw && config.expires_at.is_none()
+}
+
+fn absolute(origin: &str, url: String) -> String {
+    if url.starts_with('/') { format!("{}{}", origin, url) } else { url }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// OpenGraph details for a room's landing page, if it's one to describe
+pub fn room_meta(room_id: &str, origin: &str) -> Option<Value> {
+    let room = CHAT_STATE.rooms.read().get(room_id).cloned();
+    let (topic, users) = match &room {
+        Some(room) => {
+            let config = room.config.read();
+            if !listed(&config) {
+                return None;
+            }
+            (config.topic.clone(), room.users.read().len())
+        },
+        None if valid_room_id(room_id) => (None, 0),
+        None => return None,
+    };
+
+    let people = match users {
+        0 => "Nobody here yet".to_string(),
+        1 => "1 person chatting".to_string(),
+        n => format!("{} people chatting", n),
+    };
+    let description = match topic {
+        Some(topic) => format!("{} · {}", topic, people),
+        None => people,
+    };
+    Some(json!({
+        "title": format!("{} - {}", room_id, CONFIG.theme.name),
+        "description": description,
+        "url": format!("{}{}", origin, proxy::url(format!("/?rid={}", room_id))),
+        "image": absolute(origin, pwa::icon_url()),
+        "site_name": CONFIG.theme.name,
+    }))
+}
+
+#[rocket::get("/sitemap.xml")]
+fn sitemap(origin: proxy::Origin) -> Option<(ContentType, String)> {
+    if !CONFIG.sitemap {
+        return None;
+    }
+    let mut room_ids: Vec<String> = CHAT_STATE
+        .rooms
+        .read()
+        .iter()
+        .filter(|(room_id, room)| valid_room_id(room_id) && listed(&room.config.read()))
+        .map(|(room_id, _)| room_id.clone())
+        .collect();
+    room_ids.sort();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for room_id in room_ids {
+        let url = format!("{}{}", origin.0, proxy::url(format!("/?rid={}", room_id)));
+        xml.push_str(&format!("  <url><loc>{}</loc><changefreq>hourly</changefreq></url>\n", escape_xml(&url)));
+    }
+    xml.push_str("</urlset>\n");
+    Some((ContentType::XML, xml))
+}
+
+// Keeps crawlers on the landing pages and away from the API
+#[rocket::get("/robots.txt")]
+fn robots(origin: proxy::Origin) -> Option<String> {
+    if !CONFIG.sitemap {
+        return None;
+    }
+    Some(format!(
+        "User-agent: *\nDisallow: {}\nDisallow: {}\nSitemap: {}{}\n",
+        proxy::url("/api/"),
+        proxy::url("/rooms/"),
+        origin.0,
+        proxy::url("/sitemap.xml"),
+    ))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![sitemap, robots]
+}
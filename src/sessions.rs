@@ -0,0 +1,214 @@
+// Signed-in sessions of registered accounts, one per login on a device. The
+// private `session_id` cookie points at an entry here, so revoking the entry
+// signs that device out and closes its WebSocket connections.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Route;
+use rocket::http::{CookieJar, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+use ws::CloseCode;
+
+use crate::accounts::AccountSession;
+use crate::admin::{ApiResult, api_error};
+use crate::{CHAT_STATE, cookie_keys, proxy, storage};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub account_id: String,
+    pub created_at: String,
+    pub last_seen: String,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>,
+}
+
+pub struct SessionStore {
+    // session id -> session
+    sessions: RwLock<HashMap<String, Session>>,
+    // Set when only last_seen changed, which is saved on the next tick
+    dirty: RwLock<bool>,
+}
+
+impl SessionStore {
+    fn load() -> Self {
+        SessionStore {
+            sessions: RwLock::new(storage::load("accounts", "sessions").unwrap_or_default()),
+            dirty: RwLock::new(false),
+        }
+    }
+
+    fn save(sessions: &HashMap<String, Session>) {
+        if let Err(err) = storage::save("accounts", "sessions", sessions) {
+            eprintln!("Failed to save sessions: {}", err);
+        }
+    }
+
+    pub fn create(&self, account_id: &str, client: &ClientInfo) -> Session {
+        let now = Utc::now().to_rfc3339();
+        let session = Session {
+            id: Uuid::new_v4().to_string(),
+            account_id: account_id.to_string(),
+            created_at: now.clone(),
+            last_seen: now,
+            user_agent: client.user_agent.clone(),
+            ip: client.ip.clone(),
+        };
+        let mut sessions = self.sessions.write();
+        sessions.insert(session.id.clone(), session.clone());
+        Self::save(&sessions);
+        session
+    }
+
+    // The session behind a cookie, marking it as just seen
+    pub fn touch(&self, id: &str) -> Option<Session> {
+        let mut sessions = self.sessions.write();
+        let session = sessions.get_mut(id)?;
+        session.last_seen = Utc::now().to_rfc3339();
+        *self.dirty.write() = true;
+        Some(session.clone())
+    }
+
+    pub fn for_account(&self, account_id: &str) -> Vec<Session> {
+        let mut sessions: Vec<Session> = self
+            .sessions
+            .read()
+            .values()
+            .filter(|session| session.account_id == account_id)
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        sessions
+    }
+
+    // Removes the session and closes the connections opened with it
+    pub fn revoke(&self, id: &str) -> Option<Session> {
+        let mut sessions = self.sessions.write();
+        let session = sessions.remove(id)?;
+        Self::save(&sessions);
+        drop(sessions);
+
+        disconnect(|session_id| session_id == id);
+        Some(session)
+    }
+
+    // Signs the account out everywhere
+    pub fn revoke_account(&self, account_id: &str) {
+        let mut sessions = self.sessions.write();
+        let revoked: Vec<String> = sessions
+            .values()
+            .filter(|session| session.account_id == account_id)
+            .map(|session| session.id.clone())
+            .collect();
+        sessions.retain(|_, session| session.account_id != account_id);
+        Self::save(&sessions);
+        drop(sessions);
+
+        disconnect(|session_id| revoked.iter().any(|id| id == session_id));
+    }
+}
+
+lazy_static! {
+    pub static ref SESSIONS: SessionStore = SessionStore::load();
+}
+
+fn disconnect(revoked: impl Fn(&str) -> bool) {
+    let is_revoked = |session_id: &Option<String>| session_id.as_deref().is_some_and(&revoked);
+    let rooms: Vec<_> = CHAT_STATE.rooms.read().values().cloned().collect();
+    for room in rooms {
+        for conn in room.connections.read().iter() {
+            if is_revoked(&conn.session_id) {
+                let _ = conn.sender.close(CloseCode::Policy);
+            }
+        }
+    }
+    CHAT_STATE.ws_tickets.write().retain(|_, ticket| !is_revoked(&ticket.user.session_id));
+}
+
+// Writes out last-seen times that changed since the last save
+pub fn save_activity() {
+    let mut dirty = SESSIONS.dirty.write();
+    if *dirty {
+        SessionStore::save(&SESSIONS.sessions.read());
+        *dirty = false;
+    }
+}
+
+// The live session from the cookie, if any
+pub fn current(cookies: &CookieJar<'_>) -> Option<Session> {
+    let cookie = cookie_keys::get_private(cookies, "session_id")?;
+    SESSIONS.touch(cookie.value())
+}
+
+// Who's logging in, for telling sessions apart in the list
+pub struct ClientInfo {
+    user_agent: Option<String>,
+    ip: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn ip(&self) -> Option<&str> {
+        self.ip.as_deref()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ClientInfo {
+            user_agent: request.headers().get_one("User-Agent").map(str::to_string),
+            ip: proxy::client_ip(request).map(|ip| ip.to_string()),
+        })
+    }
+}
+
+#[rocket::get("/")]
+fn list(session: AccountSession) -> Json<Value> {
+    let sessions: Vec<Value> = SESSIONS
+        .for_account(&session.0.id)
+        .into_iter()
+        .map(|entry| {
+            json!({
+                "id": entry.id,
+                "created_at": entry.created_at,
+                "last_seen": entry.last_seen,
+                "user_agent": entry.user_agent,
+                "ip": entry.ip,
+                "current": entry.id == session.1,
+            })
+        })
+        .collect();
+    Json(json!({ "sessions": sessions }))
+}
+
+#[rocket::delete("/<id>")]
+fn revoke(session: AccountSession, id: &str, cookies: &CookieJar<'_>) -> ApiResult {
+    let owned = SESSIONS
+        .for_account(&session.0.id)
+        .iter()
+        .any(|entry| entry.id == id);
+    if !owned {
+        return Err(api_error(Status::NotFound, "No such session"));
+    }
+
+    SESSIONS.revoke(id);
+    if id == session.1 {
+        cookies.remove_private("session_id");
+    }
+    Ok(Json(json!({ "id": id, "revoked": true })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, revoke]
+}
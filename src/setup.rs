@@ -0,0 +1,166 @@
+// First-run setup. Until the server has an admin (no admin_token) and a
+// config file, GET /setup offers a form that
+//
+//   - creates the admin account, a local account with the Admin role in
+//     every room,
+//   - sets the server's name and the public URL people reach it at,
+//   - offers the storage backends, and
+//   - writes the config file (WhoChat.toml, or WHOCHAT_CONFIG) with those
+//     and a freshly generated admin_token, shown once on the result page.
+//
+// Once the file exists /setup is gone; the name, URL and admin token apply
+// from the next start. Anyone who can reach the server can finish setup,
+// so do it before exposing a new install.
+//
+// Data lives in JSON files under the data directory, the one storage
+// backend there is; the directory itself is chosen with --data-dir or
+// data_dir, since the admin account has to be written somewhere already.
+
+use std::fs;
+use std::io::{self, Write};
+
+use rand::Rng;
+use rocket::form::{Form, FromForm};
+use rocket::http::{ContentType, Status};
+use rocket::{Route, uri};
+use rocket_dyn_templates::{Template, context};
+use serde_json::json;
+
+use crate::Role;
+use crate::accounts::ACCOUNTS;
+use crate::config::{self, CONFIG};
+use crate::proxy;
+
+const MAX_NAME_LEN: usize = 60;
+// Offered in the form; files is the only one so far
+const STORAGE_BACKENDS: [&str; 1] = ["files"];
+
+// Whether the wizard is still open
+pub fn pending() -> bool {
+    CONFIG.admin_token.is_none() && !config::path().exists()
+}
+
+// Logged at startup so whoever launched the server knows where to go
+pub fn announce() {
+    if pending() {
+        eprintln!("No admin yet: finish setting up the server at {}", proxy::url(uri!(page)));
+    }
+}
+
+fn form_page(error: Option<&str>, form: Option<&SetupForm>) -> (ContentType, Template) {
+    (ContentType::HTML, Template::render("setup", context! {
+        title: "Set up the server",
+        error,
+        username: form.map(|form| form.username.as_str()),
+        name: form.map_or(CONFIG.theme.name.as_str(), |form| form.name.as_str()),
+        public_url: form.map(|form| form.public_url.as_str()).or(CONFIG.public_url.as_deref()),
+        storage: STORAGE_BACKENDS,
+        data_dir: CONFIG.data_dir.display().to_string(),
+        config_path: config::path().display().to_string(),
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    }))
+}
+
+#[rocket::get("/setup")]
+fn page() -> Option<(ContentType, Template)> {
+    pending().then(|| form_page(None, None))
+}
+
+#[derive(FromForm)]
+struct SetupForm {
+    username: String,
+    password: String,
+    confirm_password: String,
+    name: String,
+    public_url: String,
+    storage: String,
+}
+
+// A TOML basic string; JSON's escapes are valid TOML
+fn toml_string(value: &str) -> String {
+    json!(value).to_string()
+}
+
+fn config_file(form: &SetupForm, admin_token: &str) -> String {
+    let mut file = format!("# Written by the setup wizard\nadmin_token = {}\n", toml_string(admin_token));
+    if !form.public_url.trim().is_empty() {
+        file.push_str(&format!("public_url = {}\n", toml_string(form.public_url.trim().trim_end_matches('/'))));
+    }
+    file.push_str(&format!("data_dir = {}\n", toml_string(&CONFIG.data_dir.display().to_string())));
+    file.push_str(&format!("\n[theme]\nname = {}\n", toml_string(form.name.trim())));
+    file
+}
+
+// The form again with what went wrong
+type Refusal = (Status, (ContentType, Template));
+
+#[rocket::post("/setup", data = "<form>")]
+fn submit(form: Form<SetupForm>) -> Result<(ContentType, Template), Box<Refusal>> {
+    if !pending() {
+        return Err(Box::new((Status::NotFound, form_page(Some("The server is already set up"), None))));
+    }
+    let refuse = |error: &str| Err(Box::new((Status::BadRequest, form_page(Some(error), Some(&form)))));
+    let name = form.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return refuse(&format!("Server names are 1 to {} characters", MAX_NAME_LEN));
+    }
+    let public_url = form.public_url.trim();
+    if !(public_url.is_empty() || public_url.starts_with("http://") || public_url.starts_with("https://")) {
+        return refuse("The public URL starts with http:// or https://");
+    }
+    if !STORAGE_BACKENDS.contains(&form.storage.as_str()) {
+        return refuse("Choose one of the storage backends");
+    }
+    if form.password != form.confirm_password {
+        return refuse("The passwords don't match");
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::rng().fill(&mut bytes);
+    let admin_token = hex::encode(bytes);
+    // Written first so a failure leaves nothing half set up, and only ever
+    // created: of two forms sent at once, the second finds the file there
+    let path = config::path();
+    let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(Box::new((Status::NotFound, form_page(Some("The server is already set up"), None))));
+        },
+        Err(err) => {
+            let error = format!("Couldn't write {}: {}", path.display(), err);
+            return Err(Box::new((Status::InternalServerError, form_page(Some(&error), Some(&form)))));
+        },
+    };
+    if let Err(err) = file.write_all(config_file(&form, &admin_token).as_bytes()) {
+        let _ = fs::remove_file(&path);
+        let error = format!("Couldn't write {}: {}", path.display(), err);
+        return Err(Box::new((Status::InternalServerError, form_page(Some(&error), Some(&form)))));
+    }
+    let account = match ACCOUNTS.register(&form.username, &form.password) {
+        Ok(account) => account,
+        Err(err) => {
+            let _ = fs::remove_file(&path);
+            return refuse(&err);
+        },
+    };
+    ACCOUNTS.update(|accounts| {
+        if let Some(account) = accounts.get_mut(&account.id) {
+            account.directory_role = Some(Role::Admin);
+        }
+    });
+
+    Ok((ContentType::HTML, Template::render("setup", context! {
+        title: "Server set up",
+        done: true,
+        username: account.username,
+        admin_token,
+        config_path: path.display().to_string(),
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![page, submit]
+}
@@ -0,0 +1,228 @@
+// Synthetic traffic against a running server, for demos and capacity
+// planning:
+//
+//   who-chat simulate --rooms 10 --users 500 --rate 5 [--duration 60] [--url ws://host:8082]
+//
+// Connects `users` guest WebSocket clients spread evenly over the rooms
+// sim-1 .. sim-<rooms>, which together send `rate` messages a second at
+// random intervals. Every few seconds it prints what was sent, acked,
+// rejected and received, and how long acks took. Runs until `duration`
+// seconds are up, or until interrupted.
+//
+// The server turns away connections beyond its `ws_max_connections`, and
+// stalls when joins and messages queue up more than `ws_queue_size` frames
+// per connection, so raise both before big runs (e.g. 1000 and 256 for 500
+// users in 10 rooms).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use serde_json::{Value, json};
+use ws::util::Token;
+use ws::{CloseCode, Handler, Handshake, Message, Sender};
+
+use crate::config::CONFIG;
+
+const REPORT_EVERY: Duration = Duration::from_secs(5);
+const WORDS: &[&str] = &[
+    "hello", "anyone", "around", "coffee", "deploy", "lunch", "meeting", "build", "green", "again",
+    "ship", "it", "looks", "good", "to", "me", "thanks", "later", "weekend", "soon",
+];
+
+struct Options {
+    url: String,
+    rooms: usize,
+    users: usize,
+    // Messages per second across all users
+    rate: f64,
+    duration: Option<Duration>,
+}
+
+#[derive(Default)]
+struct Counters {
+    connected: AtomicU64,
+    sent: AtomicU64,
+    acked: AtomicU64,
+    rejected: AtomicU64,
+    received: AtomicU64,
+    errors: AtomicU64,
+}
+
+lazy_static! {
+    static ref STARTED: Instant = Instant::now();
+    static ref COUNTERS: Counters = Counters::default();
+    // Ack round trips since the last report, in milliseconds
+    static ref ACK_TIMES: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+}
+
+fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        url: format!("ws://127.0.0.1:{}", CONFIG.ws_port),
+        rooms: 10,
+        users: 100,
+        rate: 5.0,
+        duration: None,
+    };
+    let mut args = args;
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", flag));
+        let number = |value: String| value.parse::<f64>().ok().filter(|n| *n > 0.0).ok_or_else(|| format!("{} must be a positive number", flag));
+        match flag.as_str() {
+            "--url" => options.url = value()?.trim_end_matches('/').to_string(),
+            "--rooms" => options.rooms = number(value()?)? as usize,
+            "--users" => options.users = number(value()?)? as usize,
+            "--rate" => options.rate = number(value()?)?,
+            "--duration" => options.duration = Some(Duration::from_secs_f64(number(value()?)?)),
+            // Handled by the config loader
+            "--data-dir" => {
+                value()?;
+            },
+            _ if flag.starts_with("--data-dir=") => {},
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+    options.rooms = options.rooms.max(1);
+    options.users = options.users.max(1);
+    Ok(options)
+}
+
+struct SyntheticUser {
+    out: Sender,
+    // Average time between this user's messages
+    interval: Duration,
+}
+
+// Milliseconds until a user's next message, exponentially distributed so
+// the room's traffic arrives like real chat
+fn next_delay(interval: Duration) -> u64 {
+    let sample: f64 = rand::rng().random_range(f64::EPSILON..1.0);
+    interval.mul_f64(-sample.ln()).as_millis() as u64
+}
+
+impl Handler for SyntheticUser {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        COUNTERS.connected.fetch_add(1, Ordering::Relaxed);
+        self.out.timeout(next_delay(self.interval), Token(0))
+    }
+
+    fn on_timeout(&mut self, _: Token) -> ws::Result<()> {
+        let mut rng = rand::rng();
+        let words: Vec<&str> = (0..rng.random_range(2..8)).filter_map(|_| WORDS.choose(&mut rng).copied()).collect();
+        // The send time comes back in the ack
+        let sent_at = STARTED.elapsed().as_millis() as u64;
+        self.out.send(json!({ "content": words.join(" "), "client_id": sent_at }).to_string())?;
+        COUNTERS.sent.fetch_add(1, Ordering::Relaxed);
+        self.out.timeout(next_delay(self.interval), Token(0))
+    }
+
+    fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+        COUNTERS.received.fetch_add(1, Ordering::Relaxed);
+        let frame: Value = msg.into_text().ok().and_then(|text| serde_json::from_str(&text).ok()).unwrap_or_default();
+        match frame["type"].as_str() {
+            Some("ack") => {
+                if let Some(sent_at) = frame["client_id"].as_u64() {
+                    COUNTERS.acked.fetch_add(1, Ordering::Relaxed);
+                    let now = STARTED.elapsed().as_millis() as u64;
+                    ACK_TIMES.lock().push(now.saturating_sub(sent_at));
+                }
+            },
+            Some("nack") => {
+                COUNTERS.rejected.fetch_add(1, Ordering::Relaxed);
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn on_close(&mut self, _: CloseCode, _: &str) {
+        COUNTERS.connected.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn on_error(&mut self, _: ws::Error) {
+        COUNTERS.errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn percentile(sorted: &[u64], fraction: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[((sorted.len() - 1) as f64 * fraction).round() as usize]
+}
+
+fn report() {
+    let mut times = std::mem::take(&mut *ACK_TIMES.lock());
+    times.sort_unstable();
+    println!(
+        "{:>6.0}s  connected {}  sent {}  acked {}  rejected {}  received {}  errors {}  ack ms p50 {} p95 {} max {}",
+        STARTED.elapsed().as_secs_f64(),
+        COUNTERS.connected.load(Ordering::Relaxed),
+        COUNTERS.sent.load(Ordering::Relaxed),
+        COUNTERS.acked.load(Ordering::Relaxed),
+        COUNTERS.rejected.load(Ordering::Relaxed),
+        COUNTERS.received.load(Ordering::Relaxed),
+        COUNTERS.errors.load(Ordering::Relaxed),
+        percentile(&times, 0.5),
+        percentile(&times, 0.95),
+        times.last().copied().unwrap_or(0),
+    );
+}
+
+// Entry point for `who-chat simulate`; `args` are the ones after "simulate"
+pub fn run(args: impl Iterator<Item = String>) {
+    let options = match parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("Usage: who-chat simulate [--rooms N] [--users N] [--rate MSGS_PER_SEC] [--duration SECS] [--url WS_URL]");
+            std::process::exit(2);
+        },
+    };
+    println!(
+        "Simulating {} users in {} rooms at {} messages/s against {}",
+        options.users, options.rooms, options.rate, options.url
+    );
+    lazy_static::initialize(&STARTED);
+
+    let interval = Duration::from_secs_f64(options.users as f64 / options.rate);
+    let settings = ws::Settings { max_connections: options.users + 1, ..ws::Settings::default() };
+    let mut socket = match ws::Builder::new()
+        .with_settings(settings)
+        .build(|out| SyntheticUser { out, interval })
+    {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("Couldn't start the simulation: {}", err);
+            std::process::exit(1);
+        },
+    };
+    for user in 0..options.users {
+        let url = format!("{}/sim-{}", options.url, user % options.rooms + 1);
+        let connected = url::Url::parse(&url)
+            .map_err(|err| err.to_string())
+            .and_then(|url| socket.connect(url).map(|_| ()).map_err(|err| err.to_string()));
+        if let Err(err) = connected {
+            eprintln!("Couldn't connect to {}: {}", url, err);
+            std::process::exit(1);
+        }
+    }
+
+    let duration = options.duration;
+    thread::spawn(move || loop {
+        let remaining = duration.map(|duration| duration.saturating_sub(STARTED.elapsed()));
+        thread::sleep(remaining.map_or(REPORT_EVERY, |remaining| remaining.min(REPORT_EVERY)));
+        report();
+        if remaining.is_some_and(|remaining| remaining <= REPORT_EVERY) {
+            std::process::exit(0);
+        }
+    });
+    if let Err(err) = socket.run() {
+        eprintln!("Simulation stopped: {}", err);
+    }
+    report();
+}
@@ -0,0 +1,143 @@
+// Starred messages, a per-account list of bookmarks. Over the WebSocket,
+//
+//   {"type": "star", "message_id": "<id>", "starred": true|false}
+//
+// stars (or with false, unstars) a message in the current room, and the
+// connection gets back {"type": "star", "message_id": ..., "starred": ...}.
+// A copy of the message is kept with the star, so it stays readable after it
+// falls out of the room's history.
+//
+//   GET /api/me/starred   {"starred": [{"message": {...}, "starred_at"}, ...]}
+//   GET /saved            the same list as a page
+//
+// Newest stars come first in both.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::Either::{self, Left, Right};
+use rocket::Route;
+use rocket::http::ContentType;
+use rocket::response::Redirect;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use rocket_dyn_templates::{Template, context};
+use serde_json::json;
+
+use crate::accounts::AccountSession;
+use crate::config::CONFIG;
+use crate::protocol::{self, ErrorCode};
+use crate::{ChatMessage, RoomState, User, proxy, storage};
+
+const MAX_STARS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Star {
+    message: ChatMessage,
+    starred_at: String,
+}
+
+lazy_static! {
+    // account id -> stars, oldest first
+    static ref STARS: RwLock<HashMap<String, Vec<Star>>> =
+        RwLock::new(storage::load("starred", "starred").unwrap_or_default());
+}
+
+fn save(stars: &HashMap<String, Vec<Star>>) {
+    if let Err(err) = storage::save("starred", "starred", stars) {
+        eprintln!("Failed to save starred messages: {}", err);
+    }
+}
+
+// Handles a `star` frame and returns the reply
+pub fn handle(user: &User, room: &RoomState, message_id: Option<&str>, starred: bool) -> Result<String, protocol::Error> {
+    let Some(account_id) = &user.account_id else {
+        return Err(protocol::Error::new(ErrorCode::AccountRequired, "Register your nickname to star messages"));
+    };
+    let Some(message_id) = message_id else {
+        return Err(protocol::Error::new(ErrorCode::InvalidFrame, "Stars need a \"message_id\" string"));
+    };
+
+    let mut all = STARS.write();
+    let stars = all.get(account_id.as_str()).map_or(&[][..], Vec::as_slice);
+    let position = stars.iter().position(|star| star.message.id == message_id);
+    match (starred, position) {
+        (true, None) => {
+            let message = room.messages.read().iter().find(|msg| msg.id == message_id).cloned();
+            let Some(message) = message else {
+                return Err(protocol::Error::new(ErrorCode::InvalidArguments, "No such message in this room"));
+            };
+            if stars.len() >= MAX_STARS {
+                return Err(protocol::Error::new(
+                    ErrorCode::InvalidArguments,
+                    format!("You can star at most {} messages", MAX_STARS),
+                ));
+            }
+            all.entry(account_id.clone())
+                .or_default()
+                .push(Star { message, starred_at: Utc::now().to_rfc3339() });
+        },
+        (false, Some(position)) => {
+            if let Some(stars) = all.get_mut(account_id.as_str()) {
+                stars.remove(position);
+                if stars.is_empty() {
+                    all.remove(account_id.as_str());
+                }
+            }
+        },
+        // Already the way it was asked for
+        _ => {},
+    }
+    save(&all);
+
+    Ok(json!({ "type": "star", "message_id": message_id, "starred": starred }).to_string())
+}
+
+fn starred(account_id: &str) -> Vec<Star> {
+    let mut stars = STARS.read().get(account_id).cloned().unwrap_or_default();
+    stars.reverse();
+    stars
+}
+
+#[rocket::get("/api/me/starred")]
+fn list(session: AccountSession) -> Json<Value> {
+    let stars: Vec<Value> = starred(&session.0.id)
+        .iter()
+        .map(|star| json!({ "message": star.message.to_frame(), "starred_at": star.starred_at }))
+        .collect();
+    Json(json!({ "starred": stars }))
+}
+
+#[rocket::get("/saved")]
+fn page(session: Option<AccountSession>) -> Either<(ContentType, Template), Redirect> {
+    let Some(session) = session else {
+        return Right(Redirect::to(proxy::url("/")));
+    };
+    let stars: Vec<Value> = starred(&session.0.id)
+        .into_iter()
+        .map(|star| {
+            let time = DateTime::parse_from_rfc3339(&star.message.timestamp)
+                .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            json!({
+                "room_id": star.message.room_id,
+                "sender": star.message.sender,
+                "content": star.message.content,
+                "time": time,
+            })
+        })
+        .collect();
+    Left((ContentType::HTML, Template::render("saved", context! {
+        title: "Saved messages",
+        nickname: session.0.username,
+        stars,
+        base: proxy::prefix(),
+        theme: &CONFIG.theme,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![list, page]
+}
@@ -0,0 +1,77 @@
+// Activity figures for the landing page and room discovery, worked out from
+// what's in memory: messages posted in the last hour and who is in a room.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use rocket::serde::Serialize;
+
+use crate::{CHAT_STATE, MessageType, RoomState};
+
+const TRENDING_WINDOW_MINS: i64 = 60;
+// Rooms shown on the login page
+pub const LANDING_TRENDING: usize = 5;
+pub const MAX_TRENDING: usize = 50;
+
+#[derive(Serialize)]
+pub struct TrendingRoom {
+    pub id: String,
+    pub users: usize,
+    pub messages_last_hour: usize,
+}
+
+// Messages people and bots posted since the cutoff; history is in posting
+// order, so this stops at the first older message
+fn messages_since(room: &RoomState, cutoff: DateTime<Utc>) -> usize {
+    room.messages
+        .read()
+        .iter()
+        .rev()
+        .take_while(|msg| DateTime::parse_from_rfc3339(&msg.timestamp).is_ok_and(|at| at >= cutoff))
+        .filter(|msg| msg.message_type != MessageType::SystemMessage)
+        .count()
+}
+
+// Busiest listed rooms of the last hour, skipping private and NSFW rooms
+// and rooms nobody has posted in
+pub fn trending(limit: usize) -> Vec<TrendingRoom> {
+    let cutoff = Utc::now() - Duration::minutes(TRENDING_WINDOW_MINS);
+    let rooms: Vec<_> = CHAT_STATE
+        .rooms
+        .read()
+        .iter()
+        .filter(|(_, room)| {
+            let config = room.config.read();
+            config.members.is_none() && !config.nsfw
+        })
+        .map(|(room_id, room)| (room_id.clone(), room.clone()))
+        .collect();
+
+    let mut trending: Vec<TrendingRoom> = rooms
+        .into_iter()
+        .map(|(id, room)| TrendingRoom {
+            id,
+            users: room.users.read().len(),
+            messages_last_hour: messages_since(&room, cutoff),
+        })
+        .filter(|room| room.messages_last_hour > 0)
+        .collect();
+    trending.sort_by(|a, b| {
+        b.messages_last_hour
+            .cmp(&a.messages_last_hour)
+            .then(b.users.cmp(&a.users))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    trending.truncate(limit);
+    trending
+}
+
+// Distinct users in any room right now
+pub fn online_users() -> usize {
+    let rooms = CHAT_STATE.rooms.read();
+    let mut users = HashSet::new();
+    for room in rooms.values() {
+        users.extend(room.users.read().keys().cloned());
+    }
+    users.len()
+}
@@ -0,0 +1,177 @@
+// JSON files under the data directory, one per (kind, key), e.g.
+// data/whiteboards/lobby.json, plus append-only JSON-lines logs
+// (data/audit/lobby.jsonl) and raw files (data/attachments/<id>.bin). All
+// of it is what backups (see backup.rs) save and restore.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rocket::serde::Serialize;
+use rocket::serde::de::DeserializeOwned;
+
+use crate::config::CONFIG;
+
+lazy_static! {
+    // Held shared by every write and exclusively while a backup reads the
+    // data directory, so a backup never catches a change halfway
+    static ref WRITES: RwLock<()> = RwLock::new(());
+}
+
+// Runs `read` with all writes to the data directory on hold
+pub fn paused<R>(read: impl FnOnce() -> R) -> R {
+    let _paused = WRITES.write();
+    read()
+}
+
+// Keys are room ids and the like, so anything outside a safe set of
+// characters is hex-escaped to keep them inside the data directory
+fn file_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02x}", byte));
+        }
+    }
+    name
+}
+
+fn path(kind: &str, key: &str) -> PathBuf {
+    CONFIG.data_dir.join(kind).join(format!("{}.json", file_name(key)))
+}
+
+fn blob_path(kind: &str, key: &str) -> PathBuf {
+    CONFIG.data_dir.join(kind).join(format!("{}.bin", file_name(key)))
+}
+
+fn log_path(kind: &str, key: &str) -> PathBuf {
+    CONFIG.data_dir.join(kind).join(format!("{}.jsonl", file_name(key)))
+}
+
+pub fn append_line(kind: &str, key: &str, line: &str) -> io::Result<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::storage_fault()?;
+    let _writing = WRITES.read();
+    let path = log_path(kind, key);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+// All lines of a log; empty if it doesn't exist yet
+pub fn read_lines(kind: &str, key: &str) -> io::Result<Vec<String>> {
+    match fs::read_to_string(log_path(kind, key)) {
+        Ok(data) => Ok(data.lines().map(str::to_string).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+// Keys of the existing logs of a kind, for keys that needed no escaping
+pub fn log_keys(kind: &str) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(CONFIG.data_dir.join(kind)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut keys = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name();
+        if let Some(key) = name.to_str().and_then(|name| name.strip_suffix(".jsonl")) {
+            keys.push(key.to_string());
+        }
+    }
+    keys.sort();
+    Ok(keys)
+}
+
+pub fn load<T: DeserializeOwned>(kind: &str, key: &str) -> Option<T> {
+    let path = path(kind, key);
+    let data = fs::read_to_string(&path).ok()?;
+    serde_json::from_str(&data)
+        .map_err(|err| eprintln!("Ignoring corrupt {}: {}", path.display(), err))
+        .ok()
+}
+
+pub fn remove(kind: &str, key: &str) -> io::Result<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::storage_fault()?;
+    let _writing = WRITES.read();
+    match fs::remove_file(path(kind, key)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+// Writes to a temporary file first so a crash never leaves a half-written file
+pub fn save<T: Serialize>(kind: &str, key: &str, value: &T) -> io::Result<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::storage_fault()?;
+    let _writing = WRITES.read();
+    let path = path(kind, key);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(value)?)?;
+    fs::rename(tmp, path)
+}
+
+// For state kept in memory and saved from the task loop: writes it out if
+// `dirty` says it changed since the last save, `what` naming it in errors
+pub fn save_if_dirty<T: Serialize>(kind: &str, key: &str, value: &mut T, dirty: fn(&mut T) -> &mut bool, what: &str) {
+    if !*dirty(value) {
+        return;
+    }
+    match save(kind, key, &*value) {
+        Ok(()) => *dirty(value) = false,
+        Err(err) => eprintln!("Failed to save {}: {}", what, err),
+    }
+}
+
+pub fn write_blob(kind: &str, key: &str, data: &[u8]) -> io::Result<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::storage_fault()?;
+    let _writing = WRITES.read();
+    let path = blob_path(kind, key);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("bin.tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)
+}
+
+// Adds to the end of a raw file, creating it if need be
+pub fn append_blob(kind: &str, key: &str, data: &[u8]) -> io::Result<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::storage_fault()?;
+    let _writing = WRITES.read();
+    let path = blob_path(kind, key);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(data)
+}
+
+pub fn remove_blob(kind: &str, key: &str) -> io::Result<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::storage_fault()?;
+    let _writing = WRITES.read();
+    match fs::remove_file(blob_path(kind, key)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+pub fn read_blob(kind: &str, key: &str) -> io::Result<Vec<u8>> {
+    fs::read(blob_path(kind, key))
+}
@@ -0,0 +1,59 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::{anonymous, archive, banner, calendars, escalation, events, honeypot, login_throttle, passkeys, pow, presence, preview, quota, ranks, rate_limit, reminders, retention, rooms, secrets, sessions, trivia, whiteboard};
+
+const TICK: Duration = Duration::from_secs(1);
+
+// Lets a job that runs every tick do its work only once per `period`
+pub struct Every {
+    period: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl Every {
+    pub const fn new(period: Duration) -> Self {
+        Every { period, last: Mutex::new(None) }
+    }
+
+    // True when the job is due, counting from now if so
+    pub fn due(&self) -> bool {
+        let mut last = self.last.lock();
+        if last.is_some_and(|last| last.elapsed() < self.period) {
+            return false;
+        }
+        *last = Some(Instant::now());
+        true
+    }
+}
+
+// Runs periodic jobs (timeouts, expiry) on a background thread
+pub fn start() {
+    thread::spawn(|| loop {
+        thread::sleep(TICK);
+        trivia::tick();
+        whiteboard::save_snapshots();
+        rooms::expire();
+        retention::sweep();
+        archive::sweep();
+        presence::expire();
+        banner::expire();
+        honeypot::expire();
+        events::remind();
+        calendars::remind();
+        reminders::fire();
+        quota::save_usage();
+        ranks::save_activity();
+        sessions::save_activity();
+        rate_limit::prune();
+        login_throttle::prune();
+        preview::prune();
+        anonymous::prune();
+        pow::prune();
+        passkeys::prune();
+        escalation::prune();
+        secrets::refresh();
+    });
+}
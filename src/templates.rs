@@ -0,0 +1,52 @@
+// Page templates. The defaults in templates/ are compiled into the binary,
+// so nothing has to be written next to it at startup. Operators can override
+// a page, or theme.css.hbs holding the CSS colors, by putting a file of the
+// same name in the override directory; simpler branding goes through the
+// [theme] config, which every page gets as `theme`.
+
+use std::path::PathBuf;
+
+use rocket::fairing::Fairing;
+use rocket_dyn_templates::Template;
+use rocket_dyn_templates::handlebars::handlebars_helper;
+use rust_embed::RustEmbed;
+
+use crate::assets;
+use crate::config::CONFIG;
+
+// {{ asset "chat.css" }} -> the file's content-hashed URL
+handlebars_helper!(asset: |name: String| assets::url(&name));
+
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+struct DefaultTemplates;
+
+// Where override templates are looked for. Rocket refuses to start if the
+// template directory is missing, so an empty one is created.
+pub fn override_dir() -> PathBuf {
+    let dir = CONFIG.template_dir.clone().unwrap_or_else(|| CONFIG.data_dir.join("templates"));
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        eprintln!("Failed to create {}: {}", dir.display(), err);
+    }
+    dir
+}
+
+// Registers every built-in template the override directory doesn't replace
+pub fn fairing() -> impl Fairing {
+    Template::custom(|engines| {
+        engines.handlebars.register_helper("asset", Box::new(asset));
+        for file in DefaultTemplates::iter() {
+            let name = file.split('.').next().unwrap_or(&file).to_string();
+            if engines.handlebars.get_template(&name).is_some() {
+                continue;
+            }
+            let Some(template) = DefaultTemplates::get(&file) else {
+                continue;
+            };
+            let source = String::from_utf8_lossy(&template.data);
+            if let Err(err) = engines.handlebars.register_template_string(&name, source) {
+                eprintln!("Invalid built-in template {}: {}", file, err);
+            }
+        }
+    })
+}
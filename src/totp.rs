@@ -0,0 +1,263 @@
+// Two-factor authentication with time-based one-time passwords (RFC 6238:
+// HMAC-SHA1, 30 second steps, 6 digits), as used by common authenticator
+// apps. Enrolling hands out the secret as an otpauth:// URI and QR code;
+// confirming a first code switches it on and returns single-use recovery
+// codes, of which only hashes are kept.
+
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use qrcode::QrCode;
+use qrcode::render::svg;
+use rand::Rng;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::accounts::{ACCOUNTS, Account, AccountSession};
+use crate::admin::{ApiResult, api_error};
+
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+// Steps either side of now that are still accepted, for clock drift
+const SKEW: i64 = 1;
+const RECOVERY_CODES: usize = 10;
+const ISSUER: &str = "WhoChat";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TotpSettings {
+    // Base32 secret shared with the authenticator app
+    secret: String,
+    // Set once a code has been confirmed; until then the secret is pending
+    enabled: bool,
+    // SHA-256 hashes of unused recovery codes
+    recovery_codes: Vec<String>,
+    // Last time step a code was accepted for, so codes can't be replayed
+    last_step: i64,
+}
+
+impl TotpSettings {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+fn code_at(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    binary % 10u32.pow(DIGITS)
+}
+
+// The step the code is valid for, if it matches one near now
+fn matching_step(secret: &str, code: &str) -> Option<i64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code: u32 = code.trim().parse().ok()?;
+    let now = Utc::now().timestamp() / STEP_SECS;
+    (now - SKEW..=now + SKEW).find(|&step| code_at(&secret, step) == code)
+}
+
+fn hash_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_lowercase().as_bytes()))
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand::rng().fill(&mut buf[..]);
+    hex::encode(buf)
+}
+
+// Accepts a current code or an unused recovery code, using it up either way
+fn redeem(settings: &mut TotpSettings, code: &str) -> bool {
+    if let Some(step) = matching_step(&settings.secret, code)
+        && step > settings.last_step
+    {
+        settings.last_step = step;
+        return true;
+    }
+    let hash = hash_code(code);
+    let before = settings.recovery_codes.len();
+    settings.recovery_codes.retain(|recovery| *recovery != hash);
+    settings.recovery_codes.len() < before
+}
+
+// Whether the account may log in with the given code; always true without 2FA
+pub fn check_login(account: &Account, code: Option<&str>) -> bool {
+    if !account.totp.as_ref().is_some_and(TotpSettings::enabled) {
+        return true;
+    }
+    let Some(code) = code.filter(|code| !code.trim().is_empty()) else {
+        return false;
+    };
+    ACCOUNTS.update(|accounts| {
+        accounts
+            .get_mut(&account.id)
+            .and_then(|account| account.totp.as_mut())
+            .is_some_and(|settings| redeem(settings, code))
+    })
+}
+
+#[rocket::get("/")]
+fn status(session: AccountSession) -> Json<Value> {
+    let settings = session.0.totp.filter(TotpSettings::enabled);
+    Json(json!({
+        "enabled": settings.is_some(),
+        "recovery_codes_left": settings.map(|settings| settings.recovery_codes.len()),
+    }))
+}
+
+// Starts enrollment with a fresh secret; replaces any unconfirmed one
+#[rocket::post("/setup")]
+fn setup(session: AccountSession) -> ApiResult {
+    let account = session.0;
+    if account.totp.as_ref().is_some_and(TotpSettings::enabled) {
+        return Err(api_error(Status::Conflict, "Two-factor authentication is already on"));
+    }
+
+    let mut bytes = [0u8; 20];
+    rand::rng().fill(&mut bytes);
+    let secret = BASE32_NOPAD.encode(&bytes);
+    ACCOUNTS.update(|accounts| {
+        if let Some(account) = accounts.get_mut(&account.id) {
+            account.totp = Some(TotpSettings {
+                secret: secret.clone(),
+                ..TotpSettings::default()
+            });
+        }
+    });
+
+    let label = format!("{}:{}", ISSUER, account.username).replace(' ', "%20");
+    let uri = format!(
+        "otpauth://totp/{}?secret={}&issuer={}&digits={}&period={}",
+        label, secret, ISSUER, DIGITS, STEP_SECS
+    );
+    let qr_svg = QrCode::new(uri.as_bytes())
+        .map(|code| code.render::<svg::Color>().min_dimensions(200, 200).build())
+        .map_err(|err| api_error(Status::InternalServerError, err))?;
+
+    Ok(Json(json!({
+        "secret": secret,
+        "otpauth_uri": uri,
+        "qr_svg": qr_svg,
+    })))
+}
+
+#[derive(Deserialize)]
+struct CodeRequest {
+    code: String,
+}
+
+// Turns 2FA on once the app produces a valid code, returning recovery codes
+#[rocket::post("/confirm", data = "<request>")]
+fn confirm(session: AccountSession, request: Json<CodeRequest>) -> ApiResult {
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = random_hex(4);
+            format!("{}-{}", &code[..4], &code[4..])
+        })
+        .collect();
+
+    ACCOUNTS.update(|accounts| {
+        let settings = accounts
+            .get_mut(&session.0.id)
+            .and_then(|account| account.totp.as_mut())
+            .filter(|settings| !settings.enabled)
+            .ok_or_else(|| api_error(Status::Conflict, "Start setup first"))?;
+        let step = matching_step(&settings.secret, &request.code)
+            .ok_or_else(|| api_error(Status::BadRequest, "Wrong authentication code"))?;
+
+        settings.enabled = true;
+        settings.last_step = step;
+        settings.recovery_codes = recovery_codes.iter().map(|code| hash_code(code)).collect();
+        Ok(())
+    })?;
+
+    Ok(Json(json!({ "enabled": true, "recovery_codes": recovery_codes })))
+}
+
+// Turns 2FA off; needs a current code or a recovery code
+#[rocket::delete("/", data = "<request>")]
+fn disable(session: AccountSession, request: Json<CodeRequest>) -> ApiResult {
+    ACCOUNTS.update(|accounts| {
+        let account = accounts
+            .get_mut(&session.0.id)
+            .ok_or_else(|| api_error(Status::NotFound, "No such account"))?;
+        let Some(settings) = account.totp.as_mut().filter(|settings| settings.enabled) else {
+            return Err(api_error(Status::Conflict, "Two-factor authentication is off"));
+        };
+        if !redeem(settings, &request.code) {
+            return Err(api_error(Status::BadRequest, "Wrong authentication code"));
+        }
+        account.totp = None;
+        Ok(())
+    })?;
+
+    Ok(Json(json!({ "enabled": false })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![status, setup, confirm, disable]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238's SHA-1 seed, "12345678901234567890"
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn settings() -> TotpSettings {
+        TotpSettings {
+            secret: SECRET.to_string(),
+            enabled: true,
+            recovery_codes: vec![hash_code("abcd-1234")],
+            last_step: 0,
+        }
+    }
+
+    fn current_code() -> String {
+        let secret = BASE32_NOPAD.decode(SECRET.as_bytes()).unwrap();
+        format!("{:06}", code_at(&secret, Utc::now().timestamp() / STEP_SECS))
+    }
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        let secret = b"12345678901234567890";
+        // The RFC's 8-digit codes, cut to their last six digits
+        for (time, code) in [(59, 287082), (1111111109, 81804), (1111111111, 50471), (1234567890, 5924), (2000000000, 279037), (20000000000, 353130)] {
+            assert_eq!(code_at(secret, time / STEP_SECS), code, "at {}", time);
+        }
+    }
+
+    #[test]
+    fn accepts_a_current_code_once() {
+        let mut settings = settings();
+        let code = current_code();
+        assert!(redeem(&mut settings, &code));
+        assert!(!redeem(&mut settings, &code));
+    }
+
+    #[test]
+    fn rejects_wrong_codes() {
+        let mut settings = settings();
+        assert!(matching_step(SECRET, "not a code").is_none());
+        assert!(matching_step("not base32!", &current_code()).is_none());
+        // Seven digits never come out of code_at
+        assert!(!redeem(&mut settings, "1000000"));
+        assert!(!redeem(&mut settings, ""));
+    }
+
+    #[test]
+    fn recovery_codes_work_once() {
+        let mut settings = settings();
+        assert!(redeem(&mut settings, " ABCD-1234 "));
+        assert!(settings.recovery_codes.is_empty());
+        assert!(!redeem(&mut settings, "abcd-1234"));
+    }
+}
@@ -0,0 +1,89 @@
+// Trace context for following a message through the server, in the W3C
+// Trace Context format (https://www.w3.org/TR/trace-context/). Every message
+// gets a trace id when it's created, or continues the trace of the request
+// that posted it:
+//
+//   POST /api/rooms/<id>/messages   the `traceparent` header
+//   WebSocket message frames        a "traceparent" field next to "content"
+//
+// The ids are written to the audit log with the message, and outbound HTTP
+// made on its behalf (webhook deliveries, bridges) sends a `traceparent`
+// header for a child span, so a tracing backend can stitch the hops together.
+
+use rand::Rng;
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::Request;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceContext {
+    // 32 lowercase hex digits, shared by every span of the trace
+    pub trace_id: String,
+    // 16 lowercase hex digits, this hop's span
+    pub span_id: String,
+}
+
+fn random_hex<const N: usize>() -> String {
+    let mut bytes = [0u8; N];
+    // All zeroes is invalid in both ids
+    while bytes.iter().all(|&byte| byte == 0) {
+        rand::rng().fill(&mut bytes);
+    }
+    hex::encode(bytes)
+}
+
+fn is_id(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value.bytes().all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+        && value.bytes().any(|byte| byte != b'0')
+}
+
+impl TraceContext {
+    // Starts a new trace
+    pub fn start() -> Self {
+        TraceContext {
+            trace_id: random_hex::<16>(),
+            span_id: random_hex::<8>(),
+        }
+    }
+
+    // A new span in the same trace
+    pub fn child(&self) -> Self {
+        TraceContext {
+            trace_id: self.trace_id.clone(),
+            span_id: random_hex::<8>(),
+        }
+    }
+
+    // Continues the trace from a `traceparent` value, or starts a new one if
+    // it's missing or malformed
+    pub fn continue_from(traceparent: Option<&str>) -> Self {
+        let parent = traceparent.and_then(|value| {
+            let mut parts = value.trim().split('-');
+            let (version, trace_id, parent_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+            let valid = version.len() == 2 && version != "ff" && is_id(trace_id, 32) && is_id(parent_id, 16) && flags.len() == 2;
+            valid.then(|| trace_id.to_string())
+        });
+        match parent {
+            Some(trace_id) => TraceContext { trace_id, span_id: random_hex::<8>() },
+            None => Self::start(),
+        }
+    }
+
+    // Header value naming this span as the parent of the next hop
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", self.trace_id, self.span_id)
+    }
+}
+
+// The request's `traceparent` header, if any
+pub struct TraceParent(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TraceParent {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(TraceParent(request.headers().get_one("traceparent").map(str::to_string)))
+    }
+}
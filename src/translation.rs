@@ -0,0 +1,150 @@
+// Auto-translation for rooms with a language set (the room settings'
+// "language", e.g. "en"), through a LibreTranslate-compatible provider
+// configured with a [translation] table:
+//
+//   [translation]
+//   url = "https://libretranslate.example.com"
+//   api_key = "..."        # if the provider wants one
+//
+// Messages people post in such a room go out as usual, then are translated
+// on a background thread so a slow provider never holds up chat. When the
+// message wasn't already in the room's language, the room gets
+//
+//   {"type": "translation", "message_id": ..., "translation": {"language", "source_language", "content"}}
+//
+// and the translation is kept on the message, so the history replay carries
+// both texts.
+
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::{CONFIG, TranslationConfig};
+use crate::plugins::{BotReply, Plugin};
+use crate::{CHAT_STATE, ChatMessage, MessageType, secrets};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_LANGUAGE_LEN: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Translation {
+    pub language: String,
+    // As detected by the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_language: Option<String>,
+    pub content: String,
+}
+
+// Language codes like "en", "pt-BR" or "zh-Hant"
+pub fn valid_language(language: &str) -> bool {
+    !language.is_empty()
+        && language.len() <= MAX_LANGUAGE_LEN
+        && language.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+}
+
+struct Job {
+    room_id: String,
+    message_id: String,
+    sender: String,
+    content: String,
+    language: String,
+}
+
+fn translate(agent: &ureq::Agent, config: &TranslationConfig, job: &Job) -> Result<Option<Translation>, String> {
+    let url = format!("{}/translate", config.url.trim_end_matches('/'));
+    let body = json!({
+        "q": job.content,
+        "source": "auto",
+        "target": job.language,
+        "format": "text",
+        "api_key": config.api_key.as_deref().map(secrets::resolve),
+    });
+    let response = agent
+        .post(&url)
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+        .map_err(|err| err.to_string())?
+        .into_string()
+        .map_err(|err| err.to_string())?;
+    let response: Value = serde_json::from_str(&response).map_err(|err| err.to_string())?;
+
+    let content = response["translatedText"].as_str().ok_or("no translatedText in the response")?;
+    let source_language = response["detectedLanguage"]["language"].as_str();
+    // Already in the room's language
+    if source_language == Some(job.language.as_str()) || content.trim() == job.content.trim() {
+        return Ok(None);
+    }
+    Ok(Some(Translation {
+        language: job.language.clone(),
+        source_language: source_language.map(str::to_string),
+        content: content.to_string(),
+    }))
+}
+
+// Keeps the translation on the message and sends it to the room
+fn deliver(job: &Job, translation: Translation) {
+    let room = CHAT_STATE.rooms.read().get(&job.room_id).cloned();
+    let Some(room) = room else {
+        return;
+    };
+    {
+        let mut messages = room.messages.write();
+        let Some(msg) = messages.iter_mut().find(|msg| msg.id == job.message_id) else {
+            return;
+        };
+        msg.translation = Some(translation.clone());
+    }
+    let frame = json!({
+        "type": "translation",
+        "message_id": job.message_id,
+        "translation": translation,
+    }).to_string();
+    room.send_where(&frame, |conn| !conn.blocks(&job.sender));
+}
+
+lazy_static! {
+    static ref JOBS: Option<Sender<Job>> = {
+        let config = CONFIG.translation.clone()?;
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+            for job in receiver {
+                match translate(&agent, &config, &job) {
+                    Ok(Some(translation)) => deliver(&job, translation),
+                    Ok(None) => {},
+                    Err(err) => eprintln!("Translating message {} in {} failed: {}", job.message_id, job.room_id, err),
+                }
+            }
+        });
+        Some(sender)
+    };
+}
+
+pub struct TranslationPlugin;
+
+impl Plugin for TranslationPlugin {
+    fn name(&self) -> &str {
+        "translation"
+    }
+
+    fn on_message_posted(&self, message: &ChatMessage) -> Option<BotReply> {
+        if message.message_type != MessageType::UserMessage {
+            return None;
+        }
+        let jobs = JOBS.as_ref()?;
+        let room = CHAT_STATE.rooms.read().get(&message.room_id).cloned()?;
+        let language = room.config.read().language.clone()?;
+        let _ = jobs.send(Job {
+            room_id: message.room_id.clone(),
+            message_id: message.id.clone(),
+            sender: message.sender.clone(),
+            content: message.content.clone(),
+            language,
+        });
+        None
+    }
+}
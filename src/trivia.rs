@@ -0,0 +1,260 @@
+// Trivia rounds: `/trivia start` asks questions from the question bank, the
+// first chat message matching an answer scores a point, and unanswered
+// questions time out on the background tick.
+
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rand::seq::SliceRandom;
+use rocket::serde::Deserialize;
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::config::CONFIG;
+use crate::plugins::{BotReply, Plugin};
+use crate::protocol::ErrorCode;
+use crate::{CHAT_STATE, ChatMessage, MessageType};
+
+const BOT_NAME: &str = "Trivia";
+const DEFAULT_ROUNDS: usize = 5;
+const MAX_ROUNDS: usize = 50;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Question {
+    question: String,
+    answers: Vec<String>,
+}
+
+impl Question {
+    fn is_answer(&self, guess: &str) -> bool {
+        let guess = normalize(guess);
+        self.answers.iter().any(|answer| normalize(answer) == guess)
+    }
+}
+
+// Lowercase and drop punctuation so "Paris!" matches "paris"
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn builtin_questions() -> Vec<Question> {
+    [
+        ("What is the capital of France?", &["Paris"][..]),
+        ("How many legs does a spider have?", &["8", "eight"][..]),
+        ("Which planet is known as the Red Planet?", &["Mars"][..]),
+        ("What is the chemical symbol for gold?", &["Au"][..]),
+        ("Who wrote 'Romeo and Juliet'?", &["Shakespeare", "William Shakespeare"][..]),
+        ("What is the largest ocean on Earth?", &["Pacific", "Pacific Ocean"][..]),
+        ("How many sides does a hexagon have?", &["6", "six"][..]),
+        ("What gas do plants absorb from the air?", &["Carbon dioxide", "CO2"][..]),
+        ("In which year did the first person walk on the Moon?", &["1969"][..]),
+        ("What is the smallest prime number?", &["2", "two"][..]),
+    ]
+    .iter()
+    .map(|(question, answers)| Question {
+        question: question.to_string(),
+        answers: answers.iter().map(|a| a.to_string()).collect(),
+    })
+    .collect()
+}
+
+fn load_questions() -> Vec<Question> {
+    let Some(path) = &CONFIG.trivia_questions else {
+        return builtin_questions();
+    };
+
+    let loaded = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| serde_json::from_str::<Vec<Question>>(&data).map_err(|e| e.to_string()))
+        .map(|questions| questions.into_iter().filter(|q| !q.answers.is_empty()).collect::<Vec<_>>());
+
+    match loaded {
+        Ok(questions) if !questions.is_empty() => questions,
+        Ok(_) => {
+            eprintln!("Trivia question bank {} has no usable questions, using built-in questions", path.display());
+            builtin_questions()
+        },
+        Err(err) => {
+            eprintln!("Failed to load trivia questions from {}: {}", path.display(), err);
+            builtin_questions()
+        }
+    }
+}
+
+struct Game {
+    questions: Vec<Question>,
+    current: Question,
+    asked_at: Instant,
+    round: usize,
+    rounds: usize,
+    scores: HashMap<String, u32>,
+}
+
+impl Game {
+    fn prompt(&self) -> String {
+        format!("Question {}/{}: {}", self.round, self.rounds, self.current.question)
+    }
+
+    fn leaderboard(&self) -> String {
+        if self.scores.is_empty() {
+            return "No points scored.".to_string();
+        }
+
+        let mut scores: Vec<_> = self.scores.iter().collect();
+        scores.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        scores
+            .iter()
+            .enumerate()
+            .map(|(i, (nickname, score))| format!("{}. {} - {}", i + 1, nickname, score))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Move to the next question, or None when the game is over
+    fn advance(&mut self) -> Option<String> {
+        let next = self.questions.pop()?;
+        self.current = next;
+        self.round += 1;
+        self.asked_at = Instant::now();
+        Some(self.prompt())
+    }
+}
+
+lazy_static! {
+    static ref QUESTIONS: Vec<Question> = load_questions();
+    // room id -> running game
+    static ref GAMES: Mutex<HashMap<String, Game>> = Mutex::new(HashMap::new());
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register("trivia", "/trivia start [rounds] | stop | scores - play trivia", trivia);
+}
+
+fn trivia(ctx: &CommandContext) -> CommandOutput {
+    let room_id = &ctx.user.room_id;
+    let (action, rest) = ctx.args.split_once(' ').unwrap_or((ctx.args, ""));
+
+    match action {
+        "start" => {
+            let mut games = GAMES.lock();
+            if games.contains_key(room_id) {
+                return CommandOutput::Reply("A trivia game is already running in this room".to_string());
+            }
+
+            let rounds = rest.trim().parse().unwrap_or(DEFAULT_ROUNDS).clamp(1, MAX_ROUNDS);
+            let mut questions = QUESTIONS.clone();
+            questions.shuffle(&mut rand::rng());
+            questions.truncate(rounds);
+            let rounds = questions.len();
+            let Some(current) = questions.pop() else {
+                return CommandOutput::Reply("The question bank is empty".to_string());
+            };
+
+            let game = Game {
+                questions,
+                current,
+                asked_at: Instant::now(),
+                round: 1,
+                rounds,
+                scores: HashMap::new(),
+            };
+            let content = format!(
+                "{} started a trivia game with {} questions! Answer in the chat.\n{}",
+                ctx.user.nickname, rounds, game.prompt()
+            );
+            games.insert(room_id.clone(), game);
+            CommandOutput::bot(BOT_NAME, content)
+        },
+        "stop" => match GAMES.lock().remove(room_id) {
+            Some(game) => CommandOutput::bot(
+                BOT_NAME,
+                format!("{} stopped the game.\nFinal scores:\n{}", ctx.user.nickname, game.leaderboard()),
+            ),
+            None => CommandOutput::Reply("No trivia game is running".to_string()),
+        },
+        "scores" => match GAMES.lock().get(room_id) {
+            Some(game) => CommandOutput::hint(BOT_NAME, format!("Scores:\n{}", game.leaderboard())),
+            None => CommandOutput::Reply("No trivia game is running".to_string()),
+        },
+        _ => CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /trivia start [rounds] | stop | scores"),
+    }
+}
+
+// Checks chat messages for answers to the current question
+pub struct TriviaPlugin;
+
+impl Plugin for TriviaPlugin {
+    fn name(&self) -> &str {
+        "trivia"
+    }
+
+    fn on_room_destroy(&self, room_id: &str) {
+        GAMES.lock().remove(room_id);
+    }
+
+    fn on_message_posted(&self, message: &ChatMessage) -> Option<BotReply> {
+        let mut games = GAMES.lock();
+        let game = games.get_mut(&message.room_id)?;
+        if !game.current.is_answer(&message.content) {
+            return None;
+        }
+
+        *game.scores.entry(message.sender.clone()).or_insert(0) += 1;
+        let mut content = format!("{} got it! The answer was {}.", message.sender, game.current.answers[0]);
+        match game.advance() {
+            Some(prompt) => content = format!("{}\n{}", content, prompt),
+            None => {
+                let game = games.remove(&message.room_id)?;
+                content = format!("{}\nGame over! Final scores:\n{}", content, game.leaderboard());
+            }
+        }
+
+        Some(BotReply {
+            sender: BOT_NAME.to_string(),
+            content,
+        })
+    }
+}
+
+// Times out unanswered questions
+pub fn tick() {
+    let timeout = Duration::from_secs(CONFIG.trivia_answer_secs);
+    let mut announcements = Vec::new();
+
+    {
+        let mut games = GAMES.lock();
+        let expired: Vec<String> = games
+            .iter()
+            .filter(|(_, game)| game.asked_at.elapsed() >= timeout)
+            .map(|(room_id, _)| room_id.clone())
+            .collect();
+
+        for room_id in expired {
+            let Some(game) = games.get_mut(&room_id) else { continue };
+            let reveal = format!("Time's up! The answer was {}.", game.current.answers[0]);
+            let content = match game.advance() {
+                Some(prompt) => format!("{}\n{}", reveal, prompt),
+                None => {
+                    let leaderboard = game.leaderboard();
+                    games.remove(&room_id);
+                    format!("{}\nGame over! Final scores:\n{}", reveal, leaderboard)
+                }
+            };
+            announcements.push((room_id, content));
+        }
+    }
+
+    // Post outside the games lock
+    for (room_id, content) in announcements {
+        let room_state = CHAT_STATE.get_or_create_room(&room_id);
+        room_state.post(ChatMessage::new(&room_id, BOT_NAME, &content, MessageType::Bot));
+    }
+}
@@ -0,0 +1,104 @@
+// Guests claiming their nickname mid-session:
+//
+//   POST /api/account/upgrade   {"password"}   -> {"account_id", "username"}
+//
+// registers the guest's nickname as an account and hands over what was kept
+// for the guest's user id: messages, room memberships, read markers,
+// keywords, quiet rooms and knock approvals. Registering is the only step
+// that can fail and comes first, so a refused nickname or password leaves
+// everything as it was. The rest happens with every room locked, so no
+// frame or request sees the guest half handed over. Messages in memory under
+// the nickname that no account has claimed yet become the account's;
+// archived ones stay as they are. Connections and tickets carry on as the
+// account, and connections are told with
+//
+//   {"type": "account", "account_id", "username"}
+//
+// so an open WebSocket stays open; the connection picks the account up with
+// its next frame. The browser gets the account's session cookie.
+
+use rocket::Route;
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::serde::Deserialize;
+use rocket::serde::json::Json;
+use serde_json::json;
+
+use crate::accounts::ACCOUNTS;
+use crate::admin::{ApiResult, api_error};
+use crate::sessions::{ClientInfo, SESSIONS};
+use crate::{CHAT_STATE, ChatMessage, MessageType, RoomState, UserSession, actions, dashboard, keywords, knock, quiet};
+
+#[derive(Deserialize)]
+struct Upgrade {
+    password: String,
+}
+
+// What the guest sent themselves, as opposed to notices and bot replies
+fn sent_as_guest(msg: &ChatMessage, nickname: &str) -> bool {
+    msg.account_id.is_none()
+        && msg.sender == nickname
+        && matches!(msg.message_type, MessageType::UserMessage | MessageType::Location | MessageType::Call)
+}
+
+#[rocket::post("/upgrade", data = "<request>")]
+fn upgrade(user: UserSession, client: ClientInfo, cookies: &CookieJar<'_>, request: Json<Upgrade>) -> ApiResult {
+    if user.account_id.is_some() {
+        return Err(api_error(Status::Conflict, "Already signed in to an account"));
+    }
+    let account = ACCOUNTS.register(&user.nickname, &request.password).map_err(|err| api_error(Status::BadRequest, err))?;
+    let session = SESSIONS.create(&account.id, &client);
+
+    let guest_key = knock::key(&user.user_id, None);
+    let account_key = knock::key(&user.user_id, Some(&account.id));
+    let frame = json!({ "type": "account", "account_id": account.id, "username": account.username }).to_string();
+    let mut rooms: Vec<RoomState> = CHAT_STATE.rooms.read().values().cloned().collect();
+    // In id order, so two upgrades can't each hold a room the other waits for
+    rooms.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut locked: Vec<_> = rooms
+        .iter()
+        .map(|room| (room, room.config.write(), room.users.write(), room.connections.write(), room.messages.write()))
+        .collect();
+    let mut joined = Vec::new();
+    for (room, config, users, connections, messages) in &mut locked {
+        if config.approved.remove(&guest_key) {
+            config.approved.insert(account_key.clone());
+        }
+        if let Some(nickname) = config.knocks.remove(&guest_key) {
+            config.knocks.insert(account_key.clone(), nickname);
+        }
+        if let Some(member) = users.get_mut(&user.user_id) {
+            member.account_id = Some(account.id.clone());
+            member.session_id = Some(session.id.clone());
+        }
+        for msg in messages.iter_mut().filter(|msg| sent_as_guest(msg, &user.nickname)) {
+            msg.account_id = Some(account.id.clone());
+        }
+        for conn in connections.iter_mut().filter(|conn| conn.user_id == user.user_id) {
+            conn.account_id = Some(account.id.clone());
+            conn.session_id = Some(session.id.clone());
+            let _ = conn.sender.send(frame.clone());
+            if !joined.contains(&room.id) {
+                joined.push(room.id.clone());
+            }
+        }
+    }
+    for ticket in CHAT_STATE.ws_tickets.write().values_mut().filter(|ticket| ticket.user.id == user.user_id) {
+        ticket.user.account_id = Some(account.id.clone());
+        ticket.user.session_id = Some(session.id.clone());
+    }
+    actions::adopt(&user.user_id, &account.id);
+    keywords::adopt(&user.user_id, &account.id);
+    quiet::adopt(&user.user_id, &account.id);
+    drop(locked);
+
+    for room_id in joined {
+        dashboard::remember(&account.id, &room_id);
+    }
+
+    cookies.add_private(Cookie::new("session_id", session.id));
+    Ok(Json(json!({ "account_id": account.id, "username": account.username })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![upgrade]
+}
@@ -0,0 +1,214 @@
+// Chunked uploads, for content too big to send as one chat message or WS
+// frame, such as long pastes or files. The client starts an upload,
+//
+//   POST /api/uploads                     {"name", "content_type", "size"}
+//     -> 201 {"id", "chunk_size", "offset": 0}
+//
+// sends the bytes in order, at most chunk_size at a time,
+//
+//   PUT /api/uploads/<id>?offset=<n>      (raw bytes)
+//     -> {"offset", "complete", "attachment"}
+//
+// (GET /api/uploads/<id> says where to resume after a dropped connection),
+// and once complete posts a message carrying the reference:
+//
+//   {"type": "message", "content": "<preview>", "upload": "<id>"}
+//
+// over the WebSocket, or "upload" in a POST /api/rooms/<room_id>/messages
+// body. The upload becomes the message's attachment. Uploads belong to the
+// chat session or API token that started them, and ones not used within
+// UPLOAD_TTL are dropped.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use rocket::Route;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status::Created;
+use rocket::serde::Deserialize;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::UserSession;
+use crate::admin::{ApiResult, api_error};
+use crate::api_tokens::{ApiToken, Scope, authorize};
+use crate::attachments::{self, Attachment, MAX_ATTACHMENT_BYTES};
+use crate::flags::{self, Flag};
+use crate::protocol::{self, ErrorCode};
+use crate::proxy;
+
+pub const CHUNK_SIZE: usize = 256 * 1024;
+const UPLOAD_TTL: Duration = Duration::from_secs(60 * 60);
+// Unfinished or unused uploads one session or token can have at once
+const MAX_PENDING: usize = 4;
+
+struct Upload {
+    owner: String,
+    name: String,
+    content_type: String,
+    size: usize,
+    data: Vec<u8>,
+    started: Instant,
+    // Set once all the bytes are in
+    attachment: Option<Attachment>,
+}
+
+lazy_static! {
+    static ref UPLOADS: Mutex<HashMap<String, Upload>> = Mutex::new(HashMap::new());
+}
+
+// Who an upload belongs to: "user:<id>" for chat sessions, "token:<id>" for
+// API tokens and "admin" for the admin token
+pub struct Uploader(String);
+
+impl Uploader {
+    pub fn user(user_id: &str) -> Self {
+        Uploader(format!("user:{}", user_id))
+    }
+
+    // The admin token when there's no API token
+    pub fn api(token: Option<&ApiToken>) -> Self {
+        match token {
+            Some(token) => Uploader(format!("token:{}", token.id)),
+            None => Uploader("admin".to_string()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Uploader {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Outcome::Success(session) = request.guard::<UserSession>().await {
+            return Outcome::Success(Uploader::user(&session.user_id));
+        }
+        authorize(request, Scope::PostMessages).map(|token| Uploader::api(token.as_ref()))
+    }
+}
+
+// Hands over a finished upload to attach to a message in the room; each can
+// be used once
+pub fn take(uploader: &Uploader, id: &str, room_id: &str) -> Result<Attachment, protocol::Error> {
+    flags::check(Flag::Uploads, Some(room_id))?;
+    let mut uploads = UPLOADS.lock();
+    let finished = uploads
+        .get(id)
+        .is_some_and(|upload| upload.owner == uploader.0 && upload.attachment.is_some());
+    if !finished {
+        return Err(protocol::Error::new(ErrorCode::UnknownUpload, "No such finished upload"));
+    }
+    Ok(uploads.remove(id).and_then(|upload| upload.attachment).expect("checked above"))
+}
+
+fn status(id: &str, upload: &Upload) -> Value {
+    json!({
+        "id": id,
+        "name": upload.name,
+        "size": upload.size,
+        "offset": if upload.attachment.is_some() { upload.size } else { upload.data.len() },
+        "chunk_size": CHUNK_SIZE,
+        "complete": upload.attachment.is_some(),
+        "attachment": upload.attachment.as_ref().map(attachments::linked),
+    })
+}
+
+#[derive(Deserialize)]
+struct NewUpload {
+    #[serde(default)]
+    name: String,
+    content_type: Option<String>,
+    size: usize,
+}
+
+#[rocket::post("/", data = "<upload>")]
+fn start(uploader: Uploader, upload: Json<NewUpload>) -> Result<Created<Json<Value>>, (Status, Json<Value>)> {
+    if !flags::enabled(Flag::Uploads, None) {
+        return Err(api_error(Status::Forbidden, Flag::Uploads.error().detail));
+    }
+    if upload.size == 0 {
+        return Err(api_error(Status::BadRequest, "Uploads need a size"));
+    }
+    if upload.size > MAX_ATTACHMENT_BYTES {
+        return Err(api_error(Status::PayloadTooLarge, format!("Uploads are limited to {} bytes", MAX_ATTACHMENT_BYTES)));
+    }
+    let mut uploads = UPLOADS.lock();
+    uploads.retain(|_, upload| upload.started.elapsed() < UPLOAD_TTL);
+    if uploads.values().filter(|upload| upload.owner == uploader.0).count() >= MAX_PENDING {
+        return Err(api_error(Status::TooManyRequests, format!("At most {} uploads can be pending at once", MAX_PENDING)));
+    }
+
+    let id = Uuid::new_v4().simple().to_string();
+    let upload = Upload {
+        owner: uploader.0,
+        name: upload.name.clone(),
+        content_type: upload.content_type.clone().unwrap_or_else(|| "application/octet-stream".to_string()),
+        size: upload.size,
+        data: Vec::with_capacity(upload.size),
+        started: Instant::now(),
+        attachment: None,
+    };
+    let body = status(&id, &upload);
+    uploads.insert(id.clone(), upload);
+    Ok(Created::new(proxy::url(format!("/api/uploads/{}", id))).body(Json(body)))
+}
+
+#[rocket::get("/<id>")]
+fn progress(uploader: Uploader, id: &str) -> ApiResult {
+    let uploads = UPLOADS.lock();
+    let upload = uploads
+        .get(id)
+        .filter(|upload| upload.owner == uploader.0)
+        .ok_or(api_error(Status::NotFound, "No such upload"))?;
+    Ok(Json(status(id, upload)))
+}
+
+// Chunks must come in order; a chunk at the wrong offset gets 409 with the
+// offset to carry on from
+#[rocket::put("/<id>?<offset>", data = "<chunk>")]
+async fn append(uploader: Uploader, id: &str, offset: usize, chunk: Data<'_>) -> ApiResult {
+    let chunk = chunk
+        .open(CHUNK_SIZE.bytes())
+        .into_bytes()
+        .await
+        .map_err(|err| api_error(Status::BadRequest, err))?;
+    if !chunk.is_complete() {
+        return Err(api_error(Status::PayloadTooLarge, format!("Chunks are limited to {} bytes", CHUNK_SIZE)));
+    }
+
+    let mut uploads = UPLOADS.lock();
+    let upload = uploads
+        .get_mut(id)
+        .filter(|upload| upload.owner == uploader.0)
+        .ok_or(api_error(Status::NotFound, "No such upload"))?;
+    if upload.attachment.is_some() {
+        return Err(api_error(Status::Conflict, "This upload is already complete"));
+    }
+    if offset != upload.data.len() {
+        return Err((Status::Conflict, Json(json!({
+            "error": format!("Expected the chunk at offset {}", upload.data.len()),
+            "offset": upload.data.len(),
+        }))));
+    }
+    if upload.data.len() + chunk.len() > upload.size {
+        return Err(api_error(Status::BadRequest, "More data than the upload's size"));
+    }
+
+    upload.data.extend_from_slice(&chunk);
+    if upload.data.len() == upload.size {
+        let attachment = attachments::store(&upload.name, &upload.content_type, &upload.data)
+            .map_err(|err| api_error(Status::InternalServerError, err))?;
+        upload.data = Vec::new();
+        upload.attachment = Some(attachment);
+    }
+    Ok(Json(status(id, upload)))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![start, progress, append]
+}
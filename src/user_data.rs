@@ -0,0 +1,144 @@
+// Data-subject requests for registered accounts: an export of everything
+// stored about a user, and erasure, which deletes the account and
+// anonymizes the messages it sent. Rooms in compliance mode keep their
+// history untouched since it must stay as recorded.
+//
+// Messages are matched on the sender's account id. Messages stored before
+// that was recorded carry none and are matched by nickname instead, counting
+// only those sent after the account was created: a guest may have used the
+// nickname before it was registered, and those messages aren't the
+// account's. Messages the account sent under another nickname, or before it
+// was registered as a guest, aren't found either way.
+
+use std::collections::BTreeSet;
+
+use chrono::DateTime;
+use rocket::Route;
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::{CHAT_STATE, ChatMessage, permalinks};
+use crate::accounts::{ACCOUNTS, Account};
+use crate::admin::{ApiResult, ServerAdmin, api_error};
+use crate::proxy::Origin;
+use crate::sessions::SESSIONS;
+
+// Shown in place of the sender of anonymized messages
+pub const DELETED_USER: &str = "deleted-user";
+
+fn usernames(account_ids: &BTreeSet<String>) -> Vec<String> {
+    account_ids
+        .iter()
+        .filter_map(|id| ACCOUNTS.get(id))
+        .map(|account| account.username)
+        .collect()
+}
+
+fn profile(account: &Account) -> Value {
+    json!({
+        "id": account.id,
+        "username": account.username,
+        "created_at": account.created_at,
+        "friends": usernames(&account.friends),
+        "incoming_requests": usernames(&account.incoming_requests),
+        "outgoing_requests": usernames(&account.outgoing_requests),
+        "blocked": account.blocked,
+    })
+}
+
+fn sent_by(msg: &ChatMessage, account: &Account) -> bool {
+    match &msg.account_id {
+        Some(account_id) => *account_id == account.id,
+        None => {
+            let since = |at: &str| DateTime::parse_from_rfc3339(at).ok();
+            msg.sender.eq_ignore_ascii_case(&account.username)
+                && since(&msg.timestamp).zip(since(&account.created_at)).is_some_and(|(sent, created)| sent >= created)
+        },
+    }
+}
+
+// A message as exported, with a link back to it
+fn exported(msg: &ChatMessage, origin: &Origin) -> Value {
+    let mut frame = msg.to_frame();
+    frame["permalink"] = json!(permalinks::url(Some(&origin.0), msg));
+    frame
+}
+
+#[rocket::get("/<id>/data")]
+fn export(_admin: ServerAdmin, origin: Origin, id: &str) -> ApiResult {
+    let account = ACCOUNTS.get(id).ok_or_else(|| api_error(Status::NotFound, "No such account"))?;
+    let rooms: Vec<_> = CHAT_STATE.rooms.read().values().cloned().collect();
+
+    let mut messages = Vec::new();
+    let mut direct_messages = Vec::new();
+    for room in rooms {
+        let members = room.config.read().members.clone();
+        let is_dm = members.as_ref().is_some_and(|members| members.contains(&account.id));
+
+        for msg in room.messages.read().iter() {
+            if is_dm {
+                // The whole conversation, since both sides are the user's correspondence
+                direct_messages.push(exported(msg, &origin));
+            } else if sent_by(msg, &account) {
+                messages.push(exported(msg, &origin));
+            }
+        }
+    }
+
+    Ok(Json(json!({
+        "profile": profile(&account),
+        "messages": messages,
+        "direct_messages": direct_messages,
+    })))
+}
+
+// Replaces the sender on everything the account sent, outside compliance
+// rooms. Returns how many messages changed and the rooms that were left alone.
+pub fn anonymize_messages(account: &Account) -> (usize, Vec<String>) {
+    let rooms: Vec<_> = CHAT_STATE
+        .rooms
+        .read()
+        .iter()
+        .map(|(room_id, room)| (room_id.clone(), room.clone()))
+        .collect();
+
+    let mut anonymized = 0;
+    let mut retained = Vec::new();
+    for (room_id, room) in rooms {
+        let compliance = room.config.read().compliance;
+        let mut messages = room.messages.write();
+        for msg in messages.iter_mut() {
+            if !sent_by(msg, account) {
+                continue;
+            }
+            if compliance {
+                retained.push(room_id.clone());
+                break;
+            }
+            msg.sender = DELETED_USER.to_string();
+            msg.account_id = None;
+            anonymized += 1;
+        }
+    }
+    retained.sort();
+    (anonymized, retained)
+}
+
+#[rocket::delete("/<id>")]
+fn erase(_admin: ServerAdmin, id: &str) -> ApiResult {
+    let account = ACCOUNTS.remove(id).ok_or_else(|| api_error(Status::NotFound, "No such account"))?;
+    SESSIONS.revoke_account(&account.id);
+    let (anonymized, retained) = anonymize_messages(&account);
+
+    Ok(Json(json!({
+        "id": account.id,
+        "deleted": true,
+        "anonymized_messages": anonymized,
+        "retained_in_compliance_rooms": retained,
+    })))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![export, erase]
+}
@@ -0,0 +1,222 @@
+// Outbound webhooks: messages posted in a room are POSTed as JSON to each of
+// the room's webhook URLs. Deliveries are signed so receivers can check they
+// came from this server:
+//   X-WhoChat-Timestamp: <unix seconds>
+//   X-WhoChat-Signature: sha256=<hex HMAC-SHA256 of "<timestamp>.<body>">
+// keyed with the webhook's secret. sdk/js/webhooks.js verifies them.
+// Deliveries also carry a W3C `traceparent` header in the message's trace.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use rand::Rng;
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::plugins::{BotReply, Plugin};
+use crate::trace::TraceContext;
+use crate::{ChatMessage, permalinks, storage};
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    secret: String,
+    pub created_at: String,
+}
+
+impl Webhook {
+    // What the admin API lists; the secret is only shown when created
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "url": self.url,
+            "created_at": self.created_at,
+        })
+    }
+}
+
+pub struct WebhookStore {
+    // room id -> webhooks
+    hooks: RwLock<HashMap<String, Vec<Webhook>>>,
+}
+
+impl WebhookStore {
+    fn load() -> Self {
+        WebhookStore {
+            hooks: RwLock::new(storage::load("webhooks", "webhooks").unwrap_or_default()),
+        }
+    }
+
+    fn save(hooks: &HashMap<String, Vec<Webhook>>) {
+        if let Err(err) = storage::save("webhooks", "webhooks", hooks) {
+            eprintln!("Failed to save webhooks: {}", err);
+        }
+    }
+
+    pub fn list(&self, room_id: &str) -> Vec<Webhook> {
+        self.hooks.read().get(room_id).cloned().unwrap_or_default()
+    }
+
+    // Returns the webhook and its signing secret
+    pub fn add(&self, room_id: &str, url: &str) -> Result<(Webhook, String), String> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err("Webhook URLs must be http:// or https://".to_string());
+        }
+
+        let mut bytes = [0u8; 32];
+        rand::rng().fill(&mut bytes);
+        let secret = format!("whsec_{}", hex::encode(bytes));
+        let webhook = Webhook {
+            id: Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            secret: secret.clone(),
+            created_at: Utc::now().to_rfc3339(),
+        };
+
+        let mut hooks = self.hooks.write();
+        hooks.entry(room_id.to_string()).or_default().push(webhook.clone());
+        Self::save(&hooks);
+        Ok((webhook, secret))
+    }
+
+    pub fn remove(&self, room_id: &str, id: &str) -> bool {
+        let mut hooks = self.hooks.write();
+        let Some(room_hooks) = hooks.get_mut(room_id) else {
+            return false;
+        };
+        let before = room_hooks.len();
+        room_hooks.retain(|hook| hook.id != id);
+        let removed = room_hooks.len() != before;
+        if room_hooks.is_empty() {
+            hooks.remove(room_id);
+        }
+        if removed {
+            Self::save(&hooks);
+        }
+        removed
+    }
+}
+
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+struct Delivery {
+    webhook: Webhook,
+    body: String,
+    trace: Option<TraceContext>,
+}
+
+fn deliver(agent: &ureq::Agent, delivery: Delivery) {
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(&delivery.webhook.secret, timestamp, &delivery.body);
+    let mut request = agent
+        .post(&delivery.webhook.url)
+        .set("Content-Type", "application/json")
+        .set("X-WhoChat-Timestamp", &timestamp.to_string())
+        .set("X-WhoChat-Signature", &signature);
+    if let Some(trace) = &delivery.trace {
+        request = request.set("traceparent", &trace.traceparent());
+    }
+    if let Err(err) = request.send_string(&delivery.body) {
+        match &delivery.trace {
+            Some(trace) => eprintln!("Webhook delivery to {} failed (trace {}): {}", delivery.webhook.url, trace.trace_id, err),
+            None => eprintln!("Webhook delivery to {} failed: {}", delivery.webhook.url, err),
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref WEBHOOKS: WebhookStore = WebhookStore::load();
+    // Deliveries go out on their own thread so a slow receiver never holds up chat
+    static ref DELIVERIES: Sender<Delivery> = {
+        let (sender, receiver) = mpsc::channel::<Delivery>();
+        thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(DELIVERY_TIMEOUT).build();
+            for delivery in receiver {
+                deliver(&agent, delivery);
+            }
+        });
+        sender
+    };
+}
+
+// Sends a body to a URL that isn't one of a room's webhooks, signed the same way
+pub fn post(url: &str, secret: &str, body: String) {
+    let webhook = Webhook {
+        id: String::new(),
+        url: url.to_string(),
+        secret: secret.to_string(),
+        created_at: String::new(),
+    };
+    let _ = DELIVERIES.send(Delivery { webhook, body, trace: None });
+}
+
+pub struct WebhookPlugin;
+
+impl Plugin for WebhookPlugin {
+    fn name(&self) -> &str {
+        "webhooks"
+    }
+
+    fn on_message_posted(&self, message: &ChatMessage) -> Option<BotReply> {
+        let hooks = WEBHOOKS.list(&message.room_id);
+        if hooks.is_empty() {
+            return None;
+        }
+
+        let body = json!({
+            "event": "message.posted",
+            "room_id": message.room_id,
+            "message": message.to_frame(),
+            "permalink": permalinks::url(None, message),
+        }).to_string();
+        for webhook in hooks {
+            // Each delivery is its own span in the message's trace
+            let _ = DELIVERIES.send(Delivery {
+                webhook,
+                body: body.clone(),
+                trace: message.trace.as_ref().map(TraceContext::child),
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        assert_eq!(
+            sign("whsec", 1700000000, r#"{"text":"hi"}"#),
+            "sha256=851e82bbf388a45568eab8df189dcbe3cc07fb269da556bc168ef1d7e4ed5ef2"
+        );
+    }
+
+    #[test]
+    fn signature_covers_every_part() {
+        let signature = sign("whsec", 1700000000, "body");
+        assert_ne!(sign("other", 1700000000, "body"), signature);
+        assert_ne!(sign("whsec", 1700000001, "body"), signature);
+        assert_ne!(sign("whsec", 1700000000, "body!"), signature);
+        // The separator keeps digits from moving between timestamp and body
+        assert_ne!(sign("whsec", 170000000, "0body"), sign("whsec", 1700000000, "body"));
+    }
+}
@@ -0,0 +1,123 @@
+// Shared per-room whiteboard. Clients send events of the form
+//   {"kind": "stroke", "color": "#333", "width": 3, "points": [[x, y], ...]}
+//   {"kind": "clear"}
+// with coordinates normalized to 0..1 so boards of any size line up. The
+// board keeps the strokes drawn since the last clear so late joiners get a
+// snapshot, optionally saved to the data directory.
+
+use rocket::serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::CONFIG;
+use crate::{CHAT_STATE, storage};
+
+const MAX_EVENTS: usize = 5_000;
+const MAX_POINTS: usize = 2_000;
+const MAX_WIDTH: f64 = 50.0;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Whiteboard {
+    events: Vec<Value>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+fn validate_stroke(event: &Value) -> Result<(), &'static str> {
+    let points = event
+        .get("points")
+        .and_then(|points| points.as_array())
+        .ok_or("Stroke has no points")?;
+    if points.is_empty() || points.len() > MAX_POINTS {
+        return Err("Stroke has too many points");
+    }
+
+    let in_range = |v: &Value| v.as_f64().is_some_and(|v| (0.0..=1.0).contains(&v));
+    let valid_point = |point: &Value| {
+        point
+            .as_array()
+            .is_some_and(|xy| xy.len() == 2 && xy.iter().all(in_range))
+    };
+    if !points.iter().all(valid_point) {
+        return Err("Stroke points must be [x, y] pairs between 0 and 1");
+    }
+
+    if let Some(width) = event.get("width")
+        && !width.as_f64().is_some_and(|w| w > 0.0 && w <= MAX_WIDTH)
+    {
+        return Err("Invalid stroke width");
+    }
+    if let Some(color) = event.get("color")
+        && color.as_str().is_none_or(|c| c.len() > 32)
+    {
+        return Err("Invalid stroke color");
+    }
+    Ok(())
+}
+
+impl Whiteboard {
+    pub fn restore(room_id: &str) -> Self {
+        if !CONFIG.whiteboard_snapshots {
+            return Whiteboard::default();
+        }
+        storage::load("whiteboards", room_id).unwrap_or_default()
+    }
+
+    pub fn apply(&mut self, event: &Value) -> Result<(), &'static str> {
+        match event.get("kind").and_then(|kind| kind.as_str()) {
+            Some("stroke") => {
+                validate_stroke(event)?;
+                self.events.push(event.clone());
+                if self.events.len() > MAX_EVENTS {
+                    let excess = self.events.len() - MAX_EVENTS;
+                    self.events.drain(..excess);
+                }
+            },
+            Some("clear") => self.events.clear(),
+            _ => return Err("Unknown whiteboard event"),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn snapshot_frame(&self) -> Option<String> {
+        if self.events.is_empty() {
+            return None;
+        }
+        Some(json!({
+            "type": "whiteboard_snapshot",
+            "events": self.events,
+        }).to_string())
+    }
+}
+
+// Forgets the saved board of a deleted room
+pub fn discard(room_id: &str) {
+    if let Err(err) = storage::remove("whiteboards", room_id) {
+        eprintln!("Failed to remove whiteboard for {}: {}", room_id, err);
+    }
+}
+
+// Writes out boards that changed since the last save
+pub fn save_snapshots() {
+    if !CONFIG.whiteboard_snapshots {
+        return;
+    }
+
+    let rooms: Vec<_> = CHAT_STATE
+        .rooms
+        .read()
+        .iter()
+        .map(|(room_id, room)| (room_id.clone(), room.clone()))
+        .collect();
+
+    for (room_id, room) in rooms {
+        let mut whiteboard = room.whiteboard.lock();
+        if !whiteboard.dirty {
+            continue;
+        }
+        match storage::save("whiteboards", &room_id, &*whiteboard) {
+            Ok(()) => whiteboard.dirty = false,
+            Err(err) => eprintln!("Failed to save whiteboard for {}: {}", room_id, err),
+        }
+    }
+}
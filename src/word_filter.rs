@@ -0,0 +1,159 @@
+// Per-room word lists kept by room admins. Each entry either masks the word
+// with asterisks or rejects the whole message, before it is stored.
+
+use regex::{Regex, RegexBuilder};
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::commands::{CommandContext, CommandOutput, CommandRegistry};
+use crate::plugins::{MessageVerdict, Plugin};
+use crate::protocol::ErrorCode;
+use crate::{CHAT_STATE, ChatMessage};
+
+const MAX_WORD_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterPolicy {
+    #[default]
+    Mask,
+    Reject,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordFilterSpec {
+    pub word: String,
+    #[serde(default)]
+    pub policy: FilterPolicy,
+}
+
+// A word filter with its matcher compiled; (de)serialized as a WordFilterSpec
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "WordFilterSpec", into = "WordFilterSpec")]
+pub struct WordFilter {
+    spec: WordFilterSpec,
+    regex: Regex,
+}
+
+impl TryFrom<WordFilterSpec> for WordFilter {
+    type Error = String;
+
+    fn try_from(mut spec: WordFilterSpec) -> Result<Self, Self::Error> {
+        spec.word = spec.word.trim().to_string();
+        if spec.word.is_empty() || spec.word.chars().count() > MAX_WORD_LEN {
+            return Err(format!("Filtered words must be 1 to {} characters", MAX_WORD_LEN));
+        }
+
+        // Match whole words only, so filtering "ass" leaves "class" alone
+        let is_word_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let start = if is_word_char(spec.word.chars().next()) { r"\b" } else { "" };
+        let end = if is_word_char(spec.word.chars().last()) { r"\b" } else { "" };
+        let regex = RegexBuilder::new(&format!("{}{}{}", start, regex::escape(&spec.word), end))
+            .case_insensitive(true)
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(WordFilter { spec, regex })
+    }
+}
+
+impl From<WordFilter> for WordFilterSpec {
+    fn from(filter: WordFilter) -> Self {
+        filter.spec
+    }
+}
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(
+        "filter",
+        "/filter add <word> [mask|reject] | remove <word> | list - manage the room's word filter (room admins)",
+        filter,
+    );
+}
+
+fn filter(ctx: &CommandContext) -> CommandOutput {
+    let room_state = CHAT_STATE.get_or_create_room(&ctx.user.room_id);
+    let mut config = room_state.config.write();
+    if !config.is_admin(ctx.user.account_id.as_deref()) {
+        return CommandOutput::error(ErrorCode::Forbidden, "Only room admins can manage the word filter");
+    }
+
+    let (action, rest) = ctx.args.split_once(' ').unwrap_or((ctx.args, ""));
+    let rest = rest.trim();
+    match action {
+        "add" => {
+            // A trailing policy is optional; everything before it is the word or phrase
+            let (word, policy) = match rest.rsplit_once(' ') {
+                Some((word, "mask")) => (word, FilterPolicy::Mask),
+                Some((word, "reject")) => (word, FilterPolicy::Reject),
+                _ => (rest, FilterPolicy::Mask),
+            };
+            let filter = match WordFilter::try_from(WordFilterSpec { word: word.to_string(), policy }) {
+                Ok(filter) => filter,
+                Err(err) => return CommandOutput::error(ErrorCode::InvalidArguments, err),
+            };
+            config.word_filters.retain(|existing| !existing.spec.word.eq_ignore_ascii_case(&filter.spec.word));
+            let reply = format!("Added \"{}\" to the word filter ({})", filter.spec.word, policy_name(policy));
+            config.word_filters.push(filter);
+            config.version += 1;
+            CommandOutput::Reply(reply)
+        },
+        "remove" => {
+            let before = config.word_filters.len();
+            config.word_filters.retain(|existing| !existing.spec.word.eq_ignore_ascii_case(rest));
+            if config.word_filters.len() == before {
+                CommandOutput::Reply(format!("\"{}\" isn't in the word filter", rest))
+            } else {
+                config.version += 1;
+                CommandOutput::Reply(format!("Removed \"{}\" from the word filter", rest))
+            }
+        },
+        "list" => {
+            if config.word_filters.is_empty() {
+                return CommandOutput::Reply("The word filter is empty".to_string());
+            }
+            let entries: Vec<String> = config
+                .word_filters
+                .iter()
+                .map(|filter| format!("{} ({})", filter.spec.word, policy_name(filter.spec.policy)))
+                .collect();
+            CommandOutput::Reply(format!("Filtered words:\n{}", entries.join("\n")))
+        },
+        _ => CommandOutput::error(ErrorCode::InvalidArguments, "Usage: /filter add <word> [mask|reject] | remove <word> | list"),
+    }
+}
+
+fn policy_name(policy: FilterPolicy) -> &'static str {
+    match policy {
+        FilterPolicy::Mask => "mask",
+        FilterPolicy::Reject => "reject",
+    }
+}
+
+pub struct WordFilterPlugin;
+
+impl Plugin for WordFilterPlugin {
+    fn name(&self) -> &str {
+        "word_filter"
+    }
+
+    fn on_message(&self, message: &mut ChatMessage) -> MessageVerdict {
+        let room = CHAT_STATE.get_or_create_room(&message.room_id);
+        let config = room.config.read();
+
+        // Rejections win over masks, whatever order the entries are in
+        let rejected = config
+            .word_filters
+            .iter()
+            .any(|filter| filter.spec.policy == FilterPolicy::Reject && filter.regex.is_match(&message.content));
+        if rejected {
+            return MessageVerdict::Reject("it contains a word that isn't allowed in this room".to_string());
+        }
+
+        for filter in config.word_filters.iter().filter(|filter| filter.spec.policy == FilterPolicy::Mask) {
+            let masked = filter
+                .regex
+                .replace_all(&message.content, |caps: &regex::Captures| "*".repeat(caps[0].chars().count()));
+            message.content = masked.into_owned();
+        }
+        MessageVerdict::Accept
+    }
+}
@@ -0,0 +1,117 @@
+// Where clients should open their WebSocket. Served directly, that's the
+// page's host on the WebSocket port. Behind a trusted reverse proxy it's the
+// same origin as the page, with the proxy expected to pass WebSocket upgrades
+// under the path prefix on to the WebSocket port, e.g. for nginx:
+//
+//   location /chat/ {
+//       proxy_pass http://127.0.0.1:8000;
+//       if ($http_upgrade = "websocket") { proxy_pass http://127.0.0.1:8082; }
+//       proxy_http_version 1.1;
+//       proxy_set_header Upgrade $http_upgrade;
+//       proxy_set_header Connection "upgrade";
+//       proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+//       proxy_set_header X-Forwarded-Proto $scheme;
+//   }
+//
+// `ws_public_url` overrides all of this for anything more unusual.
+
+use rocket::Route;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::config::CONFIG;
+use crate::{CHAT_STATE, User, UserSession, proxy};
+
+pub struct WsEndpoint {
+    scheme: String,
+    host: String,
+    path: String,
+    same_origin: bool,
+}
+
+fn from_public_url(url: &str) -> WsEndpoint {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("ws", url));
+    let (host, path) = rest.split_once('/').map_or((rest, ""), |(host, path)| (host, path));
+    let path = path.trim_end_matches('/');
+    WsEndpoint {
+        scheme: scheme.to_string(),
+        host: host.to_string(),
+        path: if path.is_empty() { String::new() } else { format!("/{}", path) },
+        same_origin: false,
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WsEndpoint {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Some(url) = &CONFIG.ws_public_url {
+            return Outcome::Success(from_public_url(url));
+        }
+
+        let headers = request.headers();
+        let hostname = request
+            .host()
+            .map(|host| host.domain().to_string())
+            .unwrap_or_else(|| "localhost".to_string());
+
+        if proxy::behind_proxy(request) {
+            let secure = headers.get_one("X-Forwarded-Proto").is_some_and(|proto| proto.eq_ignore_ascii_case("https"));
+            let host = headers
+                .get_one("X-Forwarded-Host")
+                .or_else(|| headers.get_one("Host"))
+                .map(str::to_string)
+                .unwrap_or(hostname);
+            return Outcome::Success(WsEndpoint {
+                scheme: if secure { "wss" } else { "ws" }.to_string(),
+                host,
+                path: proxy::prefix().to_string(),
+                same_origin: true,
+            });
+        }
+
+        Outcome::Success(WsEndpoint {
+            scheme: "ws".to_string(),
+            host: format!("{}:{}", hostname, CONFIG.ws_port),
+            path: proxy::prefix().to_string(),
+            same_origin: false,
+        })
+    }
+}
+
+// Clients connect to `<url>/<room id>?ticket=<ticket>`
+#[rocket::get("/")]
+fn ws_config(endpoint: WsEndpoint) -> Json<Value> {
+    Json(json!({
+        "url": format!("{}://{}{}", endpoint.scheme, endpoint.host, endpoint.path),
+        "scheme": endpoint.scheme,
+        "host": endpoint.host,
+        "path": endpoint.path,
+        "same_origin": endpoint.same_origin,
+        "port": CONFIG.ws_port,
+    }))
+}
+
+// A ticket for the signed-in user's room, good for one connection within a
+// minute; clients ask for a new one each time they (re)connect
+#[rocket::get("/")]
+fn ws_ticket(session: UserSession) -> Json<Value> {
+    let ticket = CHAT_STATE.issue_ws_ticket(User {
+        id: session.user_id,
+        nickname: session.nickname,
+        room_id: session.room_id,
+        account_id: session.account_id,
+        session_id: session.session_id,
+    });
+    Json(json!({ "ticket": ticket }))
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![ws_config]
+}
+
+pub fn ticket_routes() -> Vec<Route> {
+    rocket::routes![ws_ticket]
+}
@@ -0,0 +1,787 @@
+// Page settings come from data attributes on <body>
+const nickname = document.body.dataset.nickname;
+const roomId = document.body.dataset.roomId;
+// Path prefix when served behind a reverse proxy, "" otherwise
+const basePath = document.body.dataset.base;
+// Message to scroll to, from a permalink; only asked for on the first connect
+let focusId = document.body.dataset.focus;
+// Longer messages are uploaded and sent as a preview with a link
+const maxMessageLen = Number(document.body.dataset.maxMessageLen);
+const previewLen = 200;
+// Only accounts can star and vote on messages
+const registered = document.body.dataset.registered === "true";
+// Filled in from /api/ws-config, which knows about ports and proxies
+let wsUrl;
+
+let ws;
+
+function connect() {
+    if (!wsUrl) {
+        fetch(basePath + "/api/ws-config").then(response => response.json()).then(config => {
+            wsUrl = config.url + "/" + roomId;
+            connect();
+        });
+        return;
+    }
+    // Tickets are good for one connection, so each attempt gets a new one;
+    // without a session the page reloads to the sign-in form
+    fetch(basePath + "/api/ws-ticket").then(response => {
+        if (!response.ok || response.redirected) {
+            window.location.reload();
+            return;
+        }
+        return response.json().then(body => openSocket(body.ticket));
+    }).catch(() => setTimeout(connect, 3000));
+}
+
+function openSocket(ticket) {
+    const url = wsUrl + "?ticket=" + encodeURIComponent(ticket);
+    ws = new WebSocket(focusId ? url + "&focus=" + encodeURIComponent(focusId) : url);
+    focusId = null;
+
+    ws.onopen = function() {
+        console.log("Connected to WebSocket");
+    };
+
+    ws.onmessage = function(event) {
+        const data = JSON.parse(event.data);
+
+        if (data.type === "command") {
+            handleCommand(data);
+        } else if (data.type === "whiteboard") {
+            applyWhiteboardEvent(data.event);
+        } else if (data.type === "whiteboard_snapshot") {
+            strokes = [];
+            data.events.forEach(applyWhiteboardEvent);
+        } else if (data.type === "presence" || data.type === "friend_request") {
+            loadFriends();
+        } else if (data.type === "ack") {
+            delete pending[data.client_id];
+        } else if (data.type === "nack") {
+            sendFailed(data);
+        } else if (data.type === "banner") {
+            showBanner(data.banner);
+        } else if (data.type === "star") {
+            showStarred(data.message_id, data.starred);
+        } else if (data.type === "focus") {
+            focusMessage(data.message_id, data.found);
+        } else if (data.type === "delete") {
+            removeMessage(data.message_id);
+        } else if (data.type === "votes") {
+            showVotes(data.message_id, data.votes);
+        } else if (data.type === "call_joined") {
+            showCallJoined(data.message_id, data.joined);
+        } else if (data.type === "knock") {
+            addMessage({ type: "system", content: `${data.nickname} is asking to join: /approve ${data.nickname} or /deny ${data.nickname}` });
+        } else if (data.type === "escalation") {
+            addMessage({ type: "system", content: `Needs attention: ${data.nickname} (${data.reason}), ${data.detail}`, ephemeral: true });
+        } else if (data.type === "account") {
+            addMessage({ type: "system", content: `You're now signed in as ${data.username}`, ephemeral: true });
+        } else if (data.type === "moved") {
+            window.location.href = `${basePath}/?rid=${encodeURIComponent(data.room_id)}`;
+        } else if (data.type === "translation") {
+            showTranslation(data.message_id, data.translation);
+        } else if (data.type === "error") {
+            addMessage({ type: "system", content: data.detail, ephemeral: data.ephemeral });
+        } else if (data.type === "read_state" || data.type === "action_result") {
+            // Read markers; this page just marks everything read while it's visible
+            if (data.type === "read_state" && data.unread > 0 && !document.hidden) {
+                markRead();
+            }
+        } else {
+            addMessage(data);
+            notifyInBackground(data);
+        }
+    };
+
+    ws.onclose = function() {
+        console.log("Disconnected from WebSocket");
+        // Try to reconnect after a delay
+        setTimeout(connect, 3000);
+    };
+
+    ws.onerror = function(error) {
+        console.error("WebSocket error:", error);
+    };
+}
+
+// Sent messages waiting for the server's ack, by client id
+const pending = {};
+let nextClientId = 1;
+
+function sendTracked(frame, text) {
+    const clientId = String(nextClientId++);
+    pending[clientId] = text;
+    ws.send(JSON.stringify({ ...frame, client_id: clientId }));
+}
+
+// The server turned a message down; say why and offer the text again
+function sendFailed(data) {
+    const text = pending[data.client_id];
+    delete pending[data.client_id];
+    addMessage({ type: "system", content: "Not sent: " + data.detail });
+    const input = document.getElementById("message-input");
+    if (text && !input.value) {
+        input.value = text;
+    }
+}
+
+function markRead() {
+    if (ws && ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify({ type: "action", action: "mark_read" }));
+    }
+}
+
+document.addEventListener("visibilitychange", function() {
+    if (!document.hidden) {
+        markRead();
+    }
+});
+
+function handleCommand(data) {
+    switch (data.command) {
+        case "clear":
+            document.getElementById("messages").innerHTML = "";
+            break;
+        case "logout":
+            window.location.href = basePath + "/logout";
+            break;
+    }
+}
+
+function addMessage(data) {
+    const messagesDiv = document.getElementById("messages");
+    const messageDiv = document.createElement("div");
+
+    messageDiv.className = `message ${data.type}`;
+    if (data.id) {
+        messageDiv.dataset.messageId = data.id;
+    }
+    // Only sent to this connection, and gone on reload
+    if (data.ephemeral) {
+        messageDiv.classList.add("ephemeral");
+        messageDiv.title = "Only visible to you";
+    }
+    // Matches one of the user's /keyword words
+    if (data.highlight) {
+        messageDiv.classList.add("highlight");
+    }
+    // Monitoring alerts are colored by severity, or as resolved
+    if (data.alert) {
+        messageDiv.classList.add("monitoring", data.alert.status === "resolved" ? "monitoring-resolved" : `monitoring-${data.alert.severity.replace(/\W/g, "")}`);
+    }
+
+    if (data.type === "message" || data.type === "bot" || data.type === "location" || data.type === "call") {
+        const senderDiv = document.createElement("div");
+        senderDiv.className = "sender";
+        senderDiv.textContent = data.sender;
+        if (data.rank) {
+            const rankSpan = document.createElement("span");
+            rankSpan.className = "rank";
+            rankSpan.textContent = data.rank;
+            senderDiv.appendChild(rankSpan);
+        }
+        messageDiv.appendChild(senderDiv);
+
+        if (data.forwarded) {
+            const forwardedDiv = document.createElement("div");
+            forwardedDiv.className = "forwarded";
+            forwardedDiv.textContent = `Forwarded from #${data.forwarded.room_id} (${data.forwarded.sender}) by ${data.forwarded.by}`;
+            messageDiv.appendChild(forwardedDiv);
+        }
+
+        const contentDiv = document.createElement("div");
+        contentDiv.className = "content";
+        if (data.html) {
+            // Rendered and escaped by the server
+            contentDiv.innerHTML = data.html;
+        } else {
+            contentDiv.textContent = data.content;
+        }
+        if (data.spoiler) {
+            const warningDiv = document.createElement("div");
+            warningDiv.className = "content-warning";
+            warningDiv.textContent = (data.content_warning ? "CW: " + data.content_warning : "Spoiler") + " (click to reveal)";
+            messageDiv.appendChild(warningDiv);
+            contentDiv.classList.add("spoiler");
+            contentDiv.addEventListener("click", function() {
+                contentDiv.classList.remove("spoiler");
+                warningDiv.remove();
+            }, { once: true });
+        }
+        messageDiv.appendChild(contentDiv);
+        if (data.translation) {
+            messageDiv.appendChild(translationDiv(data.translation));
+        }
+
+        if (data.preview) {
+            const previewLink = document.createElement("a");
+            previewLink.className = "preview";
+            previewLink.href = data.preview.url;
+            previewLink.target = "_blank";
+            previewLink.rel = "noopener";
+            if (data.preview.image_url) {
+                const image = document.createElement("img");
+                image.src = data.preview.image_url;
+                image.alt = data.preview.title;
+                previewLink.appendChild(image);
+            } else {
+                previewLink.textContent = data.preview.title;
+            }
+            messageDiv.appendChild(previewLink);
+        }
+
+        if (data.call) {
+            const callDiv = document.createElement("div");
+            callDiv.className = "call";
+            const joinLink = document.createElement("a");
+            joinLink.href = data.call.url;
+            joinLink.target = "_blank";
+            joinLink.rel = "noopener";
+            joinLink.textContent = "Join call";
+            joinLink.addEventListener("click", function() {
+                ws.send(JSON.stringify({ type: "join_call", message_id: data.id }));
+            });
+            callDiv.appendChild(joinLink);
+            const joinedSpan = document.createElement("span");
+            joinedSpan.className = "call-joined";
+            joinedSpan.dataset.messageId = data.id;
+            joinedSpan.textContent = callJoinedText(data.call.joined);
+            callDiv.appendChild(joinedSpan);
+            messageDiv.appendChild(callDiv);
+        }
+
+        if (data.attachments && data.attachments.length) {
+            const attachmentsDiv = document.createElement("div");
+            attachmentsDiv.className = "attachments";
+            for (const attachment of data.attachments) {
+                const link = document.createElement("a");
+                link.href = attachment.url;
+                link.target = "_blank";
+                link.rel = "noopener";
+                if (attachment.content_type.startsWith("image/")) {
+                    const image = document.createElement("img");
+                    image.src = attachment.url;
+                    image.alt = attachment.name;
+                    link.appendChild(image);
+                } else {
+                    link.textContent = attachment.name;
+                }
+                attachmentsDiv.appendChild(link);
+            }
+            messageDiv.appendChild(attachmentsDiv);
+        }
+
+        if (data.html) {
+            const rawLink = document.createElement("a");
+            rawLink.className = "raw-link";
+            rawLink.href = basePath + "/paste/" + data.id;
+            rawLink.target = "_blank";
+            rawLink.textContent = "View raw" + (data.code_language ? " (" + data.code_language + ")" : "");
+            messageDiv.appendChild(rawLink);
+        }
+
+        if (data.tags && data.tags.length > 0) {
+            const tagsDiv = document.createElement("div");
+            tagsDiv.className = "tags";
+            tagsDiv.textContent = data.tags.map(tag => '#' + tag).join(" ");
+            messageDiv.appendChild(tagsDiv);
+        }
+
+        if (registered) {
+            const starLink = document.createElement("a");
+            starLink.href = "#";
+            starLink.className = "star-link";
+            starLink.dataset.messageId = data.id;
+            starLink.textContent = "Star";
+            starLink.addEventListener("click", function(e) {
+                e.preventDefault();
+                const starred = starLink.textContent === "Star";
+                ws.send(JSON.stringify({ type: "star", message_id: data.id, starred: starred }));
+            });
+            messageDiv.appendChild(starLink);
+        }
+
+        if (data.type === "message") {
+            messageDiv.appendChild(votesSpan(data));
+        }
+
+        if (data.sender !== nickname) {
+            const reportLink = document.createElement("a");
+            reportLink.href = "#";
+            reportLink.className = "report-link";
+            reportLink.textContent = "Report";
+            reportLink.addEventListener("click", function(e) {
+                e.preventDefault();
+                const reason = prompt("What's wrong with this message? (optional)");
+                if (reason !== null) {
+                    ws.send(JSON.stringify({ type: "report", message_id: data.id, reason: reason }));
+                }
+            });
+            messageDiv.appendChild(reportLink);
+        }
+
+        const timeDiv = document.createElement("div");
+        timeDiv.className = "time";
+        timeDiv.textContent = new Date(data.timestamp).toLocaleTimeString();
+        messageDiv.appendChild(timeDiv);
+    } else if (data.type === "system") {
+        // Bot hints say which bot they're from
+        messageDiv.textContent = data.sender ? `${data.sender}: ${data.content}` : data.content;
+    } else if (data.type === "alert") {
+        messageDiv.textContent = `Alert (${data.pattern}) from ${data.sender}: ${data.content}`;
+    }
+
+    messagesDiv.appendChild(messageDiv);
+    messagesDiv.scrollTop = messagesDiv.scrollHeight;
+}
+
+// Room notice from the admins, or null to take it down
+function showBanner(banner) {
+    const bannerDiv = document.getElementById("banner");
+    bannerDiv.hidden = !banner;
+    bannerDiv.className = banner ? "banner " + banner.severity : "banner";
+    bannerDiv.textContent = banner ? banner.text : "";
+}
+
+function focusMessage(messageId, found) {
+    const messageDiv = found && document.querySelector(`.message[data-message-id="${CSS.escape(messageId)}"]`);
+    if (!messageDiv) {
+        addMessage({ type: "system", content: "That message is no longer in this room's history" });
+        return;
+    }
+    messageDiv.classList.add("focused");
+    messageDiv.scrollIntoView({ block: "center" });
+}
+
+function translationDiv(translation) {
+    const div = document.createElement("div");
+    div.className = "translation";
+    div.lang = translation.language;
+    div.title = translation.source_language ? `Translated from ${translation.source_language}` : "Translated";
+    div.textContent = translation.content;
+    return div;
+}
+
+// Translations arrive after the message; shown under the original text
+function showTranslation(messageId, translation) {
+    const messageDiv = document.querySelector(`.message[data-message-id="${CSS.escape(messageId)}"]`);
+    const contentDiv = messageDiv && messageDiv.querySelector(".content");
+    if (!contentDiv || messageDiv.querySelector(".translation")) {
+        return;
+    }
+    contentDiv.after(translationDiv(translation));
+}
+
+function callJoinedText(joined) {
+    return joined.length ? "Joined: " + joined.join(", ") : "";
+}
+
+function showCallJoined(messageId, joined) {
+    document.querySelectorAll(".call-joined").forEach(function(span) {
+        if (span.dataset.messageId === messageId) {
+            span.textContent = callJoinedText(joined);
+        }
+    });
+}
+
+// Score with +1/-1 toggles; only accounts get the toggles, and not on their
+// own messages
+function votesSpan(data) {
+    const span = document.createElement("span");
+    span.className = "votes";
+    span.dataset.messageId = data.id;
+    const score = document.createElement("span");
+    score.className = "score";
+    span.appendChild(score);
+    if (registered && data.sender !== nickname) {
+        for (const [vote, label] of [[1, "+1"], [-1, "-1"]]) {
+            const button = document.createElement("button");
+            button.type = "button";
+            button.dataset.vote = vote;
+            button.textContent = label;
+            button.addEventListener("click", function() {
+                // Clicking the vote already cast takes it back
+                const current = button.classList.contains("voted") ? 0 : vote;
+                ws.send(JSON.stringify({ type: "vote", message_id: data.id, vote: current }));
+            });
+            span.insertBefore(button, vote === 1 ? score : null);
+        }
+    }
+    fillVotes(span, data.votes);
+    return span;
+}
+
+function fillVotes(span, votes) {
+    const up = votes ? votes.up : [];
+    const down = votes ? votes.down : [];
+    span.querySelector(".score").textContent = up.length - down.length;
+    span.querySelectorAll("button").forEach(function(button) {
+        const voters = button.dataset.vote === "1" ? up : down;
+        button.classList.toggle("voted", voters.includes(nickname));
+    });
+}
+
+function showVotes(messageId, votes) {
+    document.querySelectorAll(".votes").forEach(function(span) {
+        if (span.dataset.messageId === messageId) {
+            fillVotes(span, votes);
+        }
+    });
+}
+
+// Taken down by an admin, see reports.rs
+function removeMessage(messageId) {
+    document.querySelectorAll(".message").forEach(function(div) {
+        if (div.dataset.messageId === messageId) {
+            div.remove();
+        }
+    });
+}
+
+function showStarred(messageId, starred) {
+    document.querySelectorAll(".star-link").forEach(function(link) {
+        if (link.dataset.messageId === messageId) {
+            link.textContent = starred ? "Unstar" : "Star";
+        }
+    });
+}
+
+document.getElementById("send-button").addEventListener("click", sendMessage);
+document.getElementById("message-input").addEventListener("keydown", function(e) {
+    // Shift+Enter inserts a newline, e.g. for fenced code blocks
+    if (e.key === "Enter" && !e.shiftKey) {
+        e.preventDefault();
+        sendMessage();
+    }
+});
+
+function sendMessage() {
+    const input = document.getElementById("message-input");
+    const message = input.value.trim();
+
+    if (message && ws && ws.readyState === WebSocket.OPEN) {
+        if ([...message].length > maxMessageLen) {
+            sendLong(message);
+        } else {
+            sendTracked({ content: message }, message);
+        }
+
+        input.value = "";
+    } else if (message && (!ws || ws.readyState !== WebSocket.OPEN)) {
+        console.log("WebSocket not connected, attempting to reconnect...");
+        connect();
+    }
+}
+
+// Uploads the text in chunks through /api/uploads, then posts the start of
+// it with the upload attached
+async function sendLong(message) {
+    const data = new TextEncoder().encode(message);
+    try {
+        let response = await fetch(basePath + "/api/uploads", {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ name: "message.txt", content_type: "text/plain; charset=utf-8", size: data.length })
+        });
+        let upload = await response.json();
+        if (!response.ok) {
+            throw new Error(upload.error);
+        }
+        while (!upload.complete) {
+            const chunk = data.subarray(upload.offset, upload.offset + upload.chunk_size);
+            response = await fetch(`${basePath}/api/uploads/${upload.id}?offset=${upload.offset}`, { method: "PUT", body: chunk });
+            upload = await response.json();
+            if (!response.ok) {
+                throw new Error(upload.error);
+            }
+        }
+        const preview = [...message].slice(0, previewLen).join("") + "…";
+        sendTracked({ content: preview, upload: upload.id }, message);
+    } catch (error) {
+        sendFailed({ client_id: null, detail: error.message });
+        document.getElementById("message-input").value = message;
+    }
+}
+
+document.getElementById("location-button").addEventListener("click", function() {
+    if (!navigator.geolocation) {
+        console.log("Geolocation is not available");
+        return;
+    }
+    navigator.geolocation.getCurrentPosition(function(position) {
+        if (ws && ws.readyState === WebSocket.OPEN) {
+            sendTracked({
+                type: "location",
+                lat: position.coords.latitude,
+                lon: position.coords.longitude
+            });
+        }
+    }, function(error) {
+        console.error("Could not get location:", error);
+    });
+});
+
+// Whiteboard: strokes use coordinates normalized to 0..1
+const board = document.getElementById("whiteboard");
+const boardContext = board.getContext("2d");
+let strokes = [];
+let currentStroke = null;
+
+function drawStroke(stroke) {
+    if (stroke.points.length === 0) {
+        return;
+    }
+    boardContext.strokeStyle = stroke.color || "#333333";
+    boardContext.lineWidth = stroke.width || 3;
+    boardContext.lineCap = "round";
+    boardContext.lineJoin = "round";
+    boardContext.beginPath();
+    boardContext.moveTo(stroke.points[0][0] * board.width, stroke.points[0][1] * board.height);
+    stroke.points.forEach(([x, y]) => boardContext.lineTo(x * board.width, y * board.height));
+    boardContext.stroke();
+}
+
+function redrawWhiteboard() {
+    board.width = board.clientWidth;
+    board.height = board.clientHeight;
+    boardContext.clearRect(0, 0, board.width, board.height);
+    strokes.forEach(drawStroke);
+}
+
+function applyWhiteboardEvent(event) {
+    if (event.kind === "stroke") {
+        strokes.push(event);
+        drawStroke(event);
+    } else if (event.kind === "clear") {
+        strokes = [];
+        redrawWhiteboard();
+    }
+}
+
+function sendWhiteboardEvent(event) {
+    if (ws && ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify({ type: "whiteboard", event: event }));
+    }
+}
+
+function boardPoint(e) {
+    const rect = board.getBoundingClientRect();
+    const clamp = v => Math.min(1, Math.max(0, v));
+    return [clamp((e.clientX - rect.left) / rect.width), clamp((e.clientY - rect.top) / rect.height)];
+}
+
+board.addEventListener("pointerdown", function(e) {
+    board.setPointerCapture(e.pointerId);
+    currentStroke = {
+        kind: "stroke",
+        color: document.getElementById("whiteboard-color").value,
+        width: 3,
+        points: [boardPoint(e)]
+    };
+});
+board.addEventListener("pointermove", function(e) {
+    if (currentStroke) {
+        currentStroke.points.push(boardPoint(e));
+        drawStroke({ ...currentStroke, points: currentStroke.points.slice(-2) });
+    }
+});
+board.addEventListener("pointerup", function() {
+    if (currentStroke) {
+        strokes.push(currentStroke);
+        sendWhiteboardEvent(currentStroke);
+        currentStroke = null;
+    }
+});
+
+document.getElementById("whiteboard-clear").addEventListener("click", function() {
+    applyWhiteboardEvent({ kind: "clear" });
+    sendWhiteboardEvent({ kind: "clear" });
+});
+document.getElementById("whiteboard-toggle").addEventListener("click", function(e) {
+    e.preventDefault();
+    document.getElementById("whiteboard-panel").classList.toggle("open");
+    redrawWhiteboard();
+});
+window.addEventListener("resize", redrawWhiteboard);
+
+// Join link and QR code, for rooms anyone can join
+const invitePanel = document.getElementById("invite-panel");
+if (invitePanel) {
+    const inviteLink = document.getElementById("invite-link");
+    inviteLink.textContent = inviteLink.href;
+    document.getElementById("invite-toggle").addEventListener("click", function(e) {
+        e.preventDefault();
+        invitePanel.classList.toggle("open");
+    });
+}
+
+// Contact list, only shown to registered accounts
+const friendsPanel = document.getElementById("friends-panel");
+
+function friendsRequest(method, path, body) {
+    return fetch(basePath + path, {
+        method: method,
+        headers: { "Content-Type": "application/json" },
+        body: body ? JSON.stringify(body) : undefined
+    }).then(response => response.json()).then(data => {
+        if (data.error) {
+            addMessage({ type: "system", content: data.error });
+        }
+        loadFriends();
+        loadSessions();
+        return data;
+    });
+}
+
+function friendItem(username, status, online, actions) {
+    const item = document.createElement("li");
+    const name = document.createElement("span");
+    name.textContent = username;
+    item.appendChild(name);
+    const statusSpan = document.createElement("span");
+    statusSpan.className = "status" + (online ? " online" : "");
+    statusSpan.textContent = status;
+    item.appendChild(statusSpan);
+    actions.forEach(([label, action]) => {
+        const button = document.createElement("button");
+        button.textContent = label;
+        button.addEventListener("click", action);
+        item.appendChild(button);
+    });
+    return item;
+}
+
+function loadFriends() {
+    if (!friendsPanel) {
+        return;
+    }
+    fetch(basePath + "/api/friends").then(response => response.ok ? response.json() : null).then(data => {
+        if (!data) {
+            return;
+        }
+        const list = document.getElementById("friends-list");
+        list.innerHTML = "";
+        data.friends.forEach(friend => {
+            const status = !friend.online ? "offline"
+                : friend.rooms.length > 0 ? "online in " + friend.rooms.join(", ") : "online";
+            const path = "/api/friends/" + encodeURIComponent(friend.username);
+            list.appendChild(friendItem(friend.username, status, friend.online, [
+                ["Message", () => friendsRequest("POST", path + "/dm").then(dm => {
+                    if (dm.url) {
+                        window.location.href = dm.url;
+                    }
+                })],
+                ["Remove", () => friendsRequest("DELETE", path)]
+            ]));
+        });
+
+        const requests = document.getElementById("friend-requests");
+        requests.innerHTML = "";
+        data.incoming.forEach(username => {
+            const path = "/api/friends/requests/" + encodeURIComponent(username);
+            requests.appendChild(friendItem(username, "wants to be friends", false, [
+                ["Accept", () => friendsRequest("POST", path + "/accept")],
+                ["Decline", () => friendsRequest("DELETE", path)]
+            ]));
+        });
+        data.outgoing.forEach(username => {
+            const path = "/api/friends/requests/" + encodeURIComponent(username);
+            requests.appendChild(friendItem(username, "request sent", false, [
+                ["Cancel", () => friendsRequest("DELETE", path)]
+            ]));
+        });
+    });
+}
+
+// Devices signed in to this account, each of which can be signed out
+function loadSessions() {
+    if (!friendsPanel) {
+        return;
+    }
+    fetch(basePath + "/api/sessions").then(response => response.ok ? response.json() : null).then(data => {
+        if (!data) {
+            return;
+        }
+        const list = document.getElementById("sessions-list");
+        list.innerHTML = "";
+        data.sessions.forEach(session => {
+            const device = session.user_agent || "Unknown device";
+            const status = (session.current ? "this device, " : "") + "last seen "
+                + new Date(session.last_seen).toLocaleString() + (session.ip ? " from " + session.ip : "");
+            list.appendChild(friendItem(device, status, session.current, [
+                ["Sign out", () => friendsRequest("DELETE", "/api/sessions/" + session.id)]
+            ]));
+        });
+    });
+}
+
+if (friendsPanel) {
+    document.getElementById("friends-toggle").addEventListener("click", function(e) {
+        e.preventDefault();
+        friendsPanel.classList.toggle("open");
+        loadFriends();
+        loadSessions();
+    });
+    document.getElementById("friend-add").addEventListener("click", function() {
+        const input = document.getElementById("friend-name");
+        const username = input.value.trim();
+        if (username) {
+            friendsRequest("POST", "/api/friends/requests", { username: username });
+            input.value = "";
+        }
+    });
+    loadFriends();
+}
+
+// Installed as an app: the service worker shows notifications for mentions
+// (and every DM) while the page is in the background
+const pageLoaded = Date.now();
+let worker;
+
+if (document.querySelector('link[rel="manifest"]') && "serviceWorker" in navigator) {
+    navigator.serviceWorker.register(basePath + "/sw.js", { scope: basePath + "/" }).then(registration => {
+        worker = registration;
+    });
+}
+
+function notifyInBackground(data) {
+    if (!worker || !worker.active || !document.hidden || !("Notification" in window) || Notification.permission !== "granted") {
+        return;
+    }
+    // History replayed on connect isn't news
+    if (!data.sender || data.sender === nickname || new Date(data.timestamp).getTime() < pageLoaded) {
+        return;
+    }
+    const mentioned = (data.content || "").toLowerCase().includes(nickname.toLowerCase());
+    if (!mentioned && !roomId.startsWith("dm_")) {
+        return;
+    }
+    worker.active.postMessage({
+        type: "notify",
+        title: data.sender + " in " + roomId,
+        body: data.spoiler ? "Spoiler" : data.content,
+        url: window.location.href,
+        tag: roomId
+    });
+}
+
+const notificationsToggle = document.getElementById("notifications-toggle");
+if (notificationsToggle) {
+    if (!("Notification" in window)) {
+        notificationsToggle.remove();
+    } else {
+        notificationsToggle.addEventListener("click", function(e) {
+            e.preventDefault();
+            Notification.requestPermission().then(permission => {
+                notificationsToggle.textContent = permission === "granted" ? "Notifications on" : "Notifications";
+            });
+        });
+        if (Notification.permission === "granted") {
+            notificationsToggle.textContent = "Notifications on";
+        }
+    }
+}
+
+// Connect to WebSocket when page loads
+connect();
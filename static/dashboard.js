@@ -0,0 +1,108 @@
+// Keeps the dashboard's unread badges and last messages up to date over one
+// WebSocket for all the rooms, see dashboard.rs
+const basePath = document.body.dataset.base;
+const wsTicket = document.body.dataset.wsTicket;
+const baseTitle = document.body.dataset.title;
+const list = document.getElementById("rooms");
+const empty = document.getElementById("empty");
+
+function roomItem(roomId) {
+    return Array.from(list.children).find(item => item.dataset.roomId === roomId);
+}
+
+function createItem(room) {
+    const item = document.createElement("li");
+    item.dataset.roomId = room.room_id;
+    if (room.accent_color) {
+        item.style.borderLeftColor = room.accent_color;
+    }
+    if (room.icon_url) {
+        const icon = document.createElement("img");
+        icon.className = "room-icon";
+        icon.src = room.icon_url;
+        icon.alt = "";
+        item.appendChild(icon);
+    } else if (room.icon) {
+        const icon = document.createElement("span");
+        icon.className = "room-icon";
+        icon.textContent = room.icon;
+        item.appendChild(icon);
+    }
+    const link = document.createElement("a");
+    link.href = room.url;
+    const name = document.createElement("strong");
+    name.textContent = "#" + room.room_id;
+    link.appendChild(name);
+    const unread = document.createElement("span");
+    unread.className = "unread";
+    const last = document.createElement("span");
+    last.className = "last";
+    const forget = document.createElement("button");
+    forget.type = "button";
+    forget.className = "forget";
+    forget.title = `Take #${room.room_id} off the dashboard`;
+    forget.textContent = "Remove";
+    item.append(link, " ", unread, " ", last, " ", forget);
+    return item;
+}
+
+function showRoom(room) {
+    let item = roomItem(room.room_id);
+    if (!item) {
+        item = createItem(room);
+        const after = Array.from(list.children).find(other => other.dataset.roomId > room.room_id);
+        list.insertBefore(item, after || null);
+    }
+    const unread = item.querySelector(".unread");
+    unread.textContent = room.unread;
+    unread.hidden = room.unread === 0;
+    item.querySelector(".last").textContent = room.last_message
+        ? `${room.last_message.sender}: ${room.last_message.preview}`
+        : "No messages yet.";
+    updateTotals();
+}
+
+function updateTotals() {
+    const total = Array.from(list.querySelectorAll(".unread")).reduce((sum, badge) => sum + Number(badge.textContent || 0), 0);
+    document.title = total ? `(${total}) ${baseTitle}` : baseTitle;
+    empty.hidden = list.children.length > 0;
+}
+
+list.addEventListener("click", event => {
+    if (!event.target.classList.contains("forget")) {
+        return;
+    }
+    const item = event.target.closest("li");
+    fetch(`${basePath}/api/dashboard/rooms/${encodeURIComponent(item.dataset.roomId)}`, { method: "DELETE" })
+        .then(response => {
+            if (response.ok) {
+                item.remove();
+                updateTotals();
+            }
+        });
+});
+
+function connect(url) {
+    const ws = new WebSocket(url);
+    ws.onmessage = function(event) {
+        const data = JSON.parse(event.data);
+        if (data.type === "rooms") {
+            data.rooms.forEach(showRoom);
+        } else if (data.type === "room" && data.room) {
+            // Rooms taken off here stay off until joined again
+            if (roomItem(data.room.room_id) || data.room.unread > 0) {
+                showRoom(data.room);
+            }
+        }
+    };
+    ws.onclose = function(event) {
+        // Policy means the ticket is no good any more; reloading gets a new one
+        if (event.code !== 1008) {
+            setTimeout(() => connect(url), 3000);
+        }
+    };
+}
+
+fetch(basePath + "/api/ws-config").then(response => response.json()).then(config => {
+    connect(config.url + "/.dashboard?ticket=" + wsTicket);
+});
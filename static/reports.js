@@ -0,0 +1,143 @@
+// The abuse report queue, see reports.rs. The admin token is kept for the
+// tab only, and every request goes to the admin API with it.
+const basePath = document.body.dataset.base;
+const list = document.getElementById("reports");
+const empty = document.getElementById("empty");
+const errorBox = document.getElementById("error");
+const tokenForm = document.getElementById("token-form");
+let status = "open";
+
+function api(method, path, body) {
+    return fetch(`${basePath}/api/admin${path}`, {
+        method: method,
+        headers: {
+            "Authorization": "Bearer " + sessionStorage.getItem("adminToken"),
+            "Content-Type": "application/json",
+        },
+        body: body ? JSON.stringify(body) : undefined,
+    }).then(response => {
+        if (response.status === 401) {
+            sessionStorage.removeItem("adminToken");
+            tokenForm.hidden = false;
+            throw new Error("Enter the admin token");
+        }
+        return response.json().then(data => {
+            if (!response.ok) {
+                throw new Error(data.error || response.statusText);
+            }
+            return data;
+        });
+    });
+}
+
+function showError(error) {
+    errorBox.textContent = error.message;
+    errorBox.hidden = !error.message;
+}
+
+function line(text, className) {
+    const element = document.createElement("div");
+    element.textContent = text;
+    if (className) {
+        element.className = className;
+    }
+    return element;
+}
+
+function contextList(report) {
+    const context = document.createElement("ol");
+    context.className = "context";
+    report.context.forEach(msg => {
+        const item = document.createElement("li");
+        item.textContent = `${msg.sender || "System"}: ${msg.content}`;
+        if (msg.id === report.message_id) {
+            item.className = "reported";
+        }
+        context.appendChild(item);
+    });
+    return context;
+}
+
+function actionButton(report, action, label) {
+    const button = document.createElement("button");
+    button.type = "button";
+    button.textContent = label;
+    button.addEventListener("click", () => {
+        const note = report.element.querySelector(".note").value;
+        api("POST", `/reports/${encodeURIComponent(report.id)}/resolve`, { action: action, note: note || null })
+            .then(() => {
+                showError({ message: "" });
+                load();
+            })
+            .catch(showError);
+    });
+    return button;
+}
+
+function reportItem(report) {
+    const item = document.createElement("li");
+    report.element = item;
+    const when = new Date(report.created_at).toLocaleString();
+    item.appendChild(line(`#${report.room_id}, reported by ${report.reporter} at ${when}`, "meta"));
+    item.appendChild(line(`${report.sender}: ${report.content}`, "reported"));
+    if (report.reason) {
+        item.appendChild(line(`Reason: ${report.reason}`));
+    }
+    if (report.context.length > 0) {
+        item.appendChild(contextList(report));
+    } else {
+        item.appendChild(line("The message is no longer in the room.", "system"));
+    }
+    if (report.resolution) {
+        const resolved = new Date(report.resolution.resolved_at).toLocaleString();
+        const note = report.resolution.note ? ` (${report.resolution.note})` : "";
+        item.appendChild(line(`Resolved: ${report.resolution.action} at ${resolved}${note}`, "system"));
+        return item;
+    }
+    const actions = document.createElement("div");
+    actions.className = "actions";
+    const note = document.createElement("input");
+    note.type = "text";
+    note.className = "note";
+    note.placeholder = "Note (optional)";
+    actions.append(
+        note,
+        actionButton(report, "delete", "Delete message"),
+        actionButton(report, "mute", "Mute for an hour"),
+        actionButton(report, "ban", "Ban from room"),
+        actionButton(report, "dismiss", "Dismiss"),
+    );
+    item.appendChild(actions);
+    return item;
+}
+
+function load() {
+    if (!sessionStorage.getItem("adminToken")) {
+        tokenForm.hidden = false;
+        return;
+    }
+    api("GET", `/reports?status=${status}`)
+        .then(data => {
+            tokenForm.hidden = true;
+            list.replaceChildren(...data.reports.map(reportItem));
+            empty.hidden = data.reports.length > 0;
+        })
+        .catch(showError);
+}
+
+tokenForm.addEventListener("submit", event => {
+    event.preventDefault();
+    sessionStorage.setItem("adminToken", document.getElementById("token").value);
+    showError({ message: "" });
+    load();
+});
+
+document.querySelectorAll("nav a[data-status]").forEach(link => {
+    link.addEventListener("click", event => {
+        event.preventDefault();
+        status = link.dataset.status;
+        load();
+    });
+});
+
+load();